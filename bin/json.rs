@@ -0,0 +1,30 @@
+use hydra_lang::run::value::Value;
+use serde_json::{Map, Number, Value as Json};
+
+/// the value as JSON, with functions and native objects as their debug strings and
+/// non-finite floats as null
+pub fn value(value: &Value) -> Json {
+    match value {
+        Value::Null => Json::Null,
+        Value::Int(v) => Json::from(*v),
+        Value::Float(v) => Number::from_f64(*v).map_or(Json::Null, Json::Number),
+        Value::Bool(v) => Json::Bool(*v),
+        Value::Char(v) => Json::String(v.to_string()),
+        Value::String(v) => Json::String(v.clone()),
+        Value::Vector(values) => {
+            Json::Array(values.lock().unwrap().iter().map(self::value).collect())
+        }
+        Value::Tuple(values) => {
+            Json::Array(values.lock().unwrap().iter().map(self::value).collect())
+        }
+        Value::Map(entries) => Json::Object(
+            entries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, v)| (key.clone(), self::value(v)))
+                .collect::<Map<String, Json>>(),
+        ),
+        Value::Fn(_) | Value::NativeObject(_) => Json::String(format!("{value:?}")),
+    }
+}
@@ -1,120 +1,297 @@
 extern crate clap;
 extern crate hydra_lang;
+mod json;
+mod repl;
 use hydra_lang::{
-    lex, parse,
+    lex,
+    pkg::{self, Manifest},
     run::{
+        code::Closure,
         compiler::{Compilable, Compiler, Frame, Scope},
-        interpreter::{Interpreter, RunTimeError},
+        format::{self, FormatError, Header, MAGIC},
+        interpreter::{Interpreter, RunTimeError, RunTimeErrorKind},
+        replay::Replay,
         value::{Function, Value},
     },
     scan::{
         self,
-        ast::{Chunk, Expression, Statement},
-        lexer::Line,
-        parser::{Parsable, Parser},
-        position::{Located, Position},
+        ast::{Atom, Chunk, Expression, Statement},
+        lexer::{LexError, Line},
+        parser::{Parsable, ParseError, Parser},
+        position::{byte_col, Located, Position},
     },
     std_hydra,
 };
+use serde_json::{json, Value as Json};
 use std::{
+    cell::RefCell,
     error::Error,
     fmt::{Debug, Display},
     fs,
-    io::{self, Write},
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
     process::exit,
     rc::Rc,
+    time::Instant,
 };
 
+/// the name a program piped into stdin goes by in errors
+pub const STDIN_PATH: &str = "<stdin>";
+
 fn main() {
     use clap::Parser;
-    let args = HydraArgs::parse();
+    let mut args = HydraArgs::parse();
+    match args.command.take() {
+        Some(HydraCommand::Pkg) => {
+            fetch_dependencies();
+            return;
+        }
+        Some(HydraCommand::Compile { input, output }) => {
+            args.input = Some(input);
+            compile_file(&args, output);
+            return;
+        }
+        Some(HydraCommand::Run { input }) => args.input = Some(input),
+        None => {}
+    }
+    if let (true, Some(path)) = (args.print_bc_info, &args.input) {
+        print_bc_info(path);
+        return;
+    }
     if let Some(path) = &args.input {
-        let text = fs::read_to_string(path)
-            .map_err(|err| {
-                eprintln!("ERROR {path}: {err}");
-                exit(1)
-            })
-            .unwrap();
-        let value = run_args(&text, vec![], &args)
-            .map_err(|Located { value: err, pos }| {
-                eprintln!(
-                    "ERROR {path}:{}:{}: {err}",
-                    pos.ln.start + 1,
-                    pos.col.start + 1
-                );
-                exit(1)
-            })
-            .unwrap();
-        if let Some(value) = value {
-            println!("{value:?}");
-        }
-    } else {
-        let mut interpreter = Interpreter::default();
-        loop {
-            let mut input = String::new();
-            print!("> ");
-            let Ok(_) = io::stdout().flush().map_err(|err| {
-                eprintln!("{err}");
-            }) else {
-                break;
-            };
-            let Ok(_) = io::stdin().read_line(&mut input).map_err(|err| {
-                eprintln!("{err}");
-            }) else {
-                break;
-            };
-            let input = input.trim();
-            let ast = parse::<Chunk>(input)
-                .or_else(|_| {
-                    parse::<Expression>(input).map(|expr| {
-                        let pos = expr.pos.clone();
-                        Located::new(
-                            Chunk {
-                                stats: vec![Located::new(Statement::Return(Some(expr)), pos)],
-                            },
-                            Position::default(),
-                        )
-                    })
-                })
-                .map_err(|Located { value: err, pos }| {
-                    eprintln!(
-                        "ERROR <stdin>:{}:{}: {err}",
-                        pos.ln.start + 1,
-                        pos.col.start + 1
-                    );
+        if Path::new(path).is_dir() {
+            args.input = Some(project_entry(path));
+        }
+    }
+    let (path, source) = match &args.input {
+        Some(path) => {
+            let source = fs::read(path)
+                .map_err(|err| {
+                    eprintln!("ERROR {path}: {err}");
+                    exit(1)
                 })
                 .unwrap();
-            let mut compiler = Compiler::default();
-            let closure = ast.compile(&mut compiler);
-            let Ok(_) = interpreter
-                .call(
-                    &Function {
-                        closure: Rc::new(closure),
-                    },
-                    vec![],
-                    None,
-                )
-                .map_err(|RunTimeError { err, ln }| {
-                    eprintln!("ERROR <stdin>:{}:{}: {err}", ln + 1, 0);
-                })
-            else {
-                continue;
-            };
-            let Ok(value) = interpreter.run().map_err(|RunTimeError { err, ln }| {
-                eprintln!("ERROR <stdin>:{}:{}: {err}", ln + 1, 0);
-            }) else {
-                continue;
-            };
-            if let Some(value) = value {
-                println!("{value:?}")
+            (path.clone(), source)
+        }
+        // a program piped in runs as a whole instead of line by line in the REPL
+        None if !io::stdin().is_terminal() => {
+            let mut source = vec![];
+            if let Err(err) = io::stdin().read_to_end(&mut source) {
+                eprintln!("ERROR {STDIN_PATH}: {err}");
+                exit(1)
+            }
+            (STDIN_PATH.to_string(), source)
+        }
+        None => {
+            repl::run(&args);
+            return;
+        }
+    };
+    if args.output == OutputFormat::Json {
+        let (report, ok) = run_json(&source, &args);
+        println!("{report}");
+        if !ok {
+            exit(1)
+        }
+        return;
+    }
+    let value = run_args(&source, vec![], &args)
+        .map_err(|(err_path, Located { value: err, pos })| {
+            match err_path {
+                Some(err_path) => report_error(&err_path, None, &*err, &pos),
+                None => report_error(&path, std::str::from_utf8(&source).ok(), &*err, &pos),
             }
+            exit(1)
+        })
+        .unwrap();
+    match value {
+        None | Some(Value::Null) => {}
+        Some(Value::Int(code)) if !args.print_result => exit(code as i32),
+        Some(value) => println!("{value}"),
+    }
+}
+
+/// fetches the dependencies of the project the current directory belongs to
+fn fetch_dependencies() {
+    let found = Manifest::find(".")
+        .map_err(|err| {
+            eprintln!("ERROR {}: {err}", pkg::MANIFEST);
+            exit(1)
+        })
+        .unwrap();
+    let Some((root, manifest)) = found else {
+        eprintln!(
+            "ERROR no {} in this directory or its parents",
+            pkg::MANIFEST
+        );
+        exit(1)
+    };
+    let fetched = manifest
+        .fetch(&root)
+        .map_err(|err| {
+            eprintln!("ERROR {}: {err}", manifest.name);
+            exit(1)
+        })
+        .unwrap();
+    for name in fetched {
+        println!("fetched {name}");
+    }
+}
+/// prints an error at `pos` of the file at `path`, followed by the line it's on with
+/// carets under its columns if it's a syntax error and `text` is the file's source
+fn report_error(path: &str, text: Option<&str>, err: &(dyn Error + 'static), pos: &Position) {
+    eprintln!(
+        "ERROR {path}:{}:{}: {err}",
+        pos.ln.start + 1,
+        pos.col.start + 1
+    );
+    if let Some(underline) = text
+        .filter(|_| err.is::<LexError>() || err.is::<ParseError>())
+        .and_then(|text| pos.underline(text))
+    {
+        eprintln!("{underline}");
+    }
+}
+/// whether `err` only means the REPL's input ended before the statement did, like after
+/// the head of a block
+fn is_incomplete(err: &(dyn Error + 'static)) -> bool {
+    matches!(
+        err.downcast_ref(),
+        Some(ParseError::UnexpectedEOF | ParseError::ExpectedIndentedBlock)
+    )
+}
+/// compiles the input to a bytecode file at `output`, or next to it with the `.hydc`
+/// extension
+fn compile_file(args: &HydraArgs, output: Option<String>) {
+    let path = args.input.as_deref().unwrap();
+    let text = fs::read_to_string(path)
+        .map_err(|err| {
+            eprintln!("ERROR {path}: {err}");
+            exit(1)
+        })
+        .unwrap();
+    let closure = compile_args::<Chunk>(&text, args)
+        .map_err(|Located { value: err, pos }| {
+            report_error(path, Some(&text), &*err, &pos);
+            exit(1)
+        })
+        .unwrap();
+    let output = output.unwrap_or_else(|| {
+        Path::new(path)
+            .with_extension(format::EXTENSION)
+            .display()
+            .to_string()
+    });
+    fs::File::create(&output)
+        .and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            format::write_program(&closure, &mut writer)?;
+            writer.flush()
+        })
+        .map_err(|err| {
+            eprintln!("ERROR {output}: {err}");
+            exit(1)
+        })
+        .unwrap();
+}
+fn print_bc_info(path: &str) {
+    let header = fs::File::open(path)
+        .map_err(FormatError::from)
+        .and_then(|mut file| Header::read(&mut file))
+        .map_err(|err| {
+            eprintln!("ERROR {path}: {err}");
+            exit(1)
+        })
+        .unwrap();
+    let features = header.features.names();
+    println!("format:   {}", header.version);
+    println!(
+        "features: {}",
+        if features.is_empty() {
+            "none".into()
+        } else {
+            features.join(", ")
+        }
+    );
+    match header.check() {
+        Ok(()) => println!("runnable: yes"),
+        Err(err) => {
+            println!("runnable: no, {err}");
+            exit(1)
         }
     }
 }
+/// where imports get looked up besides the importing file's directory: the project the
+/// input, or the working directory, belongs to and its dependencies
+fn module_dirs(input: Option<&str>) -> Vec<PathBuf> {
+    let dir = input
+        .and_then(|input| Path::new(input).parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    match Manifest::find(dir) {
+        Ok(Some((root, _))) => pkg::module_dirs(root),
+        _ => vec![],
+    }
+}
+/// the entry point of the project in `dir`
+fn project_entry(dir: &str) -> String {
+    let dir = Path::new(dir);
+    let manifest = Manifest::load(dir.join(pkg::MANIFEST))
+        .map_err(|err| {
+            eprintln!("ERROR {}: {err}", dir.join(pkg::MANIFEST).display());
+            exit(1)
+        })
+        .unwrap();
+    dir.join(manifest.entry).display().to_string()
+}
+
+/// turns a trailing expression statement of a REPL input into a return so its value can
+/// be printed
+pub fn return_last_expression(mut ast: Located<Chunk>) -> Located<Chunk> {
+    let Some(Located { value: stat, pos }) = ast.value.stats.pop() else {
+        return ast;
+    };
+    let stat = match stat {
+        Statement::Expression(expr) => Statement::Return(Some(expr)),
+        Statement::Call { head, args } => {
+            let head_pos = head.pos.clone();
+            Statement::Return(Some(Located::new(
+                Expression::Call {
+                    head: Box::new(Located::new(
+                        Expression::Atom(Atom::Path(head.value)),
+                        head_pos,
+                    )),
+                    args,
+                },
+                pos.clone(),
+            )))
+        }
+        Statement::SelfCall { head, field, args } => {
+            let head_pos = head.pos.clone();
+            Statement::Return(Some(Located::new(
+                Expression::SelfCall {
+                    head: Box::new(Located::new(
+                        Expression::Atom(Atom::Path(head.value)),
+                        head_pos,
+                    )),
+                    field,
+                    args,
+                },
+                pos.clone(),
+            )))
+        }
+        stat => stat,
+    };
+    ast.value.stats.push(Located::new(stat, pos));
+    ast
+}
 
 #[derive(Debug, clap::Parser)]
 pub struct HydraArgs {
+    /// a script, or a project directory whose manifest names the entry point
     input: Option<String>,
+    #[clap(subcommand)]
+    command: Option<HydraCommand>,
 
     #[clap(long, short, action)]
     tokens: bool,
@@ -122,8 +299,60 @@ pub struct HydraArgs {
     ast: bool,
     #[clap(long, short, action)]
     code: bool,
+    /// dump the call stack, registers and globals to stderr when a runtime error escapes
     #[clap(long, short, action)]
     debug: bool,
+    /// log the results of non-deterministic natives to this file
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<String>,
+    /// re-run the script with the natives' results taken from a recorded log
+    #[clap(long)]
+    replay: Option<String>,
+    /// error on arithmetic and comparisons mixing ints and floats
+    #[clap(long, action)]
+    strict: bool,
+    /// let whole floats like `2.0` index vectors, tuples and strings
+    #[clap(long, action)]
+    float_indices: bool,
+    /// let the ffi module open this shared library, or any library with "*"
+    #[cfg(feature = "ffi")]
+    #[clap(long = "allow-ffi", value_name = "LIBRARY")]
+    allow_ffi: Vec<String>,
+    /// load the module exported by this shared object before running
+    #[cfg(feature = "plugins")]
+    #[clap(long = "load-module", value_name = "PATH")]
+    load_module: Vec<String>,
+    /// print the header of a compiled bytecode file and whether this build can run it
+    #[clap(long, action, requires = "input")]
+    print_bc_info: bool,
+    /// print the value a script returns at top level even if it's an int, which otherwise
+    /// becomes the exit code
+    #[clap(long, action)]
+    print_result: bool,
+    /// how the result or error of running a file gets reported
+    #[clap(long, value_enum, default_value_t)]
+    output: OutputFormat,
+}
+#[derive(Debug, Clone, PartialEq, Eq, clap::Subcommand)]
+pub enum HydraCommand {
+    /// fetch the dependencies listed in hydra.toml into hydra_modules
+    Pkg,
+    /// compile a script to a bytecode file that runs without its source
+    Compile {
+        input: String,
+        /// where to write the bytecode, the input with the .hydc extension by default
+        #[clap(long, short)]
+        output: Option<String>,
+    },
+    /// run a script or a compiled bytecode file
+    Run { input: String },
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    /// one JSON document with the value, the error and timings
+    Json,
 }
 
 pub fn lex_args(text: &str, args: &HydraArgs) -> Result<Vec<Line>, Located<Box<dyn Error>>> {
@@ -181,28 +410,208 @@ where
     }
     Ok(code)
 }
+/// compiles the script, or reads the program if `source` is a bytecode file
+pub fn load_args(source: &[u8], args: &HydraArgs) -> Result<Closure, Located<Box<dyn Error>>> {
+    if source.starts_with(&MAGIC) {
+        let closure = format::read_program(&mut &source[..])
+            .map_err(|err| Located::new(err.into(), Position::default()))?;
+        if args.code {
+            println!("CODE:");
+            println!("<main>:\n{closure}")
+        }
+        return Ok(closure);
+    }
+    let text =
+        std::str::from_utf8(source).map_err(|err| Located::new(err.into(), Position::default()))?;
+    compile_args::<Chunk>(text, args)
+}
+pub fn interpreter_args(args: &HydraArgs) -> Result<Interpreter, Located<Box<dyn Error>>> {
+    let mut interpreter = Interpreter {
+        strict_arithmetic: args.strict,
+        float_indices: args.float_indices,
+        module_dirs: module_dirs(args.input.as_deref()),
+        ..Default::default()
+    };
+    std_hydra::import(&mut interpreter);
+    #[cfg(feature = "ffi")]
+    if !args.allow_ffi.is_empty() {
+        use hydra_lang::std_hydra::std_ffi::{self, FfiPolicy};
+        let policy = FfiPolicy {
+            allow_any: args.allow_ffi.iter().any(|library| library == "*"),
+            libraries: args.allow_ffi.clone(),
+        };
+        std_ffi::import(&mut interpreter, policy);
+    }
+    #[cfg(feature = "plugins")]
+    for path in &args.load_module {
+        // the user vouches for the library by naming it on the command line
+        unsafe { interpreter.load_module_library(path) }
+            .map_err(|err| Located::new(format!("{path}: {err}").into(), Position::default()))?;
+    }
+    if args.record.is_some() {
+        interpreter.replay = Replay::Record(vec![]);
+    } else if let Some(path) = &args.replay {
+        interpreter.replay =
+            Replay::load(path).map_err(|err| Located::new(err, Position::default()))?;
+    }
+    Ok(interpreter)
+}
+/// calls the compiled script and runs it to the end
+pub fn call_closure(
+    interpreter: &mut Interpreter,
+    closure: Closure,
+    func_args: &[Value],
+) -> Result<Option<Value>, RunTimeError> {
+    interpreter.call(
+        &Function {
+            closure: Rc::new(closure),
+            upvalues: vec![],
+        },
+        func_args,
+        None,
+    )?;
+    interpreter.run()
+}
+pub fn save_record(
+    interpreter: &Interpreter,
+    args: &HydraArgs,
+) -> Result<(), Located<Box<dyn Error>>> {
+    if let Some(path) = &args.record {
+        interpreter
+            .replay
+            .save(path)
+            .map_err(|err| Located::new(err.into(), Position::default()))?;
+    }
+    Ok(())
+}
+/// an error along with the path of the file it happened in if that isn't the input, like
+/// for errors in imported modules
+pub type FileError = (Option<String>, Located<Box<dyn Error>>);
+/// runs the file to the end
 pub fn run_args(
-    text: &str,
+    source: &[u8],
     func_args: Vec<Value>,
     args: &HydraArgs,
-) -> Result<Option<Value>, Located<Box<dyn Error>>> {
-    let closure = compile_args::<Chunk>(text, args)?;
-    let mut interpreter = Interpreter::default();
-    std_hydra::import(&mut interpreter);
-    interpreter
-        .call(
-            &Function {
-                closure: Rc::new(closure),
-            },
-            func_args,
-            None,
-        )
-        .map_err(|err| Located {
+) -> Result<Option<Value>, FileError> {
+    let closure = load_args(source, args).map_err(|err| (None, err))?;
+    let mut interpreter = interpreter_args(args).map_err(|err| (None, err))?;
+    let result = call_closure(&mut interpreter, closure, &func_args).map_err(|err| {
+        if args.debug {
+            let _ = interpreter.dump_state(&mut io::stderr());
+        }
+        let path = interpreter
+            .path()
+            .filter(|path| Some(*path) != args.input.as_ref())
+            .cloned();
+        let err = Located {
             value: err.err.into(),
             pos: Position::new(err.ln..err.ln, 0..0),
-        })?;
-    interpreter.run().map_err(|err| Located {
-        value: err.err.into(),
-        pos: Position::new(err.ln..err.ln, 0..0),
-    })
+        };
+        (path, err)
+    });
+    save_record(&interpreter, args).map_err(|err| (None, err))?;
+    result
+}
+/// runs the file like `run_args` and reports the value or the error with its kind,
+/// position and traceback as a JSON document, along with whether the run succeeded. what
+/// the script prints goes into the document's `output` instead of stdout, so stdout only
+/// holds the document
+pub fn run_json(source: &[u8], args: &HydraArgs) -> (String, bool) {
+    let path = args.input.clone();
+    let start = Instant::now();
+    let compiled = load_args(source, args);
+    let compile_ms = start.elapsed().as_secs_f64() * 1000.0;
+    let mut run_ms = 0.0;
+    let output = Rc::new(RefCell::new(String::new()));
+    let result = match compiled {
+        Err(Located { value: err, pos }) => {
+            // editors index lines by bytes, so the column is given that way too
+            let byte_col = std::str::from_utf8(source)
+                .ok()
+                .and_then(|text| text.lines().nth(pos.ln.start))
+                .map(|line| byte_col(line, pos.col.start) + 1);
+            Err(json!({
+                "kind": "SyntaxError",
+                "message": err.to_string(),
+                "path": path,
+                "ln": pos.ln.start + 1,
+                "col": pos.col.start + 1,
+                "byte_col": byte_col,
+                "trace": [],
+            }))
+        }
+        Ok(closure) => {
+            let start = Instant::now();
+            let result = interpreter_args(args).and_then(|mut interpreter| {
+                let output = Rc::clone(&output);
+                interpreter.set_output(move |text| output.borrow_mut().push_str(text));
+                let result = call_closure(&mut interpreter, closure, &[]);
+                save_record(&interpreter, args)?;
+                Ok((result, interpreter.traceback()))
+            });
+            run_ms = start.elapsed().as_secs_f64() * 1000.0;
+            match result {
+                Ok((Ok(value), _)) => Ok(value),
+                Ok((Err(RunTimeError { err, ln }), traceback)) => {
+                    // a native that failed is the innermost frame, at the line calling it
+                    let native = match &err {
+                        RunTimeErrorKind::Native { name, .. } => Some(json!({
+                            "name": name,
+                            "path": null,
+                            "ln": ln + 1,
+                        })),
+                        _ => None,
+                    };
+                    let trace = native
+                        .into_iter()
+                        .chain(traceback.iter().map(|(name, path, ln)| {
+                            json!({
+                                "name": name,
+                                "path": path,
+                                "ln": ln + 1,
+                            })
+                        }))
+                        .collect::<Vec<Json>>();
+                    // errors in modules happened in the module's file, not the input
+                    let path = traceback
+                        .first()
+                        .and_then(|(_, path, _)| path.clone())
+                        .or(path);
+                    Err(json!({
+                        "kind": err.name(),
+                        "message": err.to_string(),
+                        "path": path,
+                        "ln": ln + 1,
+                        "col": null,
+                        "byte_col": null,
+                        "trace": trace,
+                    }))
+                }
+                Err(Located { value: err, pos: _ }) => Err(json!({
+                    "kind": "SetupError",
+                    "message": err.to_string(),
+                    "path": path,
+                    "ln": null,
+                    "col": null,
+                    "byte_col": null,
+                    "trace": [],
+                })),
+            }
+        }
+    };
+    let ok = result.is_ok();
+    let (value, error) = match result {
+        Ok(value) => (value.as_ref().map_or(Json::Null, json::value), Json::Null),
+        Err(error) => (Json::Null, error),
+    };
+    let report = json!({
+        "value": value,
+        "error": error,
+        "output": output.take(),
+        "time": {
+            "compile_ms": compile_ms,
+            "run_ms": run_ms,
+        },
+    });
+    (report.to_string(), ok)
 }
@@ -0,0 +1,131 @@
+use crate::{
+    is_incomplete, module_dirs, report_error, return_last_expression, HydraArgs, STDIN_PATH,
+};
+use hydra_lang::{
+    parse,
+    run::{
+        compiler::{Compilable, Compiler},
+        interpreter::{Interpreter, RunTimeError},
+        value::{Function, Value},
+    },
+    scan::{ast::Chunk, position::Located},
+    set_global, std_hydra,
+};
+use rustyline::{config::Configurer, error::ReadlineError, DefaultEditor};
+use std::{env, path::PathBuf, rc::Rc};
+
+/// the file in the home directory the REPL keeps its history in
+pub const HISTORY_FILE: &str = ".hydra_history";
+/// how many entries of history are kept
+pub const HISTORY_LIMIT: usize = 1000;
+
+/// reads statements with line editing and history until Ctrl-D, running each on the same
+/// interpreter. Ctrl-C drops the line being typed and the block it belongs to
+pub fn run(args: &HydraArgs) {
+    let mut interpreter = Interpreter {
+        strict_arithmetic: args.strict,
+        float_indices: args.float_indices,
+        warn_undefined_globals: true,
+        module_dirs: module_dirs(None),
+        ..Default::default()
+    };
+    std_hydra::import(&mut interpreter);
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("ERROR {err}");
+            return;
+        }
+    };
+    let _ = editor.set_max_history_size(HISTORY_LIMIT);
+    let history = history_path();
+    if let Some(path) = &history {
+        // there's no history before the first session
+        let _ = editor.load_history(path);
+    }
+    // the lines of a statement that opened a block, which runs once an empty line ends it
+    let mut block = String::new();
+    loop {
+        let prompt = if block.is_empty() { "> " } else { "... " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                block.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => {
+                if !block.is_empty() {
+                    eval(&mut interpreter, &block);
+                }
+                break;
+            }
+            Err(err) => {
+                eprintln!("ERROR {err}");
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        if block.is_empty() {
+            let input = line.trim();
+            if let Err(Located { value: err, .. }) = parse::<Chunk>(input) {
+                if is_incomplete(&*err) {
+                    block = input.to_string();
+                    continue;
+                }
+            }
+            eval(&mut interpreter, input);
+        } else if !line.trim().is_empty() {
+            block.push('\n');
+            block.push_str(line.trim_end());
+        } else {
+            eval(&mut interpreter, &std::mem::take(&mut block));
+        }
+    }
+    if let Some(path) = &history {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!("WARNING {}: {err}", path.display());
+        }
+    }
+}
+/// `~/.hydra_history`, if there's a home directory
+pub fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+/// runs `input`, printing its value if it has one and keeping it in `_`
+fn eval(interpreter: &mut Interpreter, input: &str) {
+    let Ok(ast) = parse::<Chunk>(input).map_err(|Located { value: err, pos }| {
+        report_error(STDIN_PATH, Some(input), &*err, &pos);
+    }) else {
+        return;
+    };
+    let ast = return_last_expression(ast);
+    let mut compiler = Compiler::default();
+    let closure = ast.compile(&mut compiler);
+    let value = interpreter
+        .call(
+            &Function {
+                closure: Rc::new(closure),
+                upvalues: vec![],
+            },
+            &[],
+            None,
+        )
+        .and_then(|_| interpreter.run());
+    match value {
+        Ok(Some(value)) if value != Value::Null => {
+            println!("{value:?}");
+            set_global!(interpreter: "_" = value);
+        }
+        Ok(_) => {}
+        Err(RunTimeError { err, ln }) => {
+            eprintln!("ERROR {STDIN_PATH}:{}:{}: {err}", ln + 1, 0);
+            // drop the frames the error unwound out of so the next input starts clean
+            interpreter.call_stack.clear();
+        }
+    }
+}
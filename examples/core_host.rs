@@ -0,0 +1,66 @@
+//! a host that embeds hydra with nothing but the `core` feature: no standard library, no
+//! files, no network and no stdio. the script only gets what the host hands it, and what
+//! it prints ends up wherever the host sends it
+//!
+//! cargo run --example core_host --no-default-features --features core
+use hydra_lang::{
+    compile, declare_module, define_native_fn,
+    run::{
+        interpreter::{Interpreter, InterpreterOptions},
+        trust::TrustLevel,
+        value::{Function, Value},
+    },
+    scan::ast::Chunk,
+    typed,
+};
+use std::{cell::RefCell, error::Error, rc::Rc};
+
+const SCRIPT: &str = "\
+let gain = host.gain(-6.0)
+host.log(\"rate\", host.sample_rate, \"gain\", gain)
+return 0.5 * gain
+";
+
+// prints its arguments separated by spaces through the interpreter's output
+define_native_fn!(_log (interpreter args): => {
+    let mut texts = vec![];
+    for (_, arg) in args {
+        texts.push(interpreter.display(&arg)?);
+    }
+    interpreter.write_output(&format!("{}\n", texts.join(" ")))?;
+    Ok(None)
+});
+// the factor a gain of `db` decibels multiplies a signal by
+define_native_fn!(_gain (_i args): db = typed!(args: Float) => {
+    Ok(Some(Value::Float(10f64.powf(db / 20.0))))
+});
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let closure =
+        compile::<Chunk>(SCRIPT, Some("plugin".into())).map_err(|located| located.value)?;
+    let mut interpreter = Interpreter::new(InterpreterOptions {
+        fuel: Some(100_000),
+        trust_level: TrustLevel::Sandbox,
+        ..Default::default()
+    });
+    let output = Rc::new(RefCell::new(String::new()));
+    let sink = Rc::clone(&output);
+    interpreter.set_output(move |text| sink.borrow_mut().push_str(text));
+    declare_module!(interpreter: "host" {
+        "log" => _log,
+        "gain" => _gain,
+        const "sample_rate" => 48000,
+    });
+    interpreter.call(
+        &Function {
+            closure: Rc::new(closure),
+            upvalues: vec![],
+        },
+        &[],
+        None,
+    )?;
+    let value = interpreter.run()?;
+    print!("{}", output.borrow());
+    println!("returned {}", value.unwrap_or_default());
+    Ok(())
+}
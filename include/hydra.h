@@ -0,0 +1,48 @@
+/* C interface of the hydra-lang library, built with `cargo build --features capi`.
+ * values cross the boundary as JSON strings. */
+#ifndef HYDRA_H
+#define HYDRA_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* an interpreter with the standard library loaded */
+typedef struct HydraState HydraState;
+/* handed to callbacks so they can raise an error with `hydra_raise` */
+typedef struct HydraCall HydraCall;
+
+/* called with the arguments as a JSON array, returns the JSON of the result or NULL for
+ * null. the returned string stays owned by the host and only needs to live until the
+ * callback returns */
+typedef const char *(*HydraCallback)(HydraCall *call, void *user_data, const char *args);
+
+HydraState *hydra_new(void);
+void hydra_free(HydraState *state);
+
+/* runs `source` and returns the JSON of its value, to be freed with `hydra_string_free`,
+ * or NULL if it failed, leaving the message in `hydra_error` */
+char *hydra_eval(HydraState *state, const char *source);
+/* the message of the last failed call on `state`, or NULL. the string is owned by the
+ * state and stays valid until the next call */
+const char *hydra_error(const HydraState *state);
+
+/* the JSON of the global `name`, to be freed with `hydra_string_free`, or NULL if it's
+ * not set */
+char *hydra_get_global(const HydraState *state, const char *name);
+/* sets the global `name` to the value `json` describes, returning 0 on success */
+int hydra_set_global(HydraState *state, const char *name, const char *json);
+
+/* sets the global `name` to a function calling `callback` with `user_data`, returning 0
+ * on success */
+int hydra_register(HydraState *state, const char *name, HydraCallback callback, void *user_data);
+/* makes the callback `call` was handed to raise `message` once it returns */
+void hydra_raise(HydraCall *call, const char *message);
+
+void hydra_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,257 @@
+//! `extern "C"` functions for embedding the interpreter in hosts that aren't written in
+//! Rust, declared in `include/hydra.h`. values cross the boundary as JSON strings
+use crate::{
+    compile,
+    run::{
+        interpreter::Interpreter,
+        value::{FnKind, Function, Map, NativeFn, Value},
+    },
+    scan::ast::Chunk,
+    set_global, std_hydra,
+};
+use serde_json::{Map as JsonMap, Number, Value as Json};
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    ptr,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+/// an interpreter with the standard library and the last error that happened in it
+pub struct HydraState {
+    interpreter: Interpreter,
+    error: Option<CString>,
+}
+/// handed to callbacks so they can raise an error with `hydra_raise`
+pub struct HydraCall {
+    error: Option<String>,
+}
+/// called with the arguments as a JSON array, returns the JSON of the result or null for
+/// `null`. the returned string stays owned by the host and only needs to live until the
+/// callback returns
+pub type HydraCallback = unsafe extern "C" fn(
+    call: *mut HydraCall,
+    user_data: *mut c_void,
+    args: *const c_char,
+) -> *const c_char;
+
+#[no_mangle]
+pub extern "C" fn hydra_new() -> *mut HydraState {
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    Box::into_raw(Box::new(HydraState {
+        interpreter,
+        error: None,
+    }))
+}
+/// # Safety
+/// `state` has to come from `hydra_new` and can't be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn hydra_free(state: *mut HydraState) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
+/// runs `source` and returns the JSON of its value, to be freed with `hydra_string_free`,
+/// or null if it failed, leaving the message in `hydra_error`
+/// # Safety
+/// `state` has to come from `hydra_new` and `source` has to be a nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn hydra_eval(state: *mut HydraState, source: *const c_char) -> *mut c_char {
+    let state = &mut *state;
+    state.error = None;
+    let result = read_str(source).and_then(|text| {
+        let closure = compile::<Chunk>(&text, None).map_err(|err| {
+            format!(
+                "{}:{}: {}",
+                err.pos.ln.start + 1,
+                err.pos.col.start + 1,
+                err.value
+            )
+        })?;
+        state
+            .interpreter
+            .call(
+                &Function {
+                    closure: Rc::new(closure),
+                    upvalues: vec![],
+                },
+                &[],
+                None,
+            )
+            .and_then(|_| state.interpreter.run())
+            .map_err(|err| format!("{}: {}", err.ln + 1, err.err))
+    });
+    match result {
+        Ok(value) => json_string(&to_json(&value.unwrap_or_default())),
+        Err(err) => {
+            // drop the frames the error unwound out of so the next eval starts clean
+            state.interpreter.call_stack.clear();
+            state.error = CString::new(err.replace('\0', "")).ok();
+            ptr::null_mut()
+        }
+    }
+}
+/// the message of the last failed call on `state`, or null. the string is owned by the
+/// state and stays valid until the next call
+/// # Safety
+/// `state` has to come from `hydra_new`
+#[no_mangle]
+pub unsafe extern "C" fn hydra_error(state: *const HydraState) -> *const c_char {
+    (*state)
+        .error
+        .as_ref()
+        .map_or(ptr::null(), |error| error.as_ptr())
+}
+/// the JSON of the global `name`, to be freed with `hydra_string_free`, or null if it's
+/// not set
+/// # Safety
+/// `state` has to come from `hydra_new` and `name` has to be a nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn hydra_get_global(
+    state: *const HydraState,
+    name: *const c_char,
+) -> *mut c_char {
+    let Ok(name) = read_str(name) else {
+        return ptr::null_mut();
+    };
+    match (*state).interpreter.globals.get(&name) {
+        Some(value) => json_string(&to_json(&value.lock().unwrap())),
+        None => ptr::null_mut(),
+    }
+}
+/// sets the global `name` to the value `json` describes, returning 0 on success
+/// # Safety
+/// `state` has to come from `hydra_new` and the strings have to be nul-terminated
+#[no_mangle]
+pub unsafe extern "C" fn hydra_set_global(
+    state: *mut HydraState,
+    name: *const c_char,
+    json: *const c_char,
+) -> c_int {
+    let state = &mut *state;
+    let value = read_str(json).and_then(|json| {
+        serde_json::from_str::<Json>(&json).map_err(|err| format!("invalid JSON: {err}"))
+    });
+    match (read_str(name), value) {
+        (Ok(name), Ok(value)) => {
+            let interpreter = &mut state.interpreter;
+            set_global!(interpreter: name = from_json(value));
+            0
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            state.error = CString::new(err).ok();
+            1
+        }
+    }
+}
+/// sets the global `name` to a function calling `callback` with `user_data`
+/// # Safety
+/// `state` has to come from `hydra_new`, `name` has to be a nul-terminated string and
+/// `user_data` has to stay valid for as long as scripts can call the function
+#[no_mangle]
+pub unsafe extern "C" fn hydra_register(
+    state: *mut HydraState,
+    name: *const c_char,
+    callback: HydraCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let state = &mut *state;
+    let name = match read_str(name) {
+        Ok(name) => name,
+        Err(err) => {
+            state.error = CString::new(err).ok();
+            return 1;
+        }
+    };
+    let func: Rc<NativeFn> = Rc::new(move |_, args| {
+        let args = CString::new(Json::Array(args.iter().map(to_json).collect()).to_string())?;
+        let mut call = HydraCall { error: None };
+        let result = callback(&mut call, user_data, args.as_ptr());
+        if let Some(err) = call.error {
+            return Err(err.into());
+        }
+        if result.is_null() {
+            return Ok(None);
+        }
+        let result = CStr::from_ptr(result).to_str()?;
+        Ok(Some(from_json(serde_json::from_str(result)?)))
+    });
+    let interpreter = &mut state.interpreter;
+    set_global!(interpreter: name = Value::Fn(FnKind::Native(func)));
+    0
+}
+/// makes the callback `call` was handed to raise `message` once it returns
+/// # Safety
+/// `call` has to be the one passed to the running callback and `message` has to be a
+/// nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn hydra_raise(call: *mut HydraCall, message: *const c_char) {
+    (*call).error = Some(read_str(message).unwrap_or_else(|err| err));
+}
+/// # Safety
+/// `string` has to come from this library, or be null
+#[no_mangle]
+pub unsafe extern "C" fn hydra_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+unsafe fn read_str(string: *const c_char) -> Result<String, String> {
+    if string.is_null() {
+        return Err("expected a string, got null".into());
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map(str::to_string)
+        .map_err(|err| err.to_string())
+}
+fn json_string(json: &Json) -> *mut c_char {
+    // serialized JSON escapes control characters, so it never contains a nul
+    CString::new(json.to_string()).map_or(ptr::null_mut(), CString::into_raw)
+}
+/// the value as JSON, with functions and native objects as their debug strings and
+/// non-finite floats as null
+pub fn to_json(value: &Value) -> Json {
+    match value {
+        Value::Null => Json::Null,
+        Value::Int(v) => Json::from(*v),
+        Value::Float(v) => Number::from_f64(*v).map_or(Json::Null, Json::Number),
+        Value::Bool(v) => Json::Bool(*v),
+        Value::Char(v) => Json::String(v.to_string()),
+        Value::String(v) => Json::String(v.clone()),
+        Value::Vector(values) => Json::Array(values.lock().unwrap().iter().map(to_json).collect()),
+        Value::Tuple(values) => Json::Array(values.lock().unwrap().iter().map(to_json).collect()),
+        Value::Map(entries) => Json::Object(
+            entries
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, v)| (key.clone(), to_json(v)))
+                .collect::<JsonMap<String, Json>>(),
+        ),
+        Value::Fn(_) | Value::NativeObject(_) => Json::String(format!("{value:?}")),
+    }
+}
+/// arrays become vectors and objects maps
+pub fn from_json(json: Json) -> Value {
+    match json {
+        Json::Null => Value::Null,
+        Json::Bool(v) => Value::Bool(v),
+        Json::Number(v) => match v.as_i64() {
+            Some(v) => Value::Int(v),
+            None => Value::Float(v.as_f64().unwrap_or_default()),
+        },
+        Json::String(v) => Value::String(v),
+        Json::Array(values) => Value::Vector(Arc::new(Mutex::new(
+            values.into_iter().map(from_json).collect(),
+        ))),
+        Json::Object(entries) => Value::Map(Arc::new(Mutex::new(
+            entries
+                .into_iter()
+                .map(|(key, v)| (key, from_json(v)))
+                .collect::<Map>(),
+        ))),
+    }
+}
@@ -1,8 +1,7 @@
-#![feature(integer_sign_cast)]
 use run::{
     compiler::{Compilable, Compiler, Frame, Scope},
     interpreter::Interpreter,
-    value::{Function, Value},
+    value::{FromValue, Function, Value},
 };
 use scan::{
     ast::Chunk,
@@ -11,13 +10,31 @@ use scan::{
     position::{Located, Position},
 };
 use std::{error::Error, rc::Rc};
+#[cfg(feature = "std_hydra")]
+use {
+    run::interpreter::{RunTimeError, RunTimeErrorKind},
+    std::{
+        cell::RefCell,
+        time::{Duration, Instant},
+    },
+};
+
+#[cfg(not(feature = "core"))]
+compile_error!("hydra-lang needs at least its `core` feature");
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std_hydra"))]
 mod tests;
 
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "std_hydra")]
+pub mod pkg;
 pub mod run;
 pub mod scan;
+#[cfg(feature = "std_hydra")]
 pub mod std_hydra;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub fn lex(text: &str) -> Result<Vec<Line>, Located<Box<dyn Error>>> {
     Lexer::from(text)
@@ -53,6 +70,8 @@ where
     Ok(ast.compile(&mut compiler))
 }
 
+/// compiles and runs `text` without the standard library, giving `None` if it finished
+/// without returning a value and `Some` of what it returned otherwise, `return null` included
 pub fn run(
     text: &str,
     args: Vec<Value>,
@@ -64,8 +83,9 @@ pub fn run(
         .call(
             &Function {
                 closure: Rc::new(closure),
+                upvalues: vec![],
             },
-            args,
+            &args,
             None,
         )
         .map_err(|err| Located {
@@ -78,19 +98,127 @@ pub fn run(
     })
 }
 
+/// like `run`, but converts the script's value with `FromValue`. a script that doesn't
+/// return anything gives `null`, so it only converts to types like `Option<T>` or `Value`
+pub fn run_as<T: FromValue>(
+    text: &str,
+    args: Vec<Value>,
+    path: Option<String>,
+) -> Result<T, Located<Box<dyn Error>>> {
+    let value = run(text, args, path)?.unwrap_or_default();
+    let typ = value.typ();
+    T::from_value(value).ok_or_else(|| Located {
+        value: format!(
+            "can't convert the script's {typ} result to {}",
+            std::any::type_name::<T>()
+        )
+        .into(),
+        pos: Position::default(),
+    })
+}
+
+/// what `run_limited` did with a script
+#[cfg(feature = "std_hydra")]
+#[derive(Debug)]
+pub struct RunReport {
+    /// the script's value, or the error that stopped it. running out of instructions is a
+    /// `RunTimeErrorKind::BudgetExceeded` error
+    pub result: Result<Option<Value>, Located<Box<dyn Error>>>,
+    /// everything the script printed, cut off at the output limit
+    pub output: String,
+    pub output_truncated: bool,
+    pub out_of_fuel: bool,
+    pub instructions: u64,
+    pub compile_time: Duration,
+    pub run_time: Duration,
+}
+/// compiles and runs `text` with the standard library, stopping it after
+/// `max_instructions` and capturing at most `max_output_bytes` of its output instead of
+/// printing it, for running untrusted scripts like in playgrounds or graders
+#[cfg(feature = "std_hydra")]
+pub fn run_limited(text: &str, max_instructions: u64, max_output_bytes: usize) -> RunReport {
+    let start = Instant::now();
+    let compiled = compile::<Chunk>(text, None);
+    let compile_time = start.elapsed();
+    let mut report = RunReport {
+        result: Ok(None),
+        output: String::new(),
+        output_truncated: false,
+        out_of_fuel: false,
+        instructions: 0,
+        compile_time,
+        run_time: Duration::ZERO,
+    };
+    let closure = match compiled {
+        Ok(closure) => closure,
+        Err(err) => {
+            report.result = Err(err);
+            return report;
+        }
+    };
+    let output = Rc::new(RefCell::new((String::new(), false)));
+    let mut interpreter = Interpreter {
+        fuel: Some(max_instructions),
+        ..Default::default()
+    };
+    std_hydra::import(&mut interpreter);
+    let sink = Rc::clone(&output);
+    interpreter.set_output(move |text| {
+        let (output, truncated) = &mut *sink.borrow_mut();
+        let mut end = text.len().min(max_output_bytes - output.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        output.push_str(&text[..end]);
+        *truncated |= end < text.len();
+    });
+    let start = Instant::now();
+    let result = interpreter
+        .call(
+            &Function {
+                closure: Rc::new(closure),
+                upvalues: vec![],
+            },
+            &[],
+            None,
+        )
+        .and_then(|_| interpreter.run());
+    report.run_time = start.elapsed();
+    report.instructions = max_instructions - interpreter.fuel.unwrap_or_default();
+    report.out_of_fuel = matches!(
+        result,
+        Err(RunTimeError {
+            err: RunTimeErrorKind::BudgetExceeded,
+            ..
+        })
+    );
+    report.result = result.map_err(|err| Located {
+        value: err.err.into(),
+        pos: Position::new(err.ln..err.ln, 0..0),
+    });
+    (report.output, report.output_truncated) = output.take();
+    report
+}
+
 #[macro_export]
 macro_rules! set_global {
     ($interpreter:ident: $key:literal = $value:expr) => {{
         use std::sync::{Arc, Mutex};
+        let key: String = $key.into();
+        let value = $value;
+        $interpreter.name_natives(&key, &value);
         $interpreter
             .globals
-            .insert($key.into(), Arc::new(Mutex::new($value)))
+            .insert(key, Arc::new(Mutex::new(value)))
     }};
     ($interpreter:ident: $key:ident = $value:expr) => {{
         use std::sync::{Arc, Mutex};
+        let key: String = $key.into();
+        let value = $value;
+        $interpreter.name_natives(&key, &value);
         $interpreter
             .globals
-            .insert($key.into(), Arc::new(Mutex::new($value)))
+            .insert(key, Arc::new(Mutex::new(value)))
     }};
 }
 #[macro_export]
@@ -114,7 +242,7 @@ macro_rules! typed {
             };
             {
                 let object = arc.lock().unwrap();
-                if object.typ() != $typ {
+                if !$crate::run::types::same(object.typ(), $typ) {
                     return Err(format!(
                         "expected {} for argument #{}, got {}",
                         $typ,
@@ -163,7 +291,7 @@ macro_rules! typed {
         };
         {
             let object = arc.lock().unwrap();
-            if object.typ() != $typ {
+            if !$crate::run::types::same(object.typ(), $typ) {
                 return Err(format!(
                     "expected {} for argument #{}, got {}",
                     $typ,
@@ -235,7 +363,7 @@ macro_rules! typed {
         };
         {
             let object = arc.lock().unwrap();
-            if object.typ() != $typ {
+            if !$crate::run::types::same(object.typ(), $typ) {
                 return Err(format!(
                     "expected {} for argument #{}, got {}",
                     $typ,
@@ -251,15 +379,15 @@ macro_rules! typed {
 #[macro_export]
 macro_rules! define_native_fn {
     ($fn_name:ident ($interpreter:ident $args:ident!) $body:block) => {
-        pub fn $fn_name($interpreter: &mut Interpreter, $args: Vec<Value>) -> Result<Option<Value>, Box<dyn Error>> {
+        pub fn $fn_name($interpreter: &mut Interpreter, $args: &[Value]) -> Result<Option<Value>, Box<dyn Error>> {
             $body
         }
     };
     ($fn_name:ident ($interpreter:ident $args:ident): $($name:pat = $macro:expr),* $(,) * => $body:block) => {
-        pub fn $fn_name($interpreter: &mut Interpreter, $args: Vec<Value>) -> Result<Option<Value>, Box<dyn Error>> {
+        pub fn $fn_name($interpreter: &mut Interpreter, $args: &[Value]) -> Result<Option<Value>, Box<dyn Error>> {
             #[allow(unused_mut)]
             #[allow(unused_variables)]
-            let mut $args = $args.into_iter().enumerate();
+            let mut $args = $args.iter().cloned().enumerate();
             $(
                 let $name = $macro;
             ) *
@@ -275,6 +403,45 @@ macro_rules! native_fn {
         Value::Fn(FnKind::Native(Rc::new($name)))
     }};
 }
+/// builds a module map out of native functions and constants, and sets it as a global
+/// when given an interpreter and a name, a literal or a constant holding one
+/// ```ignore
+/// declare_module!(interpreter: "mymod" {
+///     "add" => _add,
+///     "sub" => _sub,
+///     const "version" => "1.0",
+/// });
+/// let module: Value = declare_module!({ "add" => _add });
+/// ```
+#[macro_export]
+macro_rules! declare_module {
+    (@insert $map:ident) => {};
+    (@insert $map:ident const $key:literal => $value:expr $(, $($rest:tt)*)?) => {
+        $map.insert($key.to_string(), $crate::run::value::Value::from($value));
+        $crate::declare_module!(@insert $map $($($rest)*)?);
+    };
+    (@insert $map:ident $key:literal => $func:path $(, $($rest:tt)*)?) => {
+        $map.insert(
+            $key.to_string(),
+            $crate::run::value::Value::Fn($crate::run::value::FnKind::Native(
+                std::rc::Rc::new($func),
+            )),
+        );
+        $crate::declare_module!(@insert $map $($($rest)*)?);
+    };
+    ({ $($entries:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut map = $crate::run::value::Map::new();
+        $crate::declare_module!(@insert map $($entries)*);
+        $crate::run::value::Value::Map(std::sync::Arc::new(std::sync::Mutex::new(map)))
+    }};
+    ($interpreter:ident: $name:literal { $($entries:tt)* }) => {
+        $crate::set_global!($interpreter: $name = $crate::declare_module!({ $($entries)* }))
+    };
+    ($interpreter:ident: $name:ident { $($entries:tt)* }) => {
+        $crate::set_global!($interpreter: $name = $crate::declare_module!({ $($entries)* }))
+    };
+}
 #[macro_export]
 macro_rules! make_vec {
     ($value:expr) => {{
@@ -297,13 +464,70 @@ macro_rules! make_tuple {
         Value::Tuple(Arc::new(Mutex::new(Box::new([$($value.into()),*]))))
     }};
 }
+/// implements `FromValue` and `From<T> for Value` for a struct, mapping it to a map with a
+/// key per field. `field as "key"` renames the key and fields in `skip` are left out of the
+/// map and filled in with their default when converting back
+/// ```ignore
+/// value_struct! {
+///     Config {
+///         width,
+///         title as "name",
+///     }
+///     skip { cache }
+/// }
+/// ```
+#[macro_export]
+macro_rules! value_struct {
+    (@key $field:ident) => {
+        stringify!($field)
+    };
+    (@key $field:ident $key:literal) => {
+        $key
+    };
+    (
+        $typ:ident {
+            $($field:ident $(as $key:literal)?),* $(,)?
+        }
+        $(skip { $($skip:ident),* $(,)? })?
+    ) => {
+        impl $crate::run::value::FromValue for $typ {
+            fn from_value(value: $crate::run::value::Value) -> Option<Self> {
+                let $crate::run::value::Value::Map(map) = value else {
+                    return None;
+                };
+                let map = map.lock().unwrap();
+                Some(Self {
+                    $(
+                        $field: $crate::run::value::FromValue::from_value(
+                            map.get($crate::value_struct!(@key $field $($key)?))
+                                .cloned()
+                                .unwrap_or_default(),
+                        )?,
+                    )*
+                    $($($skip: Default::default(),)*)?
+                })
+            }
+        }
+        impl From<$typ> for $crate::run::value::Value {
+            fn from(value: $typ) -> Self {
+                let mut map = $crate::run::value::Map::new();
+                $(
+                    map.insert(
+                        $crate::value_struct!(@key $field $($key)?).to_string(),
+                        $crate::run::value::IntoValue::into_value(value.$field),
+                    );
+                )*
+                $crate::run::value::Value::Map(std::sync::Arc::new(std::sync::Mutex::new(map)))
+            }
+        }
+    };
+}
 #[macro_export]
 macro_rules! make_map {
     ($($key:literal = $value:expr),* $(,) *) => {{
         use std::sync::{Arc, Mutex};
-        use std::collections::HashMap;
         #[allow(unused_mut)]
-        let mut map = HashMap::new();
+        let mut map = $crate::run::value::Map::new();
         $(
             map.insert($key.into(), $value.into());
         ) *
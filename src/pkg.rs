@@ -0,0 +1,286 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use toml::{Table, Value as TomlValue};
+
+/// file name of a project manifest
+pub const MANIFEST: &str = "hydra.toml";
+/// directory next to the manifest that dependencies get fetched into
+pub const MODULES_DIR: &str = "hydra_modules";
+/// entry point of a project whose manifest doesn't name one
+pub const DEFAULT_ENTRY: &str = "main.hydra";
+
+/// a parsed `hydra.toml`
+/// ```toml
+/// [package]
+/// name = "app"
+/// entry = "src/main.hydra"
+///
+/// [dependencies]
+/// utils = { path = "../utils" }
+/// json = { git = "https://example.com/json.git", rev = "v1.0" }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub entry: PathBuf,
+    pub dependencies: Vec<Dependency>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub source: DependencySource,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum DependencySource {
+    /// a directory, relative to the manifest
+    Path(PathBuf),
+    /// a git repository, checked out at `rev` if there is one
+    Git { url: String, rev: Option<String> },
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManifestError {
+    Syntax(String),
+    Missing(&'static str),
+    InvalidField { key: String, expected: &'static str },
+    InvalidDependency(String),
+    InvalidName(String),
+}
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestError::Syntax(err) => write!(f, "invalid {MANIFEST}: {err}"),
+            ManifestError::Missing(key) => write!(f, "{MANIFEST} is missing {key}"),
+            ManifestError::InvalidField { key, expected } => {
+                write!(f, "expected {expected} for {key} in {MANIFEST}")
+            }
+            ManifestError::InvalidDependency(name) => write!(
+                f,
+                "dependency {name:?} needs either a path or a git url in {MANIFEST}"
+            ),
+            ManifestError::InvalidName(name) => {
+                write!(f, "{name:?} isn't a valid package name")
+            }
+        }
+    }
+}
+impl Error for ManifestError {}
+
+impl Manifest {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::parse(&fs::read_to_string(path)?)?)
+    }
+    /// looks for a manifest in `dir` and its parents, returning the project's root
+    /// directory along with the manifest
+    pub fn find(dir: impl AsRef<Path>) -> Result<Option<(PathBuf, Self)>, Box<dyn Error>> {
+        for dir in dir.as_ref().ancestors() {
+            let path = dir.join(MANIFEST);
+            if path.is_file() {
+                return Ok(Some((dir.to_path_buf(), Self::load(path)?)));
+            }
+        }
+        Ok(None)
+    }
+    pub fn parse(text: &str) -> Result<Self, ManifestError> {
+        let table = text
+            .parse::<Table>()
+            .map_err(|err| ManifestError::Syntax(err.message().to_string()))?;
+        let package = match table.get("package") {
+            Some(TomlValue::Table(package)) => package,
+            Some(_) => return Err(invalid("package", "a table")),
+            None => return Err(ManifestError::Missing("package")),
+        };
+        let name = match package.get("name") {
+            Some(TomlValue::String(name)) => name.clone(),
+            Some(_) => return Err(invalid("package.name", "a string")),
+            None => return Err(ManifestError::Missing("package.name")),
+        };
+        check_name(&name)?;
+        let entry = match package.get("entry") {
+            Some(TomlValue::String(entry)) => PathBuf::from(entry),
+            Some(_) => return Err(invalid("package.entry", "a string")),
+            None => PathBuf::from(DEFAULT_ENTRY),
+        };
+        let mut dependencies = vec![];
+        match table.get("dependencies") {
+            Some(TomlValue::Table(deps)) => {
+                for (name, dep) in deps {
+                    check_name(name)?;
+                    dependencies.push(Dependency {
+                        name: name.clone(),
+                        source: DependencySource::parse(name, dep)?,
+                    });
+                }
+            }
+            Some(_) => return Err(invalid("dependencies", "a table")),
+            None => {}
+        }
+        Ok(Self {
+            name,
+            entry,
+            dependencies,
+        })
+    }
+    /// fetches the dependencies of the project at `root` into its modules directory,
+    /// along with the dependencies they declare in their own manifests
+    pub fn fetch(&self, root: impl AsRef<Path>) -> Result<Vec<String>, Box<dyn Error>> {
+        let root = root.as_ref();
+        let modules = root.join(MODULES_DIR);
+        fs::create_dir_all(&modules)?;
+        let mut fetched = HashSet::new();
+        let mut pending = self
+            .dependencies
+            .iter()
+            .map(|dep| (root.to_path_buf(), dep.clone()))
+            .collect::<Vec<_>>();
+        let mut names = vec![];
+        while let Some((base, dep)) = pending.pop() {
+            if !fetched.insert(dep.name.clone()) {
+                continue;
+            }
+            let dst = modules.join(&dep.name);
+            dep.source.fetch(&base, &dst)?;
+            let manifest = dst.join(MANIFEST);
+            if manifest.is_file() {
+                let source_dir = match &dep.source {
+                    DependencySource::Path(path) => base.join(path),
+                    DependencySource::Git { .. } => dst.clone(),
+                };
+                for sub in Self::load(manifest)?.dependencies {
+                    pending.push((source_dir.clone(), sub));
+                }
+            }
+            names.push(dep.name);
+        }
+        Ok(names)
+    }
+}
+impl DependencySource {
+    fn parse(name: &str, value: &TomlValue) -> Result<Self, ManifestError> {
+        let dep = match value {
+            TomlValue::String(path) => return Ok(Self::Path(PathBuf::from(path))),
+            TomlValue::Table(dep) => dep,
+            _ => return Err(ManifestError::InvalidDependency(name.to_string())),
+        };
+        let string = |key: &str| match dep.get(key) {
+            Some(TomlValue::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(invalid(&format!("dependencies.{name}.{key}"), "a string")),
+            None => Ok(None),
+        };
+        // git would take values starting with `-` for options
+        let git_arg = |key: &str, expected| match string(key)? {
+            Some(value) if value.starts_with('-') => {
+                Err(invalid(&format!("dependencies.{name}.{key}"), expected))
+            }
+            value => Ok(value),
+        };
+        match (string("path")?, git_arg("git", "a git url")?) {
+            (Some(path), None) => Ok(Self::Path(PathBuf::from(path))),
+            (None, Some(url)) => Ok(Self::Git {
+                url,
+                rev: git_arg("rev", "a git revision")?,
+            }),
+            _ => Err(ManifestError::InvalidDependency(name.to_string())),
+        }
+    }
+    /// replaces `dst` with a fresh copy of the dependency, resolving relative paths
+    /// from `base`
+    pub fn fetch(&self, base: &Path, dst: &Path) -> Result<(), Box<dyn Error>> {
+        if dst.exists() {
+            fs::remove_dir_all(dst)?;
+        }
+        match self {
+            Self::Path(path) => copy_dir(&base.join(path), dst)?,
+            Self::Git { url, rev } => {
+                git(Command::new("git")
+                    .arg("clone")
+                    .arg("--quiet")
+                    .arg("--")
+                    .arg(url)
+                    .arg(dst))?;
+                if let Some(rev) = rev {
+                    git(Command::new("git")
+                        .arg("-C")
+                        .arg(dst)
+                        .args(["checkout", "--quiet", rev, "--"]))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+/// the directories a module resolver searches for imports in the project at `root`, in
+/// order: the project itself, then its fetched dependencies
+pub fn module_dirs(root: impl AsRef<Path>) -> Vec<PathBuf> {
+    let root = root.as_ref();
+    vec![root.to_path_buf(), root.join(MODULES_DIR)]
+}
+/// the file `import name` runs, looked up in `dirs` in order. each directory is checked
+/// for `name` itself, `name` with the `.hydra` extension, and a project directory `name`,
+/// whose manifest names its entry point, or which has a `main.hydra`
+pub fn resolve_import(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    dirs.iter().find_map(|dir| {
+        let path = dir.join(name);
+        if path.is_file() {
+            return Some(path);
+        }
+        let file = dir.join(format!("{name}.hydra"));
+        if file.is_file() {
+            return Some(file);
+        }
+        if path.is_dir() {
+            let entry = Manifest::load(path.join(MANIFEST))
+                .map(|manifest| manifest.entry)
+                .unwrap_or_else(|_| PathBuf::from(DEFAULT_ENTRY));
+            let entry = path.join(entry);
+            return entry.is_file().then_some(entry);
+        }
+        None
+    })
+}
+
+fn invalid(key: &str, expected: &'static str) -> ManifestError {
+    ManifestError::InvalidField {
+        key: key.to_string(),
+        expected,
+    }
+}
+/// names end up as directory names, so they're kept to identifier-like characters
+fn check_name(name: &str) -> Result<(), ManifestError> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(ManifestError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+fn copy_dir(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == MODULES_DIR || name == ".git" {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dst.join(name))?;
+        } else {
+            fs::copy(entry.path(), dst.join(name))?;
+        }
+    }
+    Ok(())
+}
+fn git(command: &mut Command) -> Result<(), Box<dyn Error>> {
+    let status = command.status()?;
+    if !status.success() {
+        return Err(format!("git exited with {status}").into());
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use super::code::{ByteCode, Closure, Location, Source};
+use std::collections::HashSet;
+
+impl Closure {
+    /// marks vector and tuple constructors whose result never leaves the frame so the
+    /// interpreter can build them in the frame's scratch buffer instead of allocating
+    pub fn escape_analysis(&mut self) {
+        for addr in 0..self.code.len() {
+            let (dst, start, amount, tuple) = match self.code[addr] {
+                ByteCode::Vector { dst, start, amount } => (dst, start, amount, false),
+                ByteCode::Tuple { dst, start, amount } => (dst, start, amount, true),
+                _ => continue,
+            };
+            let Location::Register(reg) = dst else {
+                continue;
+            };
+            if amount == 0 || self.captures(reg) || self.escapes(addr, reg) {
+                continue;
+            }
+            self.code[addr] = if tuple {
+                ByteCode::ScratchTuple { dst, start, amount }
+            } else {
+                ByteCode::ScratchVector { dst, start, amount }
+            };
+        }
+    }
+    /// follows every path from `addr` until `reg` gets overwritten and checks if the value
+    /// is read anywhere other than as the head of a field access
+    pub fn escapes(&self, addr: usize, reg: u8) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![addr + 1];
+        while let Some(addr) = pending.pop() {
+            if !visited.insert(addr) {
+                continue;
+            }
+            let Some(bytecode) = self.code.get(addr) else {
+                continue;
+            };
+            if bytecode.reads_escaping(reg) {
+                return true;
+            }
+            if bytecode.writes() == Some(Location::Register(reg)) {
+                continue;
+            }
+            match *bytecode {
+                ByteCode::Return { .. } => {}
+                ByteCode::Jump { addr: to } => pending.push(to),
+                ByteCode::JumpIf { addr: to, .. }
+                | ByteCode::JumpIfSome { addr: to, .. }
+                | ByteCode::Try { addr: to, .. } => {
+                    pending.push(addr + 1);
+                    pending.push(to);
+                }
+                _ => pending.push(addr + 1),
+            }
+        }
+        false
+    }
+}
+impl ByteCode {
+    pub fn writes(&self) -> Option<Location> {
+        match *self {
+            ByteCode::Call { dst, .. }
+            | ByteCode::CallSpread { dst, .. }
+            | ByteCode::Intrinsic { dst, .. } => dst,
+            ByteCode::Move { dst, .. }
+            | ByteCode::Copy { dst, .. }
+            | ByteCode::Field { dst, .. }
+            | ByteCode::Vector { dst, .. }
+            | ByteCode::Tuple { dst, .. }
+            | ByteCode::ScratchVector { dst, .. }
+            | ByteCode::ScratchTuple { dst, .. }
+            | ByteCode::Map { dst }
+            | ByteCode::Fn { dst, .. }
+            | ByteCode::Import { dst, .. }
+            | ByteCode::Binary { dst, .. }
+            | ByteCode::Unary { dst, .. } => Some(dst),
+            _ => None,
+        }
+    }
+    /// whether the instruction reads `reg` in a way that lets its value outlive the read
+    pub fn reads_escaping(&self, reg: u8) -> bool {
+        let src = Source::Register(reg);
+        let in_range = |start: u8, amount: u8| (start..start.saturating_add(amount)).contains(&reg);
+        match *self {
+            ByteCode::None
+            | ByteCode::Jump { .. }
+            | ByteCode::Map { .. }
+            | ByteCode::Fn { .. }
+            | ByteCode::Import { .. }
+            | ByteCode::Try { .. }
+            | ByteCode::EndTry
+            | ByteCode::Detach { .. } => false,
+            ByteCode::JumpIf { cond, .. } => cond == src,
+            ByteCode::JumpIfSome { src: value, .. } => value == src,
+            ByteCode::Call {
+                func,
+                start,
+                amount,
+                ..
+            } => func == src || in_range(start, amount),
+            ByteCode::CallSpread { func, args, .. } => func == src || args == src,
+            ByteCode::Intrinsic { start, amount, .. } => in_range(start, amount),
+            ByteCode::Defer {
+                func,
+                start,
+                amount,
+            } => func == src || in_range(start, amount),
+            ByteCode::Return { src: value } => value == Some(src),
+            ByteCode::Freeze { src: value }
+            | ByteCode::Open { src: value }
+            | ByteCode::Close { src: value } => value == src,
+            // only the elements get copied out, like a field access
+            ByteCode::Unpack { .. } => false,
+            ByteCode::Move { src: value, .. } | ByteCode::Copy { src: value, .. } => value == src,
+            ByteCode::Field { field, .. } => field == src,
+            ByteCode::SetField {
+                head,
+                field,
+                src: value,
+            } => head == src || field == src || value == src,
+            ByteCode::Extend { head, src: value } => head == src || value == src,
+            ByteCode::Vector { start, amount, .. }
+            | ByteCode::Tuple { start, amount, .. }
+            | ByteCode::ScratchVector { start, amount, .. }
+            | ByteCode::ScratchTuple { start, amount, .. } => in_range(start, amount),
+            ByteCode::Binary { left, right, .. } => left == src || right == src,
+            ByteCode::Unary { right, .. } => right == src,
+        }
+    }
+}
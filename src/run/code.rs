@@ -1,5 +1,5 @@
 use super::value::Value;
-use crate::scan::ast::{BinaryOperator, UnaryOperator};
+use crate::scan::ast::{AssignOperator, BinaryOperator, UnaryOperator};
 use std::{fmt::Display, rc::Rc};
 
 #[repr(u8)]
@@ -16,6 +16,8 @@ pub enum ByteCode {
         cond: Source,
         addr: usize,
     },
+    /// jumps if `src` isn't null, or if it is when `negative`. calls that return nothing
+    /// leave null in their destination, so they count as none like an explicit null
     JumpIfSome {
         negative: bool,
         src: Source,
@@ -28,6 +30,18 @@ pub enum ByteCode {
         start: u8,
         amount: u8,
     },
+    /// calls `func` with the elements of the vector `args`
+    CallSpread {
+        dst: Option<Location>,
+        func: Source,
+        args: Source,
+    },
+    Intrinsic {
+        dst: Option<Location>,
+        intrinsic: Intrinsic,
+        start: u8,
+        amount: u8,
+    },
     Return {
         src: Option<Source>,
     },
@@ -36,6 +50,11 @@ pub enum ByteCode {
         dst: Location,
         src: Source,
     },
+    /// a `Move` that gives `dst` its own copy of a container when `copy_containers` is set
+    Copy {
+        dst: Location,
+        src: Source,
+    },
     Field {
         dst: Location,
         head: Source,
@@ -46,6 +65,51 @@ pub enum ByteCode {
         field: Source,
         src: Source,
     },
+    /// appends the elements of the vector or tuple `src` to the vector `head`, or merges
+    /// the map `src` into the map `head`
+    Extend {
+        head: Source,
+        src: Source,
+    },
+    Freeze {
+        src: Source,
+    },
+    /// schedules a call of `func` with the registers `start..start + amount` for when the
+    /// frame returns
+    Defer {
+        func: Source,
+        start: u8,
+        amount: u8,
+    },
+    /// marks `src` as the object of a `with` statement, so an error unwinding out of it
+    /// before the matching `Close` closes it too
+    Open {
+        src: Source,
+    },
+    /// calls the `__exit` or `close` method of `src` unless it's null, ending the
+    /// innermost `Open`
+    Close {
+        src: Source,
+    },
+    /// makes errors raised until the matching `EndTry` jump to `addr` with the error value
+    /// in `dst`, unwinding the calls made in between
+    Try {
+        addr: usize,
+        dst: Location,
+    },
+    EndTry,
+    /// gives the registers `start..start + amount` fresh cells holding their values, so
+    /// closures that captured them keep their own copy when the registers get reused
+    Detach {
+        start: u8,
+        amount: u8,
+    },
+    /// writes the first `amount` elements of a tuple or vector into consecutive registers
+    Unpack {
+        start: u8,
+        amount: u8,
+        src: Source,
+    },
 
     Vector {
         dst: Location,
@@ -57,6 +121,16 @@ pub enum ByteCode {
         start: u8,
         amount: u8,
     },
+    ScratchVector {
+        dst: Location,
+        start: u8,
+        amount: u8,
+    },
+    ScratchTuple {
+        dst: Location,
+        start: u8,
+        amount: u8,
+    },
     Map {
         dst: Location,
     },
@@ -64,6 +138,11 @@ pub enum ByteCode {
         dst: Location,
         addr: u16,
     },
+    /// writes the value of the module whose path is the constant `path` into `dst`
+    Import {
+        dst: Location,
+        path: u16,
+    },
 
     Binary {
         op: BinaryOperation,
@@ -78,8 +157,38 @@ pub enum ByteCode {
     },
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intrinsic {
+    Len,
+    Push,
+    Get,
+}
+impl Intrinsic {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Intrinsic::Len => "len",
+            Intrinsic::Push => "push",
+            Intrinsic::Get => "get",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "len" => Some(Self::Len),
+            "push" => Some(Self::Push),
+            "get" => Some(Self::Get),
+            _ => None,
+        }
+    }
+}
+impl Display for Intrinsic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinaryOperation {
     Add,
+    /// `+=`, which pushes onto and extends vectors and merges maps in place
+    AddAssign,
     Sub,
     Mul,
     Div,
@@ -96,11 +205,23 @@ pub enum BinaryOperation {
     Is,
     In,
     As,
+    /// `??`, which compiled code short-circuits like `and` and `or`
+    Coalesce,
+}
+impl BinaryOperation {
+    /// whether the operation does arithmetic or compares numbers
+    pub fn is_numeric(&self) -> bool {
+        !matches!(
+            self,
+            Self::And | Self::Or | Self::Is | Self::In | Self::As | Self::Coalesce
+        )
+    }
 }
 impl Display for BinaryOperation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             BinaryOperation::Add => write!(f, "+"),
+            BinaryOperation::AddAssign => write!(f, "+="),
             BinaryOperation::Sub => write!(f, "-"),
             BinaryOperation::Mul => write!(f, "*"),
             BinaryOperation::Div => write!(f, "/"),
@@ -117,6 +238,7 @@ impl Display for BinaryOperation {
             BinaryOperation::Is => write!(f, "is"),
             BinaryOperation::In => write!(f, "in"),
             BinaryOperation::As => write!(f, "as"),
+            BinaryOperation::Coalesce => write!(f, "??"),
         }
     }
 }
@@ -140,6 +262,16 @@ impl From<BinaryOperator> for BinaryOperation {
             BinaryOperator::Is => Self::Is,
             BinaryOperator::In => Self::In,
             BinaryOperator::As => Self::As,
+            BinaryOperator::QuestionQuestion => Self::Coalesce,
+        }
+    }
+}
+impl TryFrom<AssignOperator> for BinaryOperation {
+    type Error = ();
+    fn try_from(value: AssignOperator) -> Result<Self, Self::Error> {
+        match value {
+            AssignOperator::Plus => Ok(Self::AddAssign),
+            op => Ok(TryInto::<BinaryOperator>::try_into(op)?.into()),
         }
     }
 }
@@ -176,18 +308,22 @@ pub enum Source {
     Register(u8),
     Global(u16),
     Constant(u16),
+    /// a variable of an enclosing function, see `Closure::upvalues`
+    Upvalue(u16),
 }
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Location {
     Register(u8),
     Global(u16),
+    Upvalue(u16),
 }
 impl Location {
     pub fn eq_source(&self, other: &Source) -> bool {
         match (self, other) {
             (Self::Register(loc), Source::Register(src)) => loc == src,
             (Self::Global(loc), Source::Global(src)) => loc == src,
+            (Self::Upvalue(loc), Source::Upvalue(src)) => loc == src,
             _ => false,
         }
     }
@@ -197,6 +333,7 @@ impl From<Location> for Source {
         match value {
             Location::Register(v) => Self::Register(v),
             Location::Global(v) => Self::Global(v),
+            Location::Upvalue(v) => Self::Upvalue(v),
         }
     }
 }
@@ -212,8 +349,90 @@ pub struct Closure {
     pub varargs: bool,
     pub closures: Vec<Rc<Closure>>,
     pub constants: Vec<Value>,
+    /// debug names of the registers, only used to annotate disassembly and errors
+    pub locals: Vec<Local>,
+    /// the variables of enclosing functions the closure captures when `Fn` creates it
+    pub upvalues: Vec<Upvalue>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upvalue {
+    pub name: String,
+    pub capture: Capture,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    /// a register of the enclosing function's frame, shared with it from then on
+    Register(u8),
+    /// one of the enclosing function's own upvalues
+    Upvalue(u16),
+}
+/// a local variable living in `register` for the instructions `start..end`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Local {
+    pub name: String,
+    pub register: u8,
+    pub start: usize,
+    pub end: usize,
 }
 
+impl Closure {
+    /// the name of the local living in `register` at the instruction `addr`
+    pub fn local_name(&self, register: u8, addr: usize) -> Option<&str> {
+        self.locals
+            .iter()
+            .rev()
+            .find(|local| local.register == register && (local.start..local.end).contains(&addr))
+            .map(|local| local.name.as_str())
+    }
+    /// the instruction at `addr` with constants and globals written out and registers
+    /// annotated with the names of their locals
+    pub fn disassemble(&self, addr: usize) -> String {
+        let mut s = String::new();
+        let _ = self.code[addr].write_with(
+            &mut s,
+            &|src| match src {
+                Source::Register(reg) => self.register_symbol("@", *reg, addr),
+                Source::Global(idx) => format!("global {}", self.constant_symbol(*idx)),
+                Source::Constant(idx) => format!("const {}", self.constant_symbol(*idx)),
+                Source::Upvalue(idx) => format!("upvalue {}", self.upvalue_symbol(*idx)),
+                src => src.to_string(),
+            },
+            &|dst| match dst {
+                Location::Register(reg) => self.register_symbol("!", *reg, addr),
+                Location::Global(idx) => format!("!global {}", self.constant_symbol(*idx)),
+                Location::Upvalue(idx) => format!("!upvalue {}", self.upvalue_symbol(*idx)),
+            },
+        );
+        s
+    }
+    fn register_symbol(&self, prefix: &str, register: u8, addr: usize) -> String {
+        match self.local_name(register, addr) {
+            Some(name) => format!("{prefix}{register}:{name}"),
+            None => format!("{prefix}{register}"),
+        }
+    }
+    fn upvalue_symbol(&self, idx: u16) -> String {
+        match self.upvalues.get(idx as usize) {
+            Some(upvalue) => format!("{:?}", upvalue.name),
+            None => format!("u#{idx}"),
+        }
+    }
+    /// whether a closure nested in this one captures `register`
+    pub fn captures(&self, register: u8) -> bool {
+        self.closures.iter().any(|closure| {
+            closure
+                .upvalues
+                .iter()
+                .any(|upvalue| upvalue.capture == Capture::Register(register))
+        })
+    }
+    fn constant_symbol(&self, idx: u16) -> String {
+        match self.constants.get(idx as usize) {
+            Some(value) => format!("{value:?}"),
+            None => format!("#{idx}"),
+        }
+    }
+}
 impl Display for Closure {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
@@ -225,13 +444,13 @@ impl Display for Closure {
         writeln!(f, "  parameters: {}", self.parameters)?;
         writeln!(f, "  varargs: {}", self.varargs)?;
         writeln!(f, "  code:")?;
-        let width = 30;
-        for ((addr, bytecode), line) in self.code.iter().enumerate().zip(self.lines.iter()) {
-            let s = bytecode.to_string();
+        let width: usize = 40;
+        for (addr, line) in self.lines.iter().enumerate() {
+            let s = self.disassemble(addr);
             writeln!(
                 f,
                 "    [{addr:04}] {s}{}({})",
-                " ".repeat(width - s.len()),
+                " ".repeat(width.saturating_sub(s.len()).max(1)),
                 line + 1
             )?;
         }
@@ -239,6 +458,23 @@ impl Display for Closure {
         for (addr, value) in self.constants.iter().enumerate() {
             writeln!(f, "    [{addr}] {value:?}")?;
         }
+        writeln!(f, "  locals:")?;
+        for Local {
+            name,
+            register,
+            start,
+            end,
+        } in self.locals.iter()
+        {
+            writeln!(f, "    @{register} {name} [{start:04}..{end:04}]")?;
+        }
+        writeln!(f, "  upvalues:")?;
+        for (addr, Upvalue { name, capture }) in self.upvalues.iter().enumerate() {
+            match capture {
+                Capture::Register(reg) => writeln!(f, "    [{addr}] {name} = @{reg}")?,
+                Capture::Upvalue(idx) => writeln!(f, "    [{addr}] {name} = u#{idx}")?,
+            }
+        }
         writeln!(f, "  closures:")?;
         for (addr, closure) in self.closures.iter().enumerate() {
             writeln!(f, "    [{addr}] {:08x?}", Rc::as_ptr(closure))?;
@@ -270,6 +506,17 @@ impl Display for Closure {
 }
 impl Display for ByteCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_with(f, &Source::to_string, &Location::to_string)
+    }
+}
+impl ByteCode {
+    /// writes the instruction with its operands formatted by `source` and `location`
+    pub fn write_with(
+        &self,
+        f: &mut impl std::fmt::Write,
+        source: &dyn Fn(&Source) -> String,
+        location: &dyn Fn(&Location) -> String,
+    ) -> std::fmt::Result {
         match self {
             ByteCode::None => write!(f, "none"),
             ByteCode::Jump { addr } => write!(f, "jump [{addr:04}]"),
@@ -277,28 +524,33 @@ impl Display for ByteCode {
                 negative: false,
                 cond,
                 addr,
-            } => write!(f, "jumpif     {cond} [{addr:04}]"),
+            } => write!(f, "jumpif     {} [{addr:04}]", source(cond)),
             ByteCode::JumpIf {
                 negative: true,
                 cond,
                 addr,
-            } => write!(f, "jumpif not {cond} [{addr:04}]"),
+            } => write!(f, "jumpif not {} [{addr:04}]", source(cond)),
             ByteCode::JumpIfSome {
                 negative: false,
                 src,
                 addr,
-            } => write!(f, "jumpifsome {src} [{addr:04}]"),
+            } => write!(f, "jumpifsome {} [{addr:04}]", source(src)),
             ByteCode::JumpIfSome {
                 negative: true,
                 src,
                 addr,
-            } => write!(f, "jumpifnone {src} [{addr:04}]"),
+            } => write!(f, "jumpifnone {} [{addr:04}]", source(src)),
             ByteCode::Call {
                 dst: None,
                 func,
                 start,
                 amount,
-            } => write!(f, "call       {func} ({start}..{})", start + amount - 1),
+            } => write!(
+                f,
+                "call       {} ({start}..{})",
+                source(func),
+                start + amount - 1
+            ),
             ByteCode::Call {
                 dst: Some(dst),
                 func,
@@ -306,33 +558,158 @@ impl Display for ByteCode {
                 amount,
             } => write!(
                 f,
-                "call       {func} ({start}..{}) -> {dst}",
+                "call       {} ({start}..{}) -> {}",
+                source(func),
+                start + amount - 1,
+                location(dst)
+            ),
+            ByteCode::CallSpread {
+                dst: None,
+                func,
+                args,
+            } => write!(f, "call       {} (...{})", source(func), source(args)),
+            ByteCode::CallSpread {
+                dst: Some(dst),
+                func,
+                args,
+            } => write!(
+                f,
+                "call       {} (...{}) -> {}",
+                source(func),
+                source(args),
+                location(dst)
+            ),
+            ByteCode::Intrinsic {
+                dst: None,
+                intrinsic,
+                start,
+                amount,
+            } => write!(
+                f,
+                "intrinsic  {intrinsic} ({start}..{})",
                 start + amount - 1
             ),
+            ByteCode::Intrinsic {
+                dst: Some(dst),
+                intrinsic,
+                start,
+                amount,
+            } => write!(
+                f,
+                "intrinsic  {intrinsic} ({start}..{}) -> {}",
+                start + amount - 1,
+                location(dst)
+            ),
             ByteCode::Return { src: None } => write!(f, "return"),
-            ByteCode::Return { src: Some(src) } => write!(f, "return     {src}"),
-            ByteCode::Move { dst, src } => write!(f, "move       {dst} = {src}"),
+            ByteCode::Return { src: Some(src) } => write!(f, "return     {}", source(src)),
+            ByteCode::Move { dst, src } => {
+                write!(f, "move       {} = {}", location(dst), source(src))
+            }
+            ByteCode::Copy { dst, src } => {
+                write!(f, "copy       {} = {}", location(dst), source(src))
+            }
             ByteCode::Field { dst, head, field } => {
-                write!(f, "field      {dst} = {head} . {field}")
+                write!(
+                    f,
+                    "field      {} = {} . {}",
+                    location(dst),
+                    source(head),
+                    source(field)
+                )
             }
             ByteCode::SetField { head, field, src } => {
-                write!(f, "setfield   {head} . {field} = {src}")
+                write!(
+                    f,
+                    "setfield   {} . {} = {}",
+                    source(head),
+                    source(field),
+                    source(src)
+                )
+            }
+            ByteCode::Extend { head, src } => {
+                write!(f, "extend     {} += {}", source(head), source(src))
+            }
+            ByteCode::Freeze { src } => write!(f, "freeze     {}", source(src)),
+            ByteCode::Defer {
+                func,
+                start,
+                amount,
+            } => write!(
+                f,
+                "defer      {} ({start}..{})",
+                source(func),
+                start + amount - 1
+            ),
+            ByteCode::Open { src } => write!(f, "open       {}", source(src)),
+            ByteCode::Close { src } => write!(f, "close      {}", source(src)),
+            ByteCode::Try { addr, dst } => write!(f, "try        [{addr:04}] -> {}", location(dst)),
+            ByteCode::EndTry => write!(f, "endtry"),
+            ByteCode::Detach { start, amount } => {
+                write!(f, "detach     {start}..{}", start + amount - 1)
+            }
+            ByteCode::Unpack { start, amount, src } => {
+                write!(
+                    f,
+                    "unpack     {start}..{} = {}",
+                    start + amount - 1,
+                    source(src)
+                )
             }
             ByteCode::Vector { dst, start, amount } => {
-                write!(f, "vec        {start}..{} -> {dst}", start + amount - 1)
+                write!(
+                    f,
+                    "vec        {start}..{} -> {}",
+                    start + amount - 1,
+                    location(dst)
+                )
             }
             ByteCode::Tuple { dst, start, amount } => {
-                write!(f, "tuple      {start}..{} -> {dst}", start + amount - 1)
+                write!(
+                    f,
+                    "tuple      {start}..{} -> {}",
+                    start + amount - 1,
+                    location(dst)
+                )
+            }
+            ByteCode::ScratchVector { dst, start, amount } => {
+                write!(
+                    f,
+                    "vec*       {start}..{} -> {}",
+                    start + amount - 1,
+                    location(dst)
+                )
             }
-            ByteCode::Map { dst } => write!(f, "map        {dst}"),
-            ByteCode::Fn { dst, addr } => write!(f, "fn         {dst} = c#{addr}"),
+            ByteCode::ScratchTuple { dst, start, amount } => {
+                write!(
+                    f,
+                    "tuple*     {start}..{} -> {}",
+                    start + amount - 1,
+                    location(dst)
+                )
+            }
+            ByteCode::Map { dst } => write!(f, "map        {}", location(dst)),
+            ByteCode::Fn { dst, addr } => write!(f, "fn         {} = c#{addr}", location(dst)),
+            ByteCode::Import { dst, path } => write!(
+                f,
+                "import     {} = {}",
+                location(dst),
+                source(&Source::Constant(*path))
+            ),
             ByteCode::Binary {
                 op,
                 dst,
                 left,
                 right,
-            } => write!(f, "binary     {dst} = {left} {op} {right}"),
-            ByteCode::Unary { op, dst, right } => write!(f, "unary     {dst} = {op} {right}"),
+            } => write!(
+                f,
+                "binary     {} = {} {op} {}",
+                location(dst),
+                source(left),
+                source(right)
+            ),
+            ByteCode::Unary { op, dst, right } => {
+                write!(f, "unary     {} = {op} {}", location(dst), source(right))
+            }
         }
     }
 }
@@ -347,6 +724,7 @@ impl Display for Source {
             Source::Register(reg) => write!(f, "@{reg}"),
             Source::Global(addr) => write!(f, "g#{addr}"),
             Source::Constant(addr) => write!(f, "#{addr}"),
+            Source::Upvalue(addr) => write!(f, "u#{addr}"),
         }
     }
 }
@@ -355,6 +733,7 @@ impl Display for Location {
         match self {
             Location::Register(reg) => write!(f, "!{reg}"),
             Location::Global(addr) => write!(f, "!g#{addr}"),
+            Location::Upvalue(addr) => write!(f, "!u#{addr}"),
         }
     }
 }
@@ -1,10 +1,13 @@
 use super::{
-    code::{ByteCode, Closure, Location, Source},
+    code::{
+        BinaryOperation, ByteCode, Capture, Closure, Intrinsic, Local, Location, Source, Upvalue,
+    },
     value::Value,
 };
 use crate::scan::{
     ast::{
-        AssignOperator, Atom, BinaryOperator, Block, Chunk, Expression, Parameter, Path, Statement,
+        AssignOperator, Atom, BinaryOperator, Block, Chunk, Element, Expression, MapKey, MatchCase,
+        Parameter, Path, Pattern, Statement,
     },
     position::Located,
 };
@@ -28,9 +31,29 @@ pub struct Frame {
 #[derive(Debug, Default)]
 pub struct Scope {
     pub locals: HashMap<String, u8>,
+    pub globals: HashSet<String>,
     pub offset: u8,
     pub breaks: HashSet<usize>,
     pub continues: HashSet<usize>,
+    /// whether `break` and `continue` leave this scope
+    pub is_loop: bool,
+    /// the object of a `with` statement, closed whenever the scope is left
+    pub exit: Option<Source>,
+    /// whether the scope is the body of a `try`, whose handler gets dropped whenever the
+    /// scope is left
+    pub handler: bool,
+    /// how many closures the frame had when the scope started
+    pub closures: usize,
+}
+/// where the arguments of a call end up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CallArgs {
+    Registers {
+        start: u8,
+        amount: u8,
+    },
+    /// a vector holding them, for calls that spread some of their arguments
+    Vector(Source),
 }
 
 impl Compiler {
@@ -46,7 +69,14 @@ impl Compiler {
         });
     }
     pub fn pop_frame(&mut self) -> Option<Frame> {
-        self.frame_stack.pop()
+        let mut frame = self.frame_stack.pop()?;
+        let end = frame.closure.code.len();
+        for local in frame.closure.locals.iter_mut() {
+            local.end = local.end.min(end);
+        }
+        frame.closure.peephole();
+        frame.closure.escape_analysis();
+        Some(frame)
     }
     pub fn frame(&self) -> Option<&Frame> {
         self.frame_stack.last()
@@ -69,6 +99,37 @@ impl Compiler {
         frame.closure.closures.push(closure);
         addr
     }
+    /// the upvalue of the current function referring to the local `name` of an enclosing
+    /// function, added on first use, or `None` if no enclosing function has one
+    pub fn upvalue(&mut self, name: &str) -> Option<u16> {
+        let depth = self.frame_stack.len().checked_sub(1)?;
+        if self.frame_stack[depth].is_global(name) {
+            return None;
+        }
+        self.upvalue_at(depth, name)
+    }
+    fn upvalue_at(&mut self, depth: usize, name: &str) -> Option<u16> {
+        let enclosing = &self.frame_stack[depth.checked_sub(1)?];
+        let capture = if let Some(register) = enclosing.get_local(name) {
+            Capture::Register(register)
+        } else if enclosing.is_global(name) {
+            return None;
+        } else {
+            Capture::Upvalue(self.upvalue_at(depth - 1, name)?)
+        };
+        let upvalues = &mut self.frame_stack[depth].closure.upvalues;
+        if let Some(addr) = upvalues
+            .iter()
+            .position(|upvalue| upvalue.name == name && upvalue.capture == capture)
+        {
+            return Some(addr as u16);
+        }
+        upvalues.push(Upvalue {
+            name: name.to_string(),
+            capture,
+        });
+        Some(upvalues.len() as u16 - 1)
+    }
     pub fn addr(&self) -> usize {
         self.frame().unwrap().closure.code.len()
     }
@@ -144,16 +205,354 @@ impl Compiler {
         let addr = self.write(ByteCode::None, ln);
         self.frame_mut().unwrap().alloc_continue(addr);
     }
+    /// pops the loop scope, pointing its `break`s at `exit` and its `continue`s at `next`.
+    /// `exit` has to be the current address, the captured registers get detached there
+    pub fn close_loop(&mut self, next: usize, exit: usize, ln: usize) {
+        self.detach(true, ln);
+        let scope = self.frame_mut().unwrap().pop_scope_loop().unwrap();
+        for addr in scope.breaks {
+            self.overwrite_no_ln(addr, ByteCode::Jump { addr: exit });
+        }
+        for addr in scope.continues {
+            self.overwrite_no_ln(addr, ByteCode::Jump { addr: next });
+        }
+    }
+    /// closes the `with` objects and drops the `try` handlers of the scopes that get left,
+    /// innermost first. `loop_only` stops at the innermost loop scope, for `break` and
+    /// `continue`
+    pub fn close_exits(&mut self, loop_only: bool, ln: usize) {
+        let mut exits = vec![];
+        for scope in self.frame().unwrap().scopes.iter().rev() {
+            if loop_only && scope.is_loop {
+                break;
+            }
+            if scope.handler {
+                exits.push(ByteCode::EndTry);
+            }
+            exits.extend(scope.exit.map(|src| ByteCode::Close { src }));
+        }
+        for bytecode in exits {
+            self.write(bytecode, ln);
+        }
+    }
+    /// detaches the registers of the innermost scope, or the innermost loop scope if
+    /// `loop_only`, that closures created in it captured, before they get reused
+    pub fn detach(&mut self, loop_only: bool, ln: usize) {
+        let frame = self.frame().unwrap();
+        let Some(scope) = frame
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| !loop_only || scope.is_loop)
+        else {
+            return;
+        };
+        let captured = frame.closure.closures[scope.closures..]
+            .iter()
+            .flat_map(|closure| &closure.upvalues)
+            .filter_map(|upvalue| match upvalue.capture {
+                Capture::Register(reg) if reg >= scope.offset => Some(reg),
+                _ => None,
+            });
+        let (Some(start), Some(end)) = (captured.clone().min(), captured.max()) else {
+            return;
+        };
+        self.write(
+            ByteCode::Detach {
+                start,
+                amount: end - start + 1,
+            },
+            ln,
+        );
+    }
     pub fn return_safe(&mut self, ln: usize) -> usize {
         let frame = self.frame_mut().unwrap();
-        if let Some(ByteCode::Return { src: _ }) = frame.closure.code.last() {
-            return frame.closure.code.len() - 1;
-        }
         let addr = frame.closure.code.len();
+        // a jump past a last branch that returns lands at the end, which needs its return
+        let jumped_to_end = frame.closure.code.iter().any(|bytecode| {
+            matches!(bytecode, ByteCode::Jump { addr: to }
+                | ByteCode::JumpIf { addr: to, .. }
+                | ByteCode::JumpIfSome { addr: to, .. }
+                | ByteCode::Try { addr: to, .. } if *to == addr)
+        });
+        if let (Some(ByteCode::Return { src: _ }), false) =
+            (frame.closure.code.last(), jumped_to_end)
+        {
+            return addr - 1;
+        }
         frame.closure.code.push(ByteCode::Return { src: None });
         frame.closure.lines.push(ln);
         addr
     }
+    /// compiles `head:field(args)`, the receiver is always passed as the first argument
+    /// to the function found at `head.field`. returns the result register if `returns` is set
+    pub fn self_call(
+        &mut self,
+        head: Source,
+        Located {
+            value: field,
+            pos: field_pos,
+        }: Located<String>,
+        args: Vec<Element>,
+        returns: bool,
+        head_ln: usize,
+        ln: usize,
+    ) -> Option<Source> {
+        let intrinsic =
+            Intrinsic::from_name(&field).filter(|_| !args.iter().any(Element::is_spread));
+        if let Some(intrinsic) = intrinsic {
+            let CallArgs::Registers { start, amount } = self.call_args(Some(head), args, head_ln)
+            else {
+                unreachable!("arguments without spreads stay in registers")
+            };
+            let dst = returns.then(|| Location::Register(self.frame_mut().unwrap().new_register()));
+            self.write(
+                ByteCode::Intrinsic {
+                    dst,
+                    intrinsic,
+                    start,
+                    amount,
+                },
+                ln,
+            );
+            return dst.map(Source::from);
+        }
+        self.frame_mut().unwrap().push_scope();
+        let func = {
+            let dst = self.frame_mut().unwrap().new_register();
+            let field = self.new_constant(Value::String(field));
+            self.write(
+                ByteCode::Field {
+                    dst: Location::Register(dst),
+                    head,
+                    field: Source::Constant(field),
+                },
+                field_pos.ln.start,
+            );
+            Source::Register(dst)
+        };
+        let args = self.call_args(Some(head), args, head_ln);
+        self.frame_mut().unwrap().pop_scope();
+        let dst = returns.then(|| Location::Register(self.frame_mut().unwrap().new_register()));
+        self.call(func, args, dst, ln);
+        dst.map(Source::from)
+    }
+    /// moves the receiver of a self call, if there is one, and the arguments into
+    /// consecutive registers, or collects them into a vector if any of them get spread
+    pub fn call_args(&mut self, head: Option<Source>, args: Vec<Element>, ln: usize) -> CallArgs {
+        self.frame_mut().unwrap().push_scope();
+        let args = if args.iter().any(Element::is_spread) {
+            let dst = self.frame_mut().unwrap().new_register();
+            self.spread_vector(Location::Register(dst), head, args, ln);
+            CallArgs::Vector(Source::Register(dst))
+        } else {
+            let start = self.frame().unwrap().registers;
+            let amount = args.len() as u8 + head.is_some() as u8;
+            let mut registers = self
+                .frame_mut()
+                .unwrap()
+                .alloc_registers(amount)
+                .into_iter();
+            if let Some(head) = head {
+                self.move_checked(Location::Register(registers.next().unwrap()), head, ln);
+            }
+            for (arg, reg) in args.into_iter().zip(registers) {
+                let Element::Single(arg) = arg else {
+                    unreachable!("spreads go through the vector")
+                };
+                let ln = arg.pos.ln.start;
+                self.move_expr(Location::Register(reg), arg, ln);
+            }
+            CallArgs::Registers { start, amount }
+        };
+        self.frame_mut().unwrap().pop_scope();
+        args
+    }
+    pub fn call(&mut self, func: Source, args: CallArgs, dst: Option<Location>, ln: usize) {
+        match args {
+            CallArgs::Registers { start, amount } => self.write(
+                ByteCode::Call {
+                    dst,
+                    func,
+                    start,
+                    amount,
+                },
+                ln,
+            ),
+            CallArgs::Vector(args) => self.write(ByteCode::CallSpread { dst, func, args }, ln),
+        };
+    }
+    /// compiles `head.field = expr` and its operator assignment variants into a `SetField`
+    pub fn assign_field(
+        &mut self,
+        op: AssignOperator,
+        head: Source,
+        field: Source,
+        expr: Located<Expression>,
+        ln: usize,
+    ) {
+        let src = expr.compile(self);
+        let src = match op {
+            AssignOperator::None => src,
+            op => {
+                let dst = Location::Register(self.frame_mut().unwrap().new_register());
+                self.write(ByteCode::Field { dst, head, field }, ln);
+                self.write(
+                    ByteCode::Binary {
+                        op: BinaryOperation::try_from(op).unwrap(),
+                        dst,
+                        left: dst.into(),
+                        right: src,
+                    },
+                    ln,
+                );
+                dst.into()
+            }
+        };
+        self.write(ByteCode::SetField { head, field, src }, ln);
+    }
+    /// allocates the parameter registers of a new function frame and binds their names
+    pub fn fn_parameters(
+        &mut self,
+        params: Vec<Located<Parameter>>,
+        varargs: Option<Located<String>>,
+    ) {
+        self.frame_mut()
+            .unwrap()
+            .alloc_registers(params.len() as u8);
+        if let Some(Located {
+            value: ident,
+            pos: _,
+        }) = varargs
+        {
+            self.frame_mut().unwrap().new_local(ident);
+            self.frame_mut().unwrap().closure.varargs = true;
+        }
+        for (
+            reg,
+            Located {
+                value: param,
+                pos: param_pos,
+            },
+        ) in params.into_iter().enumerate()
+        {
+            let param_ln = param_pos.ln.start;
+            match param {
+                Parameter::Ident(ident) => {
+                    self.frame_mut().unwrap().closure.parameters += 1;
+                    self.frame_mut().unwrap().set_local(ident, reg as u8);
+                }
+                Parameter::Tuple(params) | Parameter::Vector(params) => {
+                    for (
+                        idx,
+                        Located {
+                            value: ident,
+                            pos: _,
+                        },
+                    ) in params.into_iter().enumerate()
+                    {
+                        self.frame_mut().unwrap().closure.parameters += 1;
+                        let dst = Location::Register(self.frame_mut().unwrap().new_local(ident));
+                        self.write(
+                            ByteCode::Field {
+                                dst,
+                                head: Source::Register(reg as u8),
+                                field: Source::Int(idx as i64),
+                            },
+                            param_ln,
+                        );
+                    }
+                }
+                Parameter::Map(params) => {
+                    for Located {
+                        value: ident,
+                        pos: _,
+                    } in params
+                    {
+                        self.frame_mut().unwrap().closure.parameters += 1;
+                        let dst =
+                            Location::Register(self.frame_mut().unwrap().new_local(ident.clone()));
+                        let ident = self.new_constant(Value::String(ident));
+                        self.write(
+                            ByteCode::Field {
+                                dst,
+                                head: Source::Register(reg as u8),
+                                field: Source::Constant(ident),
+                            },
+                            param_ln,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    /// binds the value in `src` to the names of `param` as new locals
+    pub fn bind_parameter(&mut self, param: Parameter, src: Source, ln: usize) {
+        match param {
+            Parameter::Ident(ident) => {
+                let dst = Location::Register(self.frame_mut().unwrap().new_local(ident));
+                self.move_checked(dst, src, ln);
+            }
+            Parameter::Vector(idents) | Parameter::Tuple(idents) => self.unpack(idents, src, ln),
+            Parameter::Map(keys) => {
+                for Located { value: key, pos: _ } in keys {
+                    let field = Source::Constant(self.new_constant(Value::String(key.clone())));
+                    let dst = Location::Register(self.frame_mut().unwrap().new_local(key));
+                    self.write(
+                        ByteCode::Field {
+                            dst,
+                            head: src,
+                            field,
+                        },
+                        ln,
+                    );
+                }
+            }
+        }
+    }
+    /// binds the elements of `src` to `idents`, with a single `Unpack` into consecutive
+    /// registers if none of the names are locals yet
+    pub fn unpack(&mut self, idents: Vec<Located<String>>, src: Source, ln: usize) {
+        let frame = self.frame_mut().unwrap();
+        if !idents.is_empty()
+            && idents
+                .iter()
+                .all(|ident| frame.get_local(&ident.value).is_none())
+        {
+            let amount = idents.len() as u8;
+            let regs = frame.alloc_registers(amount);
+            for (Located { value: ident, .. }, reg) in idents.into_iter().zip(&regs) {
+                frame.set_local(ident, *reg);
+            }
+            self.write(
+                ByteCode::Unpack {
+                    start: regs[0],
+                    amount,
+                    src,
+                },
+                ln,
+            );
+            return;
+        }
+        for (
+            idx,
+            Located {
+                value: ident,
+                pos: _,
+            },
+        ) in idents.into_iter().enumerate()
+        {
+            let dst = Location::Register(self.frame_mut().unwrap().new_local(ident));
+            self.write(
+                ByteCode::Field {
+                    dst,
+                    head: src,
+                    field: Source::Int(idx as i64),
+                },
+                ln,
+            );
+        }
+    }
     pub fn move_checked(&mut self, dst: Location, src: Source, ln: usize) -> usize {
         if dst.eq_source(&src) {
             let addr = self.frame().unwrap().closure.code.len() - 1;
@@ -161,17 +560,218 @@ impl Compiler {
         }
         self.write(ByteCode::Move { dst, src }, ln)
     }
+    /// builds a vector of `head`, if there is one, followed by `exprs` in `dst`
+    pub fn vector(
+        &mut self,
+        dst: Location,
+        head: Option<Source>,
+        exprs: Vec<Located<Expression>>,
+        ln: usize,
+    ) {
+        let amount = exprs.len() as u8 + head.is_some() as u8;
+        let registers = self.frame().unwrap().registers;
+        let start = registers;
+        let mut regs = self
+            .frame_mut()
+            .unwrap()
+            .alloc_registers(amount)
+            .into_iter();
+        if let Some(head) = head {
+            self.move_checked(Location::Register(regs.next().unwrap()), head, ln);
+        }
+        for (expr, reg) in exprs.into_iter().zip(regs) {
+            let ln = expr.pos.ln.start;
+            let src = expr.compile(self);
+            self.move_checked(Location::Register(reg), src, ln);
+        }
+        self.write(ByteCode::Vector { dst, start, amount }, ln);
+        self.frame_mut().unwrap().registers = registers;
+    }
+    /// builds a vector of `head`, if there is one, followed by `elements` in `dst`, with
+    /// the elements after the first spread appended in runs, each run of plain elements as
+    /// one temporary vector
+    pub fn spread_vector(
+        &mut self,
+        dst: Location,
+        head: Option<Source>,
+        elements: Vec<Element>,
+        ln: usize,
+    ) {
+        let mut elements = elements.into_iter().peekable();
+        let mut singles = vec![];
+        while let Some(Element::Single(expr)) = elements.next_if(|element| !element.is_spread()) {
+            singles.push(expr);
+        }
+        self.vector(dst, head, singles, ln);
+        let registers = self.frame().unwrap().registers;
+        while let Some(element) = elements.next() {
+            let src = match element {
+                Element::Spread(expr) => expr.compile(self),
+                Element::Single(expr) => {
+                    let mut singles = vec![expr];
+                    while let Some(Element::Single(expr)) =
+                        elements.next_if(|element| !element.is_spread())
+                    {
+                        singles.push(expr);
+                    }
+                    let reg = self.frame_mut().unwrap().new_register();
+                    self.vector(Location::Register(reg), None, singles, ln);
+                    Source::Register(reg)
+                }
+            };
+            self.write(
+                ByteCode::Extend {
+                    head: dst.into(),
+                    src,
+                },
+                ln,
+            );
+            self.frame_mut().unwrap().registers = registers;
+        }
+    }
+    /// emits the tests and bindings of a match pattern against `subject` and returns the
+    /// placeholders for the jumps taken when the pattern doesn't match
+    pub fn compile_pattern(
+        &mut self,
+        pattern: Located<Pattern>,
+        subject: Source,
+    ) -> Vec<(usize, Source, usize)> {
+        let Located {
+            value: pattern,
+            pos,
+        } = pattern;
+        let ln = pos.ln.start;
+        let (patterns, typ) = match pattern {
+            Pattern::Wildcard => return vec![],
+            Pattern::Binding(ident) => {
+                let dst = Location::Register(self.frame_mut().unwrap().new_local(ident));
+                self.move_checked(dst, subject, ln);
+                return vec![];
+            }
+            Pattern::Value(expr) => {
+                let right = Located::new(expr, pos).compile(self);
+                return vec![self.test(BinaryOperation::EE, subject, right, ln)];
+            }
+            Pattern::Tuple(patterns) => (patterns, "tuple"),
+            Pattern::Vector(patterns) => (patterns, "vec"),
+            Pattern::Map(fields) => return self.compile_map_pattern(fields, subject, ln),
+        };
+        let typ = Source::Constant(self.new_constant(Value::String(typ.into())));
+        let mut jumps_to_fail = vec![self.test(BinaryOperation::Is, subject, typ, ln)];
+        let head = match subject {
+            Source::Register(reg) => reg,
+            src => {
+                let reg = self.frame_mut().unwrap().new_register();
+                self.move_checked(Location::Register(reg), src, ln);
+                reg
+            }
+        };
+        let len = Location::Register(self.frame_mut().unwrap().new_register());
+        self.write(
+            ByteCode::Intrinsic {
+                dst: Some(len),
+                intrinsic: Intrinsic::Len,
+                start: head,
+                amount: 1,
+            },
+            ln,
+        );
+        let amount = Source::Int(patterns.len() as i64);
+        jumps_to_fail.push(self.test(BinaryOperation::EE, Source::from(len), amount, ln));
+        for (idx, pattern) in patterns.into_iter().enumerate() {
+            let dst = Location::Register(self.frame_mut().unwrap().new_register());
+            self.write(
+                ByteCode::Field {
+                    dst,
+                    head: Source::Register(head),
+                    field: Source::Int(idx as i64),
+                },
+                ln,
+            );
+            jumps_to_fail.extend(self.compile_pattern(pattern, Source::from(dst)));
+        }
+        jumps_to_fail
+    }
+    /// like `compile_pattern` for `{ key = pattern }`, where every key has to be set to
+    /// something other than null for the fields to get matched
+    fn compile_map_pattern(
+        &mut self,
+        fields: Vec<(Located<String>, Located<Pattern>)>,
+        subject: Source,
+        ln: usize,
+    ) -> Vec<(usize, Source, usize)> {
+        let typ = Source::Constant(self.new_constant(Value::String("map".into())));
+        let mut jumps_to_fail = vec![self.test(BinaryOperation::Is, subject, typ, ln)];
+        for (key, pattern) in fields {
+            let dst = Location::Register(self.frame_mut().unwrap().new_register());
+            let field = self.new_constant(Value::String(key.value));
+            self.write(
+                ByteCode::Field {
+                    dst,
+                    head: subject,
+                    field: Source::Constant(field),
+                },
+                key.pos.ln.start,
+            );
+            let set = self.test(BinaryOperation::NE, Source::from(dst), Source::Null, ln);
+            jumps_to_fail.push(set);
+            jumps_to_fail.extend(self.compile_pattern(pattern, Source::from(dst)));
+        }
+        jumps_to_fail
+    }
+    /// writes `left op right` into a new register followed by a placeholder for the jump
+    /// depending on it
+    fn test(
+        &mut self,
+        op: BinaryOperation,
+        left: Source,
+        right: Source,
+        ln: usize,
+    ) -> (usize, Source, usize) {
+        let dst = Location::Register(self.frame_mut().unwrap().new_register());
+        self.write(
+            ByteCode::Binary {
+                op,
+                dst,
+                left,
+                right,
+            },
+            ln,
+        );
+        (self.none(), Source::from(dst), ln)
+    }
+    /// compiles `expr` into `dst`, using a `Copy` if it names an existing value so that
+    /// interpreters with `copy_containers` set don't alias the container
+    pub fn move_expr(&mut self, dst: Location, expr: Located<Expression>, ln: usize) {
+        let copy = matches!(expr.value, Expression::Atom(Atom::Path(_)));
+        let src = expr.compile(self);
+        if !copy {
+            self.move_checked(dst, src, ln);
+        } else if !dst.eq_source(&src) {
+            self.write(ByteCode::Copy { dst, src }, ln);
+        }
+    }
 }
 impl Frame {
     pub fn push_scope(&mut self) {
         self.scopes.push(Scope {
             offset: self.registers,
+            closures: self.closure.closures.len(),
+            ..Default::default()
+        });
+    }
+    pub fn push_scope_loop(&mut self) {
+        self.scopes.push(Scope {
+            offset: self.registers,
+            is_loop: true,
+            closures: self.closure.closures.len(),
             ..Default::default()
         });
     }
     pub fn pop_scope(&mut self) {
         if let Some(scope) = self.scopes.pop() {
             self.registers = scope.offset;
+            self.end_locals(&scope);
             if let Some(current) = self.scope_mut() {
                 current.breaks.extend(scope.breaks);
                 current.continues.extend(scope.continues);
@@ -181,6 +781,7 @@ impl Frame {
     pub fn pop_scope_loop(&mut self) -> Option<Scope> {
         if let Some(scope) = self.scopes.pop() {
             self.registers = scope.offset;
+            self.end_locals(&scope);
             Some(scope)
         } else {
             None
@@ -218,11 +819,58 @@ impl Frame {
             if let Some(register) = scope.locals.get(name) {
                 return Some(*register);
             }
+            if scope.globals.contains(name) {
+                return None;
+            }
         }
         None
     }
+    /// whether `name` got declared global in an open scope without being shadowed since
+    pub fn is_global(&self, name: &str) -> bool {
+        for scope in self.scopes.iter().rev() {
+            if scope.locals.contains_key(name) {
+                return false;
+            }
+            if scope.globals.contains(name) {
+                return true;
+            }
+        }
+        false
+    }
     pub fn set_local(&mut self, name: String, register: u8) {
-        self.scope_mut().unwrap().locals.insert(name, register);
+        let scope = self.scope_mut().unwrap();
+        scope.globals.remove(&name);
+        if let Some(old) = scope.locals.insert(name.clone(), register) {
+            self.end_local(&name, old);
+        }
+        self.closure.locals.push(Local {
+            name,
+            register,
+            start: self.closure.code.len(),
+            end: usize::MAX,
+        });
+    }
+    /// makes `name` refer to the global of the same name for the rest of the scope
+    pub fn declare_global(&mut self, name: String) {
+        let scope = self.scope_mut().unwrap();
+        if let Some(register) = scope.locals.remove(&name) {
+            self.end_local(&name, register);
+        }
+        self.scope_mut().unwrap().globals.insert(name);
+    }
+    /// ends the debug range of the local `name` in `register` at the current instruction
+    fn end_local(&mut self, name: &str, register: u8) {
+        let addr = self.closure.code.len();
+        if let Some(local) = self.closure.locals.iter_mut().rev().find(|local| {
+            local.end == usize::MAX && local.register == register && local.name == name
+        }) {
+            local.end = addr;
+        }
+    }
+    fn end_locals(&mut self, scope: &Scope) {
+        for (name, register) in scope.locals.iter() {
+            self.end_local(name, *register);
+        }
     }
     pub fn new_local(&mut self, name: String) -> u8 {
         if let Some(register) = self.get_local(&name) {
@@ -263,10 +911,7 @@ impl Compilable for Located<Chunk> {
 impl Compilable for Located<Block> {
     type Output = Option<Source>;
     fn compile(self, compiler: &mut Compiler) -> Self::Output {
-        let Located {
-            value: block,
-            pos: _,
-        } = self;
+        let Located { value: block, pos } = self;
         compiler.frame_mut().unwrap().push_scope();
         for stat in block.stats {
             if let Some(src) = stat.compile(compiler) {
@@ -274,6 +919,7 @@ impl Compilable for Located<Block> {
                 return Some(src);
             }
         }
+        compiler.detach(false, pos.ln.end);
         compiler.frame_mut().unwrap().pop_scope();
         None
     }
@@ -291,64 +937,84 @@ impl Compilable for Located<Statement> {
                         pos: _,
                     },
                 expr,
-            } => {
-                let src = expr.compile(compiler);
-                match param {
-                    Parameter::Ident(ident) => {
-                        let dst =
-                            Location::Register(compiler.frame_mut().unwrap().new_local(ident));
-                        compiler.move_checked(dst, src, ln);
-                    }
-                    Parameter::Vector(idents) | Parameter::Tuple(idents) => {
-                        for (
-                            idx,
-                            Located {
-                                value: ident,
-                                pos: _,
+            } => match param {
+                Parameter::Ident(ident) => {
+                    let dst = Location::Register(compiler.frame_mut().unwrap().new_local(ident));
+                    compiler.move_expr(dst, expr, ln);
+                }
+                Parameter::Vector(idents) | Parameter::Tuple(idents) => {
+                    let src = expr.compile(compiler);
+                    compiler.unpack(idents, src, ln);
+                }
+                Parameter::Map(keys) => {
+                    let src = expr.compile(compiler);
+                    for Located { value: key, pos: _ } in keys {
+                        let field =
+                            Source::Constant(compiler.new_constant(Value::String(key.clone())));
+                        let dst = Location::Register(compiler.frame_mut().unwrap().new_local(key));
+                        compiler.write(
+                            ByteCode::Field {
+                                dst,
+                                head: src,
+                                field,
                             },
-                        ) in idents.into_iter().enumerate()
-                        {
-                            let dst =
-                                Location::Register(compiler.frame_mut().unwrap().new_local(ident));
-                            compiler.write(
-                                ByteCode::Field {
-                                    dst,
-                                    head: src,
-                                    field: Source::Int(idx as i64),
-                                },
-                                ln,
-                            );
-                        }
-                    }
-                    Parameter::Map(keys) => {
-                        for Located { value: key, pos: _ } in keys {
-                            let field =
-                                Source::Constant(compiler.new_constant(Value::String(key.clone())));
-                            let dst =
-                                Location::Register(compiler.frame_mut().unwrap().new_local(key));
-                            compiler.write(
-                                ByteCode::Field {
-                                    dst,
-                                    head: src,
-                                    field,
-                                },
-                                ln,
-                            );
-                        }
+                            ln,
+                        );
                     }
                 }
+            },
+            Statement::Global {
+                name:
+                    Located {
+                        value: name,
+                        pos: _,
+                    },
+                expr,
+            } => {
+                compiler.frame_mut().unwrap().declare_global(name.clone());
+                if let Some(expr) = expr {
+                    let dst = Location::Global(compiler.new_constant(Value::String(name)));
+                    let src = expr.compile(compiler);
+                    compiler.move_checked(dst, src, ln);
+                }
+            }
+            Statement::Assign {
+                op,
+                path:
+                    Located {
+                        value: Path::Field { head, field },
+                        pos: _,
+                    },
+                expr,
+            } => {
+                let head = Source::from(head.compile(compiler));
+                let field = Source::Constant(compiler.new_constant(Value::String(field.value)));
+                compiler.assign_field(op, head, field, expr, ln);
+            }
+            Statement::Assign {
+                op,
+                path:
+                    Located {
+                        value: Path::Index { head, index },
+                        pos: _,
+                    },
+                expr,
+            } => {
+                let head = Source::from(head.compile(compiler));
+                let field = index.compile(compiler);
+                compiler.assign_field(op, head, field, expr, ln);
             }
             Statement::Assign { op, path, expr } => {
                 let dst = path.compile(compiler);
-                let src = expr.compile(compiler);
                 match op {
                     AssignOperator::None => {
-                        compiler.move_checked(dst, src, ln);
+                        compiler.move_expr(dst, expr, ln);
                     }
                     op => {
+                        let src = expr.compile(compiler);
                         compiler.write(
                             ByteCode::Binary {
-                                op: TryInto::<BinaryOperator>::try_into(op).unwrap().into(),
+                                op: BinaryOperation::try_from(op).unwrap(),
                                 dst,
                                 left: dst.into(),
                                 right: src,
@@ -368,81 +1034,10 @@ impl Compilable for Located<Statement> {
                 varargs,
                 body,
             } => {
-                let dst = Location::Register(compiler.frame_mut().unwrap().new_local(name));
-                compiler.push_frame(compiler.path.clone(), None);
+                let dst = Location::Register(compiler.frame_mut().unwrap().new_local(name.clone()));
+                compiler.push_frame(compiler.path.clone(), Some(name));
                 {
-                    compiler
-                        .frame_mut()
-                        .unwrap()
-                        .alloc_registers(params.len() as u8);
-                    if let Some(Located {
-                        value: ident,
-                        pos: _,
-                    }) = varargs
-                    {
-                        compiler.frame_mut().unwrap().new_local(ident);
-                        compiler.frame_mut().unwrap().closure.varargs = true;
-                    }
-                    for (
-                        reg,
-                        Located {
-                            value: param,
-                            pos: param_pos,
-                        },
-                    ) in params.into_iter().enumerate()
-                    {
-                        let param_ln = param_pos.ln.start;
-                        match param {
-                            Parameter::Ident(ident) => {
-                                compiler.frame_mut().unwrap().closure.parameters += 1;
-                                compiler.frame_mut().unwrap().set_local(ident, reg as u8);
-                            }
-                            Parameter::Tuple(params) | Parameter::Vector(params) => {
-                                for (
-                                    idx,
-                                    Located {
-                                        value: ident,
-                                        pos: _,
-                                    },
-                                ) in params.into_iter().enumerate()
-                                {
-                                    compiler.frame_mut().unwrap().closure.parameters += 1;
-                                    let dst = Location::Register(
-                                        compiler.frame_mut().unwrap().new_local(ident),
-                                    );
-                                    compiler.write(
-                                        ByteCode::Field {
-                                            dst,
-                                            head: Source::Register(reg as u8),
-                                            field: Source::Int(idx as i64),
-                                        },
-                                        param_ln,
-                                    );
-                                }
-                            }
-                            Parameter::Map(params) => {
-                                for Located {
-                                    value: ident,
-                                    pos: _,
-                                } in params
-                                {
-                                    compiler.frame_mut().unwrap().closure.parameters += 1;
-                                    let dst = Location::Register(
-                                        compiler.frame_mut().unwrap().new_local(ident.clone()),
-                                    );
-                                    let ident = compiler.new_constant(Value::String(ident));
-                                    compiler.write(
-                                        ByteCode::Field {
-                                            dst,
-                                            head: Source::Register(reg as u8),
-                                            field: Source::Constant(ident),
-                                        },
-                                        param_ln,
-                                    );
-                                }
-                            }
-                        }
-                    }
+                    compiler.fn_parameters(params, varargs);
                     if body.compile(compiler).is_none() {
                         compiler.write(ByteCode::Return { src: None }, ln);
                     }
@@ -451,23 +1046,81 @@ impl Compilable for Located<Statement> {
                 let addr = compiler.new_closure(Rc::new(closure));
                 compiler.write(ByteCode::Fn { dst, addr }, ln);
             }
-            Statement::Call { head, args } => {
-                let func = Source::from(head.compile(compiler));
-                compiler.frame_mut().unwrap().push_scope();
-                let start = compiler.frame().unwrap().registers;
-                let amount = args.len() as u8;
+            Statement::FieldFn {
+                head,
+                name:
+                    Located {
+                        value: name,
+                        pos: name_pos,
+                    },
+                method,
+                mut params,
+                varargs,
+                body,
+            } => {
+                let head = Source::from(head.compile(compiler));
+                if method {
+                    params.insert(0, Located::new(Parameter::Ident("self".into()), name_pos));
+                }
+                let dst = compiler.frame_mut().unwrap().new_register();
+                compiler.push_frame(compiler.path.clone(), Some(name.clone()));
                 {
-                    let registers = compiler.frame_mut().unwrap().alloc_registers(amount);
-                    for (arg, reg) in args.into_iter().zip(registers) {
-                        let ln = arg.pos.ln.start;
-                        let arg = arg.compile(compiler);
-                        compiler.move_checked(Location::Register(reg), arg, ln);
+                    compiler.fn_parameters(params, varargs);
+                    if body.compile(compiler).is_none() {
+                        compiler.write(ByteCode::Return { src: None }, ln);
                     }
                 }
-                compiler.frame_mut().unwrap().pop_scope();
+                let Frame { closure, .. } = compiler.pop_frame().unwrap();
+                let addr = compiler.new_closure(Rc::new(closure));
+                compiler.write(
+                    ByteCode::Fn {
+                        dst: Location::Register(dst),
+                        addr,
+                    },
+                    ln,
+                );
+                let field = compiler.new_constant(Value::String(name));
+                compiler.write(
+                    ByteCode::SetField {
+                        head,
+                        field: Source::Constant(field),
+                        src: Source::Register(dst),
+                    },
+                    ln,
+                );
+            }
+            Statement::Defer(Located {
+                value: expr,
+                pos: _,
+            }) => {
+                let (func, args) = match expr {
+                    Expression::Call { head, args } => {
+                        let func = head.compile(compiler);
+                        (func, compiler.call_args(None, args, ln))
+                    }
+                    Expression::SelfCall { head, field, args } => {
+                        let head_ln = head.pos.ln.start;
+                        let head = head.compile(compiler);
+                        let func = Location::Register(compiler.frame_mut().unwrap().new_register());
+                        let field =
+                            Source::Constant(compiler.new_constant(Value::String(field.value)));
+                        compiler.write(
+                            ByteCode::Field {
+                                dst: func,
+                                head,
+                                field,
+                            },
+                            ln,
+                        );
+                        (func.into(), compiler.call_args(Some(head), args, head_ln))
+                    }
+                    _ => unreachable!("the parser only allows calls after defer"),
+                };
+                let CallArgs::Registers { start, amount } = args else {
+                    unreachable!("the parser doesn't allow spreads in deferred calls")
+                };
                 compiler.write(
-                    ByteCode::Call {
-                        dst: None,
+                    ByteCode::Defer {
                         func,
                         start,
                         amount,
@@ -475,68 +1128,56 @@ impl Compilable for Located<Statement> {
                     ln,
                 );
             }
-            Statement::SelfCall {
-                head,
-                field:
+            Statement::Enum {
+                name:
                     Located {
-                        value: field,
-                        pos: field_pos,
+                        value: name,
+                        pos: _,
                     },
-                args,
+                members,
             } => {
-                let head_ln = head.pos.ln.start;
-                let head = Source::from(head.compile(compiler));
-                let func = {
-                    let dst = compiler.frame_mut().unwrap().new_register();
-                    let field = compiler.new_constant(Value::String(field));
+                let dst = Location::Register(compiler.frame_mut().unwrap().new_local(name));
+                compiler.write(ByteCode::Map { dst }, ln);
+                for (Located { value: member, .. }, value) in members {
+                    let field = Source::Constant(compiler.new_constant(Value::String(member)));
+                    let src = value.compile(compiler);
                     compiler.write(
-                        ByteCode::Field {
-                            dst: Location::Register(dst),
-                            head,
-                            field: Source::Constant(field),
+                        ByteCode::SetField {
+                            head: Source::from(dst),
+                            field,
+                            src,
                         },
-                        field_pos.ln.start,
-                    );
-                    Source::Register(dst)
-                };
-                let start = compiler.frame().unwrap().registers;
-                let amount = args.len() as u8 + 1;
-                let head_reg = {
-                    let dst = compiler.frame_mut().unwrap().new_register();
-                    compiler.move_checked(Location::Register(dst), head, head_ln);
-                    dst
-                };
-                compiler.frame_mut().unwrap().push_scope();
-                {
-                    compiler.move_checked(
-                        Location::Register(start),
-                        Source::Register(head_reg),
                         ln,
                     );
-                    let registers = compiler.frame_mut().unwrap().alloc_registers(amount);
-                    for (arg, reg) in args.into_iter().zip(registers) {
-                        let ln = arg.pos.ln.start;
-                        let arg = arg.compile(compiler);
-                        compiler.move_checked(Location::Register(reg), arg, ln);
-                    }
                 }
-                compiler.frame_mut().unwrap().pop_scope();
                 compiler.write(
-                    ByteCode::Call {
-                        dst: None,
-                        func,
-                        start,
-                        amount,
+                    ByteCode::Freeze {
+                        src: Source::from(dst),
                     },
                     ln,
                 );
             }
+            Statement::Call { head, args } => {
+                let func = Source::from(head.compile(compiler));
+                let args = compiler.call_args(None, args, ln);
+                compiler.call(func, args, None, ln);
+            }
+            Statement::SelfCall { head, field, args } => {
+                let head_ln = head.pos.ln.start;
+                let head = Source::from(head.compile(compiler));
+                compiler.self_call(head, field, args, false, head_ln, ln);
+            }
+            Statement::Expression(expr) => {
+                expr.compile(compiler);
+            }
             Statement::Return(Some(expr)) => {
                 let src = expr.compile(compiler);
+                compiler.close_exits(false, ln);
                 compiler.write(ByteCode::Return { src: Some(src) }, ln);
                 return Some(Source::default());
             }
             Statement::Return(None) => {
+                compiler.close_exits(false, ln);
                 compiler.write(ByteCode::Return { src: None }, ln);
                 return Some(Source::default());
             }
@@ -641,25 +1282,92 @@ impl Compilable for Located<Statement> {
                 compiler.frame_mut().unwrap().pop_scope();
             }
             Statement::While { cond, body } => {
-                compiler.frame_mut().unwrap().push_scope();
+                compiler.frame_mut().unwrap().push_scope_loop();
                 let start = compiler.addr();
                 let cond = cond.compile(compiler);
                 let jump_to_exit = compiler.none();
                 body.compile(compiler);
-                compiler.alloc_continue(ln);
+                compiler.detach(true, ln);
+                compiler.write(ByteCode::Jump { addr: start }, ln);
                 let exit = compiler.addr();
                 compiler.overwrite_jump_if(jump_to_exit, true, cond, exit, ln);
-                let scope = compiler.frame_mut().unwrap().pop_scope_loop().unwrap();
-                for addr in scope.breaks {
-                    if exit != addr + 1 {
-                        compiler.overwrite_no_ln(addr, ByteCode::Jump { addr: exit });
-                    }
+                compiler.close_loop(start, exit, ln);
+            }
+            Statement::With { expr, name, body } => {
+                compiler.frame_mut().unwrap().push_scope();
+                // the object stays in its own register so rebinding `name` can't skip the close
+                let reg = compiler.frame_mut().unwrap().new_register();
+                compiler.move_expr(Location::Register(reg), expr, ln);
+                compiler.write(
+                    ByteCode::Open {
+                        src: Source::Register(reg),
+                    },
+                    ln,
+                );
+                if let Some(Located {
+                    value: name,
+                    pos: _,
+                }) = name
+                {
+                    let local = compiler.frame_mut().unwrap().new_local(name);
+                    compiler.write(
+                        ByteCode::Move {
+                            dst: Location::Register(local),
+                            src: Source::Register(reg),
+                        },
+                        ln,
+                    );
                 }
-                for addr in scope.continues {
-                    if start != addr + 1 {
-                        compiler.overwrite_no_ln(addr, ByteCode::Jump { addr: start });
-                    }
+                compiler.frame_mut().unwrap().scope_mut().unwrap().exit =
+                    Some(Source::Register(reg));
+                body.compile(compiler);
+                compiler.write(
+                    ByteCode::Close {
+                        src: Source::Register(reg),
+                    },
+                    ln,
+                );
+                compiler.frame_mut().unwrap().pop_scope();
+            }
+            Statement::Try { body, name, catch } => {
+                compiler.frame_mut().unwrap().push_scope();
+                let reg = compiler.frame_mut().unwrap().new_register();
+                let enter = compiler.none();
+                compiler.frame_mut().unwrap().push_scope();
+                compiler.frame_mut().unwrap().scope_mut().unwrap().handler = true;
+                body.compile(compiler);
+                compiler.frame_mut().unwrap().pop_scope();
+                compiler.write(ByteCode::EndTry, ln);
+                let jump_to_exit = compiler.none();
+                let catch_addr = compiler.addr();
+                compiler.overwrite(
+                    enter,
+                    ByteCode::Try {
+                        addr: catch_addr,
+                        dst: Location::Register(reg),
+                    },
+                    ln,
+                );
+                compiler.frame_mut().unwrap().push_scope();
+                if let Some(Located {
+                    value: name,
+                    pos: _,
+                }) = name
+                {
+                    let local = compiler.frame_mut().unwrap().new_local(name);
+                    compiler.write(
+                        ByteCode::Move {
+                            dst: Location::Register(local),
+                            src: Source::Register(reg),
+                        },
+                        ln,
+                    );
                 }
+                catch.compile(compiler);
+                compiler.frame_mut().unwrap().pop_scope();
+                let exit = compiler.addr();
+                compiler.overwrite_jump(jump_to_exit, exit, ln);
+                compiler.frame_mut().unwrap().pop_scope();
             }
             Statement::WhileLet {
                 param:
@@ -670,7 +1378,7 @@ impl Compilable for Located<Statement> {
                 expr,
                 body,
             } => {
-                compiler.frame_mut().unwrap().push_scope();
+                compiler.frame_mut().unwrap().push_scope_loop();
                 let start = compiler.addr();
                 let src = expr.compile(compiler);
                 let jump_to_exit = compiler.none();
@@ -725,16 +1433,85 @@ impl Compilable for Located<Statement> {
                     }
                 }
                 body.compile(compiler);
-                compiler.alloc_continue(ln);
+                compiler.detach(true, ln);
+                compiler.write(ByteCode::Jump { addr: start }, ln);
                 let exit = compiler.addr();
                 compiler.overwrite_jump_if_some(jump_to_exit, true, src, exit, ln);
-                let scope = compiler.frame_mut().unwrap().pop_scope_loop().unwrap();
-                for addr in scope.breaks {
-                    compiler.overwrite_no_ln(addr, ByteCode::Jump { addr: exit });
-                }
-                for addr in scope.continues {
-                    compiler.overwrite_no_ln(addr, ByteCode::Jump { addr: start });
-                }
+                compiler.close_loop(start, exit, ln);
+            }
+            Statement::For {
+                param:
+                    Located {
+                        value: param,
+                        pos: param_pos,
+                    },
+                iter,
+                body,
+            } if matches!(
+                iter.value,
+                Expression::Atom(Atom::Vector(_) | Atom::Tuple(_))
+            ) =>
+            {
+                compiler.frame_mut().unwrap().push_scope_loop();
+                let iter_reg = compiler.frame_mut().unwrap().new_register();
+                let src = iter.compile(compiler);
+                compiler.move_checked(Location::Register(iter_reg), src, ln);
+                let len_reg = compiler.frame_mut().unwrap().new_register();
+                compiler.write(
+                    ByteCode::Intrinsic {
+                        dst: Some(Location::Register(len_reg)),
+                        intrinsic: Intrinsic::Len,
+                        start: iter_reg,
+                        amount: 1,
+                    },
+                    ln,
+                );
+                let idx_reg = compiler.frame_mut().unwrap().new_register();
+                compiler.move_checked(Location::Register(idx_reg), Source::Int(0), ln);
+                let start = compiler.addr();
+                let cond_reg = compiler.frame_mut().unwrap().new_register();
+                compiler.write(
+                    ByteCode::Binary {
+                        op: BinaryOperation::LT,
+                        dst: Location::Register(cond_reg),
+                        left: Source::Register(idx_reg),
+                        right: Source::Register(len_reg),
+                    },
+                    ln,
+                );
+                let jump_to_exit = compiler.none();
+                let elem_reg = compiler.frame_mut().unwrap().new_register();
+                compiler.write(
+                    ByteCode::Field {
+                        dst: Location::Register(elem_reg),
+                        head: Source::Register(iter_reg),
+                        field: Source::Register(idx_reg),
+                    },
+                    ln,
+                );
+                compiler.bind_parameter(param, Source::Register(elem_reg), param_pos.ln.start);
+                body.compile(compiler);
+                let step = compiler.addr();
+                compiler.write(
+                    ByteCode::Binary {
+                        op: BinaryOperation::Add,
+                        dst: Location::Register(idx_reg),
+                        left: Source::Register(idx_reg),
+                        right: Source::Int(1),
+                    },
+                    ln,
+                );
+                compiler.detach(true, ln);
+                compiler.write(ByteCode::Jump { addr: start }, ln);
+                let exit = compiler.addr();
+                compiler.overwrite_jump_if(
+                    jump_to_exit,
+                    true,
+                    Source::Register(cond_reg),
+                    exit,
+                    ln,
+                );
+                compiler.close_loop(step, exit, ln);
             }
             Statement::For {
                 param:
@@ -745,7 +1522,7 @@ impl Compilable for Located<Statement> {
                 iter,
                 body,
             } => {
-                compiler.frame_mut().unwrap().push_scope();
+                compiler.frame_mut().unwrap().push_scope_loop();
                 let iter = {
                     let dst = Location::Register(compiler.frame_mut().unwrap().new_register());
                     let iter = iter.compile(compiler);
@@ -784,74 +1561,104 @@ impl Compilable for Located<Statement> {
                     );
                 }
                 let jump_to_exit = compiler.none();
+                compiler.bind_parameter(param, src, param_pos.ln.start);
+                body.compile(compiler);
+                compiler.detach(true, ln);
+                compiler.write(ByteCode::Jump { addr: start }, ln);
+                let exit = compiler.addr();
+                compiler.overwrite_jump_if_some(jump_to_exit, true, src, exit, ln);
+                compiler.close_loop(start, exit, ln);
+            }
+            Statement::Match { expr, cases } => {
+                compiler.frame_mut().unwrap().push_scope();
                 {
-                    let ln = param_pos.ln.start;
-                    match param {
-                        Parameter::Ident(ident) => {
+                    let subject = match expr.compile(compiler) {
+                        Source::Register(reg) => Source::Register(reg),
+                        src => {
                             let dst =
-                                Location::Register(compiler.frame_mut().unwrap().new_local(ident));
+                                Location::Register(compiler.frame_mut().unwrap().new_register());
                             compiler.move_checked(dst, src, ln);
+                            Source::from(dst)
                         }
-                        Parameter::Vector(idents) | Parameter::Tuple(idents) => {
-                            for (
-                                idx,
-                                Located {
-                                    value: ident,
-                                    pos: _,
-                                },
-                            ) in idents.into_iter().enumerate()
-                            {
-                                let dst = Location::Register(
-                                    compiler.frame_mut().unwrap().new_local(ident),
-                                );
-                                compiler.write(
-                                    ByteCode::Field {
-                                        dst,
-                                        head: src,
-                                        field: Source::Int(idx as i64),
-                                    },
-                                    ln,
-                                );
+                    };
+                    let mut jumps_to_exit = vec![];
+                    for Located {
+                        value:
+                            MatchCase {
+                                patterns,
+                                guard,
+                                body,
+                            },
+                        pos,
+                    } in cases
+                    {
+                        let ln = pos.ln.start;
+                        compiler.frame_mut().unwrap().push_scope();
+                        let mut jumps_to_body = vec![];
+                        let mut jumps_to_next = vec![];
+                        let amount = patterns.len();
+                        for (idx, pattern) in patterns.into_iter().enumerate() {
+                            let jumps_to_fail = compiler.compile_pattern(pattern, subject);
+                            if idx + 1 == amount {
+                                jumps_to_next = jumps_to_fail;
+                                break;
                             }
-                        }
-                        Parameter::Map(keys) => {
-                            for Located { value: key, pos: _ } in keys {
-                                let field = Source::Constant(
-                                    compiler.new_constant(Value::String(key.clone())),
-                                );
-                                let dst = Location::Register(
-                                    compiler.frame_mut().unwrap().new_local(key),
-                                );
-                                compiler.write(
-                                    ByteCode::Field {
-                                        dst,
-                                        head: src,
-                                        field,
-                                    },
-                                    ln,
-                                );
+                            jumps_to_body.push(compiler.none());
+                            let next = compiler.addr();
+                            for (addr, cond, ln) in jumps_to_fail {
+                                compiler.overwrite_jump_if(addr, true, cond, next, ln);
                             }
                         }
+                        let start = compiler.addr();
+                        for addr in jumps_to_body {
+                            compiler.overwrite_jump(addr, start, ln);
+                        }
+                        if let Some(guard) = guard {
+                            let ln = guard.pos.ln.start;
+                            let cond = guard.compile(compiler);
+                            jumps_to_next.push((compiler.none(), cond, ln));
+                        }
+                        body.compile(compiler);
+                        jumps_to_exit.push(compiler.none());
+                        compiler.frame_mut().unwrap().pop_scope();
+                        let next = compiler.addr();
+                        for (addr, cond, ln) in jumps_to_next {
+                            compiler.overwrite_jump_if(addr, true, cond, next, ln);
+                        }
+                    }
+                    let exit = compiler.addr();
+                    for addr in jumps_to_exit {
+                        compiler.overwrite_jump(addr, exit, ln);
                     }
                 }
-                body.compile(compiler);
-                compiler.alloc_continue(ln);
-                let exit = compiler.addr();
-                compiler.overwrite_jump_if_some(jump_to_exit, true, src, exit, ln);
-                let scope = compiler.frame_mut().unwrap().pop_scope_loop().unwrap();
-                for addr in scope.breaks {
-                    compiler.overwrite_no_ln(addr, ByteCode::Jump { addr: exit });
-                }
-                for addr in scope.continues {
-                    compiler.overwrite_no_ln(addr, ByteCode::Jump { addr: start });
-                }
+                compiler.frame_mut().unwrap().pop_scope();
+            }
+            Statement::Import {
+                path:
+                    Located {
+                        value: path,
+                        pos: _,
+                    },
+                name:
+                    Located {
+                        value: name,
+                        pos: _,
+                    },
+            } => {
+                let path = compiler.new_constant(Value::String(path));
+                let dst = Location::Register(compiler.frame_mut().unwrap().new_local(name));
+                compiler.write(ByteCode::Import { dst, path }, ln);
             }
             Statement::Continue => {
+                compiler.close_exits(true, ln);
+                compiler.detach(true, ln);
                 compiler.alloc_continue(ln);
             }
             Statement::Break => {
+                compiler.close_exits(true, ln);
                 compiler.alloc_break(ln);
             }
+            Statement::Pass => {}
         }
         None
     }
@@ -865,87 +1672,17 @@ impl Compilable for Located<Expression> {
             Expression::Atom(atom) => Located::new(atom, pos).compile(compiler),
             Expression::Call { head, args } => {
                 let func = head.compile(compiler);
-                compiler.frame_mut().unwrap().push_scope();
-                let start = compiler.frame().unwrap().registers;
-                let amount = args.len() as u8;
-                {
-                    let registers = compiler.frame_mut().unwrap().alloc_registers(amount);
-                    for (arg, reg) in args.into_iter().zip(registers) {
-                        let ln = arg.pos.ln.start;
-                        let arg = arg.compile(compiler);
-                        compiler.move_checked(Location::Register(reg), arg, ln);
-                    }
-                }
-                compiler.frame_mut().unwrap().pop_scope();
+                let args = compiler.call_args(None, args, ln);
                 let dst = compiler.frame_mut().unwrap().new_register();
-                compiler.write(
-                    ByteCode::Call {
-                        dst: Some(Location::Register(dst)),
-                        func,
-                        start,
-                        amount,
-                    },
-                    ln,
-                );
+                compiler.call(func, args, Some(Location::Register(dst)), ln);
                 Source::Register(dst)
             }
-            Expression::SelfCall {
-                head,
-                field:
-                    Located {
-                        value: field,
-                        pos: field_pos,
-                    },
-                args,
-            } => {
+            Expression::SelfCall { head, field, args } => {
                 let head_ln = head.pos.ln.start;
                 let head = head.compile(compiler);
-                let func = {
-                    let dst = compiler.frame_mut().unwrap().new_register();
-                    let field = compiler.new_constant(Value::String(field));
-                    compiler.write(
-                        ByteCode::Field {
-                            dst: Location::Register(dst),
-                            head,
-                            field: Source::Constant(field),
-                        },
-                        field_pos.ln.start,
-                    );
-                    Source::Register(dst)
-                };
-                let start = compiler.frame().unwrap().registers;
-                let amount = args.len() as u8 + 1;
-                let head_reg = {
-                    let dst = compiler.frame_mut().unwrap().new_register();
-                    compiler.move_checked(Location::Register(dst), head, head_ln);
-                    dst
-                };
-                compiler.frame_mut().unwrap().push_scope();
-                {
-                    compiler.move_checked(
-                        Location::Register(start),
-                        Source::Register(head_reg),
-                        ln,
-                    );
-                    let registers = compiler.frame_mut().unwrap().alloc_registers(amount);
-                    for (arg, reg) in args.into_iter().zip(registers) {
-                        let ln = arg.pos.ln.start;
-                        let arg = arg.compile(compiler);
-                        compiler.move_checked(Location::Register(reg), arg, ln);
-                    }
-                }
-                compiler.frame_mut().unwrap().pop_scope();
-                let dst = compiler.frame_mut().unwrap().new_register();
-                compiler.write(
-                    ByteCode::Call {
-                        dst: Some(Location::Register(dst)),
-                        func,
-                        start,
-                        amount,
-                    },
-                    ln,
-                );
-                Source::Register(dst)
+                compiler
+                    .self_call(head, field, args, true, head_ln, ln)
+                    .unwrap()
             }
             Expression::Field {
                 head,
@@ -982,6 +1719,34 @@ impl Compilable for Located<Expression> {
                 );
                 Source::Register(dst)
             }
+            Expression::Binary {
+                op:
+                    op @ (BinaryOperator::And | BinaryOperator::Or | BinaryOperator::QuestionQuestion),
+                left,
+                right,
+            } => {
+                // the right side only runs if the left one doesn't decide the result, which
+                // is whichever operand got evaluated last. for `??` that's a left side of null
+                let left = left.compile(compiler);
+                let dst = Location::Register(compiler.frame_mut().unwrap().new_register());
+                compiler.move_checked(dst, left, ln);
+                let jump = compiler.none();
+                let right = right.compile(compiler);
+                compiler.move_checked(dst, right, ln);
+                let end = compiler.addr();
+                if op == BinaryOperator::QuestionQuestion {
+                    compiler.overwrite_jump_if_some(jump, false, Source::from(dst), end, ln);
+                } else {
+                    compiler.overwrite_jump_if(
+                        jump,
+                        op == BinaryOperator::And,
+                        Source::from(dst),
+                        end,
+                        ln,
+                    );
+                }
+                Source::from(dst)
+            }
             Expression::Binary { op, left, right } => {
                 let left = left.compile(compiler);
                 let right = right.compile(compiler);
@@ -1031,11 +1796,11 @@ impl Compilable for Located<Atom> {
                 let amount = exprs.len() as u8;
                 let registers = compiler.frame().unwrap().registers;
                 let start = registers;
-                for expr in exprs {
+                let regs = compiler.frame_mut().unwrap().alloc_registers(amount);
+                for (expr, reg) in exprs.into_iter().zip(regs) {
                     let ln = expr.pos.ln.start;
-                    let dst = compiler.frame_mut().unwrap().new_register();
                     let src = expr.compile(compiler);
-                    compiler.move_checked(Location::Register(dst), src, ln);
+                    compiler.move_checked(Location::Register(reg), src, ln);
                 }
                 compiler.write(
                     ByteCode::Tuple {
@@ -1048,26 +1813,9 @@ impl Compilable for Located<Atom> {
                 compiler.frame_mut().unwrap().registers = registers;
                 Source::Register(dst)
             }
-            Atom::Vector(exprs) => {
+            Atom::Vector(elements) => {
                 let dst = compiler.frame_mut().unwrap().new_register();
-                let amount = exprs.len() as u8;
-                let registers = compiler.frame().unwrap().registers;
-                let start = registers;
-                for expr in exprs {
-                    let ln = expr.pos.ln.start;
-                    let dst = compiler.frame_mut().unwrap().new_register();
-                    let src = expr.compile(compiler);
-                    compiler.move_checked(Location::Register(dst), src, ln);
-                }
-                compiler.write(
-                    ByteCode::Vector {
-                        dst: Location::Register(dst),
-                        start,
-                        amount,
-                    },
-                    ln,
-                );
-                compiler.frame_mut().unwrap().registers = registers;
+                compiler.spread_vector(Location::Register(dst), None, elements, ln);
                 Source::Register(dst)
             }
             Atom::Map(pairs) => {
@@ -1079,10 +1827,26 @@ impl Compilable for Located<Atom> {
                     ln,
                 );
                 let registers = compiler.frame().unwrap().registers;
-                for (Located { value: field, pos }, expr) in pairs {
+                for (Located { value: key, pos }, expr) in pairs {
                     let ln = pos.ln.start;
+                    let field = match key {
+                        MapKey::Name(field) => {
+                            Source::Constant(compiler.new_constant(Value::String(field)))
+                        }
+                        MapKey::Computed(key) => key.compile(compiler),
+                        MapKey::Spread => {
+                            let src = expr.compile(compiler);
+                            compiler.write(
+                                ByteCode::Extend {
+                                    head: Source::Register(dst),
+                                    src,
+                                },
+                                ln,
+                            );
+                            continue;
+                        }
+                    };
                     let src = expr.compile(compiler);
-                    let field = Source::Constant(compiler.new_constant(Value::String(field)));
                     compiler.write(
                         ByteCode::SetField {
                             head: Source::Register(dst),
@@ -1095,6 +1859,41 @@ impl Compilable for Located<Atom> {
                 compiler.frame_mut().unwrap().registers = registers;
                 Source::Register(dst)
             }
+            Atom::Import(path) => {
+                let path = compiler.new_constant(Value::String(path));
+                let dst = compiler.frame_mut().unwrap().new_register();
+                compiler.write(
+                    ByteCode::Import {
+                        dst: Location::Register(dst),
+                        path,
+                    },
+                    ln,
+                );
+                Source::Register(dst)
+            }
+            Atom::If {
+                cond,
+                case,
+                else_case,
+            } => {
+                let dst = compiler.frame_mut().unwrap().new_register();
+                let registers = compiler.frame().unwrap().registers;
+                let cond = cond.compile(compiler);
+                let jump_to_else = compiler.none();
+                let ln_case = case.pos.ln.start;
+                let src = case.compile(compiler);
+                compiler.move_checked(Location::Register(dst), src, ln_case);
+                let jump_to_exit = compiler.none();
+                let _else = compiler.addr();
+                let ln_else = else_case.pos.ln.start;
+                let src = else_case.compile(compiler);
+                compiler.move_checked(Location::Register(dst), src, ln_else);
+                let exit = compiler.addr();
+                compiler.overwrite_jump_if(jump_to_else, true, cond, _else, ln);
+                compiler.overwrite_jump(jump_to_exit, exit, ln);
+                compiler.frame_mut().unwrap().registers = registers;
+                Source::Register(dst)
+            }
             Atom::Expression(expr) => expr.compile(compiler),
             Atom::Fn {
                 params,
@@ -1104,78 +1903,7 @@ impl Compilable for Located<Atom> {
                 let dst = Location::Register(compiler.frame_mut().unwrap().new_register());
                 compiler.push_frame(compiler.path.clone(), None);
                 {
-                    compiler
-                        .frame_mut()
-                        .unwrap()
-                        .alloc_registers(params.len() as u8);
-                    if let Some(Located {
-                        value: ident,
-                        pos: _,
-                    }) = varargs
-                    {
-                        compiler.frame_mut().unwrap().new_local(ident);
-                        compiler.frame_mut().unwrap().closure.varargs = true;
-                    }
-                    for (
-                        reg,
-                        Located {
-                            value: param,
-                            pos: param_pos,
-                        },
-                    ) in params.into_iter().enumerate()
-                    {
-                        let param_ln = param_pos.ln.start;
-                        match param {
-                            Parameter::Ident(ident) => {
-                                compiler.frame_mut().unwrap().closure.parameters += 1;
-                                compiler.frame_mut().unwrap().set_local(ident, reg as u8);
-                            }
-                            Parameter::Tuple(params) | Parameter::Vector(params) => {
-                                for (
-                                    idx,
-                                    Located {
-                                        value: ident,
-                                        pos: _,
-                                    },
-                                ) in params.into_iter().enumerate()
-                                {
-                                    compiler.frame_mut().unwrap().closure.parameters += 1;
-                                    let dst = Location::Register(
-                                        compiler.frame_mut().unwrap().new_local(ident),
-                                    );
-                                    compiler.write(
-                                        ByteCode::Field {
-                                            dst,
-                                            head: Source::Register(reg as u8),
-                                            field: Source::Int(idx as i64),
-                                        },
-                                        param_ln,
-                                    );
-                                }
-                            }
-                            Parameter::Map(params) => {
-                                for Located {
-                                    value: ident,
-                                    pos: _,
-                                } in params
-                                {
-                                    compiler.frame_mut().unwrap().closure.parameters += 1;
-                                    let dst = Location::Register(
-                                        compiler.frame_mut().unwrap().new_local(ident.clone()),
-                                    );
-                                    let ident = compiler.new_constant(Value::String(ident));
-                                    compiler.write(
-                                        ByteCode::Field {
-                                            dst,
-                                            head: Source::Register(reg as u8),
-                                            field: Source::Constant(ident),
-                                        },
-                                        param_ln,
-                                    );
-                                }
-                            }
-                        }
-                    }
+                    compiler.fn_parameters(params, varargs);
                     let src = body.compile(compiler);
                     compiler.write(ByteCode::Return { src: Some(src) }, ln);
                 }
@@ -1196,6 +1924,8 @@ impl Compilable for Located<Path> {
             Path::Ident(ident) => {
                 if let Some(reg) = compiler.frame().unwrap().get_local(&ident) {
                     Location::Register(reg)
+                } else if let Some(addr) = compiler.upvalue(&ident) {
+                    Location::Upvalue(addr)
                 } else {
                     let addr = compiler.new_constant(Value::String(ident));
                     Location::Global(addr)
@@ -1236,6 +1966,14 @@ impl Compilable for Located<Path> {
                 );
                 Location::Register(dst)
             }
+            Path::Expression(expr) => match expr.compile(compiler) {
+                Source::Register(reg) => Location::Register(reg),
+                src => {
+                    let dst = Location::Register(compiler.frame_mut().unwrap().new_register());
+                    compiler.move_checked(dst, src, ln);
+                    dst
+                }
+            },
         }
     }
 }
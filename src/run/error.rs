@@ -0,0 +1,115 @@
+use super::{
+    interpreter::{Interpreter, Thrown},
+    types,
+    value::{NativeFn, NativeObject, Value},
+};
+use crate::{make_tuple, make_vec};
+use std::{error::Error, fmt::Display, rc::Rc};
+
+/// the value `try` and `pcall` hand errors over as, which scripts raise with `error`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorObject {
+    pub msg: String,
+    pub kind: String,
+    /// the error this one wraps
+    pub cause: Option<Value>,
+    pub path: Option<String>,
+    pub ln: usize,
+    pub traceback: Vec<(Option<String>, Option<String>, usize)>,
+}
+impl ErrorObject {
+    pub const TYPE: &'static str = types::ERROR;
+    pub const DEFAULT_KIND: &'static str = "error";
+    pub fn new(
+        interpreter: &Interpreter,
+        msg: String,
+        kind: Option<String>,
+        cause: Option<Value>,
+    ) -> Self {
+        let traceback = interpreter.traceback();
+        let (path, ln) = traceback
+            .first()
+            .map(|(_, path, ln)| (path.clone(), *ln))
+            .unwrap_or_default();
+        Self {
+            msg,
+            kind: kind.unwrap_or_else(|| Self::DEFAULT_KIND.into()),
+            cause,
+            path,
+            ln,
+            traceback,
+        }
+    }
+    /// raises the error value so it reaches the caller intact
+    pub fn raise(value: Value) -> Box<dyn Error> {
+        Thrown {
+            msg: Self::message(&value),
+            value,
+        }
+        .into()
+    }
+    /// the message of an error value followed by the messages of its causes
+    pub fn message(value: &Value) -> String {
+        let Value::NativeObject(object) = value else {
+            return value.to_string();
+        };
+        let object = object.lock().unwrap();
+        if !types::same(object.typ(), Self::TYPE) {
+            return value.to_string();
+        }
+        let msg = object.get("msg").unwrap_or_default().to_string();
+        match object.get("cause") {
+            Some(cause) => format!("{msg}: {}", Self::message(&cause)),
+            None => msg,
+        }
+    }
+    /// the kind of an error value, `None` for anything that isn't one
+    pub fn kind_of(value: &Value) -> Option<Value> {
+        let Value::NativeObject(object) = value else {
+            return None;
+        };
+        let object = object.lock().unwrap();
+        if !types::same(object.typ(), Self::TYPE) {
+            return None;
+        }
+        object.get("kind")
+    }
+}
+impl NativeObject for ErrorObject {
+    fn typ(&self) -> &'static str {
+        Self::TYPE
+    }
+    fn get(&self, key: &str) -> Option<Value> {
+        match key {
+            "msg" => Some(Value::String(self.msg.clone())),
+            "kind" => Some(Value::String(self.kind.clone())),
+            "cause" => self.cause.clone(),
+            "path" => self.path.clone().map(Value::String),
+            "ln" => Some(Value::Int(self.ln as i64 + 1)),
+            "traceback" => Some(make_vec!(self
+                .traceback
+                .iter()
+                .map(|(name, path, ln)| make_tuple!(
+                    name.clone(),
+                    path.clone(),
+                    Value::Int(*ln as i64 + 1)
+                ))
+                .collect::<Vec<Value>>())),
+            _ => None,
+        }
+    }
+    /// `print` and `str` show the kind before the message and the messages of the causes
+    fn __str(&self) -> Option<Rc<NativeFn>> {
+        let mut text = format!("{}: {}", self.kind, self.msg);
+        if let Some(cause) = &self.cause {
+            text = format!("{text}: {}", Self::message(cause));
+        }
+        Some(Rc::new(move |_, _| Ok(Some(Value::String(text.clone())))))
+    }
+}
+impl Display for ErrorObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+impl Error for ErrorObject {}
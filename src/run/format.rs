@@ -0,0 +1,1041 @@
+use super::{
+    code::{
+        BinaryOperation, ByteCode, Capture, Closure, Intrinsic, Local, Location, Source,
+        UnaryOperation, Upvalue,
+    },
+    value::Value,
+};
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, Read, Write},
+    ops::BitOr,
+    rc::Rc,
+};
+
+/// first bytes of every `.hydc` file
+pub const MAGIC: [u8; 4] = *b"HYDC";
+pub const EXTENSION: &str = "hydc";
+/// version of the bytecode format this build reads and writes. files with another major
+/// version or a newer minor version are rejected
+pub const FORMAT_VERSION: Version = Version {
+    major: 1,
+    minor: 1,
+    patch: 0,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+impl Version {
+    /// whether a build reading `self` can run bytecode written in `file`'s version
+    pub fn reads(self, file: Version) -> bool {
+        self.major == file.major && self.minor >= file.minor
+    }
+}
+
+/// instructions a compiled program depends on beyond the base set, so a build that
+/// doesn't know one of them refuses the file instead of misexecuting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features(u32);
+impl Features {
+    pub const NONE: Self = Self(0);
+    /// `ScratchVector` and `ScratchTuple` from escape analysis
+    pub const SCRATCH: Self = Self(1);
+    pub const DEFER: Self = Self(1 << 1);
+    /// `Open` and `Close` from `with` statements
+    pub const CLOSE: Self = Self(1 << 2);
+    pub const FREEZE: Self = Self(1 << 3);
+    pub const INTRINSIC: Self = Self(1 << 4);
+    pub const COPY: Self = Self(1 << 5);
+    /// closures capturing variables of enclosing functions
+    pub const UPVALUE: Self = Self(1 << 6);
+    pub const IMPORT: Self = Self(1 << 7);
+    /// `Try` and `EndTry` from `try` statements
+    pub const TRY: Self = Self(1 << 8);
+    /// every feature this build supports
+    pub const ALL: Self = Self(0b111111111);
+    const NAMES: [(Self, &'static str); 9] = [
+        (Self::SCRATCH, "scratch"),
+        (Self::DEFER, "defer"),
+        (Self::CLOSE, "close"),
+        (Self::FREEZE, "freeze"),
+        (Self::INTRINSIC, "intrinsic"),
+        (Self::COPY, "copy"),
+        (Self::UPVALUE, "upvalue"),
+        (Self::IMPORT, "import"),
+        (Self::TRY, "try"),
+    ];
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+    /// the features in `self` this build doesn't know
+    pub fn unsupported(self) -> Self {
+        Self(self.0 & !Self::ALL.0)
+    }
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(feature, _)| self.contains(*feature))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+impl BitOr for Features {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// the start of a `.hydc` file: the magic number, the format version and the features
+/// the program uses, all integers little-endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: Version,
+    pub features: Features,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    NotBytecode,
+    Truncated,
+    IncompatibleVersion { file: Version, current: Version },
+    UnsupportedFeatures(Features),
+    Corrupt(String),
+    Io(String),
+}
+impl Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::NotBytecode => write!(f, "not a hydra bytecode file"),
+            FormatError::Truncated => write!(f, "bytecode file ends early"),
+            FormatError::IncompatibleVersion { file, current } => write!(
+                f,
+                "bytecode format {file} can't be run by this build, which reads {current}, \
+                recompile the script"
+            ),
+            FormatError::UnsupportedFeatures(features) => write!(
+                f,
+                "bytecode uses features unknown to this build (bits {:#x}), recompile the script",
+                features.bits()
+            ),
+            FormatError::Corrupt(err) => write!(f, "corrupt bytecode file, {err}"),
+            FormatError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+impl Error for FormatError {}
+impl From<io::Error> for FormatError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => Self::Truncated,
+            _ => Self::Io(err.to_string()),
+        }
+    }
+}
+
+impl Header {
+    pub fn new(closure: &Closure) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            features: closure.features(),
+        }
+    }
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        for part in [self.version.major, self.version.minor, self.version.patch] {
+            writer.write_all(&part.to_le_bytes())?;
+        }
+        writer.write_all(&self.features.bits().to_le_bytes())
+    }
+    /// reads a header without checking if this build can run the file, see `check`
+    pub fn read(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(FormatError::NotBytecode);
+        }
+        let mut read_u16 = || -> Result<u16, FormatError> {
+            let mut bytes = [0; 2];
+            reader.read_exact(&mut bytes)?;
+            Ok(u16::from_le_bytes(bytes))
+        };
+        let version = Version {
+            major: read_u16()?,
+            minor: read_u16()?,
+            patch: read_u16()?,
+        };
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        Ok(Self {
+            version,
+            features: Features::from_bits(u32::from_le_bytes(bytes)),
+        })
+    }
+    pub fn check(&self) -> Result<(), FormatError> {
+        if !FORMAT_VERSION.reads(self.version) {
+            return Err(FormatError::IncompatibleVersion {
+                file: self.version,
+                current: FORMAT_VERSION,
+            });
+        }
+        let unsupported = self.features.unsupported();
+        if unsupported != Features::NONE {
+            return Err(FormatError::UnsupportedFeatures(unsupported));
+        }
+        Ok(())
+    }
+}
+
+impl Closure {
+    /// the features the closure and the closures nested in it use
+    pub fn features(&self) -> Features {
+        let mut features = if self.upvalues.is_empty() {
+            Features::NONE
+        } else {
+            Features::UPVALUE
+        };
+        for bytecode in &self.code {
+            features = features
+                | match bytecode {
+                    ByteCode::ScratchVector { .. } | ByteCode::ScratchTuple { .. } => {
+                        Features::SCRATCH
+                    }
+                    ByteCode::Defer { .. } => Features::DEFER,
+                    ByteCode::Open { .. } | ByteCode::Close { .. } => Features::CLOSE,
+                    ByteCode::Freeze { .. } => Features::FREEZE,
+                    ByteCode::Intrinsic { .. } => Features::INTRINSIC,
+                    ByteCode::Copy { .. } => Features::COPY,
+                    ByteCode::Detach { .. } => Features::UPVALUE,
+                    ByteCode::Import { .. } => Features::IMPORT,
+                    ByteCode::Try { .. } | ByteCode::EndTry => Features::TRY,
+                    _ => Features::NONE,
+                };
+        }
+        self.closures
+            .iter()
+            .fold(features, |features, closure| features | closure.features())
+    }
+}
+
+impl Closure {
+    /// checks that the registers, constants, upvalues, closures and addresses the code
+    /// refers to exist and that it can't run past its end, so corrupt bytecode gets
+    /// rejected instead of crashing the interpreter. `enclosing` is the closure creating
+    /// this one, which its captures refer to
+    pub fn verify(&self, enclosing: Option<&Closure>) -> Result<(), FormatError> {
+        let name = self.name.as_deref().unwrap_or("<main>");
+        let fail = |err: String| corrupt(format!("{err} in {name}"));
+        if self.lines.len() != self.code.len() {
+            return Err(fail(format!(
+                "{} lines for {} instructions",
+                self.lines.len(),
+                self.code.len()
+            )));
+        }
+        if self.varargs && self.parameters == 0 {
+            return Err(fail("varargs without a parameter".into()));
+        }
+        if !matches!(
+            self.code.last(),
+            Some(ByteCode::Return { .. } | ByteCode::Jump { .. })
+        ) {
+            return Err(fail("code running past its end".into()));
+        }
+        for upvalue in &self.upvalues {
+            let captured = match (upvalue.capture, enclosing) {
+                (Capture::Register(reg), Some(enclosing)) => reg < enclosing.registers,
+                (Capture::Upvalue(idx), Some(enclosing)) => {
+                    (idx as usize) < enclosing.upvalues.len()
+                }
+                // nothing encloses the program's closure
+                (_, None) => false,
+            };
+            if !captured {
+                return Err(fail(format!("capture of {:?} out of range", upvalue.name)));
+            }
+        }
+        for (addr, bytecode) in self.code.iter().enumerate() {
+            self.verify_operands(*bytecode)
+                .map_err(|err| fail(format!("{err} at [{addr:04}]")))?;
+        }
+        for closure in &self.closures {
+            closure.verify(Some(self))?;
+        }
+        Ok(())
+    }
+    fn verify_operands(&self, bytecode: ByteCode) -> Result<(), String> {
+        let register = |reg: u8| match reg < self.registers {
+            true => Ok(()),
+            false => Err(format!("register {reg} out of range")),
+        };
+        let registers = |start: u8, amount: u8| match start as usize + amount as usize
+            <= self.registers as usize
+        {
+            true => Ok(()),
+            false => Err(format!("registers {start}+{amount} out of range")),
+        };
+        let constant = |addr: u16| match self.constants.get(addr as usize) {
+            Some(_) => Ok(()),
+            None => Err(format!("constant {addr} out of range")),
+        };
+        let name = |addr: u16| match self.constants.get(addr as usize) {
+            Some(Value::String(_)) => Ok(()),
+            _ => Err(format!("constant {addr} isn't a name")),
+        };
+        let upvalue = |addr: u16| match (addr as usize) < self.upvalues.len() {
+            true => Ok(()),
+            false => Err(format!("upvalue {addr} out of range")),
+        };
+        let source = |src: Source| match src {
+            Source::Register(reg) => register(reg),
+            Source::Global(addr) => name(addr),
+            Source::Constant(addr) => constant(addr),
+            Source::Upvalue(addr) => upvalue(addr),
+            Source::Null | Source::Bool(_) | Source::Char(_) | Source::Int(_) => Ok(()),
+            Source::Float(_) => Ok(()),
+        };
+        let location = |dst: Location| match dst {
+            Location::Register(reg) => register(reg),
+            Location::Global(addr) => name(addr),
+            Location::Upvalue(addr) => upvalue(addr),
+        };
+        let jump = |addr: usize| match addr < self.code.len() {
+            true => Ok(()),
+            false => Err(format!("jump to [{addr:04}] out of range")),
+        };
+        match bytecode {
+            ByteCode::None | ByteCode::EndTry => Ok(()),
+            ByteCode::Jump { addr } => jump(addr),
+            ByteCode::JumpIf {
+                cond: src, addr, ..
+            }
+            | ByteCode::JumpIfSome { src, addr, .. } => {
+                source(src)?;
+                jump(addr)
+            }
+            ByteCode::Call {
+                dst,
+                func,
+                start,
+                amount,
+            } => {
+                dst.map_or(Ok(()), location)?;
+                source(func)?;
+                registers(start, amount)
+            }
+            ByteCode::Intrinsic {
+                dst, start, amount, ..
+            } => {
+                dst.map_or(Ok(()), location)?;
+                registers(start, amount)
+            }
+            ByteCode::CallSpread { dst, func, args } => {
+                dst.map_or(Ok(()), location)?;
+                source(func)?;
+                source(args)
+            }
+            ByteCode::Return { src } => src.map_or(Ok(()), source),
+            ByteCode::Move { dst, src } | ByteCode::Copy { dst, src } => {
+                location(dst)?;
+                source(src)
+            }
+            ByteCode::Field { dst, head, field } => {
+                location(dst)?;
+                source(head)?;
+                source(field)
+            }
+            ByteCode::SetField { head, field, src } => {
+                source(head)?;
+                source(field)?;
+                source(src)
+            }
+            ByteCode::Extend { head, src } => {
+                source(head)?;
+                source(src)
+            }
+            ByteCode::Freeze { src } | ByteCode::Open { src } | ByteCode::Close { src } => {
+                source(src)
+            }
+            ByteCode::Defer {
+                func,
+                start,
+                amount,
+            } => {
+                source(func)?;
+                registers(start, amount)
+            }
+            ByteCode::Try { addr, dst } => {
+                location(dst)?;
+                jump(addr)
+            }
+            ByteCode::Detach { start, amount } => registers(start, amount),
+            ByteCode::Unpack { start, amount, src } => {
+                registers(start, amount)?;
+                source(src)
+            }
+            ByteCode::Vector { dst, start, amount }
+            | ByteCode::Tuple { dst, start, amount }
+            | ByteCode::ScratchVector { dst, start, amount }
+            | ByteCode::ScratchTuple { dst, start, amount } => {
+                location(dst)?;
+                registers(start, amount)
+            }
+            ByteCode::Map { dst } => location(dst),
+            ByteCode::Fn { dst, addr } => {
+                location(dst)?;
+                match self.closures.get(addr as usize) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("closure {addr} out of range")),
+                }
+            }
+            ByteCode::Import { dst, path } => {
+                location(dst)?;
+                name(path)
+            }
+            ByteCode::Binary {
+                dst, left, right, ..
+            } => {
+                location(dst)?;
+                source(left)?;
+                source(right)
+            }
+            ByteCode::Unary { dst, right, .. } => {
+                location(dst)?;
+                source(right)
+            }
+        }
+    }
+}
+
+/// writes `closure` as a `.hydc` file: its header followed by the closure, with the nested
+/// closures, constants, line table, locals and upvalues
+pub fn write_program(closure: &Closure, writer: &mut impl Write) -> io::Result<()> {
+    Header::new(closure).write(writer)?;
+    closure.encode(writer)
+}
+/// reads a `.hydc` file written by `write_program`, rejecting it before decoding anything
+/// if its header says this build can't run it, and after decoding if the code doesn't
+/// pass `Closure::verify`
+pub fn read_program(reader: &mut impl Read) -> Result<Closure, FormatError> {
+    let header = Header::read(reader)?;
+    header.check()?;
+    let closure = Closure::decode(reader)?;
+    closure.verify(None)?;
+    Ok(closure)
+}
+
+/// integers are little-endian, lengths and addresses are u32s and options and enums start
+/// with a tag byte
+trait Encode {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()>;
+}
+trait Decode: Sized {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError>;
+}
+fn corrupt(err: impl Display) -> FormatError {
+    FormatError::Corrupt(err.to_string())
+}
+fn unencodable(err: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+}
+
+macro_rules! number_encoding {
+    ($($typ:ty),*) => {
+        $(
+            impl Encode for $typ {
+                fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+            }
+            impl Decode for $typ {
+                fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+                    let mut bytes = [0; std::mem::size_of::<$typ>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$typ>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+number_encoding!(u8, u16, u32, i64, f64);
+impl Encode for usize {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        u32::try_from(*self)
+            .map_err(|_| unencodable(format!("{self} doesn't fit the format")))?
+            .encode(writer)
+    }
+}
+impl Decode for usize {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        Ok(u32::decode(reader)? as usize)
+    }
+}
+impl Encode for bool {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u8).encode(writer)
+    }
+}
+impl Decode for bool {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(corrupt(format!("invalid bool {byte}"))),
+        }
+    }
+}
+impl Encode for char {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u32).encode(writer)
+    }
+}
+impl Decode for char {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let code = u32::decode(reader)?;
+        char::from_u32(code).ok_or_else(|| corrupt(format!("invalid char {code:#x}")))
+    }
+}
+impl Encode for String {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.len().encode(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+}
+impl Decode for String {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let len = usize::decode(reader)?;
+        let mut bytes = vec![];
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Err(FormatError::Truncated);
+        }
+        String::from_utf8(bytes).map_err(corrupt)
+    }
+}
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            None => false.encode(writer),
+            Some(value) => {
+                true.encode(writer)?;
+                value.encode(writer)
+            }
+        }
+    }
+}
+impl<T: Decode> Decode for Option<T> {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        Ok(if bool::decode(reader)? {
+            Some(T::decode(reader)?)
+        } else {
+            None
+        })
+    }
+}
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.len().encode(writer)?;
+        self.iter().try_for_each(|value| value.encode(writer))
+    }
+}
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        // the length isn't trusted with an allocation, a truncated file fails on its own
+        (0..usize::decode(reader)?)
+            .map(|_| T::decode(reader))
+            .collect()
+    }
+}
+impl<T: Encode> Encode for Rc<T> {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.as_ref().encode(writer)
+    }
+}
+impl<T: Decode> Decode for Rc<T> {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        T::decode(reader).map(Rc::new)
+    }
+}
+
+impl Encode for Closure {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.path.encode(writer)?;
+        self.name.encode(writer)?;
+        self.parameters.encode(writer)?;
+        self.registers.encode(writer)?;
+        self.varargs.encode(writer)?;
+        self.code.encode(writer)?;
+        self.lines.encode(writer)?;
+        self.constants.encode(writer)?;
+        self.locals.encode(writer)?;
+        self.upvalues.encode(writer)?;
+        self.closures.encode(writer)
+    }
+}
+impl Decode for Closure {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let closure = Self {
+            path: Decode::decode(reader)?,
+            name: Decode::decode(reader)?,
+            parameters: Decode::decode(reader)?,
+            registers: Decode::decode(reader)?,
+            varargs: Decode::decode(reader)?,
+            code: Decode::decode(reader)?,
+            lines: Decode::decode(reader)?,
+            constants: Decode::decode(reader)?,
+            locals: Decode::decode(reader)?,
+            upvalues: Decode::decode(reader)?,
+            closures: Decode::decode(reader)?,
+        };
+        if closure.lines.len() != closure.code.len() {
+            return Err(corrupt("the line table doesn't match the code"));
+        }
+        Ok(closure)
+    }
+}
+impl Encode for Local {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.name.encode(writer)?;
+        self.register.encode(writer)?;
+        self.start.encode(writer)?;
+        self.end.encode(writer)
+    }
+}
+impl Decode for Local {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        Ok(Self {
+            name: Decode::decode(reader)?,
+            register: Decode::decode(reader)?,
+            start: Decode::decode(reader)?,
+            end: Decode::decode(reader)?,
+        })
+    }
+}
+impl Encode for Upvalue {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.name.encode(writer)?;
+        match self.capture {
+            Capture::Register(reg) => {
+                0u8.encode(writer)?;
+                reg.encode(writer)
+            }
+            Capture::Upvalue(idx) => {
+                1u8.encode(writer)?;
+                idx.encode(writer)
+            }
+        }
+    }
+}
+impl Decode for Upvalue {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let name = Decode::decode(reader)?;
+        let capture = match u8::decode(reader)? {
+            0 => Capture::Register(Decode::decode(reader)?),
+            1 => Capture::Upvalue(Decode::decode(reader)?),
+            tag => return Err(corrupt(format!("invalid capture {tag}"))),
+        };
+        Ok(Self { name, capture })
+    }
+}
+/// only the values the compiler puts in the constant table can be written
+impl Encode for Value {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Value::Null => 0u8.encode(writer),
+            Value::Int(v) => {
+                1u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Value::Float(v) => {
+                2u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Value::Bool(v) => {
+                3u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Value::Char(v) => {
+                4u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Value::String(v) => {
+                5u8.encode(writer)?;
+                v.encode(writer)
+            }
+            value => Err(unencodable(format!(
+                "can't write a {} constant",
+                value.typ()
+            ))),
+        }
+    }
+}
+impl Decode for Value {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        Ok(match u8::decode(reader)? {
+            0 => Value::Null,
+            1 => Value::Int(Decode::decode(reader)?),
+            2 => Value::Float(Decode::decode(reader)?),
+            3 => Value::Bool(Decode::decode(reader)?),
+            4 => Value::Char(Decode::decode(reader)?),
+            5 => Value::String(Decode::decode(reader)?),
+            tag => return Err(corrupt(format!("invalid constant {tag}"))),
+        })
+    }
+}
+impl Encode for Source {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        match *self {
+            Source::Null => 0u8.encode(writer),
+            Source::Bool(v) => {
+                1u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Source::Char(v) => {
+                2u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Source::Int(v) => {
+                3u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Source::Float(v) => {
+                4u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Source::Register(v) => {
+                5u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Source::Global(v) => {
+                6u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Source::Constant(v) => {
+                7u8.encode(writer)?;
+                v.encode(writer)
+            }
+            Source::Upvalue(v) => {
+                8u8.encode(writer)?;
+                v.encode(writer)
+            }
+        }
+    }
+}
+impl Decode for Source {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        Ok(match u8::decode(reader)? {
+            0 => Source::Null,
+            1 => Source::Bool(Decode::decode(reader)?),
+            2 => Source::Char(Decode::decode(reader)?),
+            3 => Source::Int(Decode::decode(reader)?),
+            4 => Source::Float(Decode::decode(reader)?),
+            5 => Source::Register(Decode::decode(reader)?),
+            6 => Source::Global(Decode::decode(reader)?),
+            7 => Source::Constant(Decode::decode(reader)?),
+            8 => Source::Upvalue(Decode::decode(reader)?),
+            tag => return Err(corrupt(format!("invalid source {tag}"))),
+        })
+    }
+}
+impl Encode for Location {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        Source::from(*self).encode(writer)
+    }
+}
+impl Decode for Location {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        match Source::decode(reader)? {
+            Source::Register(v) => Ok(Location::Register(v)),
+            Source::Global(v) => Ok(Location::Global(v)),
+            Source::Upvalue(v) => Ok(Location::Upvalue(v)),
+            src => Err(corrupt(format!("{src:?} isn't a location"))),
+        }
+    }
+}
+impl Encode for Intrinsic {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        self.name().to_string().encode(writer)
+    }
+}
+impl Decode for Intrinsic {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let name = String::decode(reader)?;
+        Intrinsic::from_name(&name).ok_or_else(|| corrupt(format!("unknown intrinsic {name:?}")))
+    }
+}
+const BINARY_OPERATIONS: [BinaryOperation; 19] = [
+    BinaryOperation::Add,
+    BinaryOperation::AddAssign,
+    BinaryOperation::Sub,
+    BinaryOperation::Mul,
+    BinaryOperation::Div,
+    BinaryOperation::Mod,
+    BinaryOperation::Pow,
+    BinaryOperation::EE,
+    BinaryOperation::NE,
+    BinaryOperation::LT,
+    BinaryOperation::GT,
+    BinaryOperation::LE,
+    BinaryOperation::GE,
+    BinaryOperation::And,
+    BinaryOperation::Or,
+    BinaryOperation::Is,
+    BinaryOperation::In,
+    BinaryOperation::As,
+    BinaryOperation::Coalesce,
+];
+impl Encode for BinaryOperation {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u8).encode(writer)
+    }
+}
+impl Decode for BinaryOperation {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let op = u8::decode(reader)?;
+        BINARY_OPERATIONS
+            .get(op as usize)
+            .copied()
+            .ok_or_else(|| corrupt(format!("invalid binary operation {op}")))
+    }
+}
+impl Encode for UnaryOperation {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        (*self as u8).encode(writer)
+    }
+}
+impl Decode for UnaryOperation {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        match u8::decode(reader)? {
+            0 => Ok(UnaryOperation::Neg),
+            1 => Ok(UnaryOperation::Not),
+            op => Err(corrupt(format!("invalid unary operation {op}"))),
+        }
+    }
+}
+/// writes the operands of an instruction in order after its opcode
+macro_rules! encode_operands {
+    ($writer:ident: $opcode:literal $(, $operand:expr)*) => {{
+        ($opcode as u8).encode($writer)?;
+        $($operand.encode($writer)?;)*
+        Ok(())
+    }};
+}
+impl Encode for ByteCode {
+    fn encode(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            ByteCode::None => encode_operands!(writer: 0),
+            ByteCode::Jump { addr } => encode_operands!(writer: 1, addr),
+            ByteCode::JumpIf {
+                negative,
+                cond,
+                addr,
+            } => encode_operands!(writer: 2, negative, cond, addr),
+            ByteCode::JumpIfSome {
+                negative,
+                src,
+                addr,
+            } => encode_operands!(writer: 3, negative, src, addr),
+            ByteCode::Call {
+                dst,
+                func,
+                start,
+                amount,
+            } => encode_operands!(writer: 4, dst, func, start, amount),
+            ByteCode::Intrinsic {
+                dst,
+                intrinsic,
+                start,
+                amount,
+            } => encode_operands!(writer: 5, dst, intrinsic, start, amount),
+            ByteCode::Return { src } => encode_operands!(writer: 6, src),
+            ByteCode::Move { dst, src } => encode_operands!(writer: 7, dst, src),
+            ByteCode::Copy { dst, src } => encode_operands!(writer: 8, dst, src),
+            ByteCode::Field { dst, head, field } => encode_operands!(writer: 9, dst, head, field),
+            ByteCode::SetField { head, field, src } => {
+                encode_operands!(writer: 10, head, field, src)
+            }
+            ByteCode::Freeze { src } => encode_operands!(writer: 11, src),
+            ByteCode::Defer {
+                func,
+                start,
+                amount,
+            } => encode_operands!(writer: 12, func, start, amount),
+            ByteCode::Close { src } => encode_operands!(writer: 13, src),
+            ByteCode::Detach { start, amount } => encode_operands!(writer: 14, start, amount),
+            ByteCode::Unpack { start, amount, src } => {
+                encode_operands!(writer: 15, start, amount, src)
+            }
+            ByteCode::Vector { dst, start, amount } => {
+                encode_operands!(writer: 16, dst, start, amount)
+            }
+            ByteCode::Tuple { dst, start, amount } => {
+                encode_operands!(writer: 17, dst, start, amount)
+            }
+            ByteCode::ScratchVector { dst, start, amount } => {
+                encode_operands!(writer: 18, dst, start, amount)
+            }
+            ByteCode::ScratchTuple { dst, start, amount } => {
+                encode_operands!(writer: 19, dst, start, amount)
+            }
+            ByteCode::Map { dst } => encode_operands!(writer: 20, dst),
+            ByteCode::Fn { dst, addr } => encode_operands!(writer: 21, dst, addr),
+            ByteCode::Binary {
+                op,
+                dst,
+                left,
+                right,
+            } => encode_operands!(writer: 22, op, dst, left, right),
+            ByteCode::Unary { op, dst, right } => encode_operands!(writer: 23, op, dst, right),
+            ByteCode::Import { dst, path } => encode_operands!(writer: 24, dst, path),
+            ByteCode::Extend { head, src } => encode_operands!(writer: 25, head, src),
+            ByteCode::CallSpread { dst, func, args } => {
+                encode_operands!(writer: 26, dst, func, args)
+            }
+            ByteCode::Try { addr, dst } => encode_operands!(writer: 27, addr, dst),
+            ByteCode::EndTry => encode_operands!(writer: 28),
+            ByteCode::Open { src } => encode_operands!(writer: 29, src),
+        }
+    }
+}
+impl Decode for ByteCode {
+    fn decode(reader: &mut impl Read) -> Result<Self, FormatError> {
+        let opcode = u8::decode(reader)?;
+        macro_rules! operand {
+            () => {
+                Decode::decode(reader)?
+            };
+        }
+        Ok(match opcode {
+            0 => ByteCode::None,
+            1 => ByteCode::Jump { addr: operand!() },
+            2 => ByteCode::JumpIf {
+                negative: operand!(),
+                cond: operand!(),
+                addr: operand!(),
+            },
+            3 => ByteCode::JumpIfSome {
+                negative: operand!(),
+                src: operand!(),
+                addr: operand!(),
+            },
+            4 => ByteCode::Call {
+                dst: operand!(),
+                func: operand!(),
+                start: operand!(),
+                amount: operand!(),
+            },
+            5 => ByteCode::Intrinsic {
+                dst: operand!(),
+                intrinsic: operand!(),
+                start: operand!(),
+                amount: operand!(),
+            },
+            6 => ByteCode::Return { src: operand!() },
+            7 => ByteCode::Move {
+                dst: operand!(),
+                src: operand!(),
+            },
+            8 => ByteCode::Copy {
+                dst: operand!(),
+                src: operand!(),
+            },
+            9 => ByteCode::Field {
+                dst: operand!(),
+                head: operand!(),
+                field: operand!(),
+            },
+            10 => ByteCode::SetField {
+                head: operand!(),
+                field: operand!(),
+                src: operand!(),
+            },
+            11 => ByteCode::Freeze { src: operand!() },
+            12 => ByteCode::Defer {
+                func: operand!(),
+                start: operand!(),
+                amount: operand!(),
+            },
+            13 => ByteCode::Close { src: operand!() },
+            14 => ByteCode::Detach {
+                start: operand!(),
+                amount: operand!(),
+            },
+            15 => ByteCode::Unpack {
+                start: operand!(),
+                amount: operand!(),
+                src: operand!(),
+            },
+            16 => ByteCode::Vector {
+                dst: operand!(),
+                start: operand!(),
+                amount: operand!(),
+            },
+            17 => ByteCode::Tuple {
+                dst: operand!(),
+                start: operand!(),
+                amount: operand!(),
+            },
+            18 => ByteCode::ScratchVector {
+                dst: operand!(),
+                start: operand!(),
+                amount: operand!(),
+            },
+            19 => ByteCode::ScratchTuple {
+                dst: operand!(),
+                start: operand!(),
+                amount: operand!(),
+            },
+            20 => ByteCode::Map { dst: operand!() },
+            21 => ByteCode::Fn {
+                dst: operand!(),
+                addr: operand!(),
+            },
+            22 => ByteCode::Binary {
+                op: operand!(),
+                dst: operand!(),
+                left: operand!(),
+                right: operand!(),
+            },
+            23 => ByteCode::Unary {
+                op: operand!(),
+                dst: operand!(),
+                right: operand!(),
+            },
+            24 => ByteCode::Import {
+                dst: operand!(),
+                path: operand!(),
+            },
+            25 => ByteCode::Extend {
+                head: operand!(),
+                src: operand!(),
+            },
+            26 => ByteCode::CallSpread {
+                dst: operand!(),
+                func: operand!(),
+                args: operand!(),
+            },
+            27 => ByteCode::Try {
+                addr: operand!(),
+                dst: operand!(),
+            },
+            28 => ByteCode::EndTry,
+            29 => ByteCode::Open { src: operand!() },
+            opcode => return Err(corrupt(format!("unknown instruction {opcode:#x}"))),
+        })
+    }
+}
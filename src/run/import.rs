@@ -0,0 +1,81 @@
+#[cfg(feature = "std_hydra")]
+use super::value::Function;
+use super::{
+    interpreter::{Interpreter, RunTimeError, RunTimeErrorKind},
+    trust::Capability,
+    value::Value,
+};
+#[cfg(feature = "std_hydra")]
+use crate::{compile, pkg, scan::ast::Chunk};
+#[cfg(feature = "std_hydra")]
+use std::{fs, path::Path, rc::Rc};
+
+#[cfg(feature = "std_hydra")]
+impl Interpreter {
+    /// the value of the module `name`, see `pkg::resolve_import`. the module gets compiled
+    /// and run on its first import, later imports of the same file get the cached value
+    pub fn import(&mut self, name: &str, ln: usize) -> Result<Value, RunTimeError> {
+        self.check_capability(Capability::Import)
+            .map_err(|err| RunTimeError { err, ln })?;
+        let error = |err: String| RunTimeError {
+            err: RunTimeErrorKind::Custom(err),
+            ln,
+        };
+        // paths are relative to the importing file, or the working directory for scripts
+        // without one
+        let dir = self
+            .call_frame()
+            .and_then(|call_frame| call_frame.closure.path.as_deref())
+            .and_then(|path| Path::new(path).parent())
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let mut dirs = vec![dir];
+        dirs.extend(self.module_dirs.iter().cloned());
+        let path = pkg::resolve_import(name, &dirs)
+            .ok_or_else(|| error(format!("no module named {name:?}")))?;
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        match self.modules.get(&key) {
+            Some(Some(value)) => return Ok(value.clone()),
+            Some(None) => return Err(error(format!("module {name:?} is part of an import cycle"))),
+            None => {}
+        }
+        let path = path.display().to_string();
+        let text = fs::read_to_string(&path).map_err(|err| error(format!("{path}: {err}")))?;
+        let closure = compile::<Chunk>(&text, Some(path.clone())).map_err(|err| {
+            error(format!(
+                "{path}:{}:{}: {}",
+                err.pos.ln.start + 1,
+                err.pos.col.start + 1,
+                err.value
+            ))
+        })?;
+        self.modules.insert(key.clone(), None);
+        let function = Function {
+            closure: Rc::new(closure),
+            upvalues: vec![],
+        };
+        let value = self
+            .call(&function, &[], None)
+            .and_then(|_| self.run())
+            .inspect_err(|_| {
+                self.modules.remove(&key);
+            })?
+            .unwrap_or_default();
+        self.modules.insert(key, Some(value.clone()));
+        Ok(value)
+    }
+}
+#[cfg(not(feature = "std_hydra"))]
+impl Interpreter {
+    /// the core has no file system to load modules from, so every import fails
+    pub fn import(&mut self, name: &str, ln: usize) -> Result<Value, RunTimeError> {
+        self.check_capability(Capability::Import)
+            .map_err(|err| RunTimeError { err, ln })?;
+        Err(RunTimeError {
+            err: RunTimeErrorKind::Custom(format!(
+                "no module named {name:?}, importing files needs the std_hydra feature"
+            )),
+            ln,
+        })
+    }
+}
@@ -1,13 +1,23 @@
 use super::{
-    code::{BinaryOperation, ByteCode, Closure, Location, Source, UnaryOperation},
-    value::{FnKind, Function, Pointer, Value},
+    code::{
+        BinaryOperation, ByteCode, Capture, Closure, Intrinsic, Location, Source, UnaryOperation,
+    },
+    error::ErrorObject,
+    replay::Replay,
+    trust::{Capability, TrustLevel},
+    types,
+    value::{resolve_index, FnKind, FromValue, Function, Map, NativeFn, Pointer, Value},
 };
 use std::{
-    collections::HashMap,
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
     error::Error,
-    fmt::Display,
-    rc::Rc,
-    sync::{Arc, Mutex},
+    fmt::{Debug, Display},
+    io::{self, Write},
+    ops::BitOr,
+    path::PathBuf,
+    rc::{self, Rc},
+    sync::{Arc, Mutex, Weak},
 };
 
 pub const INT_MODULE: &str = "__int";
@@ -18,11 +28,175 @@ pub const STRING_MODULE: &str = "__string";
 pub const VECTOR_MODULE: &str = "__vector";
 pub const TUPLE_MODULE: &str = "__tuple";
 pub const MAP_MODULE: &str = "map";
+/// the global holding the path of the file the code reading it is in, unless a script
+/// sets a global of that name itself
+pub const FILE_GLOBAL: &str = "__file";
+/// like `FILE_GLOBAL`, but the line of the code reading it
+pub const LINE_GLOBAL: &str = "__line";
+/// how many finished frames are kept around per register count
+pub const FRAME_POOL_LIMIT: usize = 64;
+/// how many characters of a value `dump_state` writes
+pub const DUMP_VALUE_WIDTH: usize = 60;
+/// how many frames the call stack may hold when `max_call_depth` isn't set, so runaway
+/// recursion fails with `StackOverflow` instead of eating up the host's memory
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+/// how many `run`s natives like `pcall` or `map` may nest by calling back into scripts,
+/// since each of them takes up space on the host's stack
+pub const MAX_NESTED_RUNS: usize = 200;
 
 #[derive(Debug, Default)]
 pub struct Interpreter {
     pub call_stack: Vec<CallFrame>,
     pub globals: HashMap<String, Pointer<Value>>,
+    /// globals that only get built once something reads them, like the modules of the
+    /// standard library, with the function setting them. see `set_lazy_global`
+    pub lazy_globals: HashMap<String, fn(&mut Interpreter)>,
+    /// argument buffers reused across calls, one per nested native call
+    pub arg_buffers: Vec<Vec<Value>>,
+    /// finished call frames keyed by their closure's register count
+    pub frame_pool: HashMap<u8, Vec<CallFrame>>,
+    /// functions invoked by `value as "typ"` for type names registered with `set_conversion`
+    pub conversions: HashMap<String, Value>,
+    /// containers marked immutable by `freeze`, keyed by their allocation; the weak handle
+    /// keeps the allocation from being reused while the entry exists
+    pub frozen: HashMap<usize, Weak<dyn Any>>,
+    /// the names natives got set as globals under, like "math.log" for the ones in a
+    /// module, keyed by their allocation like `frozen`
+    pub native_names: HashMap<usize, (rc::Weak<NativeFn>, String)>,
+    /// gives containers value semantics one level deep: `Copy` instructions emitted for
+    /// bindings, assignments and call arguments copy the top level of a vector, tuple or map
+    /// right away instead of sharing it. that costs time linear in its length on every
+    /// copy, and containers nested in it are still shared
+    pub copy_containers: bool,
+    /// makes arithmetic and comparisons between an int and a float an error instead of
+    /// converting the int, so precision loss needs an explicit `as`
+    pub strict_arithmetic: bool,
+    /// lets integral floats like `2.0` index vectors, tuples and strings, other floats
+    /// still error
+    pub float_indices: bool,
+    /// reading an undefined global prints a warning and gives null instead of erroring,
+    /// meant for the REPL
+    pub warn_undefined_globals: bool,
+    /// how many more instructions may run before the script gets stopped with
+    /// `BudgetExceeded`, unlimited if unset
+    pub fuel: Option<u64>,
+    /// how many frames the call stack may hold before calls fail with `StackOverflow`,
+    /// `DEFAULT_MAX_CALL_DEPTH` if unset
+    pub max_call_depth: Option<usize>,
+    /// how many calls of `run` are running, see `MAX_NESTED_RUNS`
+    pub nested_runs: usize,
+    /// how many frames were below the one a `run_for` that ran out of instructions started
+    /// on, so the next `run_for` continues it
+    pub paused_run: Option<usize>,
+    /// which groups of natives scripts may use, see `set_trust_level`
+    pub trust_level: TrustLevel,
+    pub hook: Hook,
+    /// where `print`, `write` and `debug` send their text, stdout if unset
+    pub output: Output,
+    pub replay: Replay,
+    /// host state for natives, one value per type, see `insert_app_data`
+    pub app_data: HashMap<TypeId, Box<dyn Any>>,
+    /// names of the modules registered with `load_module`
+    pub loaded_modules: HashSet<String>,
+    /// where `import` looks for modules after the importing file's directory, usually
+    /// `pkg::module_dirs` of the project
+    pub module_dirs: Vec<PathBuf>,
+    /// the values of the scripts `import` ran, keyed by their canonical path. a module
+    /// that's still running maps to `None`
+    pub modules: HashMap<PathBuf, Option<Value>>,
+    /// shared objects modules got loaded from, kept open for as long as their functions
+    /// can be called. the field comes last so it gets dropped after the globals, and
+    /// isn't behind the `plugins` feature so the layout doesn't depend on it
+    pub libraries: Vec<Box<dyn Any>>,
+}
+/// the settings `Interpreter::new` starts an interpreter with, see the fields of the same
+/// names on `Interpreter`
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterOptions {
+    pub copy_containers: bool,
+    pub strict_arithmetic: bool,
+    pub float_indices: bool,
+    pub warn_undefined_globals: bool,
+    pub fuel: Option<u64>,
+    pub max_call_depth: Option<usize>,
+    pub trust_level: TrustLevel,
+}
+/// the events a hook set with `Interpreter::set_hook` gets called for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HookMask(u8);
+impl HookMask {
+    pub const NONE: Self = Self(0);
+    pub const INSTRUCTION: Self = Self(1);
+    pub const LINE: Self = Self(1 << 1);
+    pub const CALL: Self = Self(1 << 2);
+    pub const RETURN: Self = Self(1 << 3);
+    pub const ALL: Self = Self(0b1111);
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+impl BitOr for HookMask {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// before every instruction
+    Instruction,
+    /// before the first instruction of a new line
+    Line,
+    /// after entering a function, or before calling a native one
+    Call,
+    /// before returning from a function, or after a native one returned
+    Return,
+}
+impl HookEvent {
+    pub fn mask(self) -> HookMask {
+        match self {
+            HookEvent::Instruction => HookMask::INSTRUCTION,
+            HookEvent::Line => HookMask::LINE,
+            HookEvent::Call => HookMask::CALL,
+            HookEvent::Return => HookMask::RETURN,
+        }
+    }
+}
+pub type HookFn = dyn FnMut(&mut Interpreter, HookEvent, usize) -> Result<(), Box<dyn Error>>;
+#[derive(Default)]
+pub struct Hook {
+    pub mask: HookMask,
+    pub func: Option<Box<HookFn>>,
+    pub last_ln: Option<usize>,
+    /// set by `set_hook` and `clear_hook`, so a hook that replaced or cleared itself while
+    /// it ran doesn't get put back afterwards
+    pub replaced: bool,
+}
+impl Debug for Hook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hook")
+            .field("mask", &self.mask)
+            .field("last_ln", &self.last_ln)
+            .field("replaced", &self.replaced)
+            .finish_non_exhaustive()
+    }
+}
+pub type OutputFn = dyn FnMut(&str);
+#[derive(Default)]
+pub struct Output(pub Option<Box<OutputFn>>);
+impl Debug for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Output").finish_non_exhaustive()
+    }
+}
+/// how far a slice of instructions given to `Interpreter::run_for` got
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunStatus {
+    /// the frame the run started on returned, with what `run` would have given
+    Completed(Option<Value>),
+    /// the slice ran out before that, the call stack is left as it was for the next one
+    Paused,
+    Error(RunTimeError),
 }
 #[derive(Debug, Clone)]
 pub struct CallFrame {
@@ -30,6 +204,16 @@ pub struct CallFrame {
     pub closure: Rc<Closure>,
     pub stack: Vec<Pointer<Value>>,
     pub dst: Option<Location>,
+    pub scratch: HashMap<usize, Value>,
+    /// calls scheduled by `defer`, run in reverse order when the frame returns
+    pub defers: Vec<(Value, Vec<Value>)>,
+    /// the objects of the `with` statements being run, innermost last
+    pub opened: Vec<Value>,
+    /// the catch addresses and error registers of the `try` blocks being run, innermost
+    /// last, with how many objects were open when they started
+    pub handlers: Vec<(usize, Location, usize)>,
+    /// the cells of the running closure's upvalues
+    pub upvalues: Vec<Pointer<Value>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -46,21 +230,80 @@ pub enum RunTimeErrorKind {
     InvalidField {
         head: Type,
         field: Type,
+        /// a snippet of the key
+        key: String,
     },
     InvalidFieldHead(Type),
+    /// a float indexing a vector, tuple or string without `float_indices`, or a
+    /// non-integral one with it
+    FloatIndex {
+        head: Type,
+        index: f64,
+    },
     CannotCall(Type),
+    UndefinedGlobal(String),
+    /// the operands' types along with snippets of their values
     IllegalBinaryOperation {
         op: BinaryOperation,
         left: Type,
         right: Type,
+        values: (String, String),
     },
     IllegalUnaryOperation {
         op: UnaryOperation,
         right: Type,
+        value: String,
     },
     UnknownTypeCast(String),
+    /// an int and a float in one operation with `strict_arithmetic` on
+    MixedArithmetic {
+        op: BinaryOperation,
+        left: Type,
+        right: Type,
+    },
+    Frozen(Type),
+    /// a write into a value that can't change, like a tuple
+    Immutable(Type),
+    /// a spread of `src` in a literal of `head`
+    CannotSpread {
+        head: Type,
+        src: Type,
+    },
+    /// the interpreter ran out of `fuel`
+    BudgetExceeded,
+    /// a call nested deeper than the interpreter's `max_call_depth`
+    StackOverflow {
+        depth: usize,
+    },
+    /// a native the interpreter's trust level doesn't allow
+    Forbidden {
+        capability: Capability,
+        level: TrustLevel,
+    },
+    /// an error returned by a native with a name, see `Interpreter::name_natives`
+    Native {
+        name: String,
+        msg: String,
+    },
+    /// an error value raised by a native, like the ones `error` creates
+    Thrown {
+        msg: String,
+        value: Value,
+    },
     Custom(String),
 }
+/// returned by natives to raise `value` itself instead of just a message
+#[derive(Debug, Clone)]
+pub struct Thrown {
+    pub msg: String,
+    pub value: Value,
+}
+impl Display for Thrown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+impl Error for Thrown {}
 pub type Type = &'static str;
 impl Display for RunTimeErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -68,26 +311,105 @@ impl Display for RunTimeErrorKind {
             RunTimeErrorKind::IndexOutOfRange { index, len } => {
                 write!(f, "index {index} is out of range of {len}")
             }
-            RunTimeErrorKind::InvalidField { head, field } => {
-                write!(f, "invalid field operation on {head} with {field}")
+            RunTimeErrorKind::InvalidField { head, field, key } => {
+                write!(f, "invalid field operation on {head} with {field} {key}")
             }
             RunTimeErrorKind::InvalidFieldHead(typ) => write!(f, "can't field into {typ}"),
+            RunTimeErrorKind::FloatIndex { head, index } if index.fract() == 0.0 => write!(
+                f,
+                "can't index {head} with float {index:?}, convert it with `as \"int\"`"
+            ),
+            RunTimeErrorKind::FloatIndex { head, index } => {
+                write!(
+                    f,
+                    "can't index {head} with float {index:?}, it isn't a whole number"
+                )
+            }
             RunTimeErrorKind::CannotCall(typ) => write!(f, "can't call {typ}"),
-            RunTimeErrorKind::IllegalBinaryOperation { op, left, right } => {
+            RunTimeErrorKind::UndefinedGlobal(name) => write!(f, "undefined global {name:?}"),
+            RunTimeErrorKind::IllegalBinaryOperation {
+                op,
+                left,
+                right,
+                values: (left_value, right_value),
+            } => {
                 write!(
                     f,
-                    "illegal binary operation {:?} on {left} with {right}",
+                    "illegal binary operation {:?} on {left} {left_value} with {right} {right_value}",
                     op.to_string()
                 )
             }
-            RunTimeErrorKind::IllegalUnaryOperation { op, right } => {
-                write!(f, "illegal unary operation {:?} on {right}", op.to_string())
+            RunTimeErrorKind::IllegalUnaryOperation { op, right, value } => {
+                write!(
+                    f,
+                    "illegal unary operation {:?} on {right} {value}",
+                    op.to_string()
+                )
+            }
+            RunTimeErrorKind::UnknownTypeCast(typ) => {
+                write!(f, "unknown type to cast to {typ:?}")?;
+                match types::suggest(typ, types::CASTS) {
+                    Some(known) => write!(f, ", did you mean {known:?}?"),
+                    None => Ok(()),
+                }
+            }
+            RunTimeErrorKind::MixedArithmetic { op, left, right } => write!(
+                f,
+                "mixed {left} and {right} in {:?}, cast one of them with `as`",
+                op.to_string()
+            ),
+            RunTimeErrorKind::Frozen(typ) => write!(f, "can't mutate frozen {typ}"),
+            RunTimeErrorKind::Immutable(typ) => write!(f, "can't mutate immutable {typ}"),
+            RunTimeErrorKind::CannotSpread { head, src } => {
+                write!(f, "can't spread {src} into {head}")
+            }
+            RunTimeErrorKind::BudgetExceeded => write!(f, "ran out of fuel"),
+            RunTimeErrorKind::StackOverflow { depth } => {
+                write!(f, "stack overflow, calls nested deeper than {depth}")
             }
-            RunTimeErrorKind::UnknownTypeCast(typ) => write!(f, "unknown type to cast to {typ:?}"),
+            RunTimeErrorKind::Forbidden { capability, level } => {
+                write!(f, "{capability} isn't allowed at the {level} trust level")
+            }
+            RunTimeErrorKind::Native { name, msg } => write!(f, "{name}: {msg}"),
+            RunTimeErrorKind::Thrown { msg, value: _ } => write!(f, "{msg}"),
             RunTimeErrorKind::Custom(err) => write!(f, "{err}"),
         }
     }
 }
+impl RunTimeErrorKind {
+    /// the variant's name, for tools that tell errors apart
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunTimeErrorKind::IndexOutOfRange { .. } => "IndexOutOfRange",
+            RunTimeErrorKind::InvalidField { .. } => "InvalidField",
+            RunTimeErrorKind::InvalidFieldHead(_) => "InvalidFieldHead",
+            RunTimeErrorKind::FloatIndex { .. } => "FloatIndex",
+            RunTimeErrorKind::CannotCall(_) => "CannotCall",
+            RunTimeErrorKind::UndefinedGlobal(_) => "UndefinedGlobal",
+            RunTimeErrorKind::IllegalBinaryOperation { .. } => "IllegalBinaryOperation",
+            RunTimeErrorKind::IllegalUnaryOperation { .. } => "IllegalUnaryOperation",
+            RunTimeErrorKind::UnknownTypeCast(_) => "UnknownTypeCast",
+            RunTimeErrorKind::MixedArithmetic { .. } => "MixedArithmetic",
+            RunTimeErrorKind::Frozen(_) => "Frozen",
+            RunTimeErrorKind::Immutable(_) => "Immutable",
+            RunTimeErrorKind::CannotSpread { .. } => "CannotSpread",
+            RunTimeErrorKind::BudgetExceeded => "BudgetExceeded",
+            RunTimeErrorKind::StackOverflow { .. } => "StackOverflow",
+            RunTimeErrorKind::Forbidden { .. } => "Forbidden",
+            RunTimeErrorKind::Native { .. } => "Native",
+            RunTimeErrorKind::Thrown { .. } => "Thrown",
+            RunTimeErrorKind::Custom(_) => "Custom",
+        }
+    }
+    pub fn illegal_binary(op: BinaryOperation, left: &Value, right: &Value) -> Self {
+        Self::IllegalBinaryOperation {
+            op,
+            left: left.typ(),
+            right: right.typ(),
+            values: (left.snippet(), right.snippet()),
+        }
+    }
+}
 impl Error for RunTimeErrorKind {}
 impl Display for RunTimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -97,6 +419,28 @@ impl Display for RunTimeError {
 impl Error for RunTimeError {}
 
 impl Interpreter {
+    pub fn new(options: InterpreterOptions) -> Self {
+        Self {
+            copy_containers: options.copy_containers,
+            strict_arithmetic: options.strict_arithmetic,
+            float_indices: options.float_indices,
+            warn_undefined_globals: options.warn_undefined_globals,
+            fuel: options.fuel,
+            max_call_depth: options.max_call_depth,
+            trust_level: options.trust_level,
+            ..Default::default()
+        }
+    }
+    /// an interpreter that stops scripts with `BudgetExceeded` once they ran
+    /// `max_instructions` instructions and with `StackOverflow` once they nest more than
+    /// `max_call_depth` calls
+    pub fn with_limits(max_instructions: u64, max_call_depth: usize) -> Self {
+        Self::new(InterpreterOptions {
+            fuel: Some(max_instructions),
+            max_call_depth: Some(max_call_depth),
+            ..Default::default()
+        })
+    }
     pub fn call_frame(&self) -> Option<&CallFrame> {
         self.call_stack.last()
     }
@@ -128,7 +472,45 @@ impl Interpreter {
                 .constants
                 .get(addr as usize)
                 .cloned(),
+            Source::Upvalue(addr) => self
+                .call_frame()?
+                .upvalues
+                .get(addr as usize)
+                .map(|arc| arc.lock().unwrap().clone()),
+        }
+    }
+    /// like `source`, but reading a global that doesn't exist is an `UndefinedGlobal` error
+    /// unless `warn_undefined_globals` is set
+    pub fn read(&mut self, src: Source, ln: usize) -> Result<Value, RunTimeError> {
+        if let Some(value) = self.source(src) {
+            return Ok(value);
         }
+        let Source::Global(addr) = src else {
+            return Ok(Value::default());
+        };
+        let name = self
+            .call_frame()
+            .and_then(|call_frame| call_frame.closure.constants.get(addr as usize))
+            .map(Value::to_string)
+            .unwrap_or_default();
+        if let Some(global) = self.global(&name) {
+            return Ok(global.lock().unwrap().clone());
+        }
+        match name.as_str() {
+            FILE_GLOBAL => return Ok(self.path().cloned().map(Value::String).unwrap_or_default()),
+            LINE_GLOBAL => return Ok(Value::Int(ln as i64 + 1)),
+            _ => {}
+        }
+        if self.warn_undefined_globals {
+            // the core has no stderr to warn on, the global just reads as null
+            #[cfg(feature = "std_hydra")]
+            eprintln!("WARNING {}: undefined global {name:?}", ln + 1);
+            return Ok(Value::default());
+        }
+        Err(RunTimeError {
+            err: RunTimeErrorKind::UndefinedGlobal(name),
+            ln,
+        })
     }
     pub fn location(&mut self, dst: Location) -> Option<Pointer<Value>> {
         match dst {
@@ -154,44 +536,532 @@ impl Interpreter {
                     self.globals.get(&var).cloned()
                 }
             }
+            Location::Upvalue(addr) => self.call_frame()?.upvalues.get(addr as usize).cloned(),
         }
     }
     pub fn call(
         &mut self,
-        Function { closure }: &Function,
-        args: Vec<Value>,
+        Function { closure, upvalues }: &Function,
+        args: &[Value],
         dst: Option<Location>,
     ) -> Result<(), RunTimeError> {
-        let mut stack: Vec<Pointer<Value>> = Vec::with_capacity(closure.registers as usize);
-        let mut args = args.into_iter();
+        let max_call_depth = self.max_call_depth.unwrap_or(DEFAULT_MAX_CALL_DEPTH);
+        if self.call_stack.len() >= max_call_depth {
+            return Err(RunTimeError {
+                err: RunTimeErrorKind::StackOverflow {
+                    depth: max_call_depth,
+                },
+                ln: self.ln().unwrap_or_default(),
+            });
+        }
+        let mut call_frame = self
+            .frame_pool
+            .get_mut(&closure.registers)
+            .and_then(Vec::pop)
+            .unwrap_or_else(|| CallFrame {
+                idx: 0,
+                closure: Rc::clone(closure),
+                stack: Vec::with_capacity(closure.registers as usize + 2),
+                dst: None,
+                scratch: HashMap::new(),
+                defers: vec![],
+                opened: vec![],
+                handlers: vec![],
+                upvalues: vec![],
+            });
+        call_frame.idx = 0;
+        call_frame.closure = Rc::clone(closure);
+        call_frame.upvalues.clone_from(upvalues);
+        call_frame.dst = dst;
+        let stack = &mut call_frame.stack;
+        let mut slot = 0;
+        let mut args = args.iter();
         for _ in 0..=(closure.parameters - if closure.varargs { 1 } else { 0 }) {
-            let arg = args.next().unwrap_or_default();
-            stack.push(Arc::new(Mutex::new(arg)));
+            let arg = args.next().cloned().unwrap_or_default();
+            set_slot(stack, slot, arg);
+            slot += 1;
         }
         if closure.varargs {
-            let mut values = vec![];
-            for arg in args {
-                values.push(arg);
-            }
-            stack.push(Arc::new(Mutex::new(Value::Vector(Arc::new(Mutex::new(
-                values,
-            ))))));
+            let values = args.cloned().collect();
+            set_slot(stack, slot, Value::Vector(Arc::new(Mutex::new(values))));
+            slot += 1;
         }
         for _ in closure.parameters..=closure.registers {
-            stack.push(Arc::new(Mutex::new(Default::default())));
+            set_slot(stack, slot, Value::default());
+            slot += 1;
         }
-        let call_frame = CallFrame {
-            idx: 0,
-            closure: Rc::clone(closure),
-            stack,
-            dst,
-        };
+        stack.truncate(slot);
         self.call_stack.push(call_frame);
+        let ln = self.ln().unwrap_or_default();
+        self.fire_hook(HookEvent::Call, ln)
+    }
+    /// calls `func` for the events in `mask` until the hook is replaced or cleared, with
+    /// the frame the event happened in on top of the call stack and the line it's at, for
+    /// breakpoints, stepping or profiling. an error returned by `func` aborts the script
+    pub fn set_hook(
+        &mut self,
+        mask: HookMask,
+        func: impl FnMut(&mut Interpreter, HookEvent, usize) -> Result<(), Box<dyn Error>> + 'static,
+    ) {
+        self.hook = Hook {
+            mask,
+            func: Some(Box::new(func)),
+            last_ln: None,
+            replaced: true,
+        };
+    }
+    pub fn clear_hook(&mut self) {
+        self.hook = Hook {
+            replaced: true,
+            ..Default::default()
+        };
+    }
+    /// sends the text printed by scripts to `func` instead of stdout
+    pub fn set_output(&mut self, func: impl FnMut(&str) + 'static) {
+        self.output = Output(Some(Box::new(func)));
+    }
+    pub fn write_output(&mut self, text: &str) -> io::Result<()> {
+        match &mut self.output.0 {
+            Some(func) => {
+                func(text);
+                Ok(())
+            }
+            #[cfg(feature = "std_hydra")]
+            None => {
+                let mut stdout = io::stdout();
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()
+            }
+            // the core leaves it to the host where output goes, see `set_output`
+            #[cfg(not(feature = "std_hydra"))]
+            None => Ok(()),
+        }
+    }
+    pub fn fire_hook(&mut self, event: HookEvent, ln: usize) -> Result<(), RunTimeError> {
+        if !self.hook.mask.contains(event.mask()) {
+            return Ok(());
+        }
+        let Some(mut func) = self.hook.func.take() else {
+            return Ok(());
+        };
+        self.hook.replaced = false;
+        let res = func(self, event, ln);
+        if !self.hook.replaced {
+            self.hook.func = Some(func);
+        }
+        res.map_err(|err| RunTimeError {
+            err: RunTimeErrorKind::Custom(err.to_string()),
+            ln,
+        })
+    }
+    /// registers `func` as the conversion used by `value as "typ"`, taking precedence
+    /// over the built-in casts
+    pub fn set_conversion(&mut self, typ: impl Into<String>, func: Value) {
+        self.conversions.insert(typ.into(), func);
+    }
+    /// marks a vector, tuple or map as immutable, returning false for any other value.
+    /// only the container itself is frozen, not the values inside it
+    pub fn freeze(&mut self, value: &Value) -> bool {
+        let weak: Weak<dyn Any> = match value {
+            Value::Vector(arc) => Arc::downgrade(arc) as Weak<dyn Any>,
+            Value::Tuple(arc) => Arc::downgrade(arc) as Weak<dyn Any>,
+            Value::Map(arc) => Arc::downgrade(arc) as Weak<dyn Any>,
+            _ => return false,
+        };
+        self.frozen.retain(|_, weak| weak.strong_count() > 0);
+        self.frozen
+            .insert(weak.as_ptr() as *const () as usize, weak);
+        true
+    }
+    /// the error `func` returned, naming it if it has a name
+    fn native_error(&self, func: &Rc<NativeFn>, err: Box<dyn Error>, ln: usize) -> RunTimeError {
+        // running out of fuel or stack in a function the native ran keeps its kind, so it
+        // isn't reported as the native's own error
+        if let Some(RunTimeError {
+            err: err @ (RunTimeErrorKind::BudgetExceeded | RunTimeErrorKind::StackOverflow { .. }),
+            ..
+        }) = err.downcast_ref()
+        {
+            return RunTimeError {
+                err: err.clone(),
+                ln,
+            };
+        }
+        RunTimeError {
+            err: match (err.downcast::<Thrown>(), self.native_name(func)) {
+                (Ok(thrown), _) => RunTimeErrorKind::Thrown {
+                    msg: thrown.msg,
+                    value: thrown.value,
+                },
+                (Err(err), Some(name)) => RunTimeErrorKind::Native {
+                    name: name.to_string(),
+                    msg: err.to_string(),
+                },
+                (Err(err), None) => RunTimeErrorKind::Custom(err.to_string()),
+            },
+            ln,
+        }
+    }
+    /// remembers `name` as the name of `value` if it's a native, or `name.key` for the
+    /// natives in it if it's a map, so their errors can tell where they came from
+    pub fn name_natives(&mut self, name: &str, value: &Value) {
+        match value {
+            Value::Fn(FnKind::Native(func)) => {
+                // dropping the names of natives that are gone only when the table is full
+                // keeps naming the hundreds of natives of the standard library linear
+                if self.native_names.len() == self.native_names.capacity() {
+                    self.native_names
+                        .retain(|_, (weak, _)| weak.strong_count() > 0);
+                }
+                self.native_names.insert(
+                    Rc::as_ptr(func) as *const () as usize,
+                    (Rc::downgrade(func), name.to_string()),
+                );
+            }
+            Value::Map(map) => {
+                for (key, value) in map.lock().unwrap().iter() {
+                    if let Value::Fn(FnKind::Native(_)) = value {
+                        self.name_natives(&format!("{name}.{key}"), value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    pub fn native_name(&self, func: &Rc<NativeFn>) -> Option<&str> {
+        self.native_names
+            .get(&(Rc::as_ptr(func) as *const () as usize))
+            .map(|(_, name)| name.as_str())
+    }
+    pub fn is_frozen(&self, value: &Value) -> bool {
+        match value {
+            Value::Vector(arc) => self.check_mutable(arc, value.typ()).is_err(),
+            Value::Tuple(arc) => self.check_mutable(arc, value.typ()).is_err(),
+            Value::Map(arc) => self.check_mutable(arc, value.typ()).is_err(),
+            _ => false,
+        }
+    }
+    /// errors if the container behind `arc` has been frozen, for natives that mutate in place
+    pub fn check_mutable<T: ?Sized>(
+        &self,
+        arc: &Pointer<T>,
+        typ: Type,
+    ) -> Result<(), RunTimeErrorKind> {
+        if self
+            .frozen
+            .contains_key(&(Arc::as_ptr(arc) as *const () as usize))
+        {
+            Err(RunTimeErrorKind::Frozen(typ))
+        } else {
+            Ok(())
+        }
+    }
+    /// `vec += vec` extends, `vec += value` pushes and `map += map` merges in place,
+    /// anything else adds like `+`
+    pub fn add_assign(&self, left: Value, right: Value, ln: usize) -> Result<Value, RunTimeError> {
+        match (&left, right) {
+            (Value::Vector(arc), right) => {
+                self.check_mutable(arc, left.typ())
+                    .map_err(|err| RunTimeError { err, ln })?;
+                match right {
+                    // cloned first so `v += v` doesn't lock the vector twice
+                    Value::Vector(values) => {
+                        let values = values.lock().unwrap().clone();
+                        arc.lock().unwrap().extend(values);
+                    }
+                    right => arc.lock().unwrap().push(right),
+                }
+            }
+            (Value::Map(arc), Value::Map(entries)) => {
+                self.check_mutable(arc, left.typ())
+                    .map_err(|err| RunTimeError { err, ln })?;
+                let entries = entries.lock().unwrap().clone();
+                arc.lock().unwrap().extend(entries);
+            }
+            (_, right) => return Value::binary(BinaryOperation::AddAssign, left, right, ln),
+        }
+        Ok(left)
+    }
+    /// stores host state that natives can get back with `app_data`, replacing and
+    /// returning the previous value of the same type
+    pub fn insert_app_data<T: 'static>(&mut self, value: T) -> Option<T> {
+        self.app_data
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+    pub fn app_data<T: 'static>(&self) -> Option<&T> {
+        self.app_data.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+    pub fn app_data_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.app_data.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+    pub fn remove_app_data<T: 'static>(&mut self) -> Option<T> {
+        self.app_data
+            .remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+    /// sets the global `name` with `import` the first time something reads it instead of
+    /// right away, for globals that take a while to build and often go unused. `import`
+    /// is expected to set `name`
+    pub fn set_lazy_global(&mut self, name: impl Into<String>, import: fn(&mut Interpreter)) {
+        self.lazy_globals.insert(name.into(), import);
+    }
+    /// the cell of the global `name`, building it first if it's lazy
+    pub fn global(&mut self, name: &str) -> Option<Pointer<Value>> {
+        if let Some(import) = self.lazy_globals.remove(name) {
+            if !self.globals.contains_key(name) {
+                import(self);
+            }
+        }
+        self.globals.get(name).cloned()
+    }
+    /// the value at a dotted path like `"app.config"`, where every segment after the first
+    /// is a key into the map before it
+    pub fn get(&mut self, path: &str) -> Option<Value> {
+        let mut keys = path.split('.');
+        let mut value = self.global(keys.next()?)?.lock().unwrap().clone();
+        for key in keys {
+            let Value::Map(map) = value else {
+                return None;
+            };
+            value = map.lock().unwrap().get(key)?.clone();
+        }
+        Some(value)
+    }
+    /// like `get`, but converted to `T`, returning `None` if the value has the wrong type
+    pub fn get_as<T: FromValue>(&mut self, path: &str) -> Option<T> {
+        T::from_value(self.get(path)?)
+    }
+    /// sets the value at a dotted path, creating the maps along it that don't exist yet
+    pub fn set(&mut self, path: &str, value: impl Into<Value>) -> Result<(), RunTimeErrorKind> {
+        let value = value.into();
+        let Some((global, path)) = path.split_once('.') else {
+            self.lazy_globals.remove(path);
+            *self.globals.entry(path.into()).or_default().lock().unwrap() = value;
+            return Ok(());
+        };
+        self.global(global);
+        let head = self.globals.entry(global.into()).or_default();
+        let mut map = {
+            let mut head = head.lock().unwrap();
+            match &*head {
+                Value::Map(map) => Arc::clone(map),
+                Value::Null => {
+                    let map = Arc::new(Mutex::new(Map::new()));
+                    *head = Value::Map(Arc::clone(&map));
+                    map
+                }
+                value => return Err(RunTimeErrorKind::InvalidFieldHead(value.typ())),
+            }
+        };
+        let mut keys = path.split('.').peekable();
+        while let Some(key) = keys.next() {
+            self.check_mutable(&map, "map")?;
+            if keys.peek().is_none() {
+                map.lock().unwrap().insert(key.into(), value);
+                break;
+            }
+            let next = match map.lock().unwrap().entry(key.into()).or_default() {
+                Value::Map(next) => Arc::clone(next),
+                value @ Value::Null => {
+                    let next = Arc::new(Mutex::new(Map::new()));
+                    *value = Value::Map(Arc::clone(&next));
+                    next
+                }
+                value => return Err(RunTimeErrorKind::InvalidFieldHead(value.typ())),
+            };
+            map = next;
+        }
         Ok(())
     }
+    pub fn call_value(
+        &mut self,
+        func: Value,
+        args: &[Value],
+        dst: Option<Location>,
+        ln: usize,
+    ) -> Result<(), RunTimeError> {
+        match func {
+            Value::Fn(FnKind::Function(func)) => {
+                self.call(&func.lock().unwrap(), args, dst)?;
+            }
+            Value::Fn(FnKind::Native(func)) => {
+                self.fire_hook(HookEvent::Call, ln)?;
+                let value = func(self, args).map_err(|err| self.native_error(&func, err, ln))?;
+                self.fire_hook(HookEvent::Return, ln)?;
+                if let Some(dst) = dst {
+                    let dst = self.location(dst).unwrap();
+                    *dst.lock().unwrap() = value.unwrap_or_default();
+                }
+            }
+            Value::NativeObject(ref object) => {
+                // objects are callable through a function in their `__call` field
+                let func = object.lock().unwrap().get("__call");
+                match func {
+                    Some(func @ Value::Fn(_)) => self.call_value(func, args, dst, ln)?,
+                    _ => {
+                        return Err(RunTimeError {
+                            err: RunTimeErrorKind::CannotCall(object.lock().unwrap().typ()),
+                            ln,
+                        })
+                    }
+                }
+            }
+            value => {
+                return Err(RunTimeError {
+                    err: RunTimeErrorKind::CannotCall(value.typ()),
+                    ln,
+                })
+            }
+        }
+        Ok(())
+    }
+    /// calls `func` and runs it to completion, for natives and hooks that need its result
+    pub fn call_now(
+        &mut self,
+        func: Value,
+        args: &[Value],
+        ln: usize,
+    ) -> Result<Value, RunTimeError> {
+        match func {
+            Value::Fn(FnKind::Function(func)) => {
+                self.call(&func.lock().unwrap(), args, None)?;
+                Ok(self.run()?.unwrap_or_default())
+            }
+            Value::Fn(FnKind::Native(func)) => Ok(func(self, args)
+                .map_err(|err| self.native_error(&func, err, ln))?
+                .unwrap_or_default()),
+            value => Err(RunTimeError {
+                err: RunTimeErrorKind::CannotCall(value.typ()),
+                ln,
+            }),
+        }
+    }
+    /// the function stored under `key` of a map, like `__str` or `__eq`
+    pub fn map_hook(value: &Value, key: &str) -> Option<Value> {
+        let Value::Map(arc) = value else {
+            return None;
+        };
+        match arc.lock().unwrap().get(key) {
+            Some(func @ Value::Fn(_)) => Some(func.clone()),
+            _ => None,
+        }
+    }
+    /// formats `value` for `print` and `str`, going through the `__str` functions of maps
+    /// and native objects, also inside of containers
+    pub fn display(&mut self, value: &Value) -> Result<String, RunTimeError> {
+        self.format_value(value, false)
+    }
+    /// formats `value` for `debug` like `display`, but with strings and chars quoted
+    pub fn debug(&mut self, value: &Value) -> Result<String, RunTimeError> {
+        self.format_value(value, true)
+    }
+    fn format_value(&mut self, value: &Value, debug: bool) -> Result<String, RunTimeError> {
+        let hook = match value {
+            Value::Map(_) => Self::map_hook(value, "__str"),
+            Value::NativeObject(arc) => arc
+                .lock()
+                .unwrap()
+                .__str()
+                .map(FnKind::Native)
+                .map(Value::Fn),
+            _ => None,
+        };
+        if let Some(func) = hook {
+            let ln = self.ln().unwrap_or_default();
+            return Ok(self
+                .call_now(func, std::slice::from_ref(value), ln)?
+                .to_string());
+        }
+        Ok(match value {
+            Value::Vector(arc) => {
+                let values = arc.lock().unwrap().clone();
+                format!("[{}]", self.format_values(&values)?)
+            }
+            Value::Tuple(arc) => {
+                let values = arc.lock().unwrap().clone();
+                format!("({})", self.format_values(&values)?)
+            }
+            Value::Map(arc) => {
+                let entries = arc.lock().unwrap().clone();
+                let mut formatted = Vec::with_capacity(entries.len());
+                for (key, value) in entries.iter() {
+                    formatted.push(format!("{key:?} = {}", self.format_value(value, true)?));
+                }
+                format!("{{ {} }}", formatted.join(", "))
+            }
+            value if debug => format!("{value:?}"),
+            value => value.to_string(),
+        })
+    }
+    fn format_values(&mut self, values: &[Value]) -> Result<String, RunTimeError> {
+        let mut formatted = Vec::with_capacity(values.len());
+        for value in values {
+            formatted.push(self.format_value(value, true)?);
+        }
+        Ok(formatted.join(", "))
+    }
+    /// calls `func` with the registers `start..start + amount` of the current frame as
+    /// arguments, borrowing a pooled buffer instead of allocating one per call
+    pub fn call_registers(
+        &mut self,
+        func: Value,
+        start: u8,
+        amount: u8,
+        dst: Option<Location>,
+        ln: usize,
+    ) -> Result<(), RunTimeError> {
+        let mut args = self.arg_buffers.pop().unwrap_or_default();
+        for reg in start..(start + amount) {
+            args.push(self.source(Source::Register(reg)).unwrap_or_default());
+        }
+        let res = self.call_value(func, &args, dst, ln);
+        args.clear();
+        self.arg_buffers.push(args);
+        res
+    }
+    /// fast path for common container methods, returns `None` if the receiver
+    /// has to go through the regular field lookup and call
+    pub fn intrinsic(&self, intrinsic: Intrinsic, start: u8, amount: u8) -> Option<Value> {
+        let head = self.source(Source::Register(start))?;
+        match (intrinsic, head, amount) {
+            (Intrinsic::Len, Value::Vector(arc), 1) => Some(arc.lock().unwrap().len().into()),
+            (Intrinsic::Len, Value::Tuple(arc), 1) => Some(arc.lock().unwrap().len().into()),
+            (Intrinsic::Len, Value::String(string), 1) => Some(string.len().into()),
+            (Intrinsic::Len, Value::NativeObject(arc), 1) => {
+                arc.lock().unwrap().__len().map(Value::from)
+            }
+            // frozen vectors take the native path so it can report the error
+            (Intrinsic::Push, Value::Vector(arc), 2) if self.check_mutable(&arc, "vec").is_ok() => {
+                let value = self.source(Source::Register(start + 1))?;
+                arc.lock().unwrap().push(value);
+                Some(Value::default())
+            }
+            (Intrinsic::Get, Value::Vector(arc), 2 | 3) => {
+                let Value::Int(index) = self.source(Source::Register(start + 1))? else {
+                    return None;
+                };
+                let values = arc.lock().unwrap();
+                let value = resolve_index(index, values.len()).map(|index| values[index].clone());
+                match value {
+                    Some(value) => Some(value),
+                    None if amount == 3 => self.source(Source::Register(start + 2)),
+                    None => Some(Value::default()),
+                }
+            }
+            _ => None,
+        }
+    }
+    /// pops the frame, writing its result into the caller's destination if it has one and
+    /// returning it otherwise: `None` for a `return` without a value, `Some` of the value,
+    /// null included, for one with a value
     pub fn return_call(&mut self, src: Option<Source>) -> Option<Value> {
-        let return_value = src.and_then(|src| self.source(src));
-        let CallFrame { dst, .. } = self.call_stack.pop().unwrap();
+        let return_value = src.map(|src| self.source(src).unwrap_or_default());
+        let call_frame = self.call_stack.pop().unwrap();
+        let dst = call_frame.dst;
+        self.release_frame(call_frame);
         if let Some(dst) = dst {
             let value = return_value.unwrap_or_default();
             if let Some(dst_value) = self.location(dst) {
@@ -202,6 +1072,31 @@ impl Interpreter {
             return_value
         }
     }
+    /// resets the frame's slots and keeps it for the next call of a closure with the same
+    /// register count
+    pub fn release_frame(&mut self, mut call_frame: CallFrame) {
+        let pool = self
+            .frame_pool
+            .entry(call_frame.closure.registers)
+            .or_default();
+        if pool.len() >= FRAME_POOL_LIMIT {
+            return;
+        }
+        for cell in call_frame.stack.iter_mut() {
+            if Arc::strong_count(cell) == 1 {
+                *cell.lock().unwrap() = Value::default();
+            } else {
+                *cell = Arc::new(Mutex::new(Value::default()));
+            }
+        }
+        call_frame.scratch.clear();
+        call_frame.defers.clear();
+        call_frame.opened.clear();
+        call_frame.handlers.clear();
+        call_frame.upvalues.clear();
+        call_frame.dst = None;
+        pool.push(call_frame);
+    }
     pub fn instr(&self) -> Option<ByteCode> {
         let call_frame = self.call_frame()?;
         self.call_frame()?.closure.code.get(call_frame.idx).copied()
@@ -210,6 +1105,91 @@ impl Interpreter {
         let call_frame = self.call_frame()?;
         call_frame.closure.lines.get(call_frame.idx).copied()
     }
+    /// the function name, path and line of every call in progress, innermost first
+    pub fn traceback(&self) -> Vec<(Option<String>, Option<String>, usize)> {
+        self.call_stack
+            .iter()
+            .rev()
+            .map(|call_frame| {
+                let closure = &call_frame.closure;
+                // `idx` already points past the instruction being executed
+                let ln = closure.lines.get(call_frame.idx.saturating_sub(1)).copied();
+                (
+                    closure.name.clone(),
+                    closure.path.clone(),
+                    ln.unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+    /// writes the call stack, innermost frame first, with the registers of every frame and
+    /// a summary of the globals, meant to be attached to bug reports
+    pub fn dump_state(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "call stack:")?;
+        for (depth, call_frame) in self.call_stack.iter().enumerate().rev() {
+            let closure = &call_frame.closure;
+            let addr = call_frame.idx.saturating_sub(1);
+            writeln!(
+                out,
+                "  #{depth} {} at {}:{} [{addr:04}]",
+                closure.name.as_deref().unwrap_or("<main>"),
+                closure.path.as_deref().unwrap_or("?"),
+                closure.lines.get(addr).copied().unwrap_or_default() + 1
+            )?;
+            let registers = call_frame.stack.iter().take(closure.registers as usize);
+            for (reg, value) in registers.enumerate() {
+                let name = closure
+                    .local_name(reg as u8, addr)
+                    .map(|name| format!(" {name}"))
+                    .unwrap_or_default();
+                let value = format!("{:?}", value.lock().unwrap());
+                writeln!(out, "    @{reg}{name} = {}", truncated(&value))?;
+            }
+        }
+        let mut names = self.globals.keys().collect::<Vec<&String>>();
+        names.sort();
+        writeln!(out, "globals ({}):", names.len())?;
+        // functions, objects and modules are only listed by name, grouped by type
+        let mut grouped: Vec<(&str, Vec<&str>)> = vec![];
+        for name in names {
+            let value = self.globals[name].lock().unwrap();
+            match &*value {
+                Value::Fn(_) | Value::NativeObject(_) | Value::Map(_) => {
+                    match grouped.iter_mut().find(|(typ, _)| *typ == value.typ()) {
+                        Some((_, names)) => names.push(name),
+                        None => grouped.push((value.typ(), vec![name])),
+                    }
+                }
+                value => writeln!(out, "  {name} = {}", truncated(&format!("{value:?}")))?,
+            }
+        }
+        for (typ, names) in grouped {
+            writeln!(out, "  {typ}: {}", names.join(", "))?;
+        }
+        Ok(())
+    }
+    /// checks float keys into vectors, tuples and strings, turning integral ones into ints
+    /// if `float_indices` is set
+    pub fn index(&self, head: &Value, field: Value, ln: usize) -> Result<Value, RunTimeError> {
+        match (head, field) {
+            (Value::Vector(_) | Value::Tuple(_) | Value::String(_), Value::Float(index)) => {
+                if self.float_indices && index.fract() == 0.0 {
+                    Ok(Value::Int(index as i64))
+                } else {
+                    Err(RunTimeError {
+                        err: RunTimeErrorKind::FloatIndex {
+                            head: head.typ(),
+                            index,
+                        },
+                        ln,
+                    })
+                }
+            }
+            (_, field) => Ok(field),
+        }
+    }
+    /// the path of the file the running closure was compiled from. after an error it's
+    /// the file the error happened in, which isn't the script's for errors in modules
     pub fn path(&self) -> Option<&String> {
         let call_frame = self.call_frame()?;
         call_frame.closure.path.as_ref()
@@ -220,6 +1200,22 @@ impl Interpreter {
     pub fn step(&mut self) -> Result<Option<Option<Value>>, RunTimeError> {
         let ln = self.ln().unwrap_or_default();
         let instr = self.instr().unwrap();
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(RunTimeError {
+                    err: RunTimeErrorKind::BudgetExceeded,
+                    ln,
+                });
+            }
+            *fuel -= 1;
+        }
+        if self.hook.mask != HookMask::NONE {
+            if self.hook.mask.contains(HookMask::LINE) && self.hook.last_ln != Some(ln) {
+                self.hook.last_ln = Some(ln);
+                self.fire_hook(HookEvent::Line, ln)?;
+            }
+            self.fire_hook(HookEvent::Instruction, ln)?;
+        }
         self.call_frame_mut().unwrap().idx += 1;
         match instr {
             ByteCode::None => {}
@@ -231,7 +1227,7 @@ impl Interpreter {
                 cond,
                 addr,
             } => {
-                let mut cond = bool::from(self.source(cond).unwrap_or_default());
+                let mut cond = bool::from(self.read(cond, ln)?);
                 if negative {
                     cond = !cond;
                 }
@@ -240,7 +1236,7 @@ impl Interpreter {
                 }
             }
             ByteCode::JumpIfSome { negative, src, addr } => {
-                let mut cond = self.source(src).unwrap_or_default() != Value::default();
+                let mut cond = !matches!(self.read(src, ln)?, Value::Null);
                 if negative {
                     cond = !cond;
                 }
@@ -254,52 +1250,185 @@ impl Interpreter {
                 start,
                 amount,
             } => {
-                let func = self.source(func).unwrap_or_default();
-                let mut args = Vec::with_capacity(amount as usize);
-                for reg in start..(start + amount) {
-                    args.push(self.source(Source::Register(reg)).unwrap());
-                }
-                match func {
-                    Value::Fn(FnKind::Function(func)) => {
-                        self.call(&func.lock().unwrap(), args, dst)?;
-                    }
-                    Value::Fn(FnKind::Native(func)) => {
-                        let value = func(self, args).map_err(|err| RunTimeError {
-                            err: RunTimeErrorKind::Custom(err.to_string()),
-                            ln,
-                        })?;
-                        if let Some(dst) = dst {
-                            let dst = self.location(dst).unwrap();
-                            *dst.lock().unwrap() = value.unwrap_or_default();
-                        }
-                    }
-                    value => {
+                let func = self.read(func, ln)?;
+                self.call_registers(func, start, amount, dst, ln)?;
+            }
+            ByteCode::CallSpread { dst, func, args } => {
+                let func = self.read(func, ln)?;
+                // the compiler collects the arguments into a vector, loaded bytecode may not
+                let args = match self.read(args, ln)? {
+                    Value::Vector(values) => values.lock().unwrap().clone(),
+                    args => {
                         return Err(RunTimeError {
-                            err: RunTimeErrorKind::CannotCall(value.typ()),
+                            err: RunTimeErrorKind::CannotSpread {
+                                head: func.typ(),
+                                src: args.typ(),
+                            },
                             ln,
                         })
                     }
+                };
+                self.call_value(func, &args, dst, ln)?;
+            }
+            ByteCode::Intrinsic {
+                dst,
+                intrinsic,
+                start,
+                amount,
+            } => {
+                if let Some(value) = self.intrinsic(intrinsic, start, amount) {
+                    if let Some(dst) = dst {
+                        let dst = self.location(dst).unwrap();
+                        *dst.lock().unwrap() = value;
+                    }
+                } else {
+                    let head = self.source(Source::Register(start)).unwrap_or_default();
+                    let func = head.field(self, Value::String(intrinsic.name().into()), ln)?;
+                    self.call_registers(func, start, amount, dst, ln)?;
                 }
             }
             ByteCode::Return { src } => {
+                // each deferred call comes back to this instruction until none are left
+                if let Some((func, args)) = self.call_frame_mut().unwrap().defers.pop() {
+                    self.call_frame_mut().unwrap().idx -= 1;
+                    self.call_value(func, &args, None, ln)?;
+                    return Ok(None);
+                }
+                if let Some(src @ Source::Global(_)) = src {
+                    self.read(src, ln)?;
+                }
+                self.fire_hook(HookEvent::Return, ln)?;
                 return Ok(Some(self.return_call(src)));
             }
             ByteCode::Move { dst, src } => {
                 let dst = self.location(dst).unwrap();
-                *dst.lock().unwrap() = self.source(src).unwrap_or_default();
+                *dst.lock().unwrap() = self.read(src, ln)?;
+            }
+            ByteCode::Copy { dst, src } => {
+                let dst = self.location(dst).unwrap();
+                let value = self.read(src, ln)?;
+                *dst.lock().unwrap() = if self.copy_containers {
+                    value.shallow_copy()
+                } else {
+                    value
+                };
             }
             ByteCode::Field { dst, head, field } => {
                 let dst = self.location(dst).unwrap();
-                let head = self.source(head).unwrap_or_default();
-                let field = self.source(field).unwrap_or_default();
+                let head = self.read(head, ln)?;
+                let field = self.read(field, ln)?;
+                let field = self.index(&head, field, ln)?;
                 *dst.lock().unwrap() = head.field(self, field, ln)?;
             }
             ByteCode::SetField { head, field, src } => {
-                let head = self.source(head).unwrap_or_default();
-                let field = self.source(field).unwrap_or_default();
-                let src = self.source(src).unwrap_or_default();
+                let head = self.read(head, ln)?;
+                let field = self.read(field, ln)?;
+                let field = self.index(&head, field, ln)?;
+                let src = self.read(src, ln)?;
+                if self.is_frozen(&head) {
+                    return Err(RunTimeError {
+                        err: RunTimeErrorKind::Frozen(head.typ()),
+                        ln,
+                    });
+                }
                 head.set_field(field, src, ln)?;
             }
+            ByteCode::Extend { head, src } => {
+                let head = self.read(head, ln)?;
+                match (&head, self.read(src, ln)?) {
+                    (Value::Vector(arc), Value::Vector(values)) => {
+                        let values = values.lock().unwrap().clone();
+                        arc.lock().unwrap().extend(values);
+                    }
+                    (Value::Vector(arc), Value::Tuple(values)) => {
+                        let values = values.lock().unwrap().clone();
+                        arc.lock().unwrap().extend(values.into_vec());
+                    }
+                    (Value::Map(arc), Value::Map(entries)) => {
+                        let entries = entries.lock().unwrap().clone();
+                        arc.lock().unwrap().extend(entries);
+                    }
+                    (head, src) => {
+                        return Err(RunTimeError {
+                            err: RunTimeErrorKind::CannotSpread {
+                                head: head.typ(),
+                                src: src.typ(),
+                            },
+                            ln,
+                        })
+                    }
+                }
+            }
+            ByteCode::Freeze { src } => {
+                let value = self.read(src, ln)?;
+                self.freeze(&value);
+            }
+            ByteCode::Defer {
+                func,
+                start,
+                amount,
+            } => {
+                let func = self.read(func, ln)?;
+                let args = (start..start + amount)
+                    .map(|reg| self.source(Source::Register(reg)).unwrap_or_default())
+                    .collect();
+                self.call_frame_mut().unwrap().defers.push((func, args));
+            }
+            ByteCode::Try { addr, dst } => {
+                let call_frame = self.call_frame_mut().unwrap();
+                let opened = call_frame.opened.len();
+                call_frame.handlers.push((addr, dst, opened));
+            }
+            ByteCode::EndTry => {
+                self.call_frame_mut().unwrap().handlers.pop();
+            }
+            ByteCode::Open { src } => {
+                let value = self.read(src, ln)?;
+                self.call_frame_mut().unwrap().opened.push(value);
+            }
+            ByteCode::Close { src } => {
+                self.call_frame_mut().unwrap().opened.pop();
+                let value = self.read(src, ln)?;
+                if let Some(func) = self.exit_method(&value, ln)? {
+                    self.call_value(func, &[value], None, ln)?;
+                }
+            }
+            ByteCode::Detach { start, amount } => {
+                let call_frame = self.call_frame_mut().unwrap();
+                for cell in &mut call_frame.stack[start as usize..(start + amount) as usize] {
+                    if Arc::strong_count(cell) > 1 {
+                        let value = cell.lock().unwrap().clone();
+                        *cell = Arc::new(Mutex::new(value));
+                    }
+                }
+            }
+            ByteCode::Unpack { start, amount, src } => {
+                let head = self.read(src, ln)?;
+                let values = match &head {
+                    Value::Tuple(arc) => arc
+                        .lock()
+                        .unwrap()
+                        .get(..amount as usize)
+                        .map(<[_]>::to_vec),
+                    Value::Vector(arc) => arc
+                        .lock()
+                        .unwrap()
+                        .get(..amount as usize)
+                        .map(<[_]>::to_vec),
+                    _ => None,
+                };
+                let values = match values {
+                    Some(values) => values,
+                    // anything else goes through the field accesses it stands in for
+                    None => (0..amount as i64)
+                        .map(|idx| head.clone().field(self, Value::Int(idx), ln))
+                        .collect::<Result<_, _>>()?,
+                };
+                for (reg, value) in (start..).zip(values) {
+                    let dst = self.location(Location::Register(reg)).unwrap();
+                    *dst.lock().unwrap() = value;
+                }
+            }
             ByteCode::Vector { dst, start, amount } => {
                 let dst = self.location(dst).unwrap();
                 let mut values = vec![];
@@ -317,53 +1446,340 @@ impl Interpreter {
                 *dst.lock().unwrap() =
                     Value::Tuple(Arc::new(Mutex::new(values.into_boxed_slice())));
             }
+            ByteCode::ScratchVector { dst, start, amount } => {
+                let addr = self.call_frame().unwrap().idx - 1;
+                let dst = self.location(dst).unwrap();
+                drop(std::mem::take(&mut *dst.lock().unwrap()));
+                let vector = match self.call_frame().unwrap().scratch.get(&addr) {
+                    Some(Value::Vector(arc)) if Arc::strong_count(arc) == 1 => Arc::clone(arc),
+                    _ => {
+                        let arc = Arc::new(Mutex::new(Vec::with_capacity(amount as usize)));
+                        self.call_frame_mut()
+                            .unwrap()
+                            .scratch
+                            .insert(addr, Value::Vector(Arc::clone(&arc)));
+                        arc
+                    }
+                };
+                {
+                    let mut values = vector.lock().unwrap();
+                    values.clear();
+                    for reg in start..(start + amount) {
+                        values.push(self.source(Source::Register(reg)).unwrap_or_default());
+                    }
+                }
+                *dst.lock().unwrap() = Value::Vector(vector);
+            }
+            ByteCode::ScratchTuple { dst, start, amount } => {
+                let addr = self.call_frame().unwrap().idx - 1;
+                let dst = self.location(dst).unwrap();
+                drop(std::mem::take(&mut *dst.lock().unwrap()));
+                let tuple = match self.call_frame().unwrap().scratch.get(&addr) {
+                    Some(Value::Tuple(arc)) if Arc::strong_count(arc) == 1 => Arc::clone(arc),
+                    _ => {
+                        let arc = Arc::new(Mutex::new(
+                            vec![Value::default(); amount as usize].into_boxed_slice(),
+                        ));
+                        self.call_frame_mut()
+                            .unwrap()
+                            .scratch
+                            .insert(addr, Value::Tuple(Arc::clone(&arc)));
+                        arc
+                    }
+                };
+                {
+                    let mut values = tuple.lock().unwrap();
+                    for (value, reg) in values.iter_mut().zip(start..(start + amount)) {
+                        *value = self.source(Source::Register(reg)).unwrap_or_default();
+                    }
+                }
+                *dst.lock().unwrap() = Value::Tuple(tuple);
+            }
             ByteCode::Map { dst } => {
                 let dst = self.location(dst).unwrap();
                 *dst.lock().unwrap() = Value::Map(Arc::new(Mutex::new(Default::default())));
             }
             ByteCode::Fn { dst, addr } => {
                 let dst = self.location(dst).unwrap();
-                let closure = self.closure(addr).unwrap();
+                let closure = Rc::clone(self.closure(addr).unwrap());
+                let call_frame = self.call_frame().unwrap();
+                let upvalues = closure
+                    .upvalues
+                    .iter()
+                    .map(|upvalue| match upvalue.capture {
+                        Capture::Register(reg) => Arc::clone(&call_frame.stack[reg as usize]),
+                        Capture::Upvalue(idx) => Arc::clone(&call_frame.upvalues[idx as usize]),
+                    })
+                    .collect();
                 *dst.lock().unwrap() =
                     Value::Fn(FnKind::Function(Arc::new(Mutex::new(Function {
-                        closure: Rc::clone(closure),
+                        closure,
+                        upvalues,
                     }))));
             }
+            ByteCode::Import { dst, path } => {
+                let dst = self.location(dst).unwrap();
+                let path = match self.read(Source::Constant(path), ln)? {
+                    Value::String(path) => path,
+                    value => {
+                        return Err(RunTimeError {
+                            err: RunTimeErrorKind::Custom(format!("can't import {}", value.typ())),
+                            ln,
+                        })
+                    }
+                };
+                *dst.lock().unwrap() = self.import(&path, ln)?;
+            }
             ByteCode::Binary {
                 op,
                 dst,
                 left,
                 right,
             } => {
+                let left = self.read(left, ln)?;
+                let right = self.read(right, ln)?;
+                if self.strict_arithmetic && op.is_numeric() {
+                    if let (Value::Int(_), Value::Float(_)) | (Value::Float(_), Value::Int(_)) =
+                        (&left, &right)
+                    {
+                        return Err(RunTimeError {
+                            err: RunTimeErrorKind::MixedArithmetic {
+                                op,
+                                left: left.typ(),
+                                right: right.typ(),
+                            },
+                            ln,
+                        });
+                    }
+                }
+                if let (BinaryOperation::As, Value::String(typ)) = (op, &right) {
+                    if let Some(func) = self.conversions.get(typ).cloned() {
+                        self.call_value(func, &[left], Some(dst), ln)?;
+                        return Ok(None);
+                    }
+                }
+                let value = match op {
+                    BinaryOperation::AddAssign => self.add_assign(left, right, ln)?,
+                    BinaryOperation::EE | BinaryOperation::NE => {
+                        match Self::map_hook(&left, "__eq") {
+                            Some(func) => {
+                                let eq = bool::from(self.call_now(func, &[left, right], ln)?);
+                                Value::Bool(eq == (op == BinaryOperation::EE))
+                            }
+                            None => Value::binary(op, left, right, ln)?,
+                        }
+                    }
+                    op => Value::binary(op, left, right, ln)?,
+                };
                 let dst = self.location(dst).unwrap();
-                let left = self.source(left).unwrap_or_default();
-                let right = self.source(right).unwrap_or_default();
-                *dst.lock().unwrap() = Value::binary(op, left, right, ln)?;
+                *dst.lock().unwrap() = value;
             }
             ByteCode::Unary { op, dst, right } => {
                 let dst = self.location(dst).unwrap();
-                let right = self.source(right).unwrap_or_default();
+                let right = self.read(right, ln)?;
                 *dst.lock().unwrap() = Value::unary(op, right, ln)?;
             }
         }
         Ok(None)
     }
+    /// runs until the frame on top of the call stack returns, giving its result like
+    /// `return_call` does
     pub fn run(&mut self) -> Result<Option<Value>, RunTimeError> {
         let offset = self.call_stack.len();
         if offset == 0 {
             return Ok(None);
         }
+        // every native calling back into scripts nests another run on the host's stack
+        if self.nested_runs >= MAX_NESTED_RUNS {
+            return Err(RunTimeError {
+                err: RunTimeErrorKind::StackOverflow {
+                    depth: MAX_NESTED_RUNS,
+                },
+                ln: self.ln().unwrap_or_default(),
+            });
+        }
+        self.nested_runs += 1;
+        let result = self.run_frames(offset);
+        self.nested_runs -= 1;
+        result
+    }
+    /// like `run`, but gives control back with `Paused` after `max_instructions` steps,
+    /// for hosts like game loops that give a script a slice of every frame. until the run
+    /// completes or errors, the next call continues it where this one stopped. a native
+    /// calling back into scripts runs them to the end within its one step
+    pub fn run_for(&mut self, max_instructions: usize) -> RunStatus {
+        let offset = *self.paused_run.get_or_insert(self.call_stack.len());
+        if offset == 0 {
+            self.paused_run = None;
+            return RunStatus::Completed(None);
+        }
+        if self.nested_runs >= MAX_NESTED_RUNS {
+            self.paused_run = None;
+            return RunStatus::Error(RunTimeError {
+                err: RunTimeErrorKind::StackOverflow {
+                    depth: MAX_NESTED_RUNS,
+                },
+                ln: self.ln().unwrap_or_default(),
+            });
+        }
+        self.nested_runs += 1;
+        let mut status = RunStatus::Paused;
+        for _ in 0..max_instructions {
+            status = match self.run_step(offset) {
+                Ok(None) => continue,
+                Ok(Some(value)) => RunStatus::Completed(value),
+                Err(err) => RunStatus::Error(err),
+            };
+            self.paused_run = None;
+            break;
+        }
+        self.nested_runs -= 1;
+        status
+    }
+    fn run_frames(&mut self, offset: usize) -> Result<Option<Value>, RunTimeError> {
         loop {
-            let return_call = self.step()?;
-            if self.call_stack.len() < offset {
-                if let Some(value) = return_call {
-                    return Ok(value);
-                }
+            if let Some(value) = self.run_step(offset)? {
+                return Ok(value);
             }
-            if self.call_stack.len() < offset - 1 {
-                break;
+        }
+    }
+    /// runs one instruction of the frames from `offset` up, giving their result once the
+    /// frame at `offset` returned
+    fn run_step(&mut self, offset: usize) -> Result<Option<Option<Value>>, RunTimeError> {
+        let return_call = match self.step() {
+            Ok(return_call) => return_call,
+            Err(err) => {
+                self.catch(err, offset - 1)?;
+                return Ok(None);
             }
+        };
+        if self.call_stack.len() < offset {
+            if let Some(value) = return_call {
+                return Ok(Some(value));
+            }
+        }
+        if self.call_stack.len() < offset - 1 {
+            return Ok(Some(None));
         }
         Ok(None)
     }
+    /// unwinds to the innermost `try` of the frames from `base` up and hands it the error,
+    /// or gives the error back if none of them is in one. exceeding a budget can't be
+    /// caught
+    fn catch(&mut self, err: RunTimeError, base: usize) -> Result<(), RunTimeError> {
+        if let RunTimeErrorKind::BudgetExceeded = err.err {
+            return Err(err);
+        }
+        let Some(depth) = self
+            .call_stack
+            .iter()
+            .rposition(|call_frame| !call_frame.handlers.is_empty())
+            .filter(|depth| *depth >= base)
+        else {
+            return Err(err);
+        };
+        let (mut err, mut value) = self.unwind(depth + 1, err);
+        let (addr, dst, opened) = self.call_frame_mut().unwrap().handlers.pop().unwrap();
+        // the objects opened inside the `try` get closed before its handler runs
+        while self.call_frame().unwrap().opened.len() > opened {
+            if let RunTimeErrorKind::BudgetExceeded = err.err {
+                break;
+            }
+            let object = self.call_frame_mut().unwrap().opened.pop().unwrap();
+            let ln = self.ln().unwrap_or_default();
+            if let Err(close_err) = self.close_now(object, ln) {
+                (err, value) = self.unwind(depth + 1, close_err);
+            }
+        }
+        if let RunTimeErrorKind::BudgetExceeded = err.err {
+            return Err(err);
+        }
+        self.call_frame_mut().unwrap().idx = addr;
+        let dst = self.location(dst).unwrap();
+        *dst.lock().unwrap() = value;
+        Ok(())
+    }
+    /// pops the frames above `depth` an error unwinds out of, innermost first, closing the
+    /// objects of the `with` statements they're in and then running their deferred calls
+    /// like returning would. an error raised by one of those takes the place of `err`, the
+    /// error to carry on with is given back with its value
+    pub fn unwind(&mut self, depth: usize, err: RunTimeError) -> (RunTimeError, Value) {
+        let mut value = self.error_value(&err);
+        let mut err = err;
+        while self.call_stack.len() > depth {
+            if let RunTimeErrorKind::BudgetExceeded = err.err {
+                break;
+            }
+            let ln = self.ln().unwrap_or_default();
+            let call_frame = self.call_frame_mut().unwrap();
+            let res = if let Some(object) = call_frame.opened.pop() {
+                self.close_now(object, ln)
+            } else if let Some((func, args)) = call_frame.defers.pop() {
+                self.call_now(func, &args, ln).map(drop)
+            } else {
+                let call_frame = self.call_stack.pop().unwrap();
+                self.release_frame(call_frame);
+                continue;
+            };
+            // the frames a failed close or deferred call leaves behind get unwound next
+            if let Err(cleanup_err) = res {
+                value = self.error_value(&cleanup_err);
+                err = cleanup_err;
+            }
+        }
+        (err, value)
+    }
+    /// the `__exit` or else `close` method `Close` calls on `value`, `None` for null
+    fn exit_method(&mut self, value: &Value, ln: usize) -> Result<Option<Value>, RunTimeError> {
+        if *value == Value::Null {
+            return Ok(None);
+        }
+        let mut func = value.clone().field(self, "__exit".into(), ln)?;
+        if func == Value::Null {
+            func = value.clone().field(self, "close".into(), ln)?;
+        }
+        if func == Value::Null {
+            return Err(RunTimeError {
+                err: RunTimeErrorKind::Custom(format!(
+                    "{} has no __exit or close method",
+                    value.typ()
+                )),
+                ln,
+            });
+        }
+        Ok(Some(func))
+    }
+    /// closes `value` like `Close` does, running its method to completion
+    fn close_now(&mut self, value: Value, ln: usize) -> Result<(), RunTimeError> {
+        match self.exit_method(&value, ln)? {
+            Some(func) => self.call_now(func, &[value], ln).map(drop),
+            None => Ok(()),
+        }
+    }
+    /// the value a `catch` gets for `err`: the raised value for errors thrown with one,
+    /// like the ones `error` creates, and an error object of the kind named after the
+    /// variant otherwise
+    pub fn error_value(&self, err: &RunTimeError) -> Value {
+        if let RunTimeErrorKind::Thrown { msg: _, value } = &err.err {
+            return value.clone();
+        }
+        let kind = Some(err.err.name().to_string());
+        let mut object = ErrorObject::new(self, err.err.to_string(), kind, None);
+        object.ln = err.ln;
+        Value::NativeObject(Arc::new(Mutex::new(object)))
+    }
+}
+
+fn set_slot(stack: &mut Vec<Pointer<Value>>, idx: usize, value: Value) {
+    if let Some(cell) = stack.get(idx) {
+        *cell.lock().unwrap() = value;
+    } else {
+        stack.push(Arc::new(Mutex::new(value)));
+    }
+}
+fn truncated(text: &str) -> String {
+    match text.char_indices().nth(DUMP_VALUE_WIDTH) {
+        Some((idx, _)) => format!("{}...", &text[..idx]),
+        None => text.to_string(),
+    }
 }
@@ -1,4 +1,13 @@
+pub mod analysis;
 pub mod code;
 pub mod compiler;
+pub mod error;
+pub mod format;
+pub mod import;
 pub mod interpreter;
+pub mod module;
+pub mod peephole;
+pub mod replay;
+pub mod trust;
+pub mod types;
 pub mod value;
@@ -0,0 +1,68 @@
+use super::interpreter::Interpreter;
+
+/// a set of globals an extension crate ships, which hosts opt into with
+/// `Interpreter::load_module`
+/// ```ignore
+/// struct Geometry;
+/// impl HydraModule for Geometry {
+///     fn name(&self) -> &str {
+///         "geometry"
+///     }
+///     fn register(&self, interpreter: &mut Interpreter) {
+///         declare_module!(interpreter: "geometry" {
+///             "area" => _area,
+///         });
+///     }
+/// }
+/// ```
+pub trait HydraModule {
+    fn name(&self) -> &str;
+    fn register(&self, interpreter: &mut Interpreter);
+}
+/// the signature of the `hydra_module` function `export_module!` defines in a shared
+/// object, see `Interpreter::load_module_library`
+pub type ModuleEntry = fn() -> Box<dyn HydraModule>;
+/// the symbol `Interpreter::load_module_library` looks up
+pub const MODULE_ENTRY: &[u8] = b"hydra_module";
+
+/// defines the entry point `Interpreter::load_module_library` looks for in a `cdylib`,
+/// returning `$module`. the library has to be built with the same compiler, hydra-lang
+/// version and features as the host, since the module crosses over as a Rust trait object
+#[macro_export]
+macro_rules! export_module {
+    ($module:expr) => {
+        #[no_mangle]
+        pub fn hydra_module() -> Box<dyn $crate::run::module::HydraModule> {
+            Box::new($module)
+        }
+    };
+}
+
+impl Interpreter {
+    /// registers the globals of `module` unless a module with its name got loaded before,
+    /// returning whether it did
+    pub fn load_module(&mut self, module: Box<dyn HydraModule>) -> bool {
+        if !self.loaded_modules.insert(module.name().to_string()) {
+            return false;
+        }
+        module.register(self);
+        true
+    }
+    /// opens the shared object at `path` and loads the module its `export_module!` entry
+    /// point returns, keeping the library open for as long as the interpreter lives
+    /// # Safety
+    /// the library has to export its entry point with `export_module!` and be built with
+    /// the same compiler, hydra-lang version and features, anything else is undefined
+    /// behavior. its initialization code runs when it gets opened
+    #[cfg(feature = "plugins")]
+    pub unsafe fn load_module_library(
+        &mut self,
+        path: impl AsRef<std::ffi::OsStr>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let library = libloading::Library::new(path)?;
+        let entry = *library.get::<ModuleEntry>(MODULE_ENTRY)?;
+        let module = entry();
+        self.libraries.push(Box::new(library));
+        Ok(self.load_module(module))
+    }
+}
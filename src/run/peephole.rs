@@ -0,0 +1,120 @@
+use super::code::{ByteCode, Closure};
+use std::collections::HashSet;
+
+impl Closure {
+    /// tidies up the code the compiler emitted: jumps to jumps go straight to the end of the
+    /// chain, then the instructions nothing reaches, the `none` placeholders and jumps to
+    /// the next instruction get dropped, moving the jump addresses, lines and locals along
+    pub fn peephole(&mut self) {
+        self.thread_jumps();
+        loop {
+            let keep = self.live();
+            if keep.iter().all(|keep| *keep) {
+                break;
+            }
+            self.compact(&keep);
+        }
+    }
+    fn thread_jumps(&mut self) {
+        for addr in 0..self.code.len() {
+            if let Some(to) = self.code[addr].target() {
+                let to = self.destination(to);
+                self.code[addr].set_target(to);
+            }
+        }
+    }
+    /// the first instruction at or after `addr` that does something other than jumping or
+    /// nothing at all
+    fn destination(&self, mut addr: usize) -> usize {
+        let mut seen = HashSet::new();
+        while seen.insert(addr) {
+            match self.code.get(addr) {
+                Some(ByteCode::None) => addr += 1,
+                Some(ByteCode::Jump { addr: to }) => addr = *to,
+                _ => break,
+            }
+        }
+        addr
+    }
+    /// whether each instruction is worth keeping: something reaches it and it does more
+    /// than moving on to the next one
+    fn live(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.code.len()];
+        let mut pending = vec![0];
+        while let Some(addr) = pending.pop() {
+            let Some(bytecode) = self.code.get(addr) else {
+                continue;
+            };
+            if std::mem::replace(&mut reachable[addr], true) {
+                continue;
+            }
+            match *bytecode {
+                ByteCode::Return { .. } => {}
+                ByteCode::Jump { addr: to } => pending.push(to),
+                ByteCode::JumpIf { addr: to, .. }
+                | ByteCode::JumpIfSome { addr: to, .. }
+                | ByteCode::Try { addr: to, .. } => {
+                    pending.push(addr + 1);
+                    pending.push(to);
+                }
+                _ => pending.push(addr + 1),
+            }
+        }
+        self.code
+            .iter()
+            .zip(reachable)
+            .enumerate()
+            .map(|(addr, (bytecode, reachable))| {
+                reachable
+                    && !matches!(bytecode, ByteCode::None)
+                    && *bytecode != ByteCode::Jump { addr: addr + 1 }
+            })
+            .collect()
+    }
+    /// drops the instructions `keep` doesn't want, pointing everything that referred to one
+    /// of them at the instruction after it
+    fn compact(&mut self, keep: &[bool]) {
+        let mut moved = Vec::with_capacity(keep.len() + 1);
+        let mut next = 0;
+        for keep in keep {
+            moved.push(next);
+            next += usize::from(*keep);
+        }
+        moved.push(next);
+        let moved = |addr: usize| moved[addr.min(keep.len())];
+        let mut keeps = keep.iter();
+        self.code.retain(|_| *keeps.next().unwrap());
+        let mut keeps = keep.iter();
+        self.lines.retain(|_| *keeps.next().unwrap());
+        for bytecode in self.code.iter_mut() {
+            if let Some(to) = bytecode.target() {
+                bytecode.set_target(moved(to));
+            }
+        }
+        for local in self.locals.iter_mut() {
+            local.start = moved(local.start);
+            local.end = moved(local.end);
+        }
+    }
+}
+impl ByteCode {
+    /// the address the instruction may continue at other than the next one
+    pub fn target(&self) -> Option<usize> {
+        match *self {
+            ByteCode::Jump { addr }
+            | ByteCode::JumpIf { addr, .. }
+            | ByteCode::JumpIfSome { addr, .. }
+            | ByteCode::Try { addr, .. } => Some(addr),
+            _ => None,
+        }
+    }
+    fn set_target(&mut self, to: usize) {
+        if let ByteCode::Jump { addr }
+        | ByteCode::JumpIf { addr, .. }
+        | ByteCode::JumpIfSome { addr, .. }
+        | ByteCode::Try { addr, .. } = self
+        {
+            *addr = to;
+        }
+    }
+}
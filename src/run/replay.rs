@@ -0,0 +1,167 @@
+use super::{interpreter::Interpreter, value::Value};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{Display, Write},
+};
+#[cfg(feature = "std_hydra")]
+use std::{fs, io, path::Path};
+
+/// results of non-deterministic natives (random numbers, time, input, file reads), either
+/// being recorded or fed back in the recorded order
+#[derive(Debug, Default)]
+pub enum Replay {
+    #[default]
+    Off,
+    Record(Vec<(String, Value)>),
+    Replay(VecDeque<(String, Value)>),
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    Diverged { expected: String, got: String },
+    Exhausted(String),
+    Unrecordable(&'static str),
+    InvalidEntry(usize),
+}
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Diverged { expected, got } => write!(
+                f,
+                "replay diverged: script called {got} where the log has {expected}"
+            ),
+            ReplayError::Exhausted(name) => {
+                write!(f, "replay log ended before the call to {name}")
+            }
+            ReplayError::Unrecordable(typ) => write!(f, "can't record a {typ} value"),
+            ReplayError::InvalidEntry(ln) => write!(f, "invalid replay log entry on line {ln}"),
+        }
+    }
+}
+impl Error for ReplayError {}
+
+impl Replay {
+    #[cfg(feature = "std_hydra")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::parse(&fs::read_to_string(path)?)?)
+    }
+    /// parses a log written by `Replay::save` into a replay
+    pub fn parse(text: &str) -> Result<Self, ReplayError> {
+        let mut entries = VecDeque::new();
+        for (ln, line) in text.lines().enumerate() {
+            let (name, value) = line
+                .split_once('\t')
+                .and_then(|(name, value)| Some((name, decode(value)?)))
+                .ok_or(ReplayError::InvalidEntry(ln + 1))?;
+            entries.push_back((name.to_string(), value));
+        }
+        Ok(Self::Replay(entries))
+    }
+    /// writes the recorded entries to the file at `path`, see `Replay::log`
+    #[cfg(feature = "std_hydra")]
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        match self.log() {
+            Some(text) => fs::write(path, text),
+            None => Ok(()),
+        }
+    }
+    /// the recorded entries, one `name<TAB>value` per line, `None` unless recording
+    pub fn log(&self) -> Option<String> {
+        let Self::Record(entries) = self else {
+            return None;
+        };
+        let mut text = String::new();
+        for (name, value) in entries {
+            // entries are checked to be encodable when they get recorded
+            let _ = writeln!(text, "{name}\t{}", encode(value).unwrap_or_default());
+        }
+        Some(text)
+    }
+}
+fn encode(value: &Value) -> Result<String, ReplayError> {
+    Ok(match value {
+        Value::Null => "null".into(),
+        Value::Bool(value) => format!("bool {value}"),
+        Value::Int(value) => format!("int {value}"),
+        Value::Float(value) => format!("float {value:?}"),
+        Value::Char(value) => format!("char {}", escape(&value.to_string())),
+        Value::String(value) => format!("str {}", escape(value)),
+        value => return Err(ReplayError::Unrecordable(value.typ())),
+    })
+}
+fn decode(text: &str) -> Option<Value> {
+    let (kind, payload) = text.split_once(' ').unwrap_or((text, ""));
+    Some(match kind {
+        "null" => Value::Null,
+        "bool" => Value::Bool(payload.parse().ok()?),
+        "int" => Value::Int(payload.parse().ok()?),
+        "float" => Value::Float(payload.parse().ok()?),
+        "char" => Value::Char(unescape(payload)?.chars().next()?),
+        "str" => Value::String(unescape(payload)?),
+        _ => return None,
+    })
+}
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+fn unescape(text: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
+
+impl Interpreter {
+    /// returns the result of `f` while recording, or the next logged result in its place
+    /// while replaying. `name` identifies the native so a diverging script gets noticed
+    pub fn nondeterministic(
+        &mut self,
+        name: &str,
+        f: impl FnOnce() -> Result<Value, Box<dyn Error>>,
+    ) -> Result<Value, Box<dyn Error>> {
+        match &mut self.replay {
+            Replay::Off => f(),
+            Replay::Record(entries) => {
+                let value = f()?;
+                encode(&value)?;
+                entries.push((name.to_string(), value.clone()));
+                Ok(value)
+            }
+            Replay::Replay(entries) => {
+                let (logged, value) = entries
+                    .pop_front()
+                    .ok_or_else(|| ReplayError::Exhausted(name.to_string()))?;
+                if logged != name {
+                    return Err(ReplayError::Diverged {
+                        expected: logged,
+                        got: name.to_string(),
+                    }
+                    .into());
+                }
+                Ok(value)
+            }
+        }
+    }
+}
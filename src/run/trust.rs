@@ -0,0 +1,84 @@
+use super::interpreter::{Interpreter, RunTimeErrorKind};
+use std::fmt::Display;
+
+/// how far an interpreter trusts the scripts it runs, taking away groups of natives at
+/// once, see `Interpreter::set_trust_level`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrustLevel {
+    /// everything the loaded modules offer, `ffi` still only opens what its policy allows
+    #[default]
+    Trusted,
+    /// no code from strings, no `ffi` and nothing that changes the process, but files,
+    /// imports and the environment can still be read
+    Restricted,
+    /// only computation and the standard streams, for scripts nobody vouches for
+    Sandbox,
+}
+/// a group of natives a `TrustLevel` can take away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// `loadstring` and `eval`
+    LoadCode,
+    /// `ffi.open`
+    Ffi,
+    /// `os.exit`, `env.set_var`, `env.remove_var` and `env.set_current_dir`
+    Process,
+    /// the `fs` module
+    FileSystem,
+    /// `import` of script modules
+    Import,
+    /// reading the arguments, variables and directories of `env`
+    Environment,
+}
+impl TrustLevel {
+    pub fn allows(self, capability: Capability) -> bool {
+        match self {
+            TrustLevel::Trusted => true,
+            TrustLevel::Restricted => matches!(
+                capability,
+                Capability::FileSystem | Capability::Import | Capability::Environment
+            ),
+            TrustLevel::Sandbox => false,
+        }
+    }
+}
+impl Display for TrustLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrustLevel::Trusted => write!(f, "trusted"),
+            TrustLevel::Restricted => write!(f, "restricted"),
+            TrustLevel::Sandbox => write!(f, "sandbox"),
+        }
+    }
+}
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::LoadCode => write!(f, "loading code from strings"),
+            Capability::Ffi => write!(f, "ffi"),
+            Capability::Process => write!(f, "changing the process"),
+            Capability::FileSystem => write!(f, "file system access"),
+            Capability::Import => write!(f, "importing modules"),
+            Capability::Environment => write!(f, "reading the environment"),
+        }
+    }
+}
+
+impl Interpreter {
+    /// switches the natives scripts may use to the ones `level` allows, from then on
+    pub fn set_trust_level(&mut self, level: TrustLevel) {
+        self.trust_level = level;
+    }
+    /// a `Forbidden` error unless the trust level allows `capability`, for natives to call
+    /// before doing anything
+    pub fn check_capability(&self, capability: Capability) -> Result<(), RunTimeErrorKind> {
+        if self.trust_level.allows(capability) {
+            Ok(())
+        } else {
+            Err(RunTimeErrorKind::Forbidden {
+                capability,
+                level: self.trust_level,
+            })
+        }
+    }
+}
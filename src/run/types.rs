@@ -0,0 +1,118 @@
+//! the names `type`, `is`, `check` and `as` know values by, and the `types` global that lists
+//! them so scripts don't have to spell them out
+pub const NULL: &str = "null";
+pub const INT: &str = "int";
+pub const FLOAT: &str = "float";
+pub const BOOL: &str = "bool";
+pub const CHAR: &str = "char";
+pub const STR: &str = "str";
+pub const VEC: &str = "vec";
+pub const TUPLE: &str = "tuple";
+pub const MAP: &str = "map";
+pub const FN: &str = "fn";
+/// matches every value in a type expression
+pub const ANY: &str = "any";
+pub const ERROR: &str = "error";
+pub const ITERATOR: &str = "iterator";
+pub const FILE: &str = "file";
+pub const META_DATA: &str = "meta_data";
+pub const LIBRARY: &str = "library";
+pub const STDIN: &str = "stdin";
+pub const STDOUT: &str = "stdout";
+pub const STDERR: &str = "stderr";
+
+/// every name the standard library gives values, which are also their keys in `types`
+pub const NAMES: [&str; 19] = [
+    NULL, INT, FLOAT, BOOL, CHAR, STR, VEC, TUPLE, MAP, FN, ANY, ERROR, ITERATOR, FILE, META_DATA,
+    LIBRARY, STDIN, STDOUT, STDERR,
+];
+/// the types `as` converts to without a conversion
+pub const CASTS: [&str; 7] = [INT, FLOAT, BOOL, CHAR, STR, VEC, TUPLE];
+
+/// the kind of a value as a small tag, so checking it against a type name doesn't go
+/// through the name of the value's type. native objects all share one tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    Null,
+    Int,
+    Float,
+    Bool,
+    Char,
+    Str,
+    Vec,
+    Tuple,
+    Map,
+    Fn,
+    Native,
+}
+impl Tag {
+    /// the tag of values of the type `name`, `None` for `any`, native objects and
+    /// anything more than a plain name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            NULL => Tag::Null,
+            INT => Tag::Int,
+            FLOAT => Tag::Float,
+            BOOL => Tag::Bool,
+            CHAR => Tag::Char,
+            STR => Tag::Str,
+            VEC => Tag::Vec,
+            TUPLE => Tag::Tuple,
+            MAP => Tag::Map,
+            FN => Tag::Fn,
+            _ => return None,
+        })
+    }
+}
+/// whether two type names are the same, without comparing them character by character
+/// when both are one of the constants here
+pub fn same(a: &str, b: &str) -> bool {
+    std::ptr::eq(a, b) || a == b
+}
+
+/// the names a type expression like `vec<int|str>?` is made of
+pub fn names(typ: &str) -> impl Iterator<Item = &str> {
+    typ.split(['|', '<', '>', '?'])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
+/// the first name in `typ` that `known` doesn't accept
+pub fn unknown_name(typ: &str, known: impl Fn(&str) -> bool) -> Option<&str> {
+    names(typ).find(|name| !known(name))
+}
+/// the candidate closest to `name`, if one is close enough to be a typo of it
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+/// how many characters have to be inserted, removed, replaced or swapped with their
+/// neighbour to turn `a` into `b`
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
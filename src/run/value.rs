@@ -1,9 +1,12 @@
 use super::{
     code::{BinaryOperation, Closure, UnaryOperation},
     interpreter::{
-        Interpreter, RunTimeError, RunTimeErrorKind, STRING_MODULE, TUPLE_MODULE, VECTOR_MODULE,
+        Interpreter, RunTimeError, RunTimeErrorKind, BOOL_MODULE, CHAR_MODULE, FLOAT_MODULE,
+        INT_MODULE, STRING_MODULE, TUPLE_MODULE, VECTOR_MODULE,
     },
+    types,
 };
+use indexmap::IndexMap;
 use std::{
     cmp::Ordering,
     collections::HashMap,
@@ -15,6 +18,29 @@ use std::{
 };
 
 pub type Pointer<T> = Arc<Mutex<T>>;
+/// what a `Value::Map` holds, keeping its keys in the order they were first inserted so
+/// iterating and printing maps gives the same order on every run
+pub type Map = IndexMap<String, Value>;
+
+/// the element `index` refers to in a sequence of `len` elements, counting negative
+/// indices from the end so `-1` is the last one, or `None` if it's out of range
+pub fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    if index < 0 {
+        len.checked_sub(usize::try_from(index.unsigned_abs()).ok()?)
+    } else {
+        usize::try_from(index).ok().filter(|index| *index < len)
+    }
+}
+/// like `resolve_index` but for the positions between elements that inserting and
+/// splitting use, so `len` itself is in range and `-1` is the position before the last
+/// element
+pub fn resolve_position(index: i64, len: usize) -> Option<usize> {
+    if index < 0 {
+        len.checked_sub(usize::try_from(index.unsigned_abs()).ok()?)
+    } else {
+        usize::try_from(index).ok().filter(|index| *index <= len)
+    }
+}
 
 #[derive(Clone, Default)]
 pub enum Value {
@@ -27,7 +53,7 @@ pub enum Value {
     String(String),
     Vector(Pointer<Vec<Self>>),
     Tuple(Pointer<Box<[Self]>>),
-    Map(Pointer<HashMap<String, Self>>),
+    Map(Pointer<Map>),
     Fn(FnKind),
     NativeObject(Pointer<dyn NativeObject>),
 }
@@ -41,14 +67,25 @@ pub enum FnKind {
 #[derive(Debug, Clone)]
 pub struct Function {
     pub closure: Rc<Closure>,
+    /// the cells of the closure's upvalues, captured when it got created
+    pub upvalues: Vec<Pointer<Value>>,
 }
-pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Option<Value>, Box<dyn Error>>;
+/// natives return several results as a tuple (see `make_tuple!`), which `let a, b = f()`
+/// unpacks straight into locals. scripts see `Ok(None)` and `Ok(Some(Value::Null))` alike,
+/// as null, hosts calling natives directly can tell them apart
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Value]) -> Result<Option<Value>, Box<dyn Error>>;
 pub trait NativeObject {
     fn typ(&self) -> &'static str;
     #[allow(unused_variables)]
     fn get(&self, key: &str) -> Option<Value> {
         None
     }
+    /// handles `object.key = value`, so together with `get` fields can act as properties
+    /// that validate or compute on access
+    #[allow(unused_variables)]
+    fn set(&mut self, key: &str, value: Value) -> Result<(), Box<dyn Error>> {
+        Err(format!("can't set field {key:?} of {}", self.typ()).into())
+    }
     #[allow(unused_variables)]
     fn call(
         &self,
@@ -74,26 +111,162 @@ pub trait NativeObject {
     fn __str(&self) -> Option<Rc<NativeFn>> {
         None
     }
+    /// the length reported by `object:len()`
+    fn __len(&self) -> Option<usize> {
+        None
+    }
+    /// the values `iter(object)` and `for` loops go through
+    fn __iter(&self) -> Option<Box<dyn Iterator<Item = Value>>> {
+        None
+    }
+    /// handles `object[index]`, with negative indices left for the object to interpret
+    fn __index_int(&self, index: i64) -> Result<Value, RunTimeErrorKind> {
+        Err(RunTimeErrorKind::InvalidField {
+            head: self.typ(),
+            field: Value::Int(index).typ(),
+            key: index.to_string(),
+        })
+    }
+    /// converts the object for `object as "typ"`, falling back to the built-in casts on `None`
+    #[allow(unused_variables)]
+    fn cast(&self, typ: &str) -> Option<Value> {
+        None
+    }
 }
 
 unsafe impl Send for Function {}
 unsafe impl Sync for Function {}
 impl Value {
+    /// the value like `debug` shows it, cut short for error messages
+    pub fn snippet(&self) -> String {
+        const MAX_LEN: usize = 24;
+        let text = format!("{self:?}");
+        if text.chars().count() <= MAX_LEN {
+            return text;
+        }
+        text.chars()
+            .take(MAX_LEN - 3)
+            .chain("...".chars())
+            .collect()
+    }
     pub fn typ(&self) -> &'static str {
         match self {
-            Value::Null => "null",
-            Value::Int(_) => "int",
-            Value::Float(_) => "float",
-            Value::Bool(_) => "bool",
-            Value::Char(_) => "char",
-            Value::String(_) => "str",
-            Value::Vector(_) => "vec",
-            Value::Tuple(_) => "tuple",
-            Value::Map(_) => "map",
-            Value::Fn(_) => "fn",
+            Value::Null => types::NULL,
+            Value::Int(_) => types::INT,
+            Value::Float(_) => types::FLOAT,
+            Value::Bool(_) => types::BOOL,
+            Value::Char(_) => types::CHAR,
+            Value::String(_) => types::STR,
+            Value::Vector(_) => types::VEC,
+            Value::Tuple(_) => types::TUPLE,
+            Value::Map(_) => types::MAP,
+            Value::Fn(_) => types::FN,
             Value::NativeObject(arc) => arc.lock().unwrap().typ(),
         }
     }
+    /// the kind of the value, cheaper to compare than `typ`
+    pub fn tag(&self) -> types::Tag {
+        match self {
+            Value::Null => types::Tag::Null,
+            Value::Int(_) => types::Tag::Int,
+            Value::Float(_) => types::Tag::Float,
+            Value::Bool(_) => types::Tag::Bool,
+            Value::Char(_) => types::Tag::Char,
+            Value::String(_) => types::Tag::Str,
+            Value::Vector(_) => types::Tag::Vec,
+            Value::Tuple(_) => types::Tag::Tuple,
+            Value::Map(_) => types::Tag::Map,
+            Value::Fn(_) => types::Tag::Fn,
+            Value::NativeObject(_) => types::Tag::Native,
+        }
+    }
+    /// whether the value matches a type expression: a type name, `any`, a union like
+    /// `int|float`, a nullable `str?` or a container with its elements' type like `vec<int>`,
+    /// `tuple<str>` or `map<int>`, where every element gets checked
+    pub fn is_type(&self, typ: &str) -> bool {
+        let typ = typ.trim();
+        // plain names of built-in types are by far the most common
+        if let Some(tag) = types::Tag::from_name(typ) {
+            return self.tag() == tag;
+        }
+        let mut depth = 0;
+        for (idx, c) in typ.char_indices() {
+            match c {
+                '<' => depth += 1,
+                '>' => depth -= 1,
+                '|' if depth == 0 => {
+                    return self.is_type(&typ[..idx]) || self.is_type(&typ[idx + 1..])
+                }
+                _ => {}
+            }
+        }
+        if let Some(typ) = typ.strip_suffix('?') {
+            return matches!(self, Value::Null) || self.is_type(typ);
+        }
+        if let Some((container, elements)) =
+            typ.strip_suffix('>').and_then(|typ| typ.split_once('<'))
+        {
+            return match (container.trim(), self) {
+                (types::VEC, Value::Vector(arc)) => arc
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .all(|value| value.is_type(elements)),
+                (types::TUPLE, Value::Tuple(arc)) => arc
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .all(|value| value.is_type(elements)),
+                (types::MAP, Value::Map(arc)) => arc
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .all(|value| value.is_type(elements)),
+                _ => false,
+            };
+        }
+        typ == types::ANY || types::same(self.typ(), typ)
+    }
+    /// the most specific type expression `is_type` accepts for the value, like `vec<int|str>`
+    pub fn describe_type(&self) -> String {
+        let mut elements: Vec<String> = match self {
+            Value::Vector(arc) => arc
+                .lock()
+                .unwrap()
+                .iter()
+                .map(Self::describe_type)
+                .collect(),
+            Value::Tuple(arc) => arc
+                .lock()
+                .unwrap()
+                .iter()
+                .map(Self::describe_type)
+                .collect(),
+            Value::Map(arc) => arc
+                .lock()
+                .unwrap()
+                .values()
+                .map(Self::describe_type)
+                .collect(),
+            value => return value.typ().to_string(),
+        };
+        if elements.is_empty() {
+            return self.typ().to_string();
+        }
+        elements.sort();
+        elements.dedup();
+        format!("{}<{}>", self.typ(), elements.join("|"))
+    }
+    /// copies the top level of a vector, tuple or map into a new container, the values
+    /// in it are cloned like any other, so nested containers stay shared
+    pub fn shallow_copy(&self) -> Self {
+        match self {
+            Value::Vector(arc) => Value::Vector(Arc::new(Mutex::new(arc.lock().unwrap().clone()))),
+            Value::Tuple(arc) => Value::Tuple(Arc::new(Mutex::new(arc.lock().unwrap().clone()))),
+            Value::Map(arc) => Value::Map(Arc::new(Mutex::new(arc.lock().unwrap().clone()))),
+            value => value.clone(),
+        }
+    }
     pub fn field(
         self,
         interpreter: &mut Interpreter,
@@ -102,99 +275,56 @@ impl Value {
     ) -> Result<Value, RunTimeError> {
         Ok(match self {
             Value::String(string) => match field {
-                Value::Int(value) => if value <= -1 {
-                    if (value.unsigned_abs() - 1) as usize > string.len() {
-                        None
-                    } else {
-                        let index = string.len() - value.unsigned_abs() as usize;
-                        string.get(index..=index)
-                    }
-                } else {
-                    let index = value.unsigned_abs() as usize;
-                    string.get(index..=index)
-                }
-                .and_then(|s| s.chars().next())
-                .map(Value::Char)
-                .unwrap_or_default(),
-                Value::String(key) => {
-                    if let Some(module) = interpreter.globals.get(STRING_MODULE).cloned() {
-                        let module = module.lock().unwrap().clone();
-                        module.field(interpreter, key.into(), ln)?
-                    } else {
-                        Value::default()
-                    }
-                }
+                Value::Int(index) => resolve_index(index, string.len())
+                    .and_then(|index| string.get(index..=index))
+                    .and_then(|s| s.chars().next())
+                    .map(Value::Char)
+                    .unwrap_or_default(),
+                Value::String(key) => Self::method(interpreter, STRING_MODULE, key, ln)?,
                 field => {
                     return Err(RunTimeError {
                         err: RunTimeErrorKind::InvalidField {
-                            head: Value::Vector(Default::default()).typ(),
+                            head: Value::String(Default::default()).typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
                 }
             },
             Value::Vector(arc) => match field {
-                Value::Int(value) => {
+                Value::Int(index) => {
                     let values = arc.lock().unwrap();
-                    if value <= -1 {
-                        if (value.unsigned_abs() - 1) as usize > values.len() {
-                            None
-                        } else {
-                            values.get(values.len() - value.unsigned_abs() as usize)
-                        }
-                    } else {
-                        values.get(value.unsigned_abs() as usize)
-                    }
-                    .cloned()
-                    .unwrap_or_default()
-                }
-                Value::String(key) => {
-                    if let Some(module) = interpreter.globals.get(VECTOR_MODULE).cloned() {
-                        let module = module.lock().unwrap().clone();
-                        module.field(interpreter, key.into(), ln)?
-                    } else {
-                        Value::default()
-                    }
+                    resolve_index(index, values.len())
+                        .map(|index| values[index].clone())
+                        .unwrap_or_default()
                 }
+                Value::String(key) => Self::method(interpreter, VECTOR_MODULE, key, ln)?,
                 field => {
                     return Err(RunTimeError {
                         err: RunTimeErrorKind::InvalidField {
                             head: Value::Vector(Default::default()).typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
                 }
             },
             Value::Tuple(arc) => match field {
-                Value::Int(value) => {
+                Value::Int(index) => {
                     let values = arc.lock().unwrap();
-                    if value <= -1 {
-                        if (value.unsigned_abs() - 1) as usize > values.len() {
-                            None
-                        } else {
-                            values.get(values.len() - value.unsigned_abs() as usize)
-                        }
-                    } else {
-                        values.get(value.unsigned_abs() as usize)
-                    }
-                    .cloned()
-                    .unwrap_or_default()
-                }
-                Value::String(key) => {
-                    if let Some(module) = interpreter.globals.get(TUPLE_MODULE).cloned() {
-                        let module = module.lock().unwrap().clone();
-                        module.field(interpreter, key.into(), ln)?
-                    } else {
-                        Value::default()
-                    }
+                    resolve_index(index, values.len())
+                        .map(|index| values[index].clone())
+                        .unwrap_or_default()
                 }
+                Value::String(key) => Self::method(interpreter, TUPLE_MODULE, key, ln)?,
                 field => {
                     return Err(RunTimeError {
                         err: RunTimeErrorKind::InvalidField {
                             head: Value::Tuple(Arc::new(Mutex::new(Box::new([])))).typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
@@ -210,21 +340,45 @@ impl Value {
                         err: RunTimeErrorKind::InvalidField {
                             head: Value::Map(Default::default()).typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
                 }
             },
+            Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::Char(_) => match field {
+                Value::String(key) => {
+                    let module = match self {
+                        Value::Int(_) => INT_MODULE,
+                        Value::Float(_) => FLOAT_MODULE,
+                        Value::Bool(_) => BOOL_MODULE,
+                        _ => CHAR_MODULE,
+                    };
+                    Self::method(interpreter, module, key, ln)?
+                }
+                _ => {
+                    return Err(RunTimeError {
+                        err: RunTimeErrorKind::InvalidFieldHead(self.typ()),
+                        ln,
+                    })
+                }
+            },
             Value::NativeObject(arc) => match field {
                 Value::String(key) => {
                     let map = arc.lock().unwrap();
                     map.get(&key).unwrap_or_default()
                 }
+                Value::Int(index) => arc
+                    .lock()
+                    .unwrap()
+                    .__index_int(index)
+                    .map_err(|err| RunTimeError { err, ln })?,
                 field => {
                     return Err(RunTimeError {
                         err: RunTimeErrorKind::InvalidField {
-                            head: Value::Map(Default::default()).typ(),
+                            head: arc.lock().unwrap().typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
@@ -238,6 +392,22 @@ impl Value {
             }
         })
     }
+    /// the function `key` names in the module that holds the methods of a type, which is
+    /// how values without fields of their own get methods
+    fn method(
+        interpreter: &mut Interpreter,
+        module: &str,
+        key: String,
+        ln: usize,
+    ) -> Result<Value, RunTimeError> {
+        match interpreter.global(module) {
+            Some(module) => {
+                let module = module.lock().unwrap().clone();
+                module.field(interpreter, key.into(), ln)
+            }
+            None => Ok(Value::default()),
+        }
+    }
     pub fn set_field(
         self,
         field: Value,
@@ -246,73 +416,63 @@ impl Value {
     ) -> Result<(), RunTimeError> {
         match self {
             Value::Vector(arc) => match field {
-                Value::Int(value) => {
-                    let len = arc.lock().unwrap().len();
+                Value::Int(index) => {
                     let mut values = arc.lock().unwrap();
-                    let dst = if value <= -1 {
-                        if (value.unsigned_abs() - 1) as usize > len {
-                            None
-                        } else {
-                            values.get_mut(len - value.unsigned_abs() as usize)
-                        }
-                    } else {
-                        values.get_mut(value.unsigned_abs() as usize)
-                    }
-                    .ok_or(RunTimeError {
-                        err: RunTimeErrorKind::IndexOutOfRange { index: value, len },
+                    let len = values.len();
+                    let dst = resolve_index(index, len).ok_or(RunTimeError {
+                        err: RunTimeErrorKind::IndexOutOfRange { index, len },
                         ln,
                     })?;
-                    *dst = src;
+                    values[dst] = src;
                 }
                 field => {
                     return Err(RunTimeError {
                         err: RunTimeErrorKind::InvalidField {
                             head: Value::Vector(Default::default()).typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
                 }
             },
-            Value::Tuple(arc) => match field {
-                Value::Int(value) => {
-                    let len = arc.lock().unwrap().len();
-                    let mut values = arc.lock().unwrap();
-                    let dst = if value <= -1 {
-                        if (value.unsigned_abs() - 1) as usize > len {
-                            None
-                        } else {
-                            values.get_mut(len - value.unsigned_abs() as usize)
-                        }
-                    } else {
-                        values.get_mut(value.unsigned_abs() as usize)
-                    }
-                    .ok_or(RunTimeError {
-                        err: RunTimeErrorKind::IndexOutOfRange { index: value, len },
-                        ln,
-                    })?;
-                    *dst = src;
+            // tuples compare by value, so they can't change once created
+            Value::Tuple(_) => {
+                return Err(RunTimeError {
+                    err: RunTimeErrorKind::Immutable(self.typ()),
+                    ln,
+                })
+            }
+            Value::Map(arc) => match field {
+                Value::String(key) => {
+                    let mut map = arc.lock().unwrap();
+                    map.insert(key, src);
                 }
                 field => {
                     return Err(RunTimeError {
                         err: RunTimeErrorKind::InvalidField {
-                            head: Value::Vector(Default::default()).typ(),
+                            head: Value::Map(Default::default()).typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
                 }
             },
-            Value::Map(arc) => match field {
+            Value::NativeObject(arc) => match field {
                 Value::String(key) => {
-                    let mut map = arc.lock().unwrap();
-                    map.insert(key, src);
+                    let mut object = arc.lock().unwrap();
+                    object.set(&key, src).map_err(|err| RunTimeError {
+                        err: RunTimeErrorKind::Custom(err.to_string()),
+                        ln,
+                    })?;
                 }
                 field => {
                     return Err(RunTimeError {
                         err: RunTimeErrorKind::InvalidField {
-                            head: Value::Map(Default::default()).typ(),
+                            head: arc.lock().unwrap().typ(),
                             field: field.typ(),
+                            key: field.snippet(),
                         },
                         ln,
                     })
@@ -333,7 +493,9 @@ impl Value {
         right: Self,
         ln: usize,
     ) -> Result<Self, RunTimeError> {
-        if let (Value::Tuple(left), Value::Tuple(right)) = (&left, &right) {
+        if let (Value::Tuple(left), Value::Tuple(right), false) =
+            (&left, &right, op == BinaryOperation::Is)
+        {
             let left = left.lock().unwrap();
             let right = right.lock().unwrap();
             let mut new = Vec::with_capacity(left.len());
@@ -342,8 +504,15 @@ impl Value {
             }
             return Ok(Self::Tuple(Arc::new(Mutex::new(new.into_boxed_slice()))));
         }
+        if let (BinaryOperation::As, Value::NativeObject(object), Value::String(typ)) =
+            (op, &left, &right)
+        {
+            if let Some(value) = object.lock().unwrap().cast(typ) {
+                return Ok(value);
+            }
+        }
         Ok(match op {
-            BinaryOperation::Add => match (left, right) {
+            BinaryOperation::Add | BinaryOperation::AddAssign => match (left, right) {
                 (Value::Int(left), Value::Int(right)) => Value::Int(left + right),
                 (Value::Float(left), Value::Float(right)) => Value::Float(left + right),
                 (Value::Int(left), Value::Float(right)) => Value::Float(left as f64 + right),
@@ -351,11 +520,7 @@ impl Value {
                 (Value::String(left), Value::String(right)) => Value::String(left + &right),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -367,11 +532,7 @@ impl Value {
                 (Value::Float(left), Value::Int(right)) => Value::Float(left - right as f64),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -386,11 +547,7 @@ impl Value {
                 }
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -402,11 +559,7 @@ impl Value {
                 (Value::Float(left), Value::Int(right)) => Value::Float(left / right as f64),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -418,11 +571,7 @@ impl Value {
                 (Value::Float(left), Value::Int(right)) => Value::Float(left % right as f64),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -436,11 +585,7 @@ impl Value {
                 (Value::Float(left), Value::Int(right)) => Value::Float(left.powf(right as f64)),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -455,11 +600,7 @@ impl Value {
                 (Value::Char(left), Value::Char(right)) => Value::Bool(left < right),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -472,11 +613,7 @@ impl Value {
                 (Value::Char(left), Value::Char(right)) => Value::Bool(left > right),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -489,11 +626,7 @@ impl Value {
                 (Value::Char(left), Value::Char(right)) => Value::Bool(left <= right),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -506,51 +639,81 @@ impl Value {
                 (Value::Char(left), Value::Char(right)) => Value::Bool(left >= right),
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
             },
-            BinaryOperation::And => Value::Bool(bool::from(left) && bool::from(right)),
-            BinaryOperation::Or => Value::Bool(bool::from(left) && bool::from(right)),
+            // compiled code short-circuits these, both operands are known here already
+            BinaryOperation::And => {
+                if bool::from(left.clone()) {
+                    right
+                } else {
+                    left
+                }
+            }
+            BinaryOperation::Or => {
+                if bool::from(left.clone()) {
+                    left
+                } else {
+                    right
+                }
+            }
+            BinaryOperation::Coalesce => {
+                if left == Value::Null {
+                    right
+                } else {
+                    left
+                }
+            }
             BinaryOperation::Is => match (left, right) {
-                (left, Value::String(right)) => Value::Bool(left.typ() == right),
+                (left, Value::String(right)) => Value::Bool(left.is_type(&right)),
+                (left, Value::Vector(right)) => {
+                    let is = |value: &Value| match value {
+                        Value::String(typ) => left.is_type(typ),
+                        _ => false,
+                    };
+                    Value::Bool(right.lock().unwrap().iter().any(is))
+                }
+                (left, Value::Map(right)) => {
+                    let right = right.lock().unwrap();
+                    Value::Bool(right.values().any(|value| value == &left))
+                }
+                (left, Value::Tuple(right)) => {
+                    let is = |value: &Value| match value {
+                        Value::String(typ) => left.is_type(typ),
+                        _ => false,
+                    };
+                    Value::Bool(right.lock().unwrap().iter().any(is))
+                }
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
             },
             BinaryOperation::As => match (left, right) {
                 (left, Value::String(right)) => match right.as_str() {
-                    "int" => i64::try_from(left).ok().map(Value::Int).unwrap_or_default(),
-                    "float" => f64::try_from(left)
+                    types::INT => i64::try_from(left).ok().map(Value::Int).unwrap_or_default(),
+                    types::FLOAT => f64::try_from(left)
                         .ok()
                         .map(Value::Float)
                         .unwrap_or_default(),
-                    "bool" => Value::Bool(bool::from(left)),
-                    "char" => char::try_from(left)
+                    types::BOOL => Value::Bool(bool::from(left)),
+                    types::CHAR => char::try_from(left)
                         .ok()
                         .map(Value::Char)
                         .unwrap_or_default(),
-                    "str" => String::try_from(left)
+                    types::STR => String::try_from(left)
                         .ok()
                         .map(Value::String)
                         .unwrap_or_default(),
-                    "vec" => Vec::try_from(left)
+                    types::VEC => Vec::try_from(left)
                         .ok()
                         .map(|v| Value::Vector(Arc::new(Mutex::new(v))))
                         .unwrap_or_default(),
-                    "tuple" => TryFrom::<Value>::try_from(left)
+                    types::TUPLE => TryFrom::<Value>::try_from(left)
                         .ok()
                         .map(|v| Value::Tuple(Arc::new(Mutex::new(v))))
                         .unwrap_or_default(),
@@ -563,11 +726,7 @@ impl Value {
                 },
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -588,11 +747,7 @@ impl Value {
                 }
                 (left, right) => {
                     return Err(RunTimeError {
-                        err: RunTimeErrorKind::IllegalBinaryOperation {
-                            op,
-                            left: left.typ(),
-                            right: right.typ(),
-                        },
+                        err: RunTimeErrorKind::illegal_binary(op, &left, &right),
                         ln,
                     })
                 }
@@ -617,6 +772,7 @@ impl Value {
                         err: RunTimeErrorKind::IllegalUnaryOperation {
                             op,
                             right: right.typ(),
+                            value: right.snippet(),
                         },
                         ln,
                     })
@@ -638,6 +794,7 @@ impl PartialEq for Value {
             (Self::Char(left), Self::Char(right)) => left == right,
             (Self::String(left), Self::String(right)) => left == right,
             (Self::Vector(left), Self::Vector(right)) => Arc::as_ptr(left) == Arc::as_ptr(right),
+            (Self::Map(left), Self::Map(right)) => Arc::as_ptr(left) == Arc::as_ptr(right),
             (Self::Tuple(left), Self::Tuple(right)) => {
                 let left = left.lock().unwrap();
                 let right = right.lock().unwrap();
@@ -826,7 +983,7 @@ impl TryFrom<Value> for Box<[Value]> {
         })
     }
 }
-impl TryFrom<Value> for HashMap<String, Value> {
+impl TryFrom<Value> for Map {
     type Error = ();
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         Ok(match value {
@@ -835,6 +992,86 @@ impl TryFrom<Value> for HashMap<String, Value> {
         })
     }
 }
+/// strict conversion out of a value for host code, unlike the `TryFrom` impls that
+/// coerce like the std natives do
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Option<Self>;
+}
+/// conversion into a value, implemented for everything that has a `From` conversion
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+impl<T: Into<Value>> IntoValue for T {
+    fn into_value(self) -> Value {
+        self.into()
+    }
+}
+impl FromValue for Value {
+    fn from_value(value: Value) -> Option<Self> {
+        Some(value)
+    }
+}
+macro_rules! from_value {
+    ($typ:ty: $($pat:pat => $value:expr),* $(,)?) => {
+        impl FromValue for $typ {
+            fn from_value(value: Value) -> Option<Self> {
+                match value {
+                    $($pat => $value,)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+from_value!(bool: Value::Bool(v) => Some(v));
+from_value!(i64: Value::Int(v) => Some(v));
+from_value!(i32: Value::Int(v) => v.try_into().ok());
+from_value!(u32: Value::Int(v) => v.try_into().ok());
+from_value!(usize: Value::Int(v) => v.try_into().ok());
+from_value!(f64: Value::Float(v) => Some(v), Value::Int(v) => Some(v as f64));
+from_value!(f32: Value::Float(v) => Some(v as f32), Value::Int(v) => Some(v as f32));
+from_value!(char: Value::Char(v) => Some(v));
+from_value!(String: Value::String(v) => Some(v));
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Option<Self> {
+        let values = match value {
+            Value::Vector(v) => v.lock().unwrap().clone(),
+            Value::Tuple(v) => v.lock().unwrap().to_vec(),
+            _ => return None,
+        };
+        values.into_iter().map(T::from_value).collect()
+    }
+}
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: Value) -> Option<Self> {
+        let Value::Map(map) = value else {
+            return None;
+        };
+        let map = map.lock().unwrap().clone();
+        map.into_iter()
+            .map(|(k, v)| Some((k, T::from_value(v)?)))
+            .collect()
+    }
+}
+impl<T: FromValue> FromValue for IndexMap<String, T> {
+    fn from_value(value: Value) -> Option<Self> {
+        let Value::Map(map) = value else {
+            return None;
+        };
+        let map = map.lock().unwrap().clone();
+        map.into_iter()
+            .map(|(k, v)| Some((k, T::from_value(v)?)))
+            .collect()
+    }
+}
 impl From<i8> for Value {
     fn from(value: i8) -> Self {
         Self::Int(value.into())
@@ -915,6 +1152,11 @@ impl From<String> for Value {
         Self::String(value)
     }
 }
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or_default()
+    }
+}
 impl<T: Into<Value> + Clone> From<Vec<T>> for Value {
     fn from(value: Vec<T>) -> Self {
         Self::Vector(Arc::new(Mutex::new(
@@ -993,6 +1235,13 @@ impl<T: Into<Value>> From<HashMap<String, T>> for Value {
         )))
     }
 }
+impl<T: Into<Value>> From<IndexMap<String, T>> for Value {
+    fn from(value: IndexMap<String, T>) -> Self {
+        Self::Map(Arc::new(Mutex::new(
+            value.into_iter().map(|(k, v)| (k, v.into())).collect(),
+        )))
+    }
+}
 impl<T: Into<Value>> From<HashMap<&str, T>> for Value {
     fn from(value: HashMap<&str, T>) -> Self {
         Self::Map(Arc::new(Mutex::new(
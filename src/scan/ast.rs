@@ -14,6 +14,10 @@ pub enum Statement {
         param: Located<Parameter>,
         expr: Located<Expression>,
     },
+    Global {
+        name: Located<String>,
+        expr: Option<Located<Expression>>,
+    },
     Assign {
         op: AssignOperator,
         path: Located<Path>,
@@ -25,16 +29,31 @@ pub enum Statement {
         varargs: Option<Located<String>>,
         body: Located<Block>,
     },
+    FieldFn {
+        head: Located<Path>,
+        name: Located<String>,
+        method: bool,
+        params: Vec<Located<Parameter>>,
+        varargs: Option<Located<String>>,
+        body: Located<Block>,
+    },
+    Enum {
+        name: Located<String>,
+        members: Vec<(Located<String>, Located<Atom>)>,
+    },
     Call {
         head: Located<Path>,
-        args: Vec<Located<Expression>>,
+        args: Vec<Element>,
     },
     SelfCall {
         head: Located<Path>,
         field: Located<String>,
-        args: Vec<Located<Expression>>,
+        args: Vec<Element>,
     },
     Return(Option<Located<Expression>>),
+    /// a call run when the enclosing function returns
+    Defer(Located<Expression>),
+    Expression(Located<Expression>),
 
     If {
         cond: Located<Expression>,
@@ -51,6 +70,19 @@ pub enum Statement {
         cond: Located<Expression>,
         body: Located<Block>,
     },
+    /// runs `body` with `name` bound to `expr`, calling its `__exit` or `close` method
+    /// whenever the body is left
+    With {
+        expr: Located<Expression>,
+        name: Option<Located<String>>,
+        body: Located<Block>,
+    },
+    /// runs `body`, and `catch` with `name` bound to the error if one escapes the body
+    Try {
+        body: Located<Block>,
+        name: Option<Located<String>>,
+        catch: Located<Block>,
+    },
     WhileLet {
         param: Located<Parameter>,
         expr: Located<Expression>,
@@ -61,8 +93,36 @@ pub enum Statement {
         iter: Located<Expression>,
         body: Located<Block>,
     },
+    Match {
+        expr: Located<Expression>,
+        cases: Vec<Located<MatchCase>>,
+    },
+    /// `import "path" as name`, binding the module to a local named after the file if
+    /// there's no `as`
+    Import {
+        path: Located<String>,
+        name: Located<String>,
+    },
     Continue,
     Break,
+    Pass,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchCase {
+    pub patterns: Vec<Located<Pattern>>,
+    pub guard: Option<Located<Expression>>,
+    pub body: Located<Block>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard,
+    Binding(String),
+    Value(Expression),
+    Tuple(Vec<Located<Pattern>>),
+    Vector(Vec<Located<Pattern>>),
+    /// `{ kind = "circle", radius }`, matching maps that have every field, a lone name
+    /// binding the field of that name
+    Map(Vec<(Located<String>, Located<Pattern>)>),
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum AssignOperator {
@@ -94,12 +154,12 @@ pub enum Expression {
     Atom(Atom),
     Call {
         head: Box<Located<Self>>,
-        args: Vec<Located<Expression>>,
+        args: Vec<Element>,
     },
     SelfCall {
         head: Box<Located<Self>>,
         field: Located<String>,
-        args: Vec<Located<Expression>>,
+        args: Vec<Element>,
     },
     Field {
         head: Box<Located<Self>>,
@@ -135,6 +195,8 @@ pub enum BinaryOperator {
     GreaterEqual,
     And,
     Or,
+    /// `??`, the right operand if the left one is null
+    QuestionQuestion,
     Is,
     In,
     As,
@@ -154,9 +216,17 @@ pub enum Atom {
     Char(char),
     String(String),
     Tuple(Vec<Located<Expression>>),
-    Vector(Vec<Located<Expression>>),
-    Map(Vec<(Located<String>, Located<Expression>)>),
+    Vector(Vec<Element>),
+    Map(Vec<(Located<MapKey>, Located<Expression>)>),
     Expression(Box<Located<Expression>>),
+    /// the value of the module at the path, running it on its first import
+    Import(String),
+    /// `if cond then case else else_case`
+    If {
+        cond: Box<Located<Expression>>,
+        case: Box<Located<Expression>>,
+        else_case: Box<Located<Expression>>,
+    },
     Fn {
         params: Vec<Located<Parameter>>,
         varargs: Option<Located<String>>,
@@ -164,6 +234,25 @@ pub enum Atom {
     }
 }
 #[derive(Debug, Clone, PartialEq)]
+pub enum MapKey {
+    Name(String),
+    /// `[expr] = value`, the key is the value of `expr` when the map gets built
+    Computed(Located<Expression>),
+    /// `...expr`, merges in the entries of the map `expr`, which is the entry's value
+    Spread,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element {
+    Single(Located<Expression>),
+    /// `...expr`, the elements of the vector or tuple `expr`
+    Spread(Located<Expression>),
+}
+impl Element {
+    pub fn is_spread(&self) -> bool {
+        matches!(self, Self::Spread(_))
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
 pub enum Path {
     Ident(String),
     Field {
@@ -174,6 +263,9 @@ pub enum Path {
         head: Box<Located<Self>>,
         index: Box<Located<Expression>>,
     },
+    /// the head of an assignment target that isn't a path itself, like the call in
+    /// `f().x = 1`
+    Expression(Box<Located<Expression>>),
 }
 #[derive(Debug, Clone, PartialEq)]
 pub enum Parameter {
@@ -18,9 +18,15 @@ pub struct Line {
     pub tokens: Vec<Indexed<Token>>,
 }
 
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
 #[derive(Debug)]
 pub struct Lexer<'source> {
     pub lines: Enumerate<Lines<'source>>,
+    /// how many indentation levels a tab counts as
+    pub tab_width: usize,
+    /// the first indentation character seen, every other indented line has to use it too
+    pub indent_char: Option<char>,
 }
 #[derive(Debug)]
 pub struct LineLexer<'source> {
@@ -29,8 +35,13 @@ pub struct LineLexer<'source> {
 }
 impl<'source> From<&'source str> for Lexer<'source> {
     fn from(value: &'source str) -> Self {
+        // `lines` already drops the `\r` of Windows line endings, editors there also like
+        // to start files with a byte order mark
+        let value = value.strip_prefix('\u{feff}').unwrap_or(value);
         Self {
             lines: value.lines().enumerate(),
+            tab_width: DEFAULT_TAB_WIDTH,
+            indent_char: None,
         }
     }
 }
@@ -51,6 +62,7 @@ pub enum LexError {
     ExpectedEscape,
     UnclosedChar,
     UnclosedString,
+    MixedIndentation,
 }
 impl Display for LexError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -62,11 +74,16 @@ impl Display for LexError {
             Self::ExpectedEscape => write!(f, "expected escape character"),
             Self::UnclosedChar => write!(f, "unclosed character"),
             Self::UnclosedString => write!(f, "unclosed string"),
+            Self::MixedIndentation => write!(f, "mixed tabs and spaces in indentation"),
         }
     }
 }
 impl Error for LexError {}
 impl Lexer<'_> {
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
     pub fn lex(self) -> Result<Vec<Line>, Located<LexError>> {
         let (lines, errors): (Vec<_>, Vec<_>) = self.partition(Result::is_ok);
         let mut errors: Vec<_> = errors.into_iter().map(Result::unwrap_err).rev().collect();
@@ -74,8 +91,46 @@ impl Lexer<'_> {
             return Err(error);
         }
         let lines = lines.into_iter().map(Result::unwrap).collect();
-        Ok(lines)
+        Ok(join_lines(lines))
+    }
+}
+/// drops blank lines and appends lines that start inside an open bracket, or follow a line
+/// ending with a binary operator or `\`, to the previous line
+pub fn join_lines(lines: Vec<Line>) -> Vec<Line> {
+    let mut joined: Vec<Line> = Vec::with_capacity(lines.len());
+    let mut depth: usize = 0;
+    for line in lines {
+        let mut continued = depth > 0;
+        if let Some(last) = joined.last_mut() {
+            if let Some(Indexed { value: token, .. }) = last.tokens.last() {
+                if token.continues_line() && !line.tokens.is_empty() {
+                    continued = true;
+                    if token == &Token::Backslash {
+                        last.tokens.pop();
+                    }
+                }
+            }
+        }
+        for Indexed { value: token, .. } in &line.tokens {
+            match token {
+                Token::ParanLeft | Token::BracketLeft | Token::BraceLeft => depth += 1,
+                Token::ParanRight | Token::BracketRight | Token::BraceRight => {
+                    depth = depth.saturating_sub(1)
+                }
+                _ => {}
+            }
+        }
+        if continued {
+            if let Some(last) = joined.last_mut() {
+                last.tokens.extend(line.tokens);
+                continue;
+            }
+        }
+        if !line.tokens.is_empty() {
+            joined.push(line);
+        }
     }
+    joined
 }
 impl Iterator for Lexer<'_> {
     type Item = Result<Line, Located<LexError>>;
@@ -88,11 +143,25 @@ impl Iterator for Lexer<'_> {
                 if !c.is_ascii_whitespace() {
                     break;
                 }
+                indent += if *c == '\t' { self.tab_width } else { 1 };
                 line_lexer.chars.next();
-                indent += 1;
             }
             indent
         };
+        if line_lexer.chars.peek().is_some() {
+            for (col, c) in line
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .enumerate()
+            {
+                if *self.indent_char.get_or_insert(c) != c {
+                    return Some(Err(Located::new(
+                        LexError::MixedIndentation,
+                        Position::new(ln..ln, col..col),
+                    )));
+                }
+            }
+        }
         let (tokens, errors): (Vec<_>, Vec<_>) = line_lexer.partition(Result::is_ok);
         let mut errors: Vec<_> = errors.into_iter().map(Result::unwrap_err).rev().collect();
         if let Some(error) = errors.pop() {
@@ -234,8 +303,14 @@ impl Iterator for LineLexer<'_> {
                     Some(Ok(Indexed::new(Token::Greater, index)))
                 }
             }
+            '\\' => Some(Ok(Indexed::new(Token::Backslash, index))),
             '&' => Some(Ok(Indexed::new(Token::Ampersand, index))),
             '|' => Some(Ok(Indexed::new(Token::Pipe, index))),
+            '?' if matches!(self.chars.peek(), Some((_, '?'))) => {
+                let (col, _) = self.chars.next().unwrap();
+                index.end = col;
+                Some(Ok(Indexed::new(Token::QuestionQuestion, index)))
+            }
             '\'' => {
                 let c = match self
                     .chars
@@ -7,6 +7,7 @@ use super::{
 use std::{
     error::Error,
     fmt::{Debug, Display},
+    ops::Range,
 };
 
 pub trait Parsable: Debug + Clone + PartialEq {
@@ -43,8 +44,7 @@ impl Parser {
         while self
             .lines
             .first()
-            .and_then(|line| line.tokens.first())
-            .is_none()
+            .is_some_and(|line| line.tokens.is_empty())
         {
             self.advance_line();
         }
@@ -140,8 +140,17 @@ pub enum ParseError {
     UnexpectedEOL,
     ExpectedNewLine,
     ExpectedIndentedBlock,
+    UnexpectedIndent,
+    InconsistentDedent,
     UnexpectedToken(Token),
     Expected { expected: Token, got: Token },
+    DuplicateEnumMember(String),
+    DuplicateEnumValue,
+    ExpectedDeferCall,
+    DeferSpread,
+    ImportName(String),
+    InvalidAssignTarget,
+    ExpectedCatch,
 }
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -150,10 +159,27 @@ impl Display for ParseError {
             ParseError::UnexpectedEOL => write!(f, "unexpected end of line"),
             ParseError::ExpectedNewLine => write!(f, "expected new line"),
             ParseError::ExpectedIndentedBlock => write!(f, "expected indented block"),
+            ParseError::UnexpectedIndent => write!(f, "unexpected indent"),
+            ParseError::InconsistentDedent => {
+                write!(f, "dedent does not match any outer indentation level")
+            }
             ParseError::UnexpectedToken(token) => write!(f, "unexpected {}", token.name()),
             ParseError::Expected { expected, got } => {
                 write!(f, "expected {}, got {}", expected.name(), got.name())
             }
+            ParseError::DuplicateEnumMember(name) => write!(f, "duplicate enum member {name:?}"),
+            ParseError::DuplicateEnumValue => write!(f, "duplicate enum value"),
+            ParseError::ExpectedDeferCall => write!(f, "expected a call after defer"),
+            ParseError::DeferSpread => write!(f, "can't spread the arguments of a deferred call"),
+            ParseError::ImportName(path) => write!(
+                f,
+                "can't name the module {path:?} after its file, give it a name with `as`"
+            ),
+            ParseError::InvalidAssignTarget => write!(
+                f,
+                "invalid assignment target, only names, fields and indices can be assigned to"
+            ),
+            ParseError::ExpectedCatch => write!(f, "expected a catch block after the try block"),
         }
     }
 }
@@ -164,6 +190,12 @@ impl Parsable for Chunk {
         let mut stats = vec![];
         let mut pos = Position::default();
         while !parser.lines.is_empty() {
+            if parser.indent() > 0 {
+                return Err(Located::new(
+                    ParseError::UnexpectedIndent,
+                    Position::new(parser.ln()..parser.ln(), 0..parser.indent()),
+                ));
+            }
             let stat = Statement::parse(parser)?;
             pos.extend(&stat.pos);
             stats.push(stat);
@@ -186,11 +218,23 @@ impl Parsable for Block {
         }
         let mut stats = vec![];
         let mut pos = Position::default();
-        while parser.indent() >= base_indent {
+        while !parser.eof() && parser.indent() >= base_indent {
+            if parser.indent() > base_indent {
+                return Err(Located::new(
+                    ParseError::UnexpectedIndent,
+                    Position::new(parser.ln()..parser.ln(), 0..parser.indent()),
+                ));
+            }
             let stat = Statement::parse(parser)?;
             pos.extend(&stat.pos);
             stats.push(stat);
         }
+        if !parser.eof() && parser.indent() > parent_indent {
+            return Err(Located::new(
+                ParseError::InconsistentDedent,
+                Position::new(parser.ln()..parser.ln(), 0..parser.indent()),
+            ));
+        }
         Ok(Located::new(Self { stats }, pos))
     }
 }
@@ -202,101 +246,53 @@ impl Parsable for Statement {
             index: _,
         }) = parser.peek()
         {
-            let path = Path::parse(parser)?;
-            let Indexed {
-                value: token,
-                index,
-            } = parser.expect_any()?;
-            return match token {
-                Token::ParanLeft => {
-                    let mut pos = path.pos.clone();
-                    let mut args = vec![];
-                    while let Some(Indexed { value: token, .. }) = parser.peek() {
-                        if token == &Token::ParanRight {
-                            break;
-                        }
-                        let expr = Expression::parse(parser)?;
-                        args.push(expr);
-                        if let Some(Indexed {
-                            value: Token::ParanRight,
-                            index: _,
-                        }) = parser.peek()
-                        {
-                            break;
-                        }
-                        parser.expect(Token::Comma)?;
-                    }
-                    let Indexed {
-                        value: _,
-                        index: end,
-                    } = parser.expect(Token::ParanRight)?;
-                    pos.ln.end = parser.ln();
-                    pos.col.end = end.end;
-                    parser.expect_eol()?;
-                    parser.advance_line();
-                    Ok(Located::new(Self::Call { head: path, args }, pos))
-                }
-                Token::Colon => {
-                    let mut pos = path.pos.clone();
-                    let field: Located<String> = Parameter::parse_ident(parser)?;
-                    parser.expect(Token::ParanLeft)?;
-                    let mut args = vec![];
-                    while let Some(Indexed { value: token, .. }) = parser.peek() {
-                        if token == &Token::ParanRight {
-                            break;
-                        }
-                        let expr = Expression::parse(parser)?;
-                        args.push(expr);
-                        if let Some(Indexed {
-                            value: Token::ParanRight,
-                            index: _,
-                        }) = parser.peek()
-                        {
-                            break;
-                        }
-                        parser.expect(Token::Comma)?;
-                    }
-                    let Indexed {
-                        value: _,
-                        index: end,
-                    } = parser.expect(Token::ParanRight)?;
-                    pos.ln.end = parser.ln();
-                    pos.col.end = end.end;
-                    parser.expect_eol()?;
-                    parser.advance_line();
-                    Ok(Located::new(
-                        Self::SelfCall {
-                            head: path,
-                            field,
-                            args,
-                        },
-                        pos,
-                    ))
-                }
-                token => {
-                    if let Some(op) = AssignOperator::token(&token) {
-                        let expr = Expression::parse(parser)?;
-                        let mut pos = path.pos.clone();
-                        pos.extend(&expr.pos);
-                        parser.expect_eol()?;
-                        parser.advance_line();
-                        Ok(Located::new(Self::Assign { op, path, expr }, pos))
+            let line = parser.lines.first().cloned();
+            let lines = parser.lines.len();
+            return Self::parse_path(parser).or_else(|err| {
+                if let Some(line) = line {
+                    // the path may have run past the end of its line if it was left open
+                    if parser.lines.len() < lines {
+                        parser.lines.insert(0, line);
                     } else {
-                        Err(Located::new(
-                            ParseError::UnexpectedToken(token),
-                            Position::new(parser.ln()..parser.ln(), index),
-                        ))
+                        parser.lines[0] = line;
                     }
                 }
-            };
+                Self::parse_expression(parser).map_err(|expr_err| match expr_err.value {
+                    ParseError::InvalidAssignTarget => expr_err,
+                    _ => err,
+                })
+            });
         }
+        let ln = parser.ln();
         let Indexed {
             value: token,
             mut index,
         } = parser.expect_any()?;
         match token {
             Token::Let => {
-                let param = Parameter::parse(parser)?;
+                let mut param = Parameter::parse(parser)?;
+                // `let a, b = ...` unpacks like `let (a, b) = ...`
+                if let Parameter::Ident(ident) = &param.value {
+                    if let Some(Indexed {
+                        value: Token::Comma,
+                        index: _,
+                    }) = parser.peek()
+                    {
+                        let mut pos = param.pos.clone();
+                        let mut params = vec![Located::new(ident.clone(), param.pos.clone())];
+                        while let Some(Indexed {
+                            value: Token::Comma,
+                            index: _,
+                        }) = parser.peek()
+                        {
+                            parser.expect_any()?;
+                            let param = Parameter::parse_ident(parser)?;
+                            pos.extend(&param.pos);
+                            params.push(param);
+                        }
+                        param = Located::new(Parameter::Tuple(params), pos);
+                    }
+                }
                 parser.expect(Token::Equal)?;
                 let expr = Expression::parse(parser)?;
                 index.end = expr.pos.col.end;
@@ -304,7 +300,73 @@ impl Parsable for Statement {
                 parser.advance_line();
                 Ok(Located::new(
                     Self::LetBinding { param, expr },
-                    Position::new(parser.ln()..parser.ln(), index),
+                    Position::new(ln..ln, index),
+                ))
+            }
+            Token::Global => {
+                let name = Parameter::parse_ident(parser)?;
+                index.end = name.pos.col.end;
+                let expr = if parser.eol() {
+                    None
+                } else {
+                    parser.expect(Token::Equal)?;
+                    let expr = Expression::parse(parser)?;
+                    index.end = expr.pos.col.end;
+                    Some(expr)
+                };
+                parser.expect_eol()?;
+                parser.advance_line();
+                Ok(Located::new(
+                    Self::Global { name, expr },
+                    Position::new(ln..ln, index),
+                ))
+            }
+            Token::Enum => {
+                let name = Parameter::parse_ident(parser)?;
+                parser.expect(Token::Colon)?;
+                let mut members: Vec<(Located<String>, Located<Atom>)> = vec![];
+                let mut next = 0;
+                loop {
+                    let member = Parameter::parse_ident(parser)?;
+                    let value = if let Some(Indexed {
+                        value: Token::Equal,
+                        index: _,
+                    }) = parser.peek()
+                    {
+                        parser.expect_any()?;
+                        Self::parse_enum_value(parser)?
+                    } else {
+                        Located::new(Atom::Int(next), member.pos.clone())
+                    };
+                    if members.iter().any(|(other, _)| other.value == member.value) {
+                        return Err(Located::new(
+                            ParseError::DuplicateEnumMember(member.value),
+                            member.pos,
+                        ));
+                    }
+                    if members.iter().any(|(_, other)| other.value == value.value) {
+                        return Err(Located::new(ParseError::DuplicateEnumValue, value.pos));
+                    }
+                    if let Atom::Int(value) = value.value {
+                        next = value + 1;
+                    }
+                    index.end = value.pos.col.end;
+                    members.push((member, value));
+                    if let Some(Indexed {
+                        value: Token::Comma,
+                        index: _,
+                    }) = parser.peek()
+                    {
+                        parser.expect_any()?;
+                    } else {
+                        break;
+                    }
+                }
+                parser.expect_eol()?;
+                parser.advance_line();
+                Ok(Located::new(
+                    Self::Enum { name, members },
+                    Position::new(ln..ln, index),
                 ))
             }
             Token::Return => {
@@ -313,7 +375,7 @@ impl Parsable for Statement {
                     parser.advance_line();
                     return Ok(Located::new(
                         Self::Return(None),
-                        Position::new(parser.ln()..parser.ln(), index),
+                        Position::new(ln..ln, index),
                     ));
                 }
                 let expr = Expression::parse(parser)?;
@@ -322,12 +384,59 @@ impl Parsable for Statement {
                 parser.advance_line();
                 Ok(Located::new(
                     Self::Return(Some(expr)),
-                    Position::new(parser.ln()..parser.ln(), index),
+                    Position::new(ln..ln, index),
+                ))
+            }
+            Token::Defer => {
+                let expr = Expression::parse(parser)?;
+                match &expr.value {
+                    Expression::Call { args, .. } | Expression::SelfCall { args, .. } => {
+                        if args.iter().any(Element::is_spread) {
+                            return Err(Located::new(ParseError::DeferSpread, expr.pos));
+                        }
+                    }
+                    _ => return Err(Located::new(ParseError::ExpectedDeferCall, expr.pos)),
+                }
+                index.end = expr.pos.col.end;
+                parser.expect_eol()?;
+                parser.advance_line();
+                Ok(Located::new(
+                    Self::Defer(expr),
+                    Position::new(ln..ln, index),
                 ))
             }
             Token::Fn => {
                 let mut pos = Position::new(parser.ln()..parser.ln(), index);
-                let name = Parameter::parse_ident(parser)?;
+                let mut name = Parameter::parse_ident(parser)?;
+                let mut head: Option<Located<Path>> = None;
+                let mut method = false;
+                while let Some(Indexed {
+                    value: token @ (Token::Dot | Token::Colon),
+                    index: _,
+                }) = parser.peek()
+                {
+                    method = token == &Token::Colon;
+                    parser.expect_any()?;
+                    let field = Parameter::parse_ident(parser)?;
+                    head = Some(match head {
+                        Some(head) => {
+                            let mut pos = head.pos.clone();
+                            pos.extend(&name.pos);
+                            Located::new(
+                                Path::Field {
+                                    head: Box::new(head),
+                                    field: name,
+                                },
+                                pos,
+                            )
+                        }
+                        None => name.map(Path::Ident),
+                    });
+                    name = field;
+                    if method {
+                        break;
+                    }
+                }
                 parser.expect(Token::ParanLeft)?;
                 let mut params = vec![];
                 let mut varargs = None;
@@ -354,97 +463,30 @@ impl Parsable for Statement {
                 parser.expect(Token::ParanRight)?;
                 let body = Block::parse(parser)?;
                 pos.extend(&body.pos);
-                parser.expect_eol()?;
-                parser.advance_line();
-                Ok(Located::new(
-                    Self::Fn {
-                        name,
-                        params,
-                        varargs,
-                        body,
-                    },
-                    pos,
-                ))
-            }
-            Token::If => {
-                let mut pos = Position::new(parser.ln()..parser.ln(), index);
-                if let Some(Indexed {
-                    value: Token::Let,
-                    index: _,
-                }) = parser.peek()
-                {
-                    parser.expect_any()?;
-                    let param = Parameter::parse(parser)?;
-                    parser.expect(Token::Equal)?;
-                    let expr = Expression::parse(parser)?;
-                    let case = Block::parse(parser)?;
-                    pos.extend(&case.pos);
-                    let mut else_case = None;
-                    if let Some(Indexed {
-                        value: Token::Else,
-                        index: _,
-                    }) = parser.peek()
-                    {
-                        parser.expect_any()?;
-                        if let Some(Indexed {
-                            value: Token::If,
-                            index: _,
-                        }) = parser.peek()
-                        {
-                            let stat = Self::parse(parser)?;
-                            let stat_pos = stat.pos.clone();
-                            pos.extend(&stat_pos);
-                            else_case = Some(Located::new(Block { stats: vec![stat] }, stat_pos))
-                        } else {
-                            let block = Block::parse(parser)?;
-                            pos.extend(&block.pos);
-                            else_case = Some(block)
-                        }
-                    }
+                if let Some(head) = head {
                     return Ok(Located::new(
-                        Statement::IfLet {
-                            param,
-                            expr,
-                            case,
-                            else_case,
+                        Self::FieldFn {
+                            head,
+                            name,
+                            method,
+                            params,
+                            varargs,
+                            body,
                         },
                         pos,
                     ));
                 }
-                let cond = Expression::parse(parser)?;
-                let case = Block::parse(parser)?;
-                pos.extend(&case.pos);
-                let mut else_case = None;
-                if let Some(Indexed {
-                    value: Token::Else,
-                    index: _,
-                }) = parser.peek()
-                {
-                    parser.expect_any()?;
-                    if let Some(Indexed {
-                        value: Token::If,
-                        index: _,
-                    }) = parser.peek()
-                    {
-                        let stat = Self::parse(parser)?;
-                        let stat_pos = stat.pos.clone();
-                        pos.extend(&stat_pos);
-                        else_case = Some(Located::new(Block { stats: vec![stat] }, stat_pos))
-                    } else {
-                        let block = Block::parse(parser)?;
-                        pos.extend(&block.pos);
-                        else_case = Some(block)
-                    }
-                }
                 Ok(Located::new(
-                    Statement::If {
-                        cond,
-                        case,
-                        else_case,
+                    Self::Fn {
+                        name,
+                        params,
+                        varargs,
+                        body,
                     },
                     pos,
                 ))
             }
+            Token::If => Self::parse_if(parser, index),
             Token::While => {
                 let mut pos = Position::new(parser.ln()..parser.ln(), index);
                 if let Some(Indexed {
@@ -465,6 +507,73 @@ impl Parsable for Statement {
                 pos.extend(&body.pos);
                 Ok(Located::new(Statement::While { cond, body }, pos))
             }
+            Token::With => {
+                let mut pos = Position::new(parser.ln()..parser.ln(), index);
+                let (expr, name) = Statement::split_with_target(Expression::parse(parser)?);
+                let body = Block::parse(parser)?;
+                pos.extend(&body.pos);
+                Ok(Located::new(Statement::With { expr, name, body }, pos))
+            }
+            Token::Try => {
+                let mut pos = Position::new(parser.ln()..parser.ln(), index);
+                let indent = parser.indent();
+                let body = Block::parse(parser)?;
+                if parser.indent() != indent
+                    || !matches!(
+                        parser.peek(),
+                        Some(Indexed {
+                            value: Token::Catch,
+                            index: _,
+                        })
+                    )
+                {
+                    return Err(Located::new(ParseError::ExpectedCatch, pos));
+                }
+                parser.expect_any()?;
+                let name = match parser.peek() {
+                    Some(Indexed {
+                        value: Token::Ident(_),
+                        index: _,
+                    }) => Some(Parameter::parse_ident(parser)?),
+                    _ => None,
+                };
+                let catch = Block::parse(parser)?;
+                pos.extend(&catch.pos);
+                Ok(Located::new(Statement::Try { body, name, catch }, pos))
+            }
+            Token::Match => {
+                let mut pos = Position::new(parser.ln()..parser.ln(), index);
+                let parent_indent = parser.indent();
+                let expr = Expression::parse(parser)?;
+                parser.expect_eol()?;
+                parser.advance_line();
+                let base_indent = parser.indent();
+                if parent_indent >= base_indent {
+                    return Err(Located::new(
+                        ParseError::ExpectedIndentedBlock,
+                        Position::new(parser.ln()..parser.ln(), 0..0),
+                    ));
+                }
+                let mut cases = vec![];
+                while !parser.eof() && parser.indent() >= base_indent {
+                    if parser.indent() > base_indent {
+                        return Err(Located::new(
+                            ParseError::UnexpectedIndent,
+                            Position::new(parser.ln()..parser.ln(), 0..parser.indent()),
+                        ));
+                    }
+                    let case = MatchCase::parse(parser)?;
+                    pos.extend(&case.pos);
+                    cases.push(case);
+                }
+                if !parser.eof() && parser.indent() > parent_indent {
+                    return Err(Located::new(
+                        ParseError::InconsistentDedent,
+                        Position::new(parser.ln()..parser.ln(), 0..parser.indent()),
+                    ));
+                }
+                Ok(Located::new(Statement::Match { expr, cases }, pos))
+            }
             Token::For => {
                 let mut pos = Position::new(parser.ln()..parser.ln(), index);
                 let param = Parameter::parse(parser)?;
@@ -477,25 +586,347 @@ impl Parsable for Statement {
             Token::Continue => {
                 parser.expect_eol()?;
                 parser.advance_line();
-                Ok(Located::new(
-                    Self::Continue,
+                Ok(Located::new(Self::Continue, Position::new(ln..ln, index)))
+            }
+            Token::Break => {
+                parser.expect_eol()?;
+                parser.advance_line();
+                Ok(Located::new(Self::Break, Position::new(ln..ln, index)))
+            }
+            Token::Import => {
+                let mut pos = Position::new(parser.ln()..parser.ln(), index);
+                let path = Self::parse_import_path(parser)?;
+                pos.extend(&path.pos);
+                let name = if let Some(Indexed {
+                    value: Token::As,
+                    index: _,
+                }) = parser.peek()
+                {
+                    parser.expect_any()?;
+                    Parameter::parse_ident(parser)?
+                } else {
+                    Self::import_name(&path)?
+                };
+                pos.extend(&name.pos);
+                parser.expect_eol()?;
+                parser.advance_line();
+                Ok(Located::new(Self::Import { path, name }, pos))
+            }
+            Token::Pass => {
+                parser.expect_eol()?;
+                parser.advance_line();
+                Ok(Located::new(Self::Pass, Position::new(ln..ln, index)))
+            }
+            token => {
+                parser.lines[0]
+                    .tokens
+                    .insert(0, Indexed::new(token.clone(), index.clone()));
+                Self::parse_expression(parser).map_err(|err| match err.value {
+                    ParseError::InvalidAssignTarget => err,
+                    _ => Located::new(
+                        ParseError::UnexpectedToken(token),
+                        Position::new(parser.ln()..parser.ln(), index),
+                    ),
+                })
+            }
+        }
+    }
+}
+impl Statement {
+    /// `as` already parses as a cast, so `with expr as name` comes out as a cast to `name`
+    /// on the rightmost operand, which gets taken apart here
+    fn split_with_target(
+        expr: Located<Expression>,
+    ) -> (Located<Expression>, Option<Located<String>>) {
+        let Located { value, pos } = expr;
+        match value {
+            Expression::Binary {
+                op: BinaryOperator::As,
+                left,
+                right,
+            } => match *right {
+                Located {
+                    value: Expression::Atom(Atom::Path(Path::Ident(name))),
+                    pos: name_pos,
+                } => (*left, Some(Located::new(name, name_pos))),
+                right => {
+                    let expr = Expression::Binary {
+                        op: BinaryOperator::As,
+                        left,
+                        right: Box::new(right),
+                    };
+                    (Located::new(expr, pos), None)
+                }
+            },
+            Expression::Binary { op, left, right } => {
+                let (right, name) = Self::split_with_target(*right);
+                let expr = Expression::Binary {
+                    op,
+                    left,
+                    right: Box::new(right),
+                };
+                (Located::new(expr, pos), name)
+            }
+            value => (Located::new(value, pos), None),
+        }
+    }
+    /// parses the string literal after `import`
+    fn parse_import_path(parser: &mut Parser) -> Result<Located<String>, Located<ParseError>> {
+        let Indexed {
+            value: token,
+            index,
+        } = parser.expect_any()?;
+        let pos = Position::new(parser.ln()..parser.ln(), index);
+        match token {
+            Token::String(path) => Ok(Located::new(path, pos)),
+            token => Err(Located::new(ParseError::UnexpectedToken(token), pos)),
+        }
+    }
+    /// the rest of an `if` or `elif` statement after its keyword at `index`
+    fn parse_if(
+        parser: &mut Parser,
+        index: Range<usize>,
+    ) -> Result<Located<Self>, Located<ParseError>> {
+        let mut pos = Position::new(parser.ln()..parser.ln(), index);
+        let indent = parser.indent();
+        if let Some(Indexed {
+            value: Token::Let,
+            index: _,
+        }) = parser.peek()
+        {
+            parser.expect_any()?;
+            let param = Parameter::parse(parser)?;
+            parser.expect(Token::Equal)?;
+            let expr = Expression::parse(parser)?;
+            let case = Block::parse(parser)?;
+            pos.extend(&case.pos);
+            let else_case = Self::parse_else(parser, indent, &mut pos)?;
+            return Ok(Located::new(
+                Statement::IfLet {
+                    param,
+                    expr,
+                    case,
+                    else_case,
+                },
+                pos,
+            ));
+        }
+        let cond = Expression::parse(parser)?;
+        let case = Block::parse(parser)?;
+        pos.extend(&case.pos);
+        let else_case = Self::parse_else(parser, indent, &mut pos)?;
+        Ok(Located::new(
+            Statement::If {
+                cond,
+                case,
+                else_case,
+            },
+            pos,
+        ))
+    }
+    /// an `else` block following an `if` at `indent`, where `else if ...` and `elif ...`
+    /// become an else block holding just that `if`
+    fn parse_else(
+        parser: &mut Parser,
+        indent: usize,
+        pos: &mut Position,
+    ) -> Result<Option<Located<Block>>, Located<ParseError>> {
+        if parser.indent() != indent {
+            return Ok(None);
+        }
+        let stat = match parser.peek() {
+            Some(Indexed {
+                value: Token::Elif,
+                index: _,
+            }) => {
+                let Indexed { value: _, index } = parser.expect_any()?;
+                Self::parse_if(parser, index)?
+            }
+            Some(Indexed {
+                value: Token::Else,
+                index: _,
+            }) => {
+                parser.expect_any()?;
+                if let Some(Indexed {
+                    value: Token::If,
+                    index: _,
+                }) = parser.peek()
+                {
+                    Self::parse(parser)?
+                } else {
+                    let block = Block::parse(parser)?;
+                    pos.extend(&block.pos);
+                    return Ok(Some(block));
+                }
+            }
+            _ => return Ok(None),
+        };
+        let stat_pos = stat.pos.clone();
+        pos.extend(&stat_pos);
+        Ok(Some(Located::new(Block { stats: vec![stat] }, stat_pos)))
+    }
+    /// the file name of the import's path without its extension, if it's an identifier
+    fn import_name(path: &Located<String>) -> Result<Located<String>, Located<ParseError>> {
+        let name = std::path::Path::new(&path.value)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default();
+        let is_ident = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            && matches!(Token::ident(name.to_string()), Token::Ident(_));
+        if !is_ident {
+            return Err(Located::new(
+                ParseError::ImportName(path.value.clone()),
+                path.pos.clone(),
+            ));
+        }
+        Ok(Located::new(name.to_string(), path.pos.clone()))
+    }
+    /// parses the int or string literal an enum member is backed by
+    fn parse_enum_value(parser: &mut Parser) -> Result<Located<Atom>, Located<ParseError>> {
+        let Indexed {
+            value: token,
+            mut index,
+        } = parser.expect_any()?;
+        let atom = match token {
+            Token::Int(value) => Atom::Int(value),
+            Token::String(value) => Atom::String(value),
+            Token::Minus => {
+                let Indexed {
+                    value: token,
+                    index: end,
+                } = parser.expect_any()?;
+                index.end = end.end;
+                let Token::Int(value) = token else {
+                    return Err(Located::new(
+                        ParseError::UnexpectedToken(token),
+                        Position::new(parser.ln()..parser.ln(), end),
+                    ));
+                };
+                Atom::Int(-value)
+            }
+            token => {
+                return Err(Located::new(
+                    ParseError::UnexpectedToken(token),
                     Position::new(parser.ln()..parser.ln(), index),
                 ))
             }
-            Token::Break => {
+        };
+        Ok(Located::new(
+            atom,
+            Position::new(parser.ln()..parser.ln(), index),
+        ))
+    }
+    /// parses the statements starting with a path: calls, self calls and assignments
+    fn parse_path(parser: &mut Parser) -> Result<Located<Self>, Located<ParseError>> {
+        let path = Path::parse(parser)?;
+        let Indexed {
+            value: token,
+            index,
+        } = parser.expect_any()?;
+        match token {
+            Token::ParanLeft => {
+                let mut pos = path.pos.clone();
+                let mut args = vec![];
+                while let Some(Indexed { value: token, .. }) = parser.peek() {
+                    if token == &Token::ParanRight {
+                        break;
+                    }
+                    args.push(Atom::parse_element(parser)?);
+                    if let Some(Indexed {
+                        value: Token::ParanRight,
+                        index: _,
+                    }) = parser.peek()
+                    {
+                        break;
+                    }
+                    parser.expect(Token::Comma)?;
+                }
+                let Indexed {
+                    value: _,
+                    index: end,
+                } = parser.expect(Token::ParanRight)?;
+                pos.ln.end = parser.ln();
+                pos.col.end = end.end;
+                parser.expect_eol()?;
+                parser.advance_line();
+                Ok(Located::new(Self::Call { head: path, args }, pos))
+            }
+            Token::Colon => {
+                let mut pos = path.pos.clone();
+                let field: Located<String> = Parameter::parse_ident(parser)?;
+                parser.expect(Token::ParanLeft)?;
+                let mut args = vec![];
+                while let Some(Indexed { value: token, .. }) = parser.peek() {
+                    if token == &Token::ParanRight {
+                        break;
+                    }
+                    args.push(Atom::parse_element(parser)?);
+                    if let Some(Indexed {
+                        value: Token::ParanRight,
+                        index: _,
+                    }) = parser.peek()
+                    {
+                        break;
+                    }
+                    parser.expect(Token::Comma)?;
+                }
+                let Indexed {
+                    value: _,
+                    index: end,
+                } = parser.expect(Token::ParanRight)?;
+                pos.ln.end = parser.ln();
+                pos.col.end = end.end;
                 parser.expect_eol()?;
                 parser.advance_line();
                 Ok(Located::new(
-                    Self::Break,
-                    Position::new(parser.ln()..parser.ln(), index),
+                    Self::SelfCall {
+                        head: path,
+                        field,
+                        args,
+                    },
+                    pos,
                 ))
             }
-            token => Err(Located::new(
-                ParseError::UnexpectedToken(token),
-                Position::new(parser.ln()..parser.ln(), index),
-            )),
+            token => {
+                if let Some(op) = AssignOperator::token(&token) {
+                    let expr = Expression::parse(parser)?;
+                    let mut pos = path.pos.clone();
+                    pos.extend(&expr.pos);
+                    parser.expect_eol()?;
+                    parser.advance_line();
+                    Ok(Located::new(Self::Assign { op, path, expr }, pos))
+                } else {
+                    Err(Located::new(
+                        ParseError::UnexpectedToken(token),
+                        Position::new(parser.ln()..parser.ln(), index),
+                    ))
+                }
+            }
         }
     }
+    /// parses an expression whose value gets discarded, or an assignment into a field or
+    /// index of one, like `f().x = 1`
+    fn parse_expression(parser: &mut Parser) -> Result<Located<Self>, Located<ParseError>> {
+        let expr = Expression::parse(parser)?;
+        let mut pos = expr.pos.clone();
+        if let Some(op) = parser
+            .peek()
+            .and_then(|token| AssignOperator::token(&token.value))
+        {
+            parser.expect_any()?;
+            let path = Path::assign_target(expr)
+                .map_err(|expr| Located::new(ParseError::InvalidAssignTarget, expr.pos))?;
+            let expr = Expression::parse(parser)?;
+            pos.extend(&expr.pos);
+            parser.expect_eol()?;
+            parser.advance_line();
+            return Ok(Located::new(Self::Assign { op, path, expr }, pos));
+        }
+        parser.expect_eol()?;
+        parser.advance_line();
+        Ok(Located::new(Self::Expression(expr), pos))
+    }
 }
 impl AssignOperator {
     pub fn token(token: &Token) -> Option<Self> {
@@ -534,6 +965,168 @@ impl Parameter {
         ))
     }
 }
+impl Parsable for MatchCase {
+    type Error = ParseError;
+    fn parse(parser: &mut Parser) -> Result<Located<Self>, Located<Self::Error>> {
+        let Indexed { value: _, index } = parser.expect(Token::Case)?;
+        let mut pos = Position::new(parser.ln()..parser.ln(), index);
+        let mut patterns = vec![Pattern::parse(parser)?];
+        while let Some(Indexed {
+            value: Token::Comma,
+            index: _,
+        }) = parser.peek()
+        {
+            parser.expect_any()?;
+            patterns.push(Pattern::parse(parser)?);
+        }
+        let guard = if let Some(Indexed {
+            value: Token::If,
+            index: _,
+        }) = parser.peek()
+        {
+            parser.expect_any()?;
+            Some(Expression::parse(parser)?)
+        } else {
+            None
+        };
+        let body = Block::parse(parser)?;
+        pos.extend(&body.pos);
+        Ok(Located::new(
+            Self {
+                patterns,
+                guard,
+                body,
+            },
+            pos,
+        ))
+    }
+}
+impl Parsable for Pattern {
+    type Error = ParseError;
+    fn parse(parser: &mut Parser) -> Result<Located<Self>, Located<Self::Error>> {
+        match parser.peek() {
+            // a lone name binds the value, a path like `Color.red` is compared against
+            Some(Indexed {
+                value: Token::Ident(_),
+                index: _,
+            }) if matches!(
+                parser.lines[0].tokens.get(1).map(|token| &token.value),
+                None | Some(
+                    Token::Comma
+                        | Token::If
+                        | Token::ParanRight
+                        | Token::BracketRight
+                        | Token::BraceRight
+                )
+            ) =>
+            {
+                let Located { value: ident, pos } = Parameter::parse_ident(parser)?;
+                let pattern = if ident == "_" {
+                    Self::Wildcard
+                } else {
+                    Self::Binding(ident)
+                };
+                return Ok(Located::new(pattern, pos));
+            }
+            Some(Indexed {
+                value: Token::ParanLeft | Token::BracketLeft,
+                index: _,
+            }) => {
+                let line = parser.lines[0].clone();
+                match Self::parse_sequence(parser) {
+                    Ok(pattern) => return Ok(pattern),
+                    Err(_) => parser.lines[0] = line,
+                }
+            }
+            Some(Indexed {
+                value: Token::BraceLeft,
+                index: _,
+            }) => return Self::parse_map(parser),
+            _ => {}
+        }
+        let Located { value: expr, pos } = Expression::parse(parser)?;
+        Ok(Located::new(Self::Value(expr), pos))
+    }
+}
+impl Pattern {
+    /// parses `(a, b)` and `[a, b]` patterns, where a single pattern in parenthesis without
+    /// a trailing comma is only grouped
+    fn parse_sequence(parser: &mut Parser) -> Result<Located<Self>, Located<ParseError>> {
+        let Indexed { value: open, index } = parser.expect_any()?;
+        let vector = open == Token::BracketLeft;
+        let close = if vector {
+            Token::BracketRight
+        } else {
+            Token::ParanRight
+        };
+        let mut pos = Position::new(parser.ln()..parser.ln(), index);
+        let mut patterns = vec![];
+        let mut comma = false;
+        while !matches!(parser.peek(), Some(Indexed { value, .. }) if value == &close) {
+            patterns.push(Self::parse(parser)?);
+            if let Some(Indexed {
+                value: Token::Comma,
+                index: _,
+            }) = parser.peek()
+            {
+                parser.expect_any()?;
+                comma = true;
+            } else {
+                break;
+            }
+        }
+        pos.col.end = parser.expect(close)?.index.end;
+        if !vector && !comma && patterns.len() == 1 {
+            return Ok(patterns.pop().unwrap());
+        }
+        Ok(Located::new(
+            if vector {
+                Self::Vector(patterns)
+            } else {
+                Self::Tuple(patterns)
+            },
+            pos,
+        ))
+    }
+    /// parses `{ kind = "circle", radius }` patterns, where a lone name stands for
+    /// `name = name`
+    fn parse_map(parser: &mut Parser) -> Result<Located<Self>, Located<ParseError>> {
+        let Indexed { value: _, index } = parser.expect(Token::BraceLeft)?;
+        let mut pos = Position::new(parser.ln()..parser.ln(), index);
+        let mut fields = vec![];
+        while !matches!(
+            parser.peek(),
+            Some(Indexed {
+                value: Token::BraceRight,
+                index: _,
+            })
+        ) {
+            let key = Parameter::parse_ident(parser)?;
+            let pattern = if let Some(Indexed {
+                value: Token::Equal,
+                index: _,
+            }) = parser.peek()
+            {
+                parser.expect_any()?;
+                Self::parse(parser)?
+            } else {
+                key.clone().map(Self::Binding)
+            };
+            fields.push((key, pattern));
+            if let Some(Indexed {
+                value: Token::Comma,
+                index: _,
+            }) = parser.peek()
+            {
+                parser.expect_any()?;
+            } else {
+                break;
+            }
+        }
+        pos.col.end = parser.expect(Token::BraceRight)?.index.end;
+        Ok(Located::new(Self::Map(fields), pos))
+    }
+}
 impl Parsable for Parameter {
     type Error = ParseError;
     fn parse(parser: &mut Parser) -> Result<Located<Self>, Located<Self::Error>> {
@@ -632,6 +1225,7 @@ impl Parsable for Expression {
 }
 impl BinaryOperator {
     const LAYERS: &'static [&'static [Self]] = &[
+        &[Self::QuestionQuestion],
         &[Self::And, Self::Or],
         &[
             Self::EqualEqual,
@@ -667,6 +1261,7 @@ impl BinaryOperator {
             Token::GreaterEqual => Some(Self::GreaterEqual),
             Token::And => Some(Self::And),
             Token::Or => Some(Self::Or),
+            Token::QuestionQuestion => Some(Self::QuestionQuestion),
             Token::Is => Some(Self::Is),
             Token::In => Some(Self::In),
             Token::As => Some(Self::As),
@@ -762,8 +1357,7 @@ impl Expression {
                         if token == &Token::ParanRight {
                             break;
                         }
-                        let expr = Expression::parse(parser)?;
-                        args.push(expr);
+                        args.push(Atom::parse_element(parser)?);
                         if let Some(Indexed {
                             value: Token::ParanRight,
                             index: _,
@@ -797,8 +1391,7 @@ impl Expression {
                         if token == &Token::ParanRight {
                             break;
                         }
-                        let expr = Expression::parse(parser)?;
-                        args.push(expr);
+                        args.push(Atom::parse_element(parser)?);
                         if let Some(Indexed {
                             value: Token::ParanRight,
                             index: _,
@@ -844,6 +1437,7 @@ impl Expression {
                         value: _,
                         index: end,
                     } = parser.expect(Token::BracketRight)?;
+                    pos.ln.end = parser.ln();
                     pos.col.end = end.end;
                     Located::new(
                         Self::Index {
@@ -883,6 +1477,31 @@ impl Parsable for Atom {
             Token::Bool(v) => Ok(Located::new(Self::Bool(v), pos)),
             Token::Char(v) => Ok(Located::new(Self::Char(v), pos)),
             Token::String(v) => Ok(Located::new(Self::String(v), pos)),
+            Token::Import => {
+                let path = Statement::parse_import_path(parser)?;
+                pos.extend(&path.pos);
+                Ok(Located::new(Self::Import(path.value), pos))
+            }
+            Token::If => {
+                let cond = Expression::parse(parser)?;
+                parser.maybe_new_line();
+                parser.expect(Token::Then)?;
+                parser.maybe_new_line();
+                let case = Expression::parse(parser)?;
+                parser.maybe_new_line();
+                parser.expect(Token::Else)?;
+                parser.maybe_new_line();
+                let else_case = Expression::parse(parser)?;
+                pos.extend(&else_case.pos);
+                Ok(Located::new(
+                    Self::If {
+                        cond: Box::new(cond),
+                        case: Box::new(case),
+                        else_case: Box::new(else_case),
+                    },
+                    pos,
+                ))
+            }
             Token::ParanLeft => {
                 parser.maybe_new_line();
                 let expr = Expression::parse(parser)?;
@@ -939,10 +1558,10 @@ impl Parsable for Atom {
                     Ok(Located::new(Self::Vector(vec![]), pos))
                 } else {
                     parser.maybe_new_line();
-                    let mut exprs = vec![];
-                    let expr = Expression::parse(parser)?;
+                    let mut elements = vec![];
+                    let element = Self::parse_element(parser)?;
                     parser.maybe_new_line();
-                    exprs.push(expr);
+                    elements.push(element);
                     while let Some(Indexed { value: token, .. }) = parser.peek() {
                         if token == &Token::BracketRight {
                             break;
@@ -956,12 +1575,12 @@ impl Parsable for Atom {
                         {
                             break;
                         }
-                        let expr = Expression::parse(parser)?;
-                        exprs.push(expr);
+                        let element = Self::parse_element(parser)?;
+                        elements.push(element);
                         parser.maybe_new_line();
                     }
                     pos.col.end = parser.expect(Token::BracketRight)?.index.end;
-                    Ok(Located::new(Self::Vector(exprs), pos))
+                    Ok(Located::new(Self::Vector(elements), pos))
                 }
             }
             Token::BraceLeft => {
@@ -975,11 +1594,8 @@ impl Parsable for Atom {
                 } else {
                     parser.maybe_new_line();
                     let mut exprs = vec![];
-                    let field = Parameter::parse_ident(parser)?;
-                    parser.expect(Token::Equal)?;
-                    let expr = Expression::parse(parser)?;
+                    exprs.push(Self::parse_map_entry(parser)?);
                     parser.maybe_new_line();
-                    exprs.push((field, expr));
                     while let Some(Indexed { value: token, .. }) = parser.peek() {
                         if token == &Token::BraceRight {
                             break;
@@ -993,11 +1609,8 @@ impl Parsable for Atom {
                         {
                             break;
                         }
-                        let field = Parameter::parse_ident(parser)?;
-                        parser.expect(Token::Equal)?;
-                        let expr = Expression::parse(parser)?;
+                        exprs.push(Self::parse_map_entry(parser)?);
                         parser.maybe_new_line();
-                        exprs.push((field, expr));
                     }
                     pos.col.end = parser.expect(Token::BraceRight)?.index.end;
                     Ok(Located::new(Self::Map(exprs), pos))
@@ -1044,6 +1657,89 @@ impl Parsable for Atom {
         }
     }
 }
+impl Atom {
+    /// parses `name = expr`, `[expr] = expr` or the shorthand `name` for `name = name`
+    fn parse_element(parser: &mut Parser) -> Result<Element, Located<ParseError>> {
+        if let Some(Indexed {
+            value: Token::DotDotDot,
+            index: _,
+        }) = parser.peek()
+        {
+            parser.expect(Token::DotDotDot)?;
+            return Ok(Element::Spread(Expression::parse(parser)?));
+        }
+        Ok(Element::Single(Expression::parse(parser)?))
+    }
+    fn parse_map_entry(
+        parser: &mut Parser,
+    ) -> Result<(Located<MapKey>, Located<Expression>), Located<ParseError>> {
+        if let Some(Indexed {
+            value: Token::DotDotDot,
+            index: _,
+        }) = parser.peek()
+        {
+            let Indexed { value: _, index } = parser.expect(Token::DotDotDot)?;
+            let pos = Position::new(parser.ln()..parser.ln(), index);
+            let expr = Expression::parse(parser)?;
+            return Ok((Located::new(MapKey::Spread, pos), expr));
+        }
+        if let Some(Indexed {
+            value: Token::BracketLeft,
+            index: _,
+        }) = parser.peek()
+        {
+            let Indexed { value: _, index } = parser.expect(Token::BracketLeft)?;
+            let mut pos = Position::new(parser.ln()..parser.ln(), index);
+            let key = Expression::parse(parser)?;
+            pos.col.end = parser.expect(Token::BracketRight)?.index.end;
+            parser.expect(Token::Equal)?;
+            let expr = Expression::parse(parser)?;
+            return Ok((Located::new(MapKey::Computed(key), pos), expr));
+        }
+        let Located { value: name, pos } = Parameter::parse_ident(parser)?;
+        if let Some(Indexed {
+            value: Token::Equal,
+            index: _,
+        }) = parser.peek()
+        {
+            parser.expect(Token::Equal)?;
+            let expr = Expression::parse(parser)?;
+            return Ok((Located::new(MapKey::Name(name), pos), expr));
+        }
+        let expr = Expression::Atom(Atom::Path(Path::Ident(name.clone())));
+        Ok((
+            Located::new(MapKey::Name(name), pos.clone()),
+            Located::new(expr, pos),
+        ))
+    }
+}
+impl Path {
+    /// the field or index `expr` accesses as something to assign to, or `expr` back if it
+    /// isn't one. names, fields and indices are the only targets, while the head of a
+    /// field or index can be any expression, like in `f(x).y = 1`
+    fn assign_target(expr: Located<Expression>) -> Result<Located<Self>, Box<Located<Expression>>> {
+        let Located { value, pos } = expr;
+        let path = match value {
+            Expression::Atom(Atom::Path(path)) => path,
+            Expression::Field { head, field } => Self::Field {
+                head: Box::new(Self::head(*head)),
+                field,
+            },
+            Expression::Index { head, index } => Self::Index {
+                head: Box::new(Self::head(*head)),
+                index,
+            },
+            value => return Err(Box::new(Located::new(value, pos))),
+        };
+        Ok(Located::new(path, pos))
+    }
+    fn head(expr: Located<Expression>) -> Located<Self> {
+        Self::assign_target(expr).unwrap_or_else(|expr| {
+            let pos = expr.pos.clone();
+            Located::new(Self::Expression(expr), pos)
+        })
+    }
+}
 impl Parsable for Path {
     type Error = ParseError;
     fn parse(parser: &mut Parser) -> Result<Located<Self>, Located<Self::Error>> {
@@ -1075,6 +1771,7 @@ impl Parsable for Path {
                         value: _,
                         index: end,
                     } = parser.expect(Token::BracketRight)?;
+                    pos.ln.end = parser.ln();
                     pos.col.end = end.end;
                     Located::new(
                         Self::Index {
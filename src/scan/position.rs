@@ -22,6 +22,68 @@ pub struct PathLocated<T> {
     pub pos: Position,
 }
 
+/// tabs advance to the next multiple of this many terminal columns
+pub const TAB_STOP: usize = 8;
+
+/// how many terminal columns `c` takes up: none for combining marks and other zero-width
+/// characters, two for wide east asian characters and emoji, one otherwise
+pub fn char_width(c: char) -> usize {
+    match c {
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{0591}'..='\u{05BD}'
+        | '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200B}'..='\u{200F}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{E0100}'..='\u{E01EF}' => 0,
+        c if c.is_control() => 0,
+        '\u{1100}'..='\u{115F}'
+        | '\u{2E80}'..='\u{303E}'
+        | '\u{3041}'..='\u{33FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{A000}'..='\u{A4CF}'
+        | '\u{AC00}'..='\u{D7A3}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{1F300}'..='\u{1F64F}'
+        | '\u{1F680}'..='\u{1F6FF}'
+        | '\u{1F900}'..='\u{1F9FF}'
+        | '\u{1FA70}'..='\u{1FAFF}'
+        | '\u{20000}'..='\u{3FFFD}' => 2,
+        _ => 1,
+    }
+}
+/// the terminal column the char at index `col` of `line` starts at, with tabs advancing
+/// to the next tab stop. columns past the end of the line count as one wide each
+pub fn display_col(line: &str, col: usize) -> usize {
+    let mut width = 0;
+    let mut chars = 0;
+    for c in line.chars().take(col) {
+        width = match c {
+            '\t' => (width / TAB_STOP + 1) * TAB_STOP,
+            c => width + char_width(c),
+        };
+        chars += 1;
+    }
+    width + (col - chars)
+}
+/// the byte offset of the char at index `col` of `line`, counting past its end like
+/// `display_col`
+pub fn byte_col(line: &str, col: usize) -> usize {
+    match line.char_indices().nth(col) {
+        Some((idx, _)) => idx,
+        None => line.len() + (col - line.chars().count()),
+    }
+}
+
 impl Position {
     #[inline(always)]
     pub fn new(ln: Range<usize>, col: Range<usize>) -> Self {
@@ -39,6 +101,31 @@ impl Position {
         self.ln.end = other.ln.end;
         self.col.end = other.col.end;
     }
+    /// the line of `text` the position starts on, with tabs expanded, and a line of carets
+    /// under its columns that stays lined up with wide characters in a terminal
+    pub fn underline(&self, text: &str) -> Option<String> {
+        let line = text.lines().nth(self.ln.start)?;
+        let start = display_col(line, self.col.start);
+        // the columns are inclusive, like the index of a token
+        let end = display_col(line, self.col.end.max(self.col.start) + 1);
+        let mut expanded = String::new();
+        let mut width = 0;
+        for c in line.chars() {
+            if c == '\t' {
+                let next = (width / TAB_STOP + 1) * TAB_STOP;
+                expanded.push_str(&" ".repeat(next - width));
+                width = next;
+            } else {
+                expanded.push(c);
+                width += char_width(c);
+            }
+        }
+        Some(format!(
+            "{expanded}\n{}{}",
+            " ".repeat(start),
+            "^".repeat((end - start).max(1))
+        ))
+    }
 }
 impl<T> Indexed<T> {
     #[inline(always)]
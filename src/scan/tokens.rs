@@ -16,6 +16,7 @@ pub enum Token {
     DotDot,
     DotDotDot,
     Colon,
+    Backslash,
     EqualArrow,
     Exclamation,
     ParanLeft,
@@ -46,6 +47,7 @@ pub enum Token {
 
     Ampersand,
     Pipe,
+    QuestionQuestion,
 
     And,
     Or,
@@ -55,15 +57,26 @@ pub enum Token {
     As,
 
     Let,
+    Global,
+    Enum,
     Fn,
     If,
     Else,
+    Elif,
+    Then,
     Match,
+    Case,
     While,
     For,
     Return,
     Break,
     Continue,
+    Defer,
+    With,
+    Try,
+    Catch,
+    Import,
+    Pass,
 }
 
 impl Token {
@@ -71,6 +84,34 @@ impl Token {
     pub fn name(&self) -> &'static str {
         self.into()
     }
+    /// whether a line ending with this token continues on the next line
+    pub fn continues_line(&self) -> bool {
+        matches!(
+            self,
+            Token::Backslash
+                | Token::Plus
+                | Token::Minus
+                | Token::Star
+                | Token::Slash
+                | Token::Percent
+                | Token::Exponent
+                | Token::EqualEqual
+                | Token::ExclamationEqual
+                | Token::Less
+                | Token::Greater
+                | Token::LessEqual
+                | Token::GreaterEqual
+                | Token::Ampersand
+                | Token::Pipe
+                | Token::QuestionQuestion
+                | Token::And
+                | Token::Or
+                | Token::Not
+                | Token::Is
+                | Token::In
+                | Token::As
+        )
+    }
     pub fn ident(s: String) -> Self {
         match s.as_str() {
             "null" => Self::Null,
@@ -80,10 +121,15 @@ impl Token {
             "or" => Self::Or,
             "not" => Self::Not,
             "let" => Self::Let,
+            "global" => Self::Global,
+            "enum" => Self::Enum,
             "fn" => Self::Fn,
             "if" => Self::If,
             "else" => Self::Else,
+            "elif" => Self::Elif,
+            "then" => Self::Then,
             "match" => Self::Match,
+            "case" => Self::Case,
             "while" => Self::While,
             "for" => Self::For,
             "in" => Self::In,
@@ -92,6 +138,12 @@ impl Token {
             "return" => Self::Return,
             "break" => Self::Break,
             "continue" => Self::Continue,
+            "defer" => Self::Defer,
+            "with" => Self::With,
+            "try" => Self::Try,
+            "catch" => Self::Catch,
+            "import" => Self::Import,
+            "pass" => Self::Pass,
             _ => Self::Ident(s),
         }
     }
@@ -112,6 +164,7 @@ impl From<&Token> for &'static str {
             Token::DotDot => "..",
             Token::DotDotDot => "...",
             Token::Colon => ":",
+            Token::Backslash => "\\",
             Token::Exclamation => "!",
             Token::ParanLeft => "(",
             Token::ParanRight => ")",
@@ -139,6 +192,7 @@ impl From<&Token> for &'static str {
             Token::GreaterEqual => ">=",
             Token::Ampersand => "&",
             Token::Pipe => "|",
+            Token::QuestionQuestion => "??",
             Token::EqualArrow => "=>",
             Token::And => "and",
             Token::Or => "or",
@@ -147,15 +201,26 @@ impl From<&Token> for &'static str {
             Token::In => "in",
             Token::As => "as",
             Token::Let => "let",
+            Token::Global => "global",
+            Token::Enum => "enum",
             Token::Fn => "fn",
             Token::If => "if",
             Token::Else => "else",
+            Token::Elif => "elif",
+            Token::Then => "then",
             Token::Match => "match",
+            Token::Case => "case",
             Token::While => "while",
             Token::For => "for",
             Token::Return => "return",
             Token::Break => "break",
             Token::Continue => "continue",
+            Token::Defer => "defer",
+            Token::With => "with",
+            Token::Try => "try",
+            Token::Catch => "catch",
+            Token::Import => "import",
+            Token::Pass => "pass",
         }
     }
 }
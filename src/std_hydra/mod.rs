@@ -1,19 +1,29 @@
+pub use crate::run::error::ErrorObject;
 use crate::run::{
-    interpreter::{Interpreter, RunTimeErrorKind},
-    value::{FnKind, NativeFn, NativeObject, Value},
+    interpreter::{
+        Interpreter, RunTimeError, RunTimeErrorKind, BOOL_MODULE, CHAR_MODULE, FLOAT_MODULE,
+        INT_MODULE, MAP_MODULE, STRING_MODULE, TUPLE_MODULE, VECTOR_MODULE,
+    },
+    trust::Capability,
+    types,
+    value::{FnKind, Function, Map, NativeFn, NativeObject, Pointer, Value},
 };
 use crate::*;
 use std::{
     error::Error,
-    fmt::Display,
-    io::Write,
     rc::Rc,
     sync::{Arc, Mutex},
 };
+use std_tuple::{
+    _concat as _tuple_concat, _get as _tuple_get, _len as _tuple_len, _map as _tuple_map,
+    _slice as _tuple_slice, _to_vec as _tuple_to_vec, _updated as _tuple_updated,
+};
+use std_vector::_extend;
 
 pub mod std_math;
 pub mod std_fs;
 pub mod std_io;
+pub mod std_path;
 pub mod std_os;
 pub mod std_net;
 pub mod std_env;
@@ -25,13 +35,19 @@ pub mod std_string;
 pub mod std_vector;
 pub mod std_tuple;
 pub mod std_map;
+#[cfg(feature = "ffi")]
+pub mod std_ffi;
+pub mod parallel;
 
 pub fn import(interpreter: &mut Interpreter) {
     set_global!(interpreter: "print" = native_fn!(_print));
     set_global!(interpreter: "write" = native_fn!(_write));
     set_global!(interpreter: "input" = native_fn!(_input));
     set_global!(interpreter: "debug" = native_fn!(_debug));
-    set_global!(interpreter: "error" = native_fn!(_error));
+    set_global!(interpreter: "error" = Value::NativeObject(Arc::new(Mutex::new(ErrorModule))));
+    set_global!(interpreter: "pcall" = native_fn!(_pcall));
+    set_global!(interpreter: "loadstring" = native_fn!(_loadstring));
+    set_global!(interpreter: "eval" = native_fn!(_eval));
     set_global!(interpreter: "iter" = native_fn!(_iter));
     set_global!(interpreter: "next" = native_fn!(_next));
     set_global!(interpreter: "int" = native_fn!(_int));
@@ -39,47 +55,90 @@ pub fn import(interpreter: &mut Interpreter) {
     set_global!(interpreter: "bool" = native_fn!(_bool));
     set_global!(interpreter: "char" = native_fn!(_char));
     set_global!(interpreter: "str" = native_fn!(_str));
-    set_global!(interpreter: "vec" = native_fn!(_vec));
-    set_global!(interpreter: "tuple" = native_fn!(_tuple));
+    set_global!(interpreter: "vec" = Value::NativeObject(Arc::new(Mutex::new(VecModule))));
+    set_global!(interpreter: "tuple" = Value::NativeObject(Arc::new(Mutex::new(TupleModule))));
     set_global!(interpreter: "type" = native_fn!(_type));
+    set_global!(interpreter: "types" = type_names());
     set_global!(interpreter: "check" = native_fn!(_check));
+    set_global!(interpreter: "expect" = native_fn!(_expect));
+    set_global!(interpreter: "conversion" = native_fn!(_conversion));
+    set_global!(interpreter: "freeze" = native_fn!(_freeze));
+    set_global!(interpreter: "is_frozen" = native_fn!(_is_frozen));
+    set_global!(interpreter: "nameof" = native_fn!(_nameof));
     set_global!(interpreter: "enumerate" = native_fn!(_enumerate));
-    std_math::import(interpreter);
-    std_fs::import(interpreter);
-    std_io::import(interpreter);
-    std_os::import(interpreter);
-    std_net::import(interpreter);
-    std_env::import(interpreter);
-    std_int::import(interpreter);
-    std_float::import(interpreter);
-    std_bool::import(interpreter);
-    std_char::import(interpreter);
-    std_string::import(interpreter);
-    std_vector::import(interpreter);
-    std_tuple::import(interpreter);
-    std_map::import(interpreter);
+    set_global!(interpreter: "range" = native_fn!(_range));
+    set_global!(interpreter: "len" = native_fn!(_len));
+    set_global!(interpreter: "sorted" = native_fn!(_sorted));
+    set_global!(interpreter: "reversed" = native_fn!(_reversed));
+    set_global!(interpreter: "unique" = native_fn!(_unique));
+    std_math::import_globals(interpreter);
+    // the modules only get built once a script reads them, most scripts use a few at most
+    interpreter.set_lazy_global("math", std_math::import);
+    #[cfg(not(feature = "wasm"))]
+    {
+        interpreter.set_lazy_global("fs", std_fs::import);
+        interpreter.set_lazy_global("os", std_os::import);
+        interpreter.set_lazy_global("net", std_net::import);
+    }
+    #[cfg(feature = "wasm")]
+    {
+        stub_module(interpreter, "fs", &["open", "list", "meta_data"]);
+        stub_module(
+            interpreter,
+            "os",
+            &["id", "exit", "time", "quote", "split_args"],
+        );
+        stub_module(interpreter, "net", &[]);
+    }
+    interpreter.set_lazy_global("io", std_io::import);
+    interpreter.set_lazy_global("path", std_path::import);
+    interpreter.set_lazy_global("env", std_env::import);
+    interpreter.set_lazy_global(INT_MODULE, std_int::import);
+    interpreter.set_lazy_global(FLOAT_MODULE, std_float::import);
+    interpreter.set_lazy_global(BOOL_MODULE, std_bool::import);
+    interpreter.set_lazy_global(CHAR_MODULE, std_char::import);
+    interpreter.set_lazy_global(STRING_MODULE, std_string::import);
+    interpreter.set_lazy_global(VECTOR_MODULE, std_vector::import);
+    interpreter.set_lazy_global(TUPLE_MODULE, std_tuple::import);
+    interpreter.set_lazy_global(MAP_MODULE, std_map::import);
+}
+
+/// sets `name` to a module whose functions all error, for builds that can't provide it
+#[cfg(feature = "wasm")]
+pub fn stub_module(interpreter: &mut Interpreter, name: &'static str, funcs: &[&'static str]) {
+    let mut module = Map::new();
+    for &func in funcs {
+        let stub: Rc<NativeFn> =
+            Rc::new(move |_, _| Err(format!("{name}.{func} isn't available in this build").into()));
+        module.insert(func.to_string(), Value::Fn(FnKind::Native(stub)));
+    }
+    set_global!(interpreter: name = make_map!(module));
 }
 
-define_native_fn!(_print (_i args): => {
-    println!("{}", args.map(|(_, v)| v.to_string()).collect::<Vec<String>>().join(" "));
+define_native_fn!(_print (interpreter args): => {
+    let texts = args.map(|(_, v)| interpreter.display(&v)).collect::<Result<Vec<_>, _>>()?;
+    interpreter.write_output(&format!("{}\n", texts.join(" ")))?;
     Ok(None)
 });
-define_native_fn!(_write (_i args): => {
-    print!("{}", args.map(|(_, v)| v.to_string()).collect::<Vec<String>>().join(" "));
+define_native_fn!(_write (interpreter args): => {
+    let texts = args.map(|(_, v)| interpreter.display(&v)).collect::<Result<Vec<_>, _>>()?;
+    interpreter.write_output(&texts.join(" "))?;
     Ok(None)
 });
-define_native_fn!(_input (_i args): text = typed!(args: String) => {
-    let mut input = String::new();
-    print!("{text}");
-    std::io::stdout().flush()?;
-    std::io::stdin().read_line(&mut input)?;
-    Ok(Some(Value::String(input)))
+define_native_fn!(_input (interpreter args): text = typed!(args: String) => {
+    interpreter.write_output(&text)?;
+    Ok(Some(interpreter.nondeterministic("input", || {
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        Ok(Value::String(std_io::unix_newlines(input)))
+    })?))
 });
-define_native_fn!(_debug (_i args): => {
+define_native_fn!(_debug (interpreter args): => {
     let mut args = args.map(|(_, v)| {
-        println!("{v:?}");
-        v
-    }).collect::<Vec<Value>>();
+        let text = interpreter.debug(&v)?;
+        interpreter.write_output(&format!("{text}\n"))?;
+        Ok(v)
+    }).collect::<Result<Vec<Value>, Box<dyn Error>>>()?;
     if args.is_empty() {
         return Ok(None)
     }
@@ -90,44 +149,168 @@ define_native_fn!(_debug (_i args): => {
         args.into_boxed_slice()
     )))))
 });
-#[derive(Debug, Clone, PartialEq)]
-pub struct ErrorObject {
-    msg: String,
-    path: Option<String>,
-    ln: usize,
+/// the `error` global, raising errors when called and holding the helpers to build them
+pub struct ErrorModule;
+impl NativeObject for ErrorModule {
+    fn typ(&self) -> &'static str {
+        "error-module"
+    }
+    fn get(&self, key: &str) -> Option<Value> {
+        match key {
+            "__call" => Some(native_fn!(_error)),
+            "new" => Some(native_fn!(_error_new)),
+            "wrap" => Some(native_fn!(_error_wrap)),
+            "kind" => Some(native_fn!(_error_kind)),
+            _ => None,
+        }
+    }
 }
-impl ErrorObject {
-    pub const TYPE: &'static str = "error";
+define_native_fn!(_error (i args): msg = typed!(args), kind = typed!(args: String?) => {
+    if ErrorObject::kind_of(&msg).is_some() {
+        return Err(ErrorObject::raise(msg))
+    }
+    let err = ErrorObject::new(i, msg.to_string(), kind, None);
+    Err(ErrorObject::raise(Value::NativeObject(Arc::new(Mutex::new(err)))))
+});
+define_native_fn!(_error_new (i args): msg = typed!(args: String), kind = typed!(args: String?) => {
+    let err = ErrorObject::new(i, msg, kind, None);
+    Ok(Some(Value::NativeObject(Arc::new(Mutex::new(err)))))
+});
+define_native_fn!(_error_wrap (i args): cause = typed!(args), msg = typed!(args: String) => {
+    let kind = ErrorObject::kind_of(&cause).map(|kind| kind.to_string());
+    let err = ErrorObject::new(i, msg, kind, Some(cause));
+    Ok(Some(Value::NativeObject(Arc::new(Mutex::new(err)))))
+});
+define_native_fn!(_error_kind (_i args): err = typed!(args) => {
+    Ok(ErrorObject::kind_of(&err))
+});
+// `pcall(func, ...args)` calls `func` and gives `(true, result)`, or `(false, err)` with
+// the error a `catch` would get if it failed. exceeding a budget isn't caught
+define_native_fn!(_pcall (i args): func = typed!(args) => {
+    let args = args.map(|(_, v)| v).collect::<Vec<Value>>();
+    let depth = i.call_stack.len();
+    let ln = i.ln().unwrap_or_default();
+    match i.call_now(func, &args, ln) {
+        Ok(value) => Ok(Some(make_tuple!(true, value))),
+        Err(err @ RunTimeError { err: RunTimeErrorKind::BudgetExceeded, .. }) => {
+            Err(err.into())
+        }
+        Err(err) => {
+            // drop the frames the error unwound out of
+            match i.unwind(depth, err) {
+                (err @ RunTimeError { err: RunTimeErrorKind::BudgetExceeded, .. }, _) => {
+                    Err(err.into())
+                }
+                (_, value) => Ok(Some(make_tuple!(false, value))),
+            }
+        }
+    }
+});
+
+/// compiles `src` into a function running it as a chunk, with `name` as its path in
+/// syntax errors and tracebacks
+pub fn load_chunk(src: &str, name: Option<String>) -> Result<Function, Box<dyn Error>> {
+    let closure = compile::<Chunk>(src, name.clone()).map_err(|err| {
+        format!(
+            "{}:{}:{}: {}",
+            name.as_deref().unwrap_or("<string>"),
+            err.pos.ln.start + 1,
+            err.pos.col.start + 1,
+            err.value
+        )
+    })?;
+    Ok(Function {
+        closure: Rc::new(closure),
+        upvalues: vec![],
+    })
 }
-impl NativeObject for ErrorObject {
+// `loadstring(src, name?)` compiles `src` without running it, the function it gives runs
+// the code with the globals of the caller and returns what it returns
+define_native_fn!(_loadstring (i args): src = typed!(args: String), name = typed!(args: String?) => {
+    i.check_capability(Capability::LoadCode)?;
+    let func = load_chunk(&src, name)?;
+    Ok(Some(Value::Fn(FnKind::Function(Arc::new(Mutex::new(func))))))
+});
+// `eval(src)` compiles and runs `src` right away, giving what it returns
+define_native_fn!(_eval (i args): src = typed!(args: String) => {
+    i.check_capability(Capability::LoadCode)?;
+    let func = load_chunk(&src, None)?;
+    i.call(&func, &[], None).map_err(Box::new)?;
+    Ok(i.run().map_err(Box::new)?)
+});
+/// the `vec` global, building vectors when called and holding the bulk constructors
+pub struct VecModule;
+impl NativeObject for VecModule {
     fn typ(&self) -> &'static str {
-        Self::TYPE
+        "vec-module"
     }
     fn get(&self, key: &str) -> Option<Value> {
         match key {
-            "msg" => Some(Value::String(self.msg.clone())),
-            "path" => self.path.clone().map(Value::String),
-            "ln" => Some(Value::Int(self.ln as i64)),
+            "__call" => Some(native_fn!(_vec)),
+            "with_capacity" => Some(native_fn!(_vec_with_capacity)),
+            "filled" => Some(native_fn!(_vec_filled)),
+            "from_iter" => Some(native_fn!(_vec_from_iter)),
+            "extend" => Some(native_fn!(_extend)),
+            "par_map" => Some(native_fn!(_vec_par_map)),
+            "par_filter" => Some(native_fn!(_vec_par_filter)),
             _ => None,
         }
     }
 }
-impl Display for ErrorObject {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            self.msg
-        )
+/// the `tuple` global, converting values to tuples when called. its functions take the
+/// tuple first and return new tuples, like the `__tuple` methods
+pub struct TupleModule;
+impl NativeObject for TupleModule {
+    fn typ(&self) -> &'static str {
+        "tuple-module"
+    }
+    fn get(&self, key: &str) -> Option<Value> {
+        match key {
+            "__call" => Some(native_fn!(_tuple)),
+            "len" => Some(native_fn!(_tuple_len)),
+            "get" => Some(native_fn!(_tuple_get)),
+            "to_vec" => Some(native_fn!(_tuple_to_vec)),
+            "concat" => Some(native_fn!(_tuple_concat)),
+            "slice" => Some(native_fn!(_tuple_slice)),
+            "map" => Some(native_fn!(_tuple_map)),
+            "updated" => Some(native_fn!(_tuple_updated)),
+            _ => None,
+        }
     }
 }
-impl Error for ErrorObject {}
-define_native_fn!(_error (i args): msg = typed!(args: String) => {
-    Err(ErrorObject {
-        msg,
-        path: i.path().cloned(),
-        ln: i.ln().unwrap_or_default(),
-    }.into())
+define_native_fn!(_vec_with_capacity (_i args): capacity = typed!(args: Int) => {
+    let mut values = Vec::new();
+    let capacity = usize::try_from(capacity)
+        .map_err(|_| format!("negative vec capacity {capacity}"))?;
+    values.try_reserve(capacity)?;
+    Ok(Some(make_vec!(values)))
+});
+// a container `value` is shared by all the elements, not copied
+define_native_fn!(_vec_filled (_i args): amount = typed!(args: Int), value = typed!(args) => {
+    let amount = usize::try_from(amount).map_err(|_| format!("negative vec length {amount}"))?;
+    let mut values = Vec::new();
+    values.try_reserve(amount)?;
+    values.resize(amount, value);
+    Ok(Some(make_vec!(values)))
+});
+define_native_fn!(_vec_from_iter (interpreter args): iter = typed!(args) => {
+    Ok(Some(make_vec!(collect_iter(interpreter, iter)?)))
+});
+// `vec.par_map(v, func)` maps `v` like `v:map(func)`, splitting it across worker threads
+// when `func` allows it, see `parallel`
+define_native_fn!(_vec_par_map (i args): values = typed!(args: Vector), func = typed!(args: Fn) => {
+    let values = values.lock().unwrap().clone();
+    let ln = i.ln().unwrap_or_default();
+    let values = parallel::par_map(i, values, Value::Fn(func), ln).map_err(Box::new)?;
+    Ok(Some(make_vec!(values)))
+});
+// `vec.par_filter(v, func)` keeps the values of `v` that `func` returns something truthy
+// for, splitting `v` like `vec.par_map`
+define_native_fn!(_vec_par_filter (i args): values = typed!(args: Vector), func = typed!(args: Fn) => {
+    let values = values.lock().unwrap().clone();
+    let ln = i.ln().unwrap_or_default();
+    let values = parallel::par_filter(i, values, Value::Fn(func), ln).map_err(Box::new)?;
+    Ok(Some(make_vec!(values)))
 });
 
 pub struct IteratorObject {
@@ -143,7 +326,7 @@ impl NativeObject for IteratorObject {
     fn get(&self, key: &str) -> Option<Value> {
         match key {
             "next" => Some(Value::Fn(FnKind::Native(Rc::clone(&self.fn_next)))),
-            _ => None,
+            key => iterator_method(key),
         }
     }
     fn call_mut(
@@ -161,7 +344,7 @@ impl NativeObject for IteratorObject {
     }
 }
 impl IteratorObject {
-    pub const TYPE: &'static str = "iterator";
+    pub const TYPE: &'static str = types::ITERATOR;
     pub fn next_(&mut self) -> Option<Value> {
         self.iter.next()
     }
@@ -170,6 +353,195 @@ impl IteratorObject {
         _self.call_mut("next", i, args.map(|(_, v)| v).collect())
     });
 }
+
+/// an iterator going through another one, made by the combinators of iterators like
+/// `it:map(f)`. nothing is taken from the source or called before `next` asks for it.
+/// its state lives outside the object's lock, so the functions it calls can use the
+/// object while it runs
+pub struct IteratorStage {
+    pub state: Pointer<StageState>,
+}
+pub struct StageState {
+    pub source: Value,
+    pub kind: StageKind,
+    /// set while `next` runs, asking for the next value from inside it is an error
+    pub running: bool,
+}
+pub enum StageKind {
+    Map(Value),
+    Filter(Value),
+    /// how many more values it gives
+    Take(usize),
+    /// how many values it still skips before giving the first one
+    Skip(usize),
+    Zip(Value),
+}
+impl NativeObject for IteratorStage {
+    fn typ(&self) -> &'static str {
+        IteratorObject::TYPE
+    }
+    fn get(&self, key: &str) -> Option<Value> {
+        match key {
+            "next" => {
+                let state = Arc::clone(&self.state);
+                let next: Rc<NativeFn> = Rc::new(move |i, _| IteratorStage::next_(&state, i));
+                Some(Value::Fn(FnKind::Native(next)))
+            }
+            key => iterator_method(key),
+        }
+    }
+    fn call_mut(
+        &mut self,
+        key: &str,
+        interpreter: &mut Interpreter,
+        _: Vec<Value>,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        match key {
+            "next" => Self::next_(&self.state, interpreter),
+            _ => Err(RunTimeErrorKind::CannotCall(Value::default().typ())
+                .to_string()
+                .into()),
+        }
+    }
+}
+impl IteratorStage {
+    /// the stage as a value scripts can use
+    pub fn value(source: Value, kind: StageKind) -> Value {
+        let state = StageState {
+            source,
+            kind,
+            running: false,
+        };
+        Value::NativeObject(Arc::new(Mutex::new(Self {
+            state: Arc::new(Mutex::new(state)),
+        })))
+    }
+    pub fn next_(
+        state: &Pointer<StageState>,
+        interpreter: &mut Interpreter,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        {
+            let mut state = state.lock().unwrap();
+            if state.running {
+                return Err(
+                    "can't get the next value of an iterator while it's getting one".into(),
+                );
+            }
+            state.running = true;
+        }
+        let next = Self::step(state, interpreter);
+        state.lock().unwrap().running = false;
+        next
+    }
+    /// takes what the stage needs out of `state` and gives its next value, without holding
+    /// the lock while it calls into scripts
+    fn step(
+        state: &Pointer<StageState>,
+        interpreter: &mut Interpreter,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        let ln = interpreter.ln().unwrap_or_default();
+        let mut guard = state.lock().unwrap();
+        let source = guard.source.clone();
+        match &mut guard.kind {
+            StageKind::Map(func) => {
+                let func = func.clone();
+                drop(guard);
+                let Some(value) = next_value(interpreter, &source)? else {
+                    return Ok(None);
+                };
+                Ok(Some(interpreter.call_now(func, &[value], ln)?))
+            }
+            StageKind::Filter(func) => {
+                let func = func.clone();
+                drop(guard);
+                while let Some(value) = next_value(interpreter, &source)? {
+                    let args = std::slice::from_ref(&value);
+                    if bool::from(interpreter.call_now(func.clone(), args, ln)?) {
+                        return Ok(Some(value));
+                    }
+                }
+                Ok(None)
+            }
+            StageKind::Take(left) => {
+                if *left == 0 {
+                    return Ok(None);
+                }
+                *left -= 1;
+                drop(guard);
+                next_value(interpreter, &source)
+            }
+            StageKind::Skip(left) => {
+                let skip = std::mem::take(left);
+                drop(guard);
+                for _ in 0..skip {
+                    if next_value(interpreter, &source)?.is_none() {
+                        return Ok(None);
+                    }
+                }
+                next_value(interpreter, &source)
+            }
+            StageKind::Zip(other) => {
+                let other = other.clone();
+                drop(guard);
+                let Some(value) = next_value(interpreter, &source)? else {
+                    return Ok(None);
+                };
+                let Some(other) = next_value(interpreter, &other)? else {
+                    return Ok(None);
+                };
+                Ok(Some(make_tuple!(value, other)))
+            }
+        }
+    }
+}
+/// the next value of the iterator `iter`, `None` once it's done
+fn next_value(
+    interpreter: &mut Interpreter,
+    iter: &Value,
+) -> Result<Option<Value>, Box<dyn Error>> {
+    Ok(_next(interpreter, std::slice::from_ref(iter))?.filter(|value| *value != Value::Null))
+}
+/// the combinators of iterators, called like `it:map(f)`
+fn iterator_method(key: &str) -> Option<Value> {
+    let func: Rc<NativeFn> = match key {
+        "map" => Rc::new(_iter_map),
+        "filter" => Rc::new(_iter_filter),
+        "take" => Rc::new(_iter_take),
+        "skip" => Rc::new(_iter_skip),
+        "zip" => Rc::new(_iter_zip),
+        "collect" => Rc::new(_iter_collect),
+        _ => return None,
+    };
+    Some(Value::Fn(FnKind::Native(func)))
+}
+// the results of `func` called with every value
+define_native_fn!(_iter_map (_i args): iter = typed!(args), func = typed!(args: Fn) => {
+    Ok(Some(IteratorStage::value(iter, StageKind::Map(Value::Fn(func)))))
+});
+// the values `func` returns something truthy for
+define_native_fn!(_iter_filter (_i args): iter = typed!(args), func = typed!(args: Fn) => {
+    Ok(Some(IteratorStage::value(iter, StageKind::Filter(Value::Fn(func)))))
+});
+// the first `n` values
+define_native_fn!(_iter_take (_i args): iter = typed!(args), n = typed!(args: Int) => {
+    let n = usize::try_from(n).map_err(|_| format!("can't take {n} values"))?;
+    Ok(Some(IteratorStage::value(iter, StageKind::Take(n))))
+});
+// the values after the first `n`
+define_native_fn!(_iter_skip (_i args): iter = typed!(args), n = typed!(args: Int) => {
+    let n = usize::try_from(n).map_err(|_| format!("can't skip {n} values"))?;
+    Ok(Some(IteratorStage::value(iter, StageKind::Skip(n))))
+});
+// tuples of a value and the value of `other` at the same position, until one of them
+// runs out. `other` can be anything `iter` takes
+define_native_fn!(_iter_zip (interpreter args): iter = typed!(args), other = typed!(args) => {
+    let other = _iter(interpreter, &[other])?.unwrap_or_default();
+    Ok(Some(IteratorStage::value(iter, StageKind::Zip(other))))
+});
+// the values left in a vector
+define_native_fn!(_iter_collect (interpreter args): iter = typed!(args) => {
+    Ok(Some(make_vec!(collect_iter(interpreter, iter)?)))
+});
 define_native_fn!(_iter (i args): value = typed!(args) => {
     match value {
         Value::Vector(values) => {
@@ -202,6 +574,12 @@ define_native_fn!(_iter (i args): value = typed!(args) => {
             if let Value::Fn(_) = next {
                 return Ok(Some(value))
             }
+            if let Some(iter) = object.lock().unwrap().__iter() {
+                return Ok(Some(Value::NativeObject(Arc::new(Mutex::new(IteratorObject {
+                    iter,
+                    fn_next: Rc::new(IteratorObject::_next)
+                })))))
+            }
             object.lock().unwrap().call("iter", i, args.map(|(_, v)| v).collect())
         }
         value => Err(format!("can't iterate over {}", value.typ()).into())
@@ -210,20 +588,88 @@ define_native_fn!(_iter (i args): value = typed!(args) => {
 define_native_fn!(_next (i args): value = typed!(args) => {
     match value {
         Value::NativeObject(object) => {
+            // a native `next` method runs without the object locked, like `it:next()` does
+            let next = object.lock().unwrap().get("next");
+            if let Some(Value::Fn(FnKind::Native(next))) = next {
+                let mut call_args = vec![Value::NativeObject(object)];
+                call_args.extend(args.map(|(_, v)| v));
+                return next(i, &call_args);
+            }
             object.lock().unwrap().call_mut("next", i, args.map(|(_, v)| v).collect())
         }
         value => Err(format!("can't get next iteration of {}", value.typ()).into())
     }
 });
+/// the values `value` iterates over, like a `for` loop would go through them
+pub fn collect_iter(
+    interpreter: &mut Interpreter,
+    value: Value,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    if let Value::Vector(values) = &value {
+        return Ok(values.lock().unwrap().clone());
+    }
+    let iter = _iter(interpreter, &[value])?.unwrap_or_default();
+    let mut values = vec![];
+    while let Some(value) = _next(interpreter, std::slice::from_ref(&iter))? {
+        if value == Value::Null {
+            break;
+        }
+        values.push(value);
+    }
+    Ok(values)
+}
+define_native_fn!(_sorted (interpreter args): value = typed!(args) => {
+    let mut values = collect_iter(interpreter, value)?;
+    values.sort();
+    Ok(Some(make_vec!(values)))
+});
+define_native_fn!(_reversed (interpreter args): value = typed!(args) => {
+    let mut values = collect_iter(interpreter, value)?;
+    values.reverse();
+    Ok(Some(make_vec!(values)))
+});
+define_native_fn!(_unique (interpreter args): value = typed!(args) => {
+    let mut values = vec![];
+    for value in collect_iter(interpreter, value)? {
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+    Ok(Some(make_vec!(values)))
+});
 
-define_native_fn!(_int (_i args): value = typed!(args) => {
+/// trims whitespace and removes `_` separators between the digits of a number literal
+pub fn number_text(text: &str) -> Option<String> {
+    let text = text.trim();
+    let digits = text.trim_start_matches(['+', '-']);
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return None;
+    }
+    Some(text.replace('_', ""))
+}
+define_native_fn!(_int (_i args): value = typed!(args), base = typed!(args: Int?) => {
+    if let (Some(base), Value::String(text)) = (base, &value) {
+        if !(2..=36).contains(&base) {
+            return Err(format!("base {base} is not between 2 and 36").into());
+        }
+        return number_text(text)
+            .and_then(|text| i64::from_str_radix(&text, base as u32).ok())
+            .map(|v| Some(Value::Int(v)))
+            .ok_or_else(|| format!("can't parse {text:?} as a base {base} int").into());
+    }
+    if base.is_some() {
+        return Err(format!("can't parse {} with a base", value.typ()).into());
+    }
     Ok(Some(Value::Int(match value {
         Value::Int(v) => v,
         Value::Float(v) => v as i64,
         Value::Bool(v) => if v { 1 } else { 0 },
         Value::Char(v) => v as u8 as i64,
-        Value::String(v) => if let Ok(v) = v.parse::<i64>() { v } else { return Ok(None); },
-        _ => return Ok(None)
+        Value::String(text) => match number_text(&text).and_then(|text| text.parse().ok()) {
+            Some(v) => v,
+            None => return Err(format!("can't parse {text:?} as an int").into()),
+        },
+        value => return Err(format!("can't convert {} to an int", value.typ()).into()),
     })))
 });
 define_native_fn!(_float (_i args): value = typed!(args) => {
@@ -232,8 +678,11 @@ define_native_fn!(_float (_i args): value = typed!(args) => {
         Value::Float(v) => v,
         Value::Bool(v) => if v { 1.0 } else { 0.0 },
         Value::Char(v) => v as u8 as f64,
-        Value::String(v) => if let Ok(v) = v.parse::<f64>() { v } else { return Ok(None); },
-        _ => return Ok(None)
+        Value::String(text) => match number_text(&text).and_then(|text| text.parse().ok()) {
+            Some(v) => v,
+            None => return Err(format!("can't parse {text:?} as a float").into()),
+        },
+        value => return Err(format!("can't convert {} to a float", value.typ()).into()),
     })))
 });
 define_native_fn!(_bool (_i args): value = typed!(args) => {
@@ -247,8 +696,9 @@ define_native_fn!(_char (_i args): value = typed!(args) => {
         _ => return Ok(None)
     })))
 });
-define_native_fn!(_str (_i args): => {
-    Ok(Some(Value::String(args.map(|(_, v)| v.to_string()).collect::<Vec<String>>().join(""))))
+define_native_fn!(_str (interpreter args): => {
+    let texts = args.map(|(_, v)| interpreter.display(&v)).collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(Value::String(texts.join(""))))
 });
 define_native_fn!(_vec (_i args): value = typed!(args) => {
     if args.len() == 0 {
@@ -288,13 +738,53 @@ define_native_fn!(_tuple (_i args): value = typed!(args) => {
         Ok(Some(make_vec!(values)))
     }
 });
+/// the `types` global, mapping every type name of the standard library to itself. hosts
+/// with native objects of their own add their names to it so `check` accepts them
+pub fn type_names() -> Value {
+    make_map!(types::NAMES
+        .iter()
+        .map(|name| (name.to_string(), Value::from(*name)))
+        .collect::<Map>())
+}
+/// fails on the first name in `typ` that isn't one of the values of the `types` global,
+/// suggesting the closest one that is. every name is fine without a `types` global
+fn check_type_names(interpreter: &Interpreter, typ: &str) -> Result<(), Box<dyn Error>> {
+    let Some(Value::Map(known)) = interpreter
+        .globals
+        .get("types")
+        .map(|value| value.lock().unwrap().clone())
+    else {
+        return Ok(());
+    };
+    let known: Vec<String> = known
+        .lock()
+        .unwrap()
+        .values()
+        .filter_map(|value| match value {
+            Value::String(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let Some(name) = types::unknown_name(typ, |name| known.iter().any(|known| known == name))
+    else {
+        return Ok(());
+    };
+    Err(
+        match types::suggest(name, known.iter().map(String::as_str)) {
+            Some(known) => format!("unknown type {name:?}, did you mean {known:?}?"),
+            None => format!("unknown type {name:?}"),
+        }
+        .into(),
+    )
+}
 define_native_fn!(_type (_i args): value = typed!(args) => {
     Ok(Some(Value::String(value.typ().to_string())))
 });
-define_native_fn!(_check (_i args): value = typed!(args) => {
+define_native_fn!(_check (i args): value = typed!(args) => {
     for (idx, arg) in args {
         if let Value::String(typ) = arg {
-            if value.typ() == typ {
+            check_type_names(i, &typ)?;
+            if value.is_type(&typ) {
                 return Ok(Some(value))
             }
         } else {
@@ -309,6 +799,43 @@ define_native_fn!(_check (_i args): value = typed!(args) => {
     }
     Ok(Some(Value::default()))
 });
+define_native_fn!(_expect (i args): value = typed!(args), typ = typed!(args: String) => {
+    check_type_names(i, &typ)?;
+    if !value.is_type(&typ) {
+        return Err(format!("expected {typ}, got {}", value.describe_type()).into());
+    }
+    Ok(Some(value))
+});
+define_native_fn!(_len (_i args): value = typed!(args) => {
+    Ok(Some(Value::from(match &value {
+        Value::String(string) => string.chars().count(),
+        Value::Vector(values) => values.lock().unwrap().len(),
+        Value::Tuple(values) => values.lock().unwrap().len(),
+        Value::Map(entries) => entries.lock().unwrap().len(),
+        Value::NativeObject(object) => {
+            let len = object.lock().unwrap().__len();
+            len.ok_or_else(|| format!("can't get the length of {}", value.typ()))?
+        }
+        value => return Err(format!("can't get the length of {}", value.typ()).into()),
+    })))
+});
+define_native_fn!(_conversion (interpreter args): typ = typed!(args: String), func = typed!(args: Fn) => {
+    interpreter.set_conversion(typ, Value::Fn(func));
+    Ok(None)
+});
+define_native_fn!(_freeze (interpreter args): value = typed!(args) => {
+    if !interpreter.freeze(&value) {
+        return Err(format!("can't freeze {}", value.typ()).into());
+    }
+    Ok(Some(value))
+});
+define_native_fn!(_is_frozen (interpreter args): value = typed!(args) => {
+    Ok(Some(Value::Bool(interpreter.is_frozen(&value))))
+});
+define_native_fn!(_nameof (_i args): value = typed!(args: Map), search = typed!(args) => {
+    let value = value.lock().unwrap();
+    Ok(value.iter().find_map(|(k, v)| (v == &search).then(|| Value::String(k.clone()))))
+});
 define_native_fn!(_enumerate (i args): value = typed!(args) => {
     match value {
         Value::Vector(values) => {
@@ -368,3 +895,22 @@ define_native_fn!(_enumerate (i args): value = typed!(args) => {
         value => Err(format!("can't enumerate over {}", value.typ()).into())
     }
 });
+// the ints from `start` up to `end` counting by `step`, or from 0 up to `start` when it's
+// the only argument. they're made one at a time, so big ranges don't take any memory
+define_native_fn!(_range (_i args): start = typed!(args: Int), end = typed!(args: Int?), step = typed!(args: Int?) => {
+    let (start, end) = match end {
+        Some(end) => (start, end),
+        None => (0, start),
+    };
+    let step = step.unwrap_or(1);
+    if step == 0 {
+        return Err("the step of a range can't be 0".into());
+    }
+    let ints = std::iter::successors(Some(start), move |int| int.checked_add(step))
+        .take_while(move |int| if step > 0 { *int < end } else { *int > end })
+        .map(Value::Int);
+    Ok(Some(Value::NativeObject(Arc::new(Mutex::new(IteratorObject {
+        iter: Box::new(ints),
+        fn_next: Rc::new(IteratorObject::_next)
+    })))))
+});
@@ -0,0 +1,296 @@
+//! the worker threads behind `vec.par_map` and `vec.par_filter`. every worker runs its part
+//! of the vector with an interpreter of its own, so only functions that can't tell the
+//! difference get split up: script functions whose upvalues and globals hold nothing but
+//! data and that don't assign to either, called on a vector of data by an interpreter
+//! without fuel, hooks, replays, conversions or frozen containers. anything else runs serially on the calling
+//! interpreter. what the workers print comes out in the order of the vector, but changes
+//! they make to shared containers happen in any order
+use crate::run::{
+    code::{ByteCode, Closure, Location},
+    interpreter::{Interpreter, InterpreterOptions, RunTimeError, RunTimeErrorKind},
+    replay::Replay,
+    value::{FnKind, Function, Pointer, Value},
+};
+use crate::std_hydra;
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+/// how many elements each worker gets at least, shorter vectors use fewer workers
+pub const MIN_CHUNK_LEN: usize = 16;
+
+/// `func` called with every value, like `v:map(func)`
+pub fn par_map(
+    interpreter: &mut Interpreter,
+    values: Vec<Value>,
+    func: Value,
+    ln: usize,
+) -> Result<Vec<Value>, RunTimeError> {
+    run_split(interpreter, values, func, ln, |_, result| Some(result))
+}
+/// the values `func` returns something truthy for
+pub fn par_filter(
+    interpreter: &mut Interpreter,
+    values: Vec<Value>,
+    func: Value,
+    ln: usize,
+) -> Result<Vec<Value>, RunTimeError> {
+    run_split(interpreter, values, func, ln, |value, result| {
+        bool::from(result).then_some(value)
+    })
+}
+
+/// calls `func` with every value and collects what `keep` makes of each value and result
+fn run_split(
+    interpreter: &mut Interpreter,
+    values: Vec<Value>,
+    func: Value,
+    ln: usize,
+    keep: fn(Value, Value) -> Option<Value>,
+) -> Result<Vec<Value>, RunTimeError> {
+    let workers = std::thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(values.len() / MIN_CHUNK_LEN);
+    let job = if workers > 1 && !cfg!(feature = "wasm") {
+        Job::new(interpreter, &func, &values)
+    } else {
+        None
+    };
+    let Some(job) = job else {
+        let mut kept = vec![];
+        for value in values {
+            let result = interpreter.call_now(func.clone(), std::slice::from_ref(&value), ln)?;
+            kept.extend(keep(value, result));
+        }
+        return Ok(kept);
+    };
+    let chunk_len = values.len().div_ceil(workers);
+    let mut chunks = vec![];
+    let mut values = values.into_iter();
+    loop {
+        let chunk = values.by_ref().take(chunk_len).collect::<Vec<Value>>();
+        if chunk.is_empty() {
+            break;
+        }
+        chunks.push(chunk);
+    }
+    let results = std::thread::scope(|scope| {
+        let handles = chunks
+            .into_iter()
+            .map(|chunk| {
+                // every worker gets its own copy of the closures, they're reference counted
+                // without atomics
+                let func = job.function();
+                let globals = job.globals.clone();
+                let options = job.options.clone();
+                scope.spawn(move || run_chunk(options, globals, func, chunk, ln, keep))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("a worker panicked"))
+            .collect::<Vec<_>>()
+    });
+    let mut kept = vec![];
+    for (values, output, err) in results {
+        kept.extend(values);
+        interpreter
+            .write_output(&output)
+            .map_err(|err| RunTimeError {
+                err: RunTimeErrorKind::Custom(err.to_string()),
+                ln,
+            })?;
+        if let Some(err) = err {
+            return Err(err);
+        }
+    }
+    Ok(kept)
+}
+/// runs `chunk` on a fresh interpreter, giving back what it kept, what it printed and the
+/// error it stopped at
+fn run_chunk(
+    options: InterpreterOptions,
+    globals: Vec<(String, Value)>,
+    func: Value,
+    chunk: Vec<Value>,
+    ln: usize,
+    keep: fn(Value, Value) -> Option<Value>,
+) -> (Vec<Value>, String, Option<RunTimeError>) {
+    let mut interpreter = Interpreter::new(options);
+    std_hydra::import(&mut interpreter);
+    for (name, value) in globals {
+        interpreter
+            .globals
+            .insert(name, Arc::new(Mutex::new(value)));
+    }
+    let output = Rc::new(RefCell::new(String::new()));
+    let sink = Rc::clone(&output);
+    interpreter.set_output(move |text| sink.borrow_mut().push_str(text));
+    let mut kept = vec![];
+    let mut err = None;
+    for value in chunk {
+        match interpreter.call_now(func.clone(), std::slice::from_ref(&value), ln) {
+            Ok(result) => kept.extend(keep(value, result)),
+            Err(stopped) => {
+                err = Some(stopped);
+                break;
+            }
+        }
+    }
+    drop(interpreter);
+    let output = output.take();
+    (kept, output, err)
+}
+
+/// what the workers need to run a function without the calling interpreter
+struct Job {
+    closure: Closure,
+    upvalues: Vec<Pointer<Value>>,
+    /// the caller's data globals the function may read
+    globals: Vec<(String, Value)>,
+    options: InterpreterOptions,
+}
+impl Job {
+    /// `None` if `func` could tell it isn't running on `interpreter`, or if the `values`
+    /// it gets called with aren't data the workers can share
+    fn new(interpreter: &Interpreter, func: &Value, values: &[Value]) -> Option<Self> {
+        let Value::Fn(FnKind::Function(func)) = func else {
+            return None;
+        };
+        if interpreter.fuel.is_some()
+            || interpreter.hook.func.is_some()
+            || !matches!(interpreter.replay, Replay::Off)
+            || !interpreter.conversions.is_empty()
+            || !interpreter.frozen.is_empty()
+        {
+            return None;
+        }
+        let func = func.lock().unwrap();
+        let mut seen = HashSet::new();
+        if !values.iter().all(|value| is_data(value, &mut seen)) {
+            return None;
+        }
+        for upvalue in &func.upvalues {
+            if !is_data(&upvalue.lock().unwrap(), &mut seen) {
+                return None;
+            }
+        }
+        let mut names = HashSet::new();
+        if !collect_names(&func.closure, &mut names, &mut seen) {
+            return None;
+        }
+        // the workers bring their own standard library, everything else the function may
+        // read has to be data they can share
+        let mut standard = Interpreter::default();
+        std_hydra::import(&mut standard);
+        let mut globals = vec![];
+        for name in names {
+            let Some(value) = interpreter.globals.get(&name) else {
+                continue;
+            };
+            let value = value.lock().unwrap().clone();
+            if is_data(&value, &mut seen) {
+                globals.push((name, value));
+            } else if !(standard.globals.contains_key(&name)
+                || standard.lazy_globals.contains_key(&name))
+                || has_script_fn(&value)
+            {
+                return None;
+            }
+        }
+        Some(Self {
+            closure: func.closure.as_ref().clone(),
+            upvalues: func.upvalues.clone(),
+            globals,
+            options: InterpreterOptions {
+                copy_containers: interpreter.copy_containers,
+                strict_arithmetic: interpreter.strict_arithmetic,
+                float_indices: interpreter.float_indices,
+                warn_undefined_globals: interpreter.warn_undefined_globals,
+                fuel: None,
+                max_call_depth: interpreter.max_call_depth,
+                trust_level: interpreter.trust_level,
+            },
+        })
+    }
+    /// the function with closures no other thread holds
+    fn function(&self) -> Value {
+        Value::Fn(FnKind::Function(Arc::new(Mutex::new(Function {
+            closure: Rc::new(detach(&self.closure)),
+            upvalues: self.upvalues.clone(),
+        }))))
+    }
+}
+/// a copy of `closure` that shares none of its nested closures
+fn detach(closure: &Closure) -> Closure {
+    Closure {
+        closures: closure
+            .closures
+            .iter()
+            .map(|closure| Rc::new(detach(closure)))
+            .collect(),
+        ..closure.clone()
+    }
+}
+/// adds the names of the globals `closure` and its nested closures may read to `names`.
+/// false if one of them assigns to a global or upvalue, imports a module, or has constants
+/// that aren't data
+fn collect_names(
+    closure: &Closure,
+    names: &mut HashSet<String>,
+    seen: &mut HashSet<usize>,
+) -> bool {
+    // assignments to captured variables would race between the workers
+    let writes_shared = closure.code.iter().any(|instr| {
+        matches!(instr, ByteCode::Import { .. })
+            || matches!(
+                instr.writes(),
+                Some(Location::Global(_) | Location::Upvalue(_))
+            )
+    });
+    if writes_shared {
+        return false;
+    }
+    for constant in &closure.constants {
+        if !is_data(constant, seen) {
+            return false;
+        }
+        // globals are looked up by their name in the constants
+        if let Value::String(name) = constant {
+            names.insert(name.clone());
+        }
+    }
+    closure
+        .closures
+        .iter()
+        .all(|closure| collect_names(closure, names, seen))
+}
+/// whether `value` holds nothing but data, so threads can share it. `seen` keeps track
+/// of the containers already checked
+fn is_data(value: &Value, seen: &mut HashSet<usize>) -> bool {
+    let values = match value {
+        Value::Vector(arc) if seen.insert(Arc::as_ptr(arc) as *const () as usize) => {
+            arc.lock().unwrap().clone()
+        }
+        Value::Tuple(arc) if seen.insert(Arc::as_ptr(arc) as *const () as usize) => {
+            arc.lock().unwrap().to_vec()
+        }
+        Value::Map(arc) if seen.insert(Arc::as_ptr(arc) as *const () as usize) => {
+            arc.lock().unwrap().values().cloned().collect()
+        }
+        Value::Fn(_) | Value::NativeObject(_) => return false,
+        _ => return true,
+    };
+    values.iter().all(|value| is_data(value, seen))
+}
+/// whether `value` is or holds a script function, natives and native objects don't count
+fn has_script_fn(value: &Value) -> bool {
+    match value {
+        Value::Fn(FnKind::Function(_)) => true,
+        Value::Map(arc) => arc.lock().unwrap().values().any(has_script_fn),
+        _ => false,
+    }
+}
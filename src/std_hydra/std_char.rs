@@ -2,23 +2,23 @@ use crate::*;
 use crate::run::interpreter::{Interpreter, CHAR_MODULE};
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: CHAR_MODULE = make_map!{
-        "lower" = native_fn!(_lower),
-        "upper" = native_fn!(_upper),
-        "to_digit" = native_fn!(_to_digit),
-        "to_hex" = native_fn!(_to_hex),
-        "to_bin" = native_fn!(_to_bin),
-        "to_radix" = native_fn!(_to_radix),
-        "is_lower" = native_fn!(_is_lower),
-        "is_upper" = native_fn!(_is_upper),
-        "is_alphabetic" = native_fn!(_is_alphabetic),
-        "is_numeric" = native_fn!(_is_numeric),
-        "is_digit" = native_fn!(_is_digit),
-        "is_hex" = native_fn!(_is_hex),
-        "is_control" = native_fn!(_is_control),
-        "is_graphic" = native_fn!(_is_graphic),
-        "is_punct" = native_fn!(_is_punct),
-        "is_space" = native_fn!(_is_space),
+    declare_module!(interpreter: CHAR_MODULE {
+        "lower" => _lower,
+        "upper" => _upper,
+        "to_digit" => _to_digit,
+        "to_hex" => _to_hex,
+        "to_bin" => _to_bin,
+        "to_radix" => _to_radix,
+        "is_lower" => _is_lower,
+        "is_upper" => _is_upper,
+        "is_alphabetic" => _is_alphabetic,
+        "is_numeric" => _is_numeric,
+        "is_digit" => _is_digit,
+        "is_hex" => _is_hex,
+        "is_control" => _is_control,
+        "is_graphic" => _is_graphic,
+        "is_punct" => _is_punct,
+        "is_space" => _is_space,
     });
 }
 
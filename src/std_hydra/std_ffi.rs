@@ -0,0 +1,186 @@
+use crate::run::{
+    interpreter::{Interpreter, RunTimeErrorKind},
+    trust::Capability,
+    types,
+    value::{FnKind, NativeFn, NativeObject},
+};
+use crate::*;
+use libloading::{Library, Symbol};
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    mem,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// the libraries `ffi.open` may load. `ffi` isn't part of `std_hydra::import`, hosts
+/// opt in with `std_ffi::import` and name the libraries they trust
+#[derive(Debug, Clone, Default)]
+pub struct FfiPolicy {
+    /// paths or file names, like "libm.so.6"
+    pub libraries: Vec<String>,
+    pub allow_any: bool,
+}
+impl FfiPolicy {
+    pub fn allows(&self, path: &str) -> bool {
+        self.allow_any
+            || self.libraries.iter().any(|library| {
+                library == path || Path::new(path).file_name() == Some(library.as_ref())
+            })
+    }
+}
+
+pub fn import(interpreter: &mut Interpreter, policy: FfiPolicy) {
+    interpreter.insert_app_data(policy);
+    declare_module!(interpreter: "ffi" {
+        "open" => _open,
+    });
+}
+
+pub struct LibraryObject {
+    pub path: String,
+    pub library: Library,
+    pub fn_call: Rc<NativeFn>,
+}
+impl LibraryObject {
+    pub const TYPE: &'static str = types::LIBRARY;
+    define_native_fn!(_call (i args): _self = typed!(args: Self::TYPE) => {
+        let _self = _self.lock().unwrap();
+        _self.call("call", i, args.map(|(_, v)| v).collect())
+    });
+    /// `library:call(name, ret, args)` calls the function `name` returning `ret` ("void",
+    /// "bool", "i32", "i64", "f32", "f64" or "str") with `args`. arguments are either all
+    /// ints, bools, strings and nulls, passed as 64-bit integers and pointers, or all
+    /// floats, passed as doubles
+    pub fn call_(
+        &self,
+        _i: &mut Interpreter,
+        args: Vec<Value>,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        let mut args = args.into_iter().enumerate();
+        let name = typed!(args: String);
+        let ret = typed!(args: String);
+        let func_args = typed!(args: Vector?)
+            .map(|values| values.lock().unwrap().clone())
+            .unwrap_or_default();
+        let func = unsafe {
+            let symbol: Symbol<unsafe extern "C" fn()> = self.library.get(name.as_bytes())?;
+            *symbol as *const c_void
+        };
+        let mut strings = vec![];
+        let mut ints = vec![];
+        let mut floats = vec![];
+        for arg in func_args {
+            match arg {
+                Value::Int(v) => ints.push(v),
+                Value::Bool(v) => ints.push(v as i64),
+                Value::Null => ints.push(0),
+                Value::String(v) => {
+                    let string = CString::new(v)?;
+                    ints.push(string.as_ptr() as i64);
+                    strings.push(string);
+                }
+                Value::Float(v) => floats.push(v),
+                arg => return Err(format!("can't pass {} to a native function", arg.typ()).into()),
+            }
+        }
+        let value = match (ints.is_empty(), floats.is_empty()) {
+            (_, true) => unsafe { call_returning(func, &ints, &ret)? },
+            (true, false) => unsafe { call_returning(func, &floats, &ret)? },
+            (false, false) => {
+                return Err("can't mix float arguments with other arguments".into());
+            }
+        };
+        Ok(Some(value))
+    }
+}
+impl NativeObject for LibraryObject {
+    fn typ(&self) -> &'static str {
+        Self::TYPE
+    }
+    fn get(&self, key: &str) -> Option<Value> {
+        match key {
+            "call" => Some(Value::Fn(FnKind::Native(Rc::clone(&self.fn_call)))),
+            "path" => Some(self.path.clone().into()),
+            _ => None,
+        }
+    }
+    fn call(
+        &self,
+        key: &str,
+        interpreter: &mut Interpreter,
+        args: Vec<Value>,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        match key {
+            "call" => self.call_(interpreter, args),
+            _ => Err(RunTimeErrorKind::CannotCall(Value::default().typ())
+                .to_string()
+                .into()),
+        }
+    }
+}
+unsafe impl Sync for LibraryObject {}
+unsafe impl Send for LibraryObject {}
+
+define_native_fn!(_open (interpreter args): path = typed!(args: String) => {
+    interpreter.check_capability(Capability::Ffi)?;
+    let allowed = interpreter
+        .app_data::<FfiPolicy>()
+        .is_some_and(|policy| policy.allows(&path));
+    if !allowed {
+        return Err(format!("ffi isn't allowed to open {path:?}").into());
+    }
+    let library = unsafe { Library::new(&path)? };
+    Ok(Some(Value::NativeObject(Arc::new(Mutex::new(LibraryObject {
+        path,
+        library,
+        fn_call: Rc::new(LibraryObject::_call),
+    })))))
+});
+
+/// calls `func` as a function taking `args` and returning the type named `ret`
+unsafe fn call_returning<A: Copy>(
+    func: *const c_void,
+    args: &[A],
+    ret: &str,
+) -> Result<Value, Box<dyn Error>> {
+    Ok(match ret {
+        "void" => {
+            call_with::<A, ()>(func, args)?;
+            Value::Null
+        }
+        "bool" => Value::Bool(call_with::<A, bool>(func, args)?),
+        "i32" => Value::Int(call_with::<A, i32>(func, args)? as i64),
+        "i64" => Value::Int(call_with::<A, i64>(func, args)?),
+        "f32" => Value::Float(call_with::<A, f32>(func, args)? as f64),
+        "f64" => Value::Float(call_with::<A, f64>(func, args)?),
+        "str" => {
+            let string = call_with::<A, *const c_char>(func, args)?;
+            if string.is_null() {
+                Value::Null
+            } else {
+                Value::String(CStr::from_ptr(string).to_string_lossy().into_owned())
+            }
+        }
+        ret => return Err(format!("unknown native return type {ret:?}").into()),
+    })
+}
+unsafe fn call_with<A: Copy, R>(func: *const c_void, args: &[A]) -> Result<R, Box<dyn Error>> {
+    Ok(match *args {
+        [] => mem::transmute::<*const c_void, extern "C" fn() -> R>(func)(),
+        [a] => mem::transmute::<*const c_void, extern "C" fn(A) -> R>(func)(a),
+        [a, b] => mem::transmute::<*const c_void, extern "C" fn(A, A) -> R>(func)(a, b),
+        [a, b, c] => mem::transmute::<*const c_void, extern "C" fn(A, A, A) -> R>(func)(a, b, c),
+        [a, b, c, d] => {
+            mem::transmute::<*const c_void, extern "C" fn(A, A, A, A) -> R>(func)(a, b, c, d)
+        }
+        [a, b, c, d, e] => mem::transmute::<*const c_void, extern "C" fn(A, A, A, A, A) -> R>(
+            func,
+        )(a, b, c, d, e),
+        [a, b, c, d, e, f] => mem::transmute::<
+            *const c_void,
+            extern "C" fn(A, A, A, A, A, A) -> R,
+        >(func)(a, b, c, d, e, f),
+        _ => return Err("native functions take at most 6 arguments".into()),
+    })
+}
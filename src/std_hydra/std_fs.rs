@@ -6,6 +6,8 @@ use std::{
 
 use run::{
     interpreter::RunTimeErrorKind,
+    trust::Capability,
+    types,
     value::{FnKind, NativeFn, NativeObject},
 };
 
@@ -13,33 +15,41 @@ use super::run::interpreter::Interpreter;
 use crate::*;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: "fs" = make_map!{
-        "open" = native_fn!(_open),
-        "list" = native_fn!(_list),
-        "meta_data" = native_fn!(_meta_data)
+    declare_module!(interpreter: "fs" {
+        "open" => _open,
+        "list" => _list,
+        "meta_data" => _meta_data,
     });
 }
 
 pub struct FileObject {
-    pub file: fs::File,
+    /// `None` once the file got closed
+    pub file: Option<fs::File>,
     pub fn_read: Rc<NativeFn>,
     pub fn_write: Rc<NativeFn>,
     pub fn_meta_data: Rc<NativeFn>,
+    pub fn_close: Rc<NativeFn>,
 }
 impl FileObject {
-    pub const TYPE: &'static str = "file";
+    pub const TYPE: &'static str = types::FILE;
+    pub fn file(&mut self) -> Result<&mut fs::File, Box<dyn Error>> {
+        self.file.as_mut().ok_or_else(|| "file is closed".into())
+    }
     define_native_fn!(_read (i args): _self = typed!(args: Self::TYPE) => {
         let mut _self = _self.lock().unwrap();
         _self.call_mut("read", i, args.map(|(_, v)| v).collect())
     });
     pub fn read_(
         &mut self,
-        _i: &mut Interpreter,
+        interpreter: &mut Interpreter,
         _args: Vec<Value>,
     ) -> Result<Option<Value>, Box<dyn Error>> {
-        let mut content = String::new();
-        self.file.read_to_string(&mut content)?;
-        Ok(Some(Value::String(content)))
+        let file = self.file()?;
+        Ok(Some(interpreter.nondeterministic("file.read", || {
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+            Ok(Value::String(content))
+        })?))
     }
     define_native_fn!(_write (i args): _self = typed!(args: Self::TYPE) => {
         let mut _self = _self.lock().unwrap();
@@ -52,7 +62,7 @@ impl FileObject {
     ) -> Result<Option<Value>, Box<dyn Error>> {
         let mut args = args.into_iter().enumerate();
         let text = typed!(args: String);
-        Ok(Some(self.file.write(text.as_bytes())?.into()))
+        Ok(Some(self.file()?.write(text.as_bytes())?.into()))
     }
     define_native_fn!(_meta_data (i args): _self = typed!(args: Self::TYPE) => {
         let mut _self = _self.lock().unwrap();
@@ -64,11 +74,23 @@ impl FileObject {
         _args: Vec<Value>,
     ) -> Result<Option<Value>, Box<dyn Error>> {
         Ok(self
-            .file
+            .file()?
             .metadata()
             .ok()
             .map(|data| Value::NativeObject(Arc::new(Mutex::new(MetaDataObject { data })))))
     }
+    define_native_fn!(_close (i args): _self = typed!(args: Self::TYPE) => {
+        let mut _self = _self.lock().unwrap();
+        _self.call_mut("close", i, args.map(|(_, v)| v).collect())
+    });
+    pub fn close_(
+        &mut self,
+        _i: &mut Interpreter,
+        _args: Vec<Value>,
+    ) -> Result<Option<Value>, Box<dyn Error>> {
+        self.file = None;
+        Ok(None)
+    }
 }
 impl NativeObject for FileObject {
     fn typ(&self) -> &'static str {
@@ -78,6 +100,9 @@ impl NativeObject for FileObject {
         match key {
             "read" => Some(Value::Fn(FnKind::Native(Rc::clone(&self.fn_read)))),
             "write" => Some(Value::Fn(FnKind::Native(Rc::clone(&self.fn_write)))),
+            "meta_data" => Some(Value::Fn(FnKind::Native(Rc::clone(&self.fn_meta_data)))),
+            "close" => Some(Value::Fn(FnKind::Native(Rc::clone(&self.fn_close)))),
+            "closed" => Some(self.file.is_none().into()),
             _ => None,
         }
     }
@@ -90,6 +115,8 @@ impl NativeObject for FileObject {
         match key {
             "read" => self.read_(interpreter, args),
             "write" => self.write_(interpreter, args),
+            "meta_data" => self.meta_data_(interpreter, args),
+            "close" => self.close_(interpreter, args),
             _ => Err(RunTimeErrorKind::CannotCall(Value::default().typ())
                 .to_string()
                 .into()),
@@ -98,7 +125,8 @@ impl NativeObject for FileObject {
 }
 unsafe impl Sync for FileObject {}
 unsafe impl Send for FileObject {}
-define_native_fn!(_open (_i args): path = typed!(args: String), options = typed!(args: String) => {
+define_native_fn!(_open (i args): path = typed!(args: String), options = typed!(args: String) => {
+    i.check_capability(Capability::FileSystem)?;
     let Ok(file) = fs::File::options()
         .create(options.contains('w'))
         .write(options.contains('w'))
@@ -107,13 +135,15 @@ define_native_fn!(_open (_i args): path = typed!(args: String), options = typed!
         return Ok(None)
     };
     Ok(Some(Value::NativeObject(Arc::new(Mutex::new(FileObject {
-        file,
+        file: Some(file),
         fn_read: Rc::new(FileObject::_read),
         fn_write: Rc::new(FileObject::_write),
         fn_meta_data: Rc::new(FileObject::_meta_data),
+        fn_close: Rc::new(FileObject::_close),
     })))))
 });
-define_native_fn!(_list (_i args): path = typed!(args: String) => {
+define_native_fn!(_list (i args): path = typed!(args: String) => {
+    i.check_capability(Capability::FileSystem)?;
     Ok(Some(Value::Vector(Arc::new(Mutex::new(
         fs::read_dir(path)?
             .flatten()
@@ -126,7 +156,8 @@ define_native_fn!(_list (_i args): path = typed!(args: String) => {
             )).collect()
     )))))
 });
-define_native_fn!(_meta_data (_i args): path = typed!(args: String) => {
+define_native_fn!(_meta_data (i args): path = typed!(args: String) => {
+    i.check_capability(Capability::FileSystem)?;
     Ok(fs::metadata(path)
         .ok()
         .map(|data| Value::NativeObject(Arc::new(Mutex::new(MetaDataObject { data })))))
@@ -137,7 +168,7 @@ pub struct MetaDataObject {
     pub data: fs::Metadata,
 }
 impl MetaDataObject {
-    pub const TYPE: &str = "meta-data";
+    pub const TYPE: &str = types::META_DATA;
 }
 impl NativeObject for MetaDataObject {
     fn typ(&self) -> &'static str {
@@ -3,18 +3,18 @@ use crate::run::interpreter::{Interpreter, INT_MODULE};
 use super::std_math::*;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: INT_MODULE = make_map!{
-        "from_bin" = native_fn!(_from_bin),
-        "from_hex" = native_fn!(_from_hex),
-        "to_bin" = native_fn!(_to_bin),
-        "to_hex" = native_fn!(_to_hex),
-        "abs" = native_fn!(_abs),
-        "sqrt" = native_fn!(_sqrt),
-        "max" = native_fn!(_max),
-        "min" = native_fn!(_min),
-        "log" = native_fn!(_log),
-        "log2" = native_fn!(_log2),
-        "log10" = native_fn!(_log10),
+    declare_module!(interpreter: INT_MODULE {
+        "from_bin" => _from_bin,
+        "from_hex" => _from_hex,
+        "to_bin" => _to_bin,
+        "to_hex" => _to_hex,
+        "abs" => _abs,
+        "sqrt" => _sqrt,
+        "max" => _max,
+        "min" => _min,
+        "log" => _log,
+        "log2" => _log2,
+        "log10" => _log10,
     });
 }
 
@@ -41,4 +41,4 @@ define_native_fn!(_log2 (_i args): value = typed!(args: Int) => {
 });
 define_native_fn!(_log10 (_i args): value = typed!(args: Int) => {
     Ok(Some(value.ilog10().into()))
-});
\ No newline at end of file
+});
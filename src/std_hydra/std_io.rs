@@ -2,17 +2,18 @@ use std::io::{self, Read, Write};
 use std::sync::{Arc, Mutex};
 
 use run::interpreter::RunTimeErrorKind;
+use run::types;
 use run::value::{FnKind, NativeFn, NativeObject};
 
 use crate::run::interpreter::Interpreter;
 use crate::*;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: "io" = make_map!{
-        "stdin" = native_fn!(_stdin),
-        "stdout" = native_fn!(_stdout),
-        "stderr" = native_fn!(_stderr),
-        "write" = native_fn!(_write),
+    declare_module!(interpreter: "io" {
+        "stdin" => _stdin,
+        "stdout" => _stdout,
+        "stderr" => _stderr,
+        "write" => _write,
     });
 }
 
@@ -22,19 +23,21 @@ pub struct StdinObject {
     fn_read_line: Rc<NativeFn>,
 }
 impl StdinObject {
-    pub const TYPE: &str = "stdin";
+    pub const TYPE: &str = types::STDIN;
     define_native_fn!(_read (i args): _self = typed!(args: Self::TYPE) => {
         let mut _self = _self.lock().unwrap();
         _self.call_mut("read", i, args.map(|(_, v)| v).collect())
     });
     pub fn read_(
         &mut self,
-        _i: &mut Interpreter,
+        interpreter: &mut Interpreter,
         _args: Vec<Value>,
     ) -> Result<Option<Value>, Box<dyn Error>> {
-        let mut buf = String::new();
-        self.stdin.read_to_string(&mut buf)?;
-        Ok(Some(buf.into()))
+        Ok(Some(interpreter.nondeterministic("stdin.read", || {
+            let mut buf = String::new();
+            self.stdin.read_to_string(&mut buf)?;
+            Ok(unix_newlines(buf).into())
+        })?))
     }
     define_native_fn!(_read_line (i args): _self = typed!(args: Self::TYPE) => {
         let mut _self = _self.lock().unwrap();
@@ -42,12 +45,17 @@ impl StdinObject {
     });
     pub fn read_line_(
         &mut self,
-        _i: &mut Interpreter,
+        interpreter: &mut Interpreter,
         _args: Vec<Value>,
     ) -> Result<Option<Value>, Box<dyn Error>> {
-        let mut buf = String::new();
-        self.stdin.read_line(&mut buf)?;
-        Ok(Some(buf.into()))
+        Ok(Some(interpreter.nondeterministic(
+            "stdin.read_line",
+            || {
+                let mut buf = String::new();
+                self.stdin.read_line(&mut buf)?;
+                Ok(unix_newlines(buf).into())
+            },
+        )?))
     }
 }
 impl NativeObject for StdinObject {
@@ -90,7 +98,7 @@ pub struct StdoutObject {
     fn_write: Rc<NativeFn>,
 }
 impl StdoutObject {
-    pub const TYPE: &str = "stdout";
+    pub const TYPE: &str = types::STDOUT;
     define_native_fn!(_write (i args): _self = typed!(args: Self::TYPE) => {
         let mut _self = _self.lock().unwrap();
         _self.call_mut("write", i, args.map(|(_, v)| v).collect())
@@ -102,7 +110,9 @@ impl StdoutObject {
     ) -> Result<Option<Value>, Box<dyn Error>> {
         let mut args = args.into_iter().enumerate();
         let text = typed!(args: String);
-        Ok(Some(self.stdout.write(text.as_bytes())?.into()))
+        let written = self.stdout.write(text.as_bytes())?;
+        self.stdout.flush()?;
+        Ok(Some(written.into()))
     }
 }
 impl NativeObject for StdoutObject {
@@ -142,7 +152,7 @@ pub struct StderrObject {
     fn_write: Rc<NativeFn>,
 }
 impl StderrObject {
-    pub const TYPE: &str = "stderr";
+    pub const TYPE: &str = types::STDERR;
     define_native_fn!(_write (i args): _self = typed!(args: Self::TYPE) => {
         let mut _self = _self.lock().unwrap();
         _self.call_mut("write", i, args.map(|(_, v)| v).collect())
@@ -190,7 +200,17 @@ define_native_fn!(_stderr (_i args): => {
     })))))
 });
 
-define_native_fn!(_write (_i args): => {
-    print!("{}", args.map(|(_, v)| v.to_string()).collect::<Vec<String>>().join(" "));
+define_native_fn!(_write (interpreter args): => {
+    let texts = args.map(|(_, v)| interpreter.display(&v)).collect::<Result<Vec<_>, _>>()?;
+    // goes through the interpreter's output so prompts show up before reading a line
+    interpreter.write_output(&texts.join(" "))?;
     Ok(None)
 });
+/// `text` with Windows line endings turned into `\n`
+pub fn unix_newlines(text: String) -> String {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    }
+}
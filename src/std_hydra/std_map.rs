@@ -1,17 +1,17 @@
 use crate::run::interpreter::{Interpreter, MAP_MODULE};
 use crate::*;
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: MAP_MODULE = make_map!{
-        "len" = native_fn!(_len),
-        "get" = native_fn!(_get),
-        "set" = native_fn!(_set),
-        "key_of" = native_fn!(_key_of),
-        "keys" = native_fn!(_keys),
-        "values" = native_fn!(_values),
-        "clear" = native_fn!(_clear),
-        "copy" = native_fn!(_copy),
+    declare_module!(interpreter: MAP_MODULE {
+        "len" => _len,
+        "get" => _get,
+        "set" => _set,
+        "key_of" => _key_of,
+        "keys" => _keys,
+        "values" => _values,
+        "clear" => _clear,
+        "copy" => _copy,
     });
 }
 define_native_fn!(_len (_i args): value = typed!(args: Map) => {
@@ -22,7 +22,8 @@ define_native_fn!(_get (_i args): value = typed!(args: Map), key = typed!(args:
     let value = value.lock().unwrap();
     Ok(Some(value.get(&key).cloned().unwrap_or(default)))
 });
-define_native_fn!(_set (_i args): value = typed!(args: Map), key = typed!(args: String), new_value = typed!(args) => {
+define_native_fn!(_set (interpreter args): value = typed!(args: Map), key = typed!(args: String), new_value = typed!(args) => {
+    interpreter.check_mutable(&value, "map")?;
     let mut value = value.lock().unwrap();
     Ok(value.insert(key, new_value))
 });
@@ -38,7 +39,8 @@ define_native_fn!(_values (_i args): value = typed!(args: Map) => {
     let value = value.lock().unwrap();
     Ok(Some(value.values().cloned().collect::<Vec<Value>>().into()))
 });
-define_native_fn!(_clear (_i args): value = typed!(args: Map) => {
+define_native_fn!(_clear (interpreter args): value = typed!(args: Map) => {
+    interpreter.check_mutable(&value, "map")?;
     let mut value = value.lock().unwrap();
     value.clear();
     Ok(None)
@@ -48,5 +50,5 @@ define_native_fn!(_copy (_i args): value = typed!(args: Map) => {
     Ok(Some(value.clone().into()))
 });
 define_native_fn!(_create_set (_i args): => {
-    Ok(Some(args.map(|(_, v)| (v.to_string(), true)).collect::<HashMap<String, bool>>().into()))
+    Ok(Some(args.map(|(_, v)| (v.to_string(), true)).collect::<IndexMap<String, bool>>().into()))
 });
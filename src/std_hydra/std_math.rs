@@ -4,53 +4,61 @@ use core::f64;
 use rand::random;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: "math" = make_map!{
-        "nan" = f64::NAN,
-        "inf" = f64::INFINITY,
-        "pi" = f64::consts::PI,
-        "tau" = f64::consts::TAU,
-        "e" = f64::consts::E,
-        "epsilon" = f64::EPSILON,
-        "floor" = native_fn!(_floor),
-        "ceil" = native_fn!(_ceil),
-        "round" = native_fn!(_round),
-        "round_ties_even" = native_fn!(_round_ties_even),
-        "abs" = native_fn!(_abs),
-        "sqrt" = native_fn!(_sqrt),
-        "cbrt" = native_fn!(_cbrt),
-        "max" = native_fn!(_max),
-        "min" = native_fn!(_min),
-        "cos" = native_fn!(_cos),
-        "sin" = native_fn!(_sin),
-        "tan" = native_fn!(_tan),
-        "cosh" = native_fn!(_cosh),
-        "sinh" = native_fn!(_sinh),
-        "tanh" = native_fn!(_tanh),
-        "acos" = native_fn!(_acos),
-        "asin" = native_fn!(_asin),
-        "atan" = native_fn!(_atan),
-        "acosh" = native_fn!(_acosh),
-        "asinh" = native_fn!(_asinh),
-        "atanh" = native_fn!(_atanh),
-        "atan2" = native_fn!(_atan2),
-        "fract" = native_fn!(_fract),
-        "exp" = native_fn!(_exp),
-        "exp2" = native_fn!(_exp2),
-        "exp_m1" = native_fn!(_exp_m1),
-        "recip" = native_fn!(_recip),
-        "clamp" = native_fn!(_clamp),
-        "ln" = native_fn!(_ln),
-        "ln_1p" = native_fn!(_ln_1p),
-        "log" = native_fn!(_log),
-        "log10" = native_fn!(_log10),
-        "log2" = native_fn!(_log2),
-        "radians" = native_fn!(_radians),
-        "degrees" = native_fn!(_degrees),
-        "random" = native_fn!(_random),
-        "random_int" = native_fn!(_random_int),
-        "random_choice" = native_fn!(_random_choice),
+    declare_module!(interpreter: "math" {
+        const "nan" => f64::NAN,
+        const "inf" => f64::INFINITY,
+        const "pi" => f64::consts::PI,
+        const "tau" => f64::consts::TAU,
+        const "e" => f64::consts::E,
+        const "epsilon" => f64::EPSILON,
+        "floor" => _floor,
+        "ceil" => _ceil,
+        "round" => _round,
+        "round_ties_even" => _round_ties_even,
+        "abs" => _abs,
+        "sqrt" => _sqrt,
+        "cbrt" => _cbrt,
+        "max" => _max,
+        "min" => _min,
+        "cos" => _cos,
+        "sin" => _sin,
+        "tan" => _tan,
+        "cosh" => _cosh,
+        "sinh" => _sinh,
+        "tanh" => _tanh,
+        "acos" => _acos,
+        "asin" => _asin,
+        "atan" => _atan,
+        "acosh" => _acosh,
+        "asinh" => _asinh,
+        "atanh" => _atanh,
+        "atan2" => _atan2,
+        "fract" => _fract,
+        "exp" => _exp,
+        "exp2" => _exp2,
+        "exp_m1" => _exp_m1,
+        "recip" => _recip,
+        "clamp" => _clamp,
+        "ln" => _ln,
+        "ln_1p" => _ln_1p,
+        "log" => _log,
+        "log10" => _log10,
+        "log2" => _log2,
+        "radians" => _radians,
+        "degrees" => _degrees,
+        "random" => _random,
+        "random_int" => _random_int,
+        "random_choice" => _random_choice,
     });
 }
+/// the globals for the math used most, which aren't part of the module
+pub fn import_globals(interpreter: &mut Interpreter) {
+    set_global!(interpreter: "abs" = native_fn!(_abs));
+    set_global!(interpreter: "min" = native_fn!(_min_of));
+    set_global!(interpreter: "max" = native_fn!(_max_of));
+    set_global!(interpreter: "sum" = native_fn!(_sum));
+    set_global!(interpreter: "clamp" = native_fn!(_clamp));
+}
 pub fn make_float(idx: usize, value: Value) -> Result<f64, Box<dyn Error>> {
     match value {
         Value::Int(value) => Ok(value as f64),
@@ -68,6 +76,19 @@ pub fn make_float(idx: usize, value: Value) -> Result<f64, Box<dyn Error>> {
         .into()),
     }
 }
+/// the numbers the `min`, `max` and `sum` globals work on: the elements of a single vector
+/// or tuple argument, otherwise the arguments themselves
+pub fn numbers(args: &[Value]) -> Result<Vec<Value>, Box<dyn Error>> {
+    let values = match args {
+        [Value::Vector(values)] => values.lock().unwrap().clone(),
+        [Value::Tuple(values)] => values.lock().unwrap().to_vec(),
+        args => args.to_vec(),
+    };
+    for (idx, value) in values.iter().enumerate() {
+        make_float(idx, value.clone())?;
+    }
+    Ok(values)
+}
 define_native_fn!(_floor (_i args): value = typed!(args: Float) => {
     Ok(Some(value.floor().into()))
 });
@@ -127,6 +148,26 @@ define_native_fn!(_min (_i args): a = typed!(args), b = typed!(args) => {
         ).into())
     }))
 });
+define_native_fn!(_min_of (_i args!) {
+    match numbers(args)?.into_iter().min() {
+        Some(value) => Ok(Some(value)),
+        None => Err("expected at least one value for min".into()),
+    }
+});
+define_native_fn!(_max_of (_i args!) {
+    match numbers(args)?.into_iter().max() {
+        Some(value) => Ok(Some(value)),
+        None => Err("expected at least one value for max".into()),
+    }
+});
+define_native_fn!(_sum (_i args!) {
+    Ok(Some(numbers(args)?.into_iter().fold(Value::Int(0), |sum, value| match (sum, value) {
+        (Value::Int(sum), Value::Int(value)) => Value::Int(sum + value),
+        (sum, value) => Value::Float(
+            f64::try_from(sum).unwrap_or_default() + f64::try_from(value).unwrap_or_default(),
+        ),
+    })))
+});
 define_native_fn!(_cos (_i args): value = typed!(args) => {
     let value = make_float(0, value)?;
     Ok(Some(value.cos().into()))
@@ -201,9 +242,18 @@ define_native_fn!(_recip (_i args): value = typed!(args) => {
     Ok(Some(value.recip().into()))
 });
 define_native_fn!(_clamp (_i args): value = typed!(args), min = typed!(args), max = typed!(args) => {
+    if let (Value::Int(value), Value::Int(min), Value::Int(max)) = (&value, &min, &max) {
+        if min > max {
+            return Err(format!("invalid clamp range {min}..{max}").into());
+        }
+        return Ok(Some(Value::Int(*value.clamp(min, max))));
+    }
     let value = make_float(0, value)?;
     let min = make_float(1, min)?;
     let max = make_float(2, max)?;
+    if min > max || min.is_nan() || max.is_nan() {
+        return Err(format!("invalid clamp range {min}..{max}").into());
+    }
     Ok(Some(value.clamp(min, max).into()))
 });
 define_native_fn!(_ln (_i args): value = typed!(args) => {
@@ -235,32 +285,42 @@ define_native_fn!(_degrees (_i args): value = typed!(args) => {
     let value = make_float(0, value)?;
     Ok(Some(value.to_degrees().into()))
 });
-define_native_fn!(_random (_i args): => {
-    Ok(Some(random::<f64>().into()))
+/// a random float in `0..1` that goes through the interpreter's record and replay log
+pub fn random_float(interpreter: &mut Interpreter) -> Result<f64, Box<dyn Error>> {
+    match interpreter.nondeterministic("random", || Ok(Value::Float(random())))? {
+        Value::Float(value) => Ok(value),
+        value => Err(format!("expected float in the replay log, got {}", value.typ()).into()),
+    }
+}
+define_native_fn!(_random (interpreter args): => {
+    Ok(Some(random_float(interpreter)?.into()))
 });
-define_native_fn!(_random_int (_i args): min = typed!(args: Int), max = typed!(args: Int?)  => {
+define_native_fn!(_random_int (interpreter args): min = typed!(args: Int), max = typed!(args: Int?)  => {
     if let Some(max) = max {
-        Ok(Some(((random::<f64>() * (max - min) as f64) as i64 + min).into()))
+        Ok(Some(((random_float(interpreter)? * (max - min) as f64) as i64 + min).into()))
     } else {
-        Ok(Some(((random::<f64>() * min as f64) as i64).into()))
+        Ok(Some(((random_float(interpreter)? * min as f64) as i64).into()))
     }
 });
-define_native_fn!(_random_choice (_i args): collection = typed!(args)  => {
+define_native_fn!(_random_choice (interpreter args): collection = typed!(args)  => {
     match collection {
         Value::Vector(values) => {
             let len = values.lock().unwrap().len();
-            let index = (random::<f64>() * len as f64) as usize;
+            let index = (random_float(interpreter)? * len as f64) as usize;
             Ok(values.lock().unwrap().get(index).cloned())
         }
         Value::Tuple(values) => {
             let len = values.lock().unwrap().len();
-            let index = (random::<f64>() * len as f64) as usize;
+            let index = (random_float(interpreter)? * len as f64) as usize;
             Ok(values.lock().unwrap().get(index).cloned())
         }
         Value::Map(values) => {
-            let len = values.lock().unwrap().len();
-            let index = (random::<f64>() * len as f64) as usize;
-            Ok(Some(values.lock().unwrap().keys().cloned().collect::<Vec<String>>().remove(index).into()))
+            // key order differs between runs, so the chosen key is logged instead
+            Ok(Some(interpreter.nondeterministic("random_choice", || {
+                let len = values.lock().unwrap().len();
+                let index = (random::<f64>() * len as f64) as usize;
+                Ok(values.lock().unwrap().keys().cloned().collect::<Vec<String>>().remove(index).into())
+            })?))
         }
         collection => Err(format!(
             "expected {} for argument #1, got {}",
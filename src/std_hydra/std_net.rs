@@ -2,6 +2,5 @@ use crate::*;
 use crate::run::interpreter::Interpreter;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: "net" = make_map!{
-    });
+    declare_module!(interpreter: "net" {});
 }
\ No newline at end of file
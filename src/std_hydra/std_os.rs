@@ -1,25 +1,190 @@
-use crate::run::interpreter::Interpreter;
+use crate::run::{interpreter::Interpreter, trust::Capability};
 use crate::*;
 use std::{process, time};
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: "os" = make_map!{
-        "id" = native_fn!(_id),
-        "exit" = native_fn!(_exit),
-        "time" = native_fn!(_time),
+    declare_module!(interpreter: "os" {
+        "id" => _id,
+        "exit" => _exit,
+        "time" => _time,
+        "quote" => _quote,
+        "split_args" => _split_args,
     });
 }
 define_native_fn!(_id (_i args): => {
     Ok(Some(process::id().into()))
 });
-define_native_fn!(_exit (_i args): code = typed!(args: Int) => {
+define_native_fn!(_exit (i args): code = typed!(args: Int) => {
+    i.check_capability(Capability::Process)?;
     process::exit(code as i32)
 });
-define_native_fn!(_time (_i args): => {
-    Ok(
+define_native_fn!(_time (interpreter args): => {
+    Ok(Some(interpreter.nondeterministic("time", || Ok(
         time::SystemTime::now()
         .duration_since(time::SystemTime::UNIX_EPOCH)
         .ok()
         .map(|d| Value::Float(d.as_secs_f64()))
-    )
+        .unwrap_or_default()
+    ))?))
 });
+// `arg` quoted so the platform's shell passes it on as a single argument
+define_native_fn!(_quote (_i args): arg = typed!(args: String) => {
+    Ok(Some(Value::String(quote(&arg))))
+});
+// the arguments the platform's shell would split `line` into
+define_native_fn!(_split_args (_i args): line = typed!(args: String) => {
+    let args = split_args(&line)?;
+    Ok(Some(args.into_iter().map(Value::String).collect::<Vec<Value>>().into()))
+});
+
+/// `arg` quoted for the shell of the platform, `sh` or the Windows command line
+pub fn quote(arg: &str) -> String {
+    if cfg!(windows) {
+        quote_windows(arg)
+    } else {
+        quote_posix(arg)
+    }
+}
+/// the arguments the shell of the platform splits `line` into
+pub fn split_args(line: &str) -> Result<Vec<String>, String> {
+    if cfg!(windows) {
+        split_args_windows(line)
+    } else {
+        split_args_posix(line)
+    }
+}
+/// `arg` as is if `sh` wouldn't treat any of it specially, in single quotes otherwise
+pub fn quote_posix(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-+=:,./@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        return arg.to_string();
+    }
+    // nothing is special in single quotes, a quote has to end them to be escaped
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+/// splits `line` like `sh` does, without expanding anything
+pub fn split_args_posix(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    // a backslash before a newline joins the lines
+                    if c != '\n' {
+                        arg.get_or_insert_with(String::new).push(c);
+                    }
+                }
+            }
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err("unterminated single quote".into()),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => arg.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err("unterminated double quote".into()),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("unterminated double quote".into()),
+                    }
+                }
+            }
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+/// `arg` as is if it has no whitespace or quotes, otherwise in double quotes with the
+/// backslashes before quotes doubled the way `CommandLineToArgvW` undoes it
+pub fn quote_windows(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    // the closing quote mustn't be escaped by the backslashes before it
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+/// splits `line` by the rules `CommandLineToArgvW` has for the arguments after the program
+/// name, except that an unterminated quote is an error instead of running to the end
+pub fn split_args_windows(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' if !quoted => args.extend(arg.take()),
+            '\\' => {
+                let mut backslashes = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    backslashes += 1;
+                }
+                let arg = arg.get_or_insert_with(String::new);
+                if chars.peek() == Some(&'"') {
+                    // pairs of backslashes before a quote are one each, an odd one
+                    // escapes the quote
+                    arg.extend(std::iter::repeat_n('\\', backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        chars.next();
+                        arg.push('"');
+                    }
+                } else {
+                    arg.extend(std::iter::repeat_n('\\', backslashes));
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                // two quotes in a quoted part are a literal quote
+                if quoted && chars.next_if_eq(&'"').is_some() {
+                    arg.push('"');
+                } else {
+                    quoted = !quoted;
+                }
+            }
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated double quote".into());
+    }
+    args.extend(arg);
+    Ok(args)
+}
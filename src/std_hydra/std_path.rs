@@ -0,0 +1,63 @@
+use crate::run::interpreter::Interpreter;
+use crate::*;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
+
+pub fn import(interpreter: &mut Interpreter) {
+    declare_module!(interpreter: "path" {
+        const "sep" => MAIN_SEPARATOR_STR,
+        "join" => _join,
+        "parent" => _parent,
+        "name" => _name,
+        "ext" => _ext,
+        "normalize" => _normalize,
+    });
+}
+/// `path` with the separators of the platform, Windows takes both `/` and `\`
+pub fn normalize(path: &str) -> String {
+    if cfg!(windows) {
+        path.replace('/', MAIN_SEPARATOR_STR)
+    } else {
+        path.to_string()
+    }
+}
+fn path_value(path: Option<&std::ffi::OsStr>) -> Value {
+    path.and_then(|path| path.to_str())
+        .map(|path| Value::String(path.to_string()))
+        .unwrap_or_default()
+}
+define_native_fn!(_join (_i args): => {
+    let mut path = PathBuf::new();
+    for (idx, arg) in args {
+        let Value::String(part) = arg else {
+            return Err(format!(
+                "expected {} for argument #{}, got {}",
+                Value::String(Default::default()).typ(),
+                idx + 1,
+                arg.typ()
+            )
+            .into());
+        };
+        path.push(normalize(&part));
+    }
+    Ok(Some(path_value(Some(path.as_os_str()))))
+});
+// the directory the path is in, null for roots and bare names
+define_native_fn!(_parent (_i args): path = typed!(args: String) => {
+    let path = normalize(&path);
+    let parent = Path::new(&path)
+        .parent()
+        .map(Path::as_os_str)
+        .filter(|parent| !parent.is_empty());
+    Ok(Some(path_value(parent)))
+});
+define_native_fn!(_name (_i args): path = typed!(args: String) => {
+    let path = normalize(&path);
+    Ok(Some(path_value(Path::new(&path).file_name())))
+});
+define_native_fn!(_ext (_i args): path = typed!(args: String) => {
+    let path = normalize(&path);
+    Ok(Some(path_value(Path::new(&path).extension())))
+});
+define_native_fn!(_normalize (_i args): path = typed!(args: String) => {
+    Ok(Some(Value::String(normalize(&path))))
+});
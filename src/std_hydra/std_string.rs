@@ -1,24 +1,26 @@
-use crate::run::interpreter::{Interpreter, STRING_MODULE};
+use crate::run::interpreter::{Interpreter, RunTimeErrorKind, STRING_MODULE};
+use crate::run::value::{resolve_index, resolve_position};
 use crate::*;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: STRING_MODULE = make_map!{
-        "len" = native_fn!(_len),
-        "get" = native_fn!(_get),
-        "lower" = native_fn!(_lower),
-        "upper" = native_fn!(_upper),
-        "sub" = native_fn!(_sub),
-        "remove" = native_fn!(_remove),
-        "split" = native_fn!(_split),
-        "sep" = native_fn!(_split),
-        "split_once" = native_fn!(_split_once),
-        "split_at" = native_fn!(_split_at),
-        "split_off" = native_fn!(_split_off),
-        "trim" = native_fn!(_trim),
-        "trim_start" = native_fn!(_trim_start),
-        "trim_end" = native_fn!(_trim_end),
-        "trim_start_matches" = native_fn!(_trim_start_matches),
-        "trim_end_matches" = native_fn!(_trim_end_matches),
+    declare_module!(interpreter: STRING_MODULE {
+        "len" => _len,
+        "get" => _get,
+        "lower" => _lower,
+        "upper" => _upper,
+        "sub" => _sub,
+        "remove" => _remove,
+        "split" => _split,
+        "sep" => _split,
+        "split_once" => _split_once,
+        "split_at" => _split_at,
+        "split_off" => _split_off,
+        "trim" => _trim,
+        "trim_start" => _trim_start,
+        "trim_end" => _trim_end,
+        "trim_start_matches" => _trim_start_matches,
+        "trim_end_matches" => _trim_end_matches,
+        "scan" => _scan,
     });
 }
 
@@ -26,16 +28,10 @@ define_native_fn!(_len (_i args): value = typed!(args: String) => {
     Ok(Some(value.len().into()))
 });
 define_native_fn!(_get (_i args): value = typed!(args: String), index = typed!(args: Int) => {
-    let index = if index <= -1 {
-        if (index.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index.unsigned_abs() as usize
-        }
-    } else {
-        index.unsigned_abs() as usize
-    };
-    Ok(value.get(index..=index).and_then(|s| s.chars().next()).map(Value::Char))
+    Ok(resolve_index(index, value.len())
+        .and_then(|index| value.get(index..=index))
+        .and_then(|s| s.chars().next())
+        .map(Value::Char))
 });
 define_native_fn!(_lower (_i args): value = typed!(args: String) => {
     Ok(Some(value.to_ascii_lowercase().into()))
@@ -44,22 +40,15 @@ define_native_fn!(_upper (_i args): value = typed!(args: String) => {
     Ok(Some(value.to_ascii_uppercase().into()))
 });
 define_native_fn!(_sub (_i args): value = typed!(args: String), start = typed!(args: Int), end = typed!(args: Int?) => {
-    if let Some(end) = end {
-        Ok(value.get(start as usize..end as usize).map(|s| Value::String(s.to_string())))
-    } else {
-        Ok(value.get(start as usize..).map(|s| Value::String(s.to_string())))
-    }
+    let start = resolve_position(start, value.len());
+    let end = resolve_position(end.unwrap_or(value.len() as i64), value.len());
+    Ok(start
+        .zip(end)
+        .and_then(|(start, end)| value.get(start..end))
+        .map(|s| Value::String(s.to_string())))
 });
 define_native_fn!(_remove (_i args): mut value = typed!(args: String), index = typed!(args: Int) => {
-    let index = if index <= -1 {
-        if (index.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index.unsigned_abs() as usize
-        }
-    } else {
-        index.unsigned_abs() as usize
-    };
+    let index = char_boundary(&value, resolve_index(index, value.len()), index)?;
     Ok(Some(value.remove(index).into()))
 });
 define_native_fn!(_split (_i args): value = typed!(args: String), sep = typed!(args: String) => {
@@ -69,28 +58,13 @@ define_native_fn!(_split_once (_i args): value = typed!(args: String), sep = typ
     Ok(value.split_once(&sep).map(|(a, b)| make_tuple!(a.to_string(), b.to_string())))
 });
 define_native_fn!(_split_off (_i args): mut value = typed!(args: String), index = typed!(args: Int) => {
-    let index = if index <= -1 {
-        if (index.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index.unsigned_abs() as usize
-        }
-    } else {
-        index.unsigned_abs() as usize
-    };
+    let index = char_boundary(&value, resolve_position(index, value.len()), index)?;
     Ok(Some(value.split_off(index).into()))
 });
 define_native_fn!(_split_at (_i args): value = typed!(args: String), index = typed!(args: Int) => {
-    let index = if index <= -1 {
-        if (index.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index.unsigned_abs() as usize
-        }
-    } else {
-        index.unsigned_abs() as usize
-    };
-    Ok(value.split_at_checked(index).map(|(a, b)| make_tuple!(a.to_string(), b.to_string())))
+    Ok(resolve_position(index, value.len())
+        .and_then(|index| value.split_at_checked(index))
+        .map(|(a, b)| make_tuple!(a.to_string(), b.to_string())))
 });
 define_native_fn!(_trim (_i args): value = typed!(args: String) => {
     Ok(Some(value.trim_ascii().into()))
@@ -110,3 +84,72 @@ define_native_fn!(_trim_start_matches (_i args): value = typed!(args: String), p
 define_native_fn!(_trim_end_matches (_i args): value = typed!(args: String), pattern = typed!(args: Char) => {
     Ok(Some(value.trim_end_matches(pattern).into()))
 });
+define_native_fn!(_scan (_i args): value = typed!(args: String), format = typed!(args: String) => {
+    Ok(scan(&value, &format).map(|captures| make_tuple!(captures)))
+});
+
+/// matches `text` against `format` where every `{}` captures the shortest text up to the
+/// next literal part, converted to an int or float if it parses as one. `{{` and `}}` match
+/// literal braces
+pub fn scan(text: &str, format: &str) -> Option<Vec<Value>> {
+    let mut literals = vec![String::new()];
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('}')) => {
+                chars.next();
+                literals.push(String::new());
+            }
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                literals.last_mut().unwrap().push(c);
+            }
+            (c, _) => literals.last_mut().unwrap().push(c),
+        }
+    }
+    let mut rest = text.strip_prefix(literals[0].as_str())?;
+    let mut captures = vec![];
+    let last = literals.len() - 1;
+    for (idx, literal) in literals.iter().enumerate().skip(1) {
+        let capture = if idx == last {
+            let capture = rest.strip_suffix(literal.as_str())?;
+            rest = "";
+            capture
+        } else {
+            let end = rest.find(literal.as_str())?;
+            let capture = &rest[..end];
+            rest = &rest[end + literal.len()..];
+            capture
+        };
+        captures.push(scan_value(capture));
+    }
+    rest.is_empty().then_some(captures)
+}
+fn scan_value(capture: &str) -> Value {
+    if let Ok(value) = capture.parse::<i64>() {
+        return Value::Int(value);
+    }
+    if capture.bytes().any(|b| b.is_ascii_digit()) {
+        if let Ok(value) = capture.parse::<f64>() {
+            return Value::Float(value);
+        }
+    }
+    Value::String(capture.to_string())
+}
+
+/// the byte offset `resolved` from `index`, erroring if it's out of range or inside of a
+/// multi-byte character
+fn char_boundary(
+    string: &str,
+    resolved: Option<usize>,
+    index: i64,
+) -> Result<usize, Box<dyn Error>> {
+    let resolved = resolved.ok_or(RunTimeErrorKind::IndexOutOfRange {
+        index,
+        len: string.len(),
+    })?;
+    if !string.is_char_boundary(resolved) {
+        return Err(format!("index {index} is inside of a character of {string:?}").into());
+    }
+    Ok(resolved)
+}
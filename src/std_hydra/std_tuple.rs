@@ -1,11 +1,17 @@
+use crate::run::interpreter::{Interpreter, RunTimeErrorKind, TUPLE_MODULE};
+use crate::run::value::{resolve_index, resolve_position};
 use crate::*;
-use crate::run::interpreter::{Interpreter, TUPLE_MODULE};
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: TUPLE_MODULE = make_map!{
-        "len" = native_fn!(_len),
-        "get" = native_fn!(_get),
-        "pos" = native_fn!(_pos),
+    declare_module!(interpreter: TUPLE_MODULE {
+        "len" => _len,
+        "get" => _get,
+        "pos" => _pos,
+        "to_vec" => _to_vec,
+        "concat" => _concat,
+        "slice" => _slice,
+        "map" => _map,
+        "updated" => _updated,
     });
 }
 define_native_fn!(_len (_i args): value = typed!(args: Tuple) => {
@@ -14,18 +20,48 @@ define_native_fn!(_len (_i args): value = typed!(args: Tuple) => {
 });
 define_native_fn!(_get (_i args): value = typed!(args: Tuple), index = typed!(args: Int), default = typed!(args) => {
     let value = value.lock().unwrap();
-    let index = if index <= -1 {
-        if (index.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index.unsigned_abs() as usize
-        }
-    } else {
-        index.unsigned_abs() as usize
-    };
-    Ok(Some(value.get(index).cloned().unwrap_or(default)))
+    Ok(Some(resolve_index(index, value.len()).map_or(default, |index| value[index].clone())))
 });
 define_native_fn!(_pos (_i args): value = typed!(args: Tuple), search = typed!(args) => {
     let value = value.lock().unwrap();
     Ok(value.iter().position(|v| v == &search).map(Value::from))
-});
\ No newline at end of file
+});
+define_native_fn!(_to_vec (_i args): value = typed!(args: Tuple) => {
+    let value = value.lock().unwrap();
+    Ok(Some(make_vec!(value.to_vec())))
+});
+define_native_fn!(_concat (_i args): value = typed!(args: Tuple) => {
+    let mut values = value.lock().unwrap().to_vec();
+    while args.len() > 0 {
+        let other = typed!(args: Tuple);
+        values.extend(other.lock().unwrap().iter().cloned());
+    }
+    Ok(Some(make_tuple!(values)))
+});
+define_native_fn!(_slice (_i args): value = typed!(args: Tuple), start = typed!(args: Int), end = typed!(args: Int?) => {
+    let value = value.lock().unwrap();
+    let start = resolve_position(start, value.len());
+    let end = resolve_position(end.unwrap_or(value.len() as i64), value.len());
+    Ok(start
+        .zip(end)
+        .and_then(|(start, end)| value.get(start..end))
+        .map(|values| make_tuple!(values.to_vec())))
+});
+define_native_fn!(_map (interpreter args): value = typed!(args: Tuple), func = typed!(args: Fn) => {
+    let values = value.lock().unwrap().clone();
+    let ln = interpreter.ln().unwrap_or_default();
+    let mut new_values = Vec::with_capacity(values.len());
+    for value in values.iter() {
+        let func = Value::Fn(func.clone());
+        new_values.push(interpreter.call_now(func, std::slice::from_ref(value), ln)?);
+    }
+    Ok(Some(make_tuple!(new_values)))
+});
+// tuples can't be changed, this copies `value` with the element at `index` replaced
+define_native_fn!(_updated (_i args): value = typed!(args: Tuple), index = typed!(args: Int), v = typed!(args) => {
+    let mut values = value.lock().unwrap().clone();
+    let len = values.len();
+    let index = resolve_index(index, len).ok_or(RunTimeErrorKind::IndexOutOfRange { index, len })?;
+    values[index] = v;
+    Ok(Some(make_tuple!(values)))
+});
@@ -1,22 +1,24 @@
 use std::sync::{Arc, Mutex};
 
-use crate::run::interpreter::{Interpreter, VECTOR_MODULE};
-use crate::run::value::FnKind;
+use crate::run::interpreter::{Interpreter, RunTimeErrorKind, VECTOR_MODULE};
+use crate::run::value::{resolve_index, resolve_position, FnKind};
+use crate::std_hydra::collect_iter;
 use crate::*;
 
 pub fn import(interpreter: &mut Interpreter) {
-    set_global!(interpreter: VECTOR_MODULE = make_map!{
-        "len" = native_fn!(_len),
-        "get" = native_fn!(_get),
-        "pos" = native_fn!(_pos),
-        "push" = native_fn!(_push),
-        "pop" = native_fn!(_pop),
-        "clear" = native_fn!(_clear),
-        "copy" = native_fn!(_copy),
-        "swap" = native_fn!(_swap),
-        "sort" = native_fn!(_sort),
-        "reduce" = native_fn!(_reduce),
-        "map" = native_fn!(_map),
+    declare_module!(interpreter: VECTOR_MODULE {
+        "len" => _len,
+        "get" => _get,
+        "pos" => _pos,
+        "push" => _push,
+        "pop" => _pop,
+        "clear" => _clear,
+        "copy" => _copy,
+        "swap" => _swap,
+        "sort" => _sort,
+        "reduce" => _reduce,
+        "map" => _map,
+        "extend" => _extend,
     });
 }
 define_native_fn!(_len (_i args): value = typed!(args: Vector) => {
@@ -25,57 +27,43 @@ define_native_fn!(_len (_i args): value = typed!(args: Vector) => {
 });
 define_native_fn!(_get (_i args): value = typed!(args: Vector), index = typed!(args: Int), default = typed!(args) => {
     let value = value.lock().unwrap();
-    let index = if index <= -1 {
-        if (index.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index.unsigned_abs() as usize
-        }
-    } else {
-        index.unsigned_abs() as usize
-    };
-    Ok(Some(value.get(index).cloned().unwrap_or(default)))
+    Ok(Some(resolve_index(index, value.len()).map_or(default, |index| value[index].clone())))
 });
 define_native_fn!(_pos (_i args): value = typed!(args: Vector), search = typed!(args) => {
     let value = value.lock().unwrap();
     Ok(value.iter().position(|v| v == &search).map(Value::from))
 });
-define_native_fn!(_push (_i args): value = typed!(args: Vector), v = typed!(args), index = typed!(args: Int?) => {
+define_native_fn!(_push (interpreter args): value = typed!(args: Vector), v = typed!(args), index = typed!(args: Int?) => {
+    interpreter.check_mutable(&value, "vec")?;
     let mut value = value.lock().unwrap();
     if let Some(index) = index {
-        let index = if index <= -1 {
-            if (index.unsigned_abs() - 1) as usize > value.len() {
-                0
-            } else {
-                value.len() - index.unsigned_abs() as usize
-            }
-        } else {
-            index.unsigned_abs() as usize
-        };
+        let index = resolve_position(index, value.len())
+            .ok_or(RunTimeErrorKind::IndexOutOfRange { index, len: value.len() })?;
         value.insert(index, v);
     } else {
         value.push(v);
     }
     Ok(None)
 });
-define_native_fn!(_pop (_i args): value = typed!(args: Vector), index = typed!(args: Int?) => {
+define_native_fn!(_pop (interpreter args): value = typed!(args: Vector), index = typed!(args: Int?) => {
+    interpreter.check_mutable(&value, "vec")?;
     let mut value = value.lock().unwrap();
     Ok(if let Some(index) = index {
-        let index = if index <= -1 {
-            if (index.unsigned_abs() - 1) as usize > value.len() {
-                0
-            } else {
-                value.len() - index.unsigned_abs() as usize
-            }
-        } else {
-            index.unsigned_abs() as usize
-        };
+        let index = resolve_index(index, value.len())
+            .ok_or(RunTimeErrorKind::IndexOutOfRange { index, len: value.len() })?;
         Some(value.remove(index))
     } else {
         value.pop()
     })
 });
-define_native_fn!(_clear (_i args): value = typed!(args: Vector) => {
+define_native_fn!(_extend (interpreter args): value = typed!(args: Vector), other = typed!(args) => {
+    interpreter.check_mutable(&value, "vec")?;
+    let values = collect_iter(interpreter, other)?;
+    value.lock().unwrap().extend(values);
+    Ok(None)
+});
+define_native_fn!(_clear (interpreter args): value = typed!(args: Vector) => {
+    interpreter.check_mutable(&value, "vec")?;
     let mut value = value.lock().unwrap();
     value.clear();
     Ok(None)
@@ -84,37 +72,25 @@ define_native_fn!(_copy (_i args): value = typed!(args: Vector) => {
     let value = value.lock().unwrap();
     Ok(Some(make_vec!(value.clone())))
 });
-define_native_fn!(_swap (_i args): value = typed!(args: Vector), index1 = typed!(args: Int), index2 = typed!(args: Int) => {
+define_native_fn!(_swap (interpreter args): value = typed!(args: Vector), index1 = typed!(args: Int), index2 = typed!(args: Int) => {
+    interpreter.check_mutable(&value, "vec")?;
     let mut value = value.lock().unwrap();
-    let index1 = if index1 <= -1 {
-        if (index1.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index1.unsigned_abs() as usize
-        }
-    } else {
-        index1.unsigned_abs() as usize
-    };
-    let index2 = if index2 <= -1 {
-        if (index2.unsigned_abs() - 1) as usize > value.len() {
-            0
-        } else {
-            value.len() - index2.unsigned_abs() as usize
-        }
-    } else {
-        index2.unsigned_abs() as usize
-    };
+    let index1 = resolve_index(index1, value.len())
+        .ok_or(RunTimeErrorKind::IndexOutOfRange { index: index1, len: value.len() })?;
+    let index2 = resolve_index(index2, value.len())
+        .ok_or(RunTimeErrorKind::IndexOutOfRange { index: index2, len: value.len() })?;
     value.swap(index1, index2);
     Ok(None)
 });
-define_native_fn!(_sort (_i args): value = typed!(args: Vector) => {
+define_native_fn!(_sort (interpreter args): value = typed!(args: Vector) => {
+    interpreter.check_mutable(&value, "vec")?;
     let mut value = value.lock().unwrap();
     value.sort();
     Ok(Some(value.clone().into()))
 });
 define_native_fn!(_reduce (interpreter args): vector = typed!(args: Vector), func = typed!(args: Fn) => {
     let vector = vector.lock().unwrap();
-    if vector.len() == 0 {
+    if vector.is_empty() {
         return Ok(None)
     }
     let mut values = vector.iter();
@@ -123,28 +99,24 @@ define_native_fn!(_reduce (interpreter args): vector = typed!(args: Vector), fun
         let clone = acc.clone();
         acc = match func {
             FnKind::Function(ref func) => {
-                interpreter.call(&func.lock().unwrap(), vec![clone, value.clone()], None).map_err(Box::new)?;
+                interpreter.call(&func.lock().unwrap(), &[clone, value.clone()], None).map_err(Box::new)?;
                 interpreter.run().map_err(Box::new)?.unwrap_or_default()
             }
-            FnKind::Native(ref func) => func(interpreter, vec![clone, value.clone()])?.unwrap_or_default(),
+            FnKind::Native(ref func) => func(interpreter, &[clone, value.clone()])?.unwrap_or_default(),
         };
     }
     Ok(Some(acc))
 });
 define_native_fn!(_map (interpreter args): vector = typed!(args: Vector), func = typed!(args: Fn) => {
-    let vector = vector.lock().unwrap();
-    if vector.len() == 0 {
+    let vector = vector.lock().unwrap().clone();
+    if vector.is_empty() {
         return Ok(None)
     }
-    let mut new_vector = vector.clone();
-    for (i, value) in vector.iter().enumerate() {
-        *new_vector.get_mut(i).unwrap() = match func {
-            FnKind::Function(ref func) => {
-                interpreter.call(&func.lock().unwrap(), vec![value.clone()], None).map_err(Box::new)?;
-                interpreter.run().map_err(Box::new)?.unwrap_or_default()
-            }
-            FnKind::Native(ref func) => func(interpreter, vec![value.clone()])?.unwrap_or_default(),
-        };
+    let ln = interpreter.ln().unwrap_or_default();
+    let mut new_vector = Vec::with_capacity(vector.len());
+    for value in vector.iter() {
+        let func = Value::Fn(func.clone());
+        new_vector.push(interpreter.call_now(func, std::slice::from_ref(value), ln)?);
     }
     Ok(Some(Value::Vector(Arc::new(Mutex::new(new_vector)))))
 });
@@ -1,14 +1,77 @@
 use crate::{
-    parse,
+    compile, make_tuple, parse,
+    pkg::{DependencySource, Manifest, ManifestError},
+    run,
+    run::{
+        code::{
+            BinaryOperation, ByteCode, Capture, Closure, Intrinsic, Location, Source,
+            UnaryOperation, Upvalue,
+        },
+        format::{self, Features, FormatError, Header, Version, FORMAT_VERSION},
+        interpreter::{
+            HookEvent, HookMask, Interpreter, InterpreterOptions, RunStatus, RunTimeError,
+            RunTimeErrorKind, DEFAULT_MAX_CALL_DEPTH,
+        },
+        module::HydraModule,
+        replay::Replay,
+        trust::{Capability, TrustLevel},
+        types::{self, Tag},
+        value::{
+            resolve_index, resolve_position, FnKind, FromValue, Function, NativeObject, Value,
+        },
+    },
+    run_as, run_limited,
     scan::{
-        ast::{Atom, BinaryOperator, Chunk, Expression, Parameter, Path, Statement, UnaryOperator},
-        lexer::{Lexer, Line},
+        ast::{
+            Atom, BinaryOperator, Chunk, Element, Expression, MapKey, Parameter, Path, Pattern,
+            Statement, UnaryOperator,
+        },
+        lexer::{LexError, Lexer, Line},
         parser::ParseError,
-        position::{Indexed, Located},
+        position::{byte_col, display_col, Indexed, Located, Position},
         tokens::Token,
     },
+    std_hydra::{self, std_io, std_os},
+};
+use proptest::{prelude::*, test_runner::TestCaseError};
+use std::{
+    cell::RefCell,
+    error::Error,
+    rc::Rc,
+    sync::{Arc, Mutex},
 };
 
+/// compiles `text` as the script at `path` and runs it in `interpreter`
+fn run_script(
+    interpreter: &mut Interpreter,
+    text: &str,
+    path: Option<String>,
+) -> Result<Option<Value>, RunTimeError> {
+    let closure = compile::<Chunk>(text, path).unwrap();
+    run_code(interpreter, closure)
+}
+/// runs `closure` as the main function in `interpreter`
+fn run_code(
+    interpreter: &mut Interpreter,
+    closure: Closure,
+) -> Result<Option<Value>, RunTimeError> {
+    interpreter.call(
+        &Function {
+            closure: Rc::new(closure),
+            upvalues: vec![],
+        },
+        &[],
+        None,
+    )?;
+    interpreter.run()
+}
+/// runs `text` in a fresh interpreter with the standard library imported
+fn run_std(text: &str) -> Result<Option<Value>, RunTimeError> {
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    run_script(&mut interpreter, text, None)
+}
+
 #[test]
 pub fn lexer_ident() {
     let text = "hello what the sigma";
@@ -236,10 +299,10 @@ pub fn parser_stat_call() {
                 stats: vec![Located::new(
                     Statement::Call {
                         head: Located::new(Path::Ident("print".to_string()), Default::default()),
-                        args: vec![Located::new(
+                        args: vec![Element::Single(Located::new(
                             Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                             Default::default()
-                        )],
+                        ))],
                     },
                     Default::default()
                 ),]
@@ -258,14 +321,14 @@ pub fn parser_stat_call() {
                     Statement::Call {
                         head: Located::new(Path::Ident("print".to_string()), Default::default()),
                         args: vec![
-                            Located::new(
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                                 Default::default()
-                            ),
-                            Located::new(
+                            )),
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                                 Default::default()
-                            )
+                            ))
                         ],
                     },
                     Default::default()
@@ -285,14 +348,14 @@ pub fn parser_stat_call() {
                     Statement::Call {
                         head: Located::new(Path::Ident("print".to_string()), Default::default()),
                         args: vec![
-                            Located::new(
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                                 Default::default()
-                            ),
-                            Located::new(
+                            )),
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                                 Default::default()
-                            )
+                            ))
                         ],
                     },
                     Default::default()
@@ -312,10 +375,10 @@ pub fn parser_stat_call() {
                     Statement::SelfCall {
                         head: Located::new(Path::Ident("player".to_string()), Default::default()),
                         field: Located::new("update".to_string(), Default::default()),
-                        args: vec![Located::new(
+                        args: vec![Element::Single(Located::new(
                             Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                             Default::default()
-                        )],
+                        ))],
                     },
                     Default::default()
                 ),]
@@ -335,14 +398,14 @@ pub fn parser_stat_call() {
                         head: Located::new(Path::Ident("player".to_string()), Default::default()),
                         field: Located::new("update".to_string(), Default::default()),
                         args: vec![
-                            Located::new(
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                                 Default::default()
-                            ),
-                            Located::new(
+                            )),
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                                 Default::default()
-                            )
+                            ))
                         ],
                     },
                     Default::default()
@@ -363,14 +426,14 @@ pub fn parser_stat_call() {
                         head: Located::new(Path::Ident("player".to_string()), Default::default()),
                         field: Located::new("update".to_string(), Default::default()),
                         args: vec![
-                            Located::new(
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                                 Default::default()
-                            ),
-                            Located::new(
+                            )),
+                            Element::Single(Located::new(
                                 Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                                 Default::default()
-                            )
+                            ))
                         ],
                     },
                     Default::default()
@@ -418,9 +481,18 @@ pub fn parser_atom_vector() {
         expr,
         Located::new(
             Atom::Vector(vec![
-                Located::new(Expression::Atom(Atom::Int(1)), Default::default()),
-                Located::new(Expression::Atom(Atom::Int(2)), Default::default()),
-                Located::new(Expression::Atom(Atom::Int(3)), Default::default()),
+                Element::Single(Located::new(
+                    Expression::Atom(Atom::Int(1)),
+                    Default::default()
+                )),
+                Element::Single(Located::new(
+                    Expression::Atom(Atom::Int(2)),
+                    Default::default()
+                )),
+                Element::Single(Located::new(
+                    Expression::Atom(Atom::Int(3)),
+                    Default::default()
+                )),
             ]),
             Default::default()
         )
@@ -435,10 +507,10 @@ pub fn parser_atom_vector() {
     assert_eq!(
         expr,
         Located::new(
-            Atom::Vector(vec![Located::new(
+            Atom::Vector(vec![Element::Single(Located::new(
                 Expression::Atom(Atom::Int(1)),
                 Default::default()
-            ),]),
+            )),]),
             Default::default()
         )
     );
@@ -448,10 +520,10 @@ pub fn parser_atom_vector() {
     assert_eq!(
         expr,
         Located::new(
-            Atom::Vector(vec![Located::new(
+            Atom::Vector(vec![Element::Single(Located::new(
                 Expression::Atom(Atom::Int(1)),
                 Default::default()
-            ),]),
+            )),]),
             Default::default()
         )
     );
@@ -524,15 +596,15 @@ pub fn parser_atom_map() {
         Located::new(
             Atom::Map(vec![
                 (
-                    Located::new("a".to_string(), Default::default()),
+                    Located::new(MapKey::Name("a".to_string()), Default::default()),
                     Located::new(Expression::Atom(Atom::Int(1)), Default::default())
                 ),
                 (
-                    Located::new("b".to_string(), Default::default()),
+                    Located::new(MapKey::Name("b".to_string()), Default::default()),
                     Located::new(Expression::Atom(Atom::Int(2)), Default::default())
                 ),
                 (
-                    Located::new("c".to_string(), Default::default()),
+                    Located::new(MapKey::Name("c".to_string()), Default::default()),
                     Located::new(Expression::Atom(Atom::Int(3)), Default::default())
                 ),
             ]),
@@ -550,7 +622,7 @@ pub fn parser_atom_map() {
         expr,
         Located::new(
             Atom::Map(vec![(
-                Located::new("a".to_string(), Default::default()),
+                Located::new(MapKey::Name("a".to_string()), Default::default()),
                 Located::new(Expression::Atom(Atom::Int(1)), Default::default())
             )]),
             Default::default()
@@ -563,12 +635,13 @@ pub fn parser_atom_map() {
         expr,
         Located::new(
             Atom::Map(vec![(
-                Located::new("a".to_string(), Default::default()),
+                Located::new(MapKey::Name("a".to_string()), Default::default()),
                 Located::new(Expression::Atom(Atom::Int(1)), Default::default())
             )]),
             Default::default()
         )
     );
+    // `a` alone is the shorthand for `a = a`, so a comma has to follow
     let text = "{a 1}";
     let err = parse::<Atom>(text).unwrap_err();
     dbg!(&err);
@@ -576,7 +649,7 @@ pub fn parser_atom_map() {
         err.to_string(),
         Located::new(
             ParseError::Expected {
-                expected: Token::Equal,
+                expected: Token::Comma,
                 got: Token::Int(1)
             },
             Default::default()
@@ -761,10 +834,10 @@ pub fn parser_expr_call() {
                     Expression::Atom(Atom::Path(Path::Ident("print".to_string()))),
                     Default::default()
                 )),
-                args: vec![Located::new(
+                args: vec![Element::Single(Located::new(
                     Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                     Default::default()
-                )],
+                ))],
             },
             Default::default()
         )
@@ -781,14 +854,14 @@ pub fn parser_expr_call() {
                     Default::default()
                 )),
                 args: vec![
-                    Located::new(
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                         Default::default()
-                    ),
-                    Located::new(
+                    )),
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                         Default::default()
-                    )
+                    ))
                 ],
             },
             Default::default()
@@ -806,14 +879,14 @@ pub fn parser_expr_call() {
                     Default::default()
                 )),
                 args: vec![
-                    Located::new(
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                         Default::default()
-                    ),
-                    Located::new(
+                    )),
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                         Default::default()
-                    )
+                    ))
                 ],
             },
             Default::default()
@@ -831,10 +904,10 @@ pub fn parser_expr_call() {
                     Default::default()
                 )),
                 field: Located::new("update".to_string(), Default::default()),
-                args: vec![Located::new(
+                args: vec![Element::Single(Located::new(
                     Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                     Default::default()
-                )],
+                ))],
             },
             Default::default()
         )
@@ -852,14 +925,14 @@ pub fn parser_expr_call() {
                 )),
                 field: Located::new("update".to_string(), Default::default()),
                 args: vec![
-                    Located::new(
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                         Default::default()
-                    ),
-                    Located::new(
+                    )),
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                         Default::default()
-                    )
+                    ))
                 ],
             },
             Default::default()
@@ -878,17 +951,3481 @@ pub fn parser_expr_call() {
                 )),
                 field: Located::new("update".to_string(), Default::default()),
                 args: vec![
-                    Located::new(
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("a".to_string()))),
                         Default::default()
-                    ),
-                    Located::new(
+                    )),
+                    Element::Single(Located::new(
                         Expression::Atom(Atom::Path(Path::Ident("b".to_string()))),
                         Default::default()
-                    )
+                    ))
                 ],
             },
             Default::default()
         )
     );
 }
+#[test]
+pub fn compiler_escape_analysis() {
+    let text = "let (a, b) = (1, 2)\nlet t = (a, b)\nreturn t";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    dbg!(&closure.code);
+    assert!(matches!(closure.code[2], ByteCode::ScratchTuple { .. }));
+    assert!(closure
+        .code
+        .iter()
+        .any(|bytecode| matches!(bytecode, ByteCode::Tuple { .. })));
+    let text = "let i = 0\nlet s = 0\nwhile i < 3\n    let [a, b] = [i, i * 2]\n    s += a + b\n    i += 1\nreturn s";
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(9)));
+}
+#[test]
+pub fn interpreter_intrinsics() {
+    let text = "let v = [1, 2]\nv:push(3)\nreturn (v:len(), v:get(2), v:get(5, 0))";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    dbg!(&closure.code);
+    assert!(closure
+        .code
+        .iter()
+        .any(|bytecode| matches!(bytecode, ByteCode::Intrinsic { .. })));
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(Value::Int(3), Value::Int(3), Value::Int(0)))
+    );
+    let text = "let m = { len = fn(self) => 42 }\nreturn m:len()";
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(42)));
+}
+#[test]
+pub fn interpreter_frame_pool() {
+    let text = "f = fn(x) => x + 1\nlet g = fn(a, b) => f(a) + f(b)\nlet i = 0\nlet sum = 0\nwhile i < 10\n    sum = sum + g(i, 1)\n    i = i + 1\nreturn sum";
+    let mut interpreter = Interpreter::default();
+    let value = run_script(&mut interpreter, text, None).unwrap();
+    assert_eq!(value, Some(Value::Int(75)));
+    assert!(interpreter.call_stack.is_empty());
+    assert!(interpreter
+        .frame_pool
+        .values()
+        .any(|frames| !frames.is_empty()));
+    for frame in interpreter.frame_pool.values().flatten() {
+        assert!(frame
+            .stack
+            .iter()
+            .all(|cell| *cell.lock().unwrap() == Value::default()));
+    }
+}
+#[test]
+pub fn compiler_for_literal() {
+    let text = "let s = 0\nfor x in [1, 2, 3, 4]\n    if x == 2\n        continue\n    if x == 4\n        break\n    s = s + x\nfor (a, b) in ((1, 2), (3, 4))\n    s = s + a * b\nreturn s";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    dbg!(&closure.code);
+    assert!(!closure
+        .code
+        .iter()
+        .any(|bytecode| matches!(bytecode, ByteCode::Call { .. })));
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(18)));
+}
+#[test]
+pub fn compiler_loop_continue() {
+    let text = "let s = 0\nlet i = 0\nwhile i < 3\n    i = i + 1\n    let j = 0\n    while true\n        j = j + 1\n        if j > i\n            break\n        if j == 2\n            continue\n        s = s + j\n    if i == 2\n        continue\n    s = s + 10\nreturn s";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    dbg!(&closure.code);
+    // inner break and continue inside nested ifs
+    assert_eq!(closure.code[12], ByteCode::Jump { addr: 19 });
+    assert_eq!(closure.code[15], ByteCode::Jump { addr: 7 });
+    // inner back edge re-evaluates the condition
+    assert_eq!(closure.code[18], ByteCode::Jump { addr: 7 });
+    // outer continue and back edge
+    assert_eq!(closure.code[21], ByteCode::Jump { addr: 2 });
+    assert_eq!(closure.code[24], ByteCode::Jump { addr: 2 });
+    assert_eq!(closure.code.len(), 26);
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(26)));
+}
+#[test]
+pub fn compiler_peephole() {
+    let text = "fn f(x)\n    if x > 1\n        if x > 2\n            x = x + 4\n        else\n            x = 2\n    else\n        x = 1\n    while x > 5\n        x = x - 1\n        if x == 6\n            continue\n        pass\n    return x\n    x = 0\nreturn (f(3), f(2), f(0))";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    let func = &closure.closures[0];
+    dbg!(&func.code);
+    assert_eq!(func.code.len(), func.lines.len());
+    assert!(!func.code.contains(&ByteCode::None));
+    for (addr, bytecode) in func.code.iter().enumerate() {
+        if let Some(to) = bytecode.target() {
+            assert_ne!(to, addr + 1);
+            assert!(!matches!(func.code.get(to), Some(ByteCode::Jump { .. })));
+        }
+    }
+    // nothing after the return survives
+    assert!(!func.code.iter().any(|bytecode| matches!(
+        bytecode,
+        ByteCode::Move {
+            src: Source::Int(0),
+            ..
+        }
+    )));
+    assert!(func
+        .locals
+        .iter()
+        .all(|local| local.start <= local.end && local.end <= func.code.len()));
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(Value::Int(5), Value::Int(2), Value::Int(1)))
+    );
+}
+#[test]
+pub fn compiler_short_circuit() {
+    let text = "calls = 0\nfn hit(v)\n    global calls = calls + 1\n    return v\nreturn (false and hit(true), true or hit(false), null or \"x\", \"a\" and \"b\", hit(false) or hit(\"y\"), calls)";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    dbg!(&closure.code);
+    assert!(!closure.code.iter().any(|bytecode| matches!(
+        bytecode,
+        ByteCode::Binary {
+            op: BinaryOperation::And | BinaryOperation::Or,
+            ..
+        }
+    )));
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::String("x".into()),
+            Value::String("b".into()),
+            Value::String("y".into()),
+            Value::Int(2)
+        ))
+    );
+}
+#[test]
+pub fn compiler_null_coalescing() {
+    let text = "calls = 0\nfn hit(v)\n    global calls = calls + 1\n    return v\nlet m = { a = 0 }\nreturn (m.b ?? \"none\", m.a ?? hit(1), false ?? hit(1), null ?? null ?? 3, hit(null) ?? hit(2), \"a\" or 5, null or 5, true and 'x', false or 1.5, calls)";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    assert!(!closure.code.iter().any(|bytecode| matches!(
+        bytecode,
+        ByteCode::Binary {
+            op: BinaryOperation::Coalesce,
+            ..
+        }
+    )));
+    let value = run(text, vec![], None).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        "(\"none\", 0, false, 3, 2, \"a\", 5, 'x', 1.5, 2)"
+    );
+    assert_eq!(
+        Lexer::from("a ?? b").lex().unwrap()[0].tokens[1].value,
+        Token::QuestionQuestion
+    );
+    assert!(Lexer::from("a ? b").lex().is_err());
+}
+#[test]
+pub fn compiler_self_call() {
+    let text = "let m = { f = fn(self, a, b) => self.x + a + b, x = 1 }\nreturn m:f(2, 3)";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    dbg!(&closure.code);
+    let Some(&ByteCode::Call { start, amount, .. }) = closure
+        .code
+        .iter()
+        .find(|bytecode| matches!(bytecode, ByteCode::Call { .. }))
+    else {
+        panic!("expected a call");
+    };
+    assert_eq!(amount, 3);
+    let Some(&ByteCode::Move {
+        dst: Location::Register(local),
+        ..
+    }) = closure
+        .code
+        .iter()
+        .find(|bytecode| matches!(bytecode, ByteCode::Move { .. }))
+    else {
+        panic!("expected the let binding");
+    };
+    // the receiver goes straight from the local into the first argument register
+    assert!(closure.code.contains(&ByteCode::Move {
+        dst: Location::Register(start),
+        src: Source::Register(local),
+    }));
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(6)));
+}
+#[test]
+pub fn interpreter_methods() {
+    let text = "let counter = { n = 0, step = { by = 2 } }\nfn counter:inc()\n    self.n += self.step.by\n    return self.n\nfn counter.step:double()\n    self.by *= 2\ncounter:inc()\ncounter.step:double()\nreturn counter:inc()";
+    let ast = parse::<Chunk>(text).unwrap();
+    assert!(matches!(
+        &ast.value.stats[2].value,
+        Statement::FieldFn {
+            head: Located {
+                value: Path::Field { .. },
+                ..
+            },
+            method: true,
+            ..
+        }
+    ));
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(6)));
+}
+#[test]
+pub fn compiler_global() {
+    let text = "count = 0\nlet count = 10\nif true\n    global count\n    count = count + 1\nlet local = count\nglobal count = count + 100\nreturn (local, count)";
+    let ast = parse::<Chunk>(text).unwrap();
+    assert!(matches!(
+        &ast.value.stats[4].value,
+        Statement::Global { expr: Some(_), .. }
+    ));
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(make_tuple!(Value::Int(10), Value::Int(101))));
+}
+#[test]
+pub fn parser_expression_statement() {
+    let text = "let v = [1]\nv:get(0) + 1\n(v)\nv:push(2)\nreturn v:len()";
+    let ast = parse::<Chunk>(text).unwrap();
+    assert!(matches!(
+        &ast.value.stats[1].value,
+        Statement::Expression(Located {
+            value: Expression::Binary {
+                op: BinaryOperator::Plus,
+                ..
+            },
+            ..
+        })
+    ));
+    assert!(matches!(
+        &ast.value.stats[2].value,
+        Statement::Expression(_)
+    ));
+    assert!(matches!(
+        &ast.value.stats[3].value,
+        Statement::SelfCall { .. }
+    ));
+    assert!(parse::<Chunk>("let x = 1\nx +").is_err());
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(2)));
+}
+#[test]
+pub fn lexer_bracket_continuation() {
+    let text = "f(a,\n\n    b)\ng";
+    let lines = Lexer::from(text).lex().unwrap();
+    dbg!(&lines);
+    assert_eq!(
+        lines,
+        vec![
+            Line {
+                indent: 0,
+                ln: 0,
+                tokens: vec![
+                    Indexed::new(Token::Ident("f".to_string()), 0..0),
+                    Indexed::new(Token::ParanLeft, 0..0),
+                    Indexed::new(Token::Ident("a".to_string()), 0..0),
+                    Indexed::new(Token::Comma, 0..0),
+                    Indexed::new(Token::Ident("b".to_string()), 0..0),
+                    Indexed::new(Token::ParanRight, 0..0),
+                ]
+            },
+            Line {
+                indent: 0,
+                ln: 3,
+                tokens: vec![Indexed::new(Token::Ident("g".to_string()), 0..0)]
+            },
+        ]
+    );
+    let text = "let m = {\n    a = [\n        1,\n    ],\n}\nreturn m.a:len()";
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(1)));
+}
+#[test]
+pub fn lexer_operator_continuation() {
+    let text = "let a = 1 +\n    2\nlet b = a \\\n    * 3";
+    let lines = Lexer::from(text).lex().unwrap();
+    dbg!(&lines);
+    assert_eq!(lines.len(), 2);
+    assert!(!lines
+        .iter()
+        .flat_map(|line| &line.tokens)
+        .any(|token| token.value == Token::Backslash));
+    let text =
+        "let a = 1 +\n    2\nif a == 3 and\n        a > 0\n    a = a \\\n        * 3\nreturn a";
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(9)));
+}
+#[test]
+pub fn lexer_windows_line_endings() {
+    let text = "let a = 1\nif a\n    a = \"x\"\nreturn a";
+    let crlf = format!("\u{feff}{}\r\n", text.replace('\n', "\r\n"));
+    assert_eq!(
+        Lexer::from(crlf.as_str()).lex().unwrap(),
+        Lexer::from(text).lex().unwrap()
+    );
+    assert_eq!(
+        run(&crlf, vec![], None).unwrap(),
+        run(text, vec![], None).unwrap()
+    );
+    assert_eq!(
+        std_io::unix_newlines("a\r\nb\r\n".into()),
+        String::from("a\nb\n")
+    );
+}
+#[test]
+pub fn parser_indentation() {
+    let text = "let x = 0\nif false\n    if true\n        x = 1\nelse\n    x = 2\nreturn x";
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(2)));
+    let text = "let x = 0\nif true\n\tx = 1\n\tif false\n\t\tx = 2\nreturn x";
+    let lines = Lexer::from(text).tab_width(8).lex().unwrap();
+    assert_eq!(lines[2].indent, 8);
+    assert_eq!(lines[4].indent, 16);
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(1)));
+    let text = "if true\n    x = 1\n\tx = 2";
+    assert_eq!(
+        Lexer::from(text).lex().unwrap_err().value,
+        LexError::MixedIndentation
+    );
+    let text = "if true\n    x = 1\n        x = 2";
+    assert!(parse::<Chunk>(text).is_err());
+    let text = "if true\n        x = 1\n    x = 2";
+    assert!(parse::<Chunk>(text).is_err());
+}
+#[test]
+pub fn compiler_pass() {
+    let text = "let noop = fn() => null\nfn empty()\n    pass\nlet x = 1\nif x == 1\n    pass\nelse\n    x = 2\nwhile false\n    pass\nreturn x";
+    let ast = parse::<Chunk>(text).unwrap();
+    let Statement::Fn { body, .. } = &ast.value.stats[1].value else {
+        panic!("expected fn");
+    };
+    assert_eq!(body.value.stats[0].value, Statement::Pass);
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(value, Some(Value::Int(1)));
+}
+#[test]
+pub fn compiler_match() {
+    let text = "fn kind(x)\n    match x\n        case 1, 2\n            return \"small\"\n        case 3\n            return \"three\"\n        case _\n            return \"other\"\nlet n = 0\nmatch n\n    case 1\n        n = 10\nreturn (kind(1), kind(3), kind(9), n, 2.5 is (\"int\", \"float\"), 'a' is [\"int\"], (1, 2) is (\"tuple\",))";
+    let value = run(text, vec![], None).unwrap();
+    let Some(Value::Tuple(values)) = value else {
+        panic!("expected tuple");
+    };
+    assert_eq!(
+        values.lock().unwrap().as_ref(),
+        &[
+            Value::String("small".into()),
+            Value::String("three".into()),
+            Value::String("other".into()),
+            Value::Int(0),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(true),
+        ]
+    );
+}
+#[test]
+pub fn interpreter_conversions() {
+    let text = "conversion(\"pair\", fn(v) => (v, v))\nreturn (3 as \"pair\", 2 as \"double\", 1 as \"float\")";
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    interpreter.set_conversion(
+        "double",
+        Value::Fn(FnKind::Native(Rc::new(
+            |_: &mut Interpreter, args: &[Value]| -> Result<Option<Value>, Box<dyn Error>> {
+                let Some(Value::Int(value)) = args.first() else {
+                    return Err("expected int".into());
+                };
+                Ok(Some(Value::Int(value * 2)))
+            },
+        ))),
+    );
+    let value = run_script(&mut interpreter, text, None).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            make_tuple!(Value::Int(3), Value::Int(3)),
+            Value::Int(4),
+            Value::Float(1.0)
+        ))
+    );
+}
+#[test]
+pub fn interpreter_freeze() {
+    let text = "let v = freeze([1, 2])\nlet m = {a = 1}\nreturn (is_frozen(v), is_frozen(m), is_frozen(v:copy()), v[0])";
+    let value = run_std(text).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(false),
+            Value::Int(1)
+        ))
+    );
+    for text in [
+        "let v = freeze([1])\nv[0] = 2",
+        "let v = freeze([1])\nv:push(2)",
+        "let v = freeze([1])\nv += 2",
+        "let m = freeze({})\nm += { a = 1 }",
+    ] {
+        assert!(run_std(text).is_err());
+    }
+}
+#[test]
+pub fn interpreter_copy_containers() {
+    // only the top level gets copied, the vector nested in `n` stays shared with `m`
+    let text = "f = fn(v) => v:push(3)\nlet a = [1]\nlet b = a\nb:push(2)\nf(a)\nlet n = [[1]]\nlet m = n\nm:push([])\nm[0]:push(2)\nreturn (a:len(), b:len(), n:len(), n[0]:len())";
+    let closure = Rc::new(compile::<Chunk>(text, None).unwrap());
+    assert!(closure
+        .code
+        .iter()
+        .any(|bytecode| matches!(bytecode, ByteCode::Copy { .. })));
+    for (copy_containers, expected) in [(false, [3, 3, 2, 2]), (true, [1, 2, 1, 2])] {
+        let mut interpreter = Interpreter {
+            copy_containers,
+            ..Default::default()
+        };
+        std_hydra::import(&mut interpreter);
+        interpreter
+            .call(
+                &Function {
+                    closure: Rc::clone(&closure),
+                    upvalues: vec![],
+                },
+                &[],
+                None,
+            )
+            .unwrap();
+        let value = interpreter.run().unwrap();
+        assert_eq!(
+            value,
+            Some(make_tuple!(
+                Value::Int(expected[0]),
+                Value::Int(expected[1]),
+                Value::Int(expected[2]),
+                Value::Int(expected[3])
+            ))
+        );
+    }
+}
+#[test]
+pub fn compiler_enum() {
+    let text = "enum Color: red, green = 5, blue\nenum Dir: up = \"up\", down = \"down\"\nlet c = Color.blue\nlet kind = 0\nmatch c\n    case Color.red\n        kind = 1\n    case Color.blue\n        kind = 2\nreturn (Color.red, c, Dir.down, kind, c is Color, 1 is Color, nameof(Color, 6), is_frozen(Color))";
+    let value = run_std(text).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            Value::Int(0),
+            Value::Int(6),
+            Value::String("down".into()),
+            Value::Int(2),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::String("blue".into()),
+            Value::Bool(true)
+        ))
+    );
+    assert_eq!(
+        parse::<Chunk>("enum Color: red, green = 0")
+            .unwrap_err()
+            .value
+            .to_string(),
+        ParseError::DuplicateEnumValue.to_string()
+    );
+    assert!(run("enum Color: red\nColor.red = 1", vec![], None).is_err());
+}
+#[test]
+pub fn compiler_match_patterns() {
+    let text = "fn classify(v)\n    match v\n        case (x, y) if y == 2\n            return x - y\n        case (0, [a, _]), (a, 0)\n            return a\n        case [first, _]\n            return first\n        case (x)\n            return x\n\nreturn (classify((5, 2)), classify((0, [7, 8])), classify((9, 0)), classify([4, 1]), classify(\"s\"), classify((1, 3)))";
+    let value = run(text, vec![], None).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            Value::Int(3),
+            Value::Int(7),
+            Value::Int(9),
+            Value::Int(4),
+            Value::String("s".into()),
+            make_tuple!(Value::Int(1), Value::Int(3))
+        ))
+    );
+}
+#[test]
+pub fn compiler_match_map_patterns() {
+    let text = "fn area(shape)\n    match shape\n        case { kind = \"circle\", r }\n            return 3 * r * r\n        case { kind = \"rect\", size = (w, h) } if w > 0\n            return w * h\n        case { kind }\n            return kind\n        case {}\n            return \"untagged\"\n        case _\n            return null\nreturn (area({ kind = \"circle\", r = 2 }), area({ kind = \"rect\", size = (2, 5) }), area({ kind = \"rect\", size = 3 }), area({ r = 1, kind = null }), area(4))";
+    let value = run(text, vec![], None).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        "(12, 10, \"rect\", \"untagged\", null)"
+    );
+    let Located {
+        value: Statement::Match { cases, .. },
+        ..
+    } = parse::<Statement>("match v\n    case { a, b = [c] }\n        pass").unwrap()
+    else {
+        panic!("expected a match");
+    };
+    let Pattern::Map(fields) = &cases[0].value.patterns[0].value else {
+        panic!("expected a map pattern");
+    };
+    let keys = fields
+        .iter()
+        .map(|(key, _)| key.value.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["a", "b"]);
+    assert_eq!(fields[0].1.value, Pattern::Binding("a".into()));
+    assert!(parse::<Statement>("match v\n    case { 1 }\n        pass").is_err());
+}
+#[test]
+pub fn std_string_scan() {
+    use crate::std_hydra::std_string::scan;
+    assert_eq!(
+        scan("v1.2 - beta", "v{}.{} - {}"),
+        Some(vec![
+            Value::Int(1),
+            Value::Int(2),
+            Value::String("beta".into())
+        ])
+    );
+    assert_eq!(
+        scan("{x} = 1.5", "{{{}}} = {}"),
+        Some(vec![Value::String("x".into()), Value::Float(1.5)])
+    );
+    assert_eq!(scan("nan", "{}"), Some(vec![Value::String("nan".into())]));
+    assert_eq!(scan("a-b", "{}+{}"), None);
+    assert_eq!(scan("abc", "abc"), Some(vec![]));
+    assert_eq!(scan("abcd", "abc"), None);
+}
+#[test]
+pub fn interpreter_hooks() {
+    let text = "f = fn(x) => x * 2\nlet a = f(1)\nlet b = f(a)\nreturn b";
+    let mut interpreter = Interpreter::default();
+    let events = Rc::new(RefCell::new(vec![]));
+    {
+        let events = Rc::clone(&events);
+        interpreter.set_hook(
+            HookMask::LINE | HookMask::CALL | HookMask::RETURN,
+            move |_, event, ln| {
+                events.borrow_mut().push((event, ln));
+                Ok(())
+            },
+        );
+    }
+    assert_eq!(
+        run_script(&mut interpreter, text, None).unwrap(),
+        Some(Value::Int(4))
+    );
+    let events = events.borrow();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|(event, _)| *event == HookEvent::Call)
+            .count(),
+        3
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|(event, _)| *event == HookEvent::Return)
+            .count(),
+        3
+    );
+    assert!(events.contains(&(HookEvent::Line, 3)));
+    let mut interpreter = Interpreter::default();
+    interpreter.set_hook(HookMask::INSTRUCTION, |_, _, ln| {
+        if ln >= 1 {
+            Err("stopped".into())
+        } else {
+            Ok(())
+        }
+    });
+    assert_eq!(
+        run_script(&mut interpreter, "let a = 1\nlet b = 2", None)
+            .unwrap_err()
+            .err,
+        RunTimeErrorKind::Custom("stopped".into())
+    );
+    // a hook clearing itself stays cleared, one replacing itself gets replaced
+    let mut interpreter = Interpreter::default();
+    let fired = Rc::new(RefCell::new(vec![]));
+    {
+        let fired = Rc::clone(&fired);
+        interpreter.set_hook(HookMask::INSTRUCTION, move |interpreter, _, _| {
+            fired.borrow_mut().push("clear");
+            interpreter.clear_hook();
+            Ok(())
+        });
+    }
+    run_script(&mut interpreter, "let a = 1\nlet b = 2", None).unwrap();
+    assert_eq!(*fired.borrow(), ["clear"]);
+    assert!(interpreter.hook.func.is_none());
+    fired.borrow_mut().clear();
+    {
+        let fired = Rc::clone(&fired);
+        interpreter.set_hook(HookMask::INSTRUCTION, move |interpreter, _, _| {
+            fired.borrow_mut().push("first");
+            let fired = Rc::clone(&fired);
+            interpreter.set_hook(HookMask::LINE, move |_, _, _| {
+                fired.borrow_mut().push("second");
+                Ok(())
+            });
+            Ok(())
+        });
+    }
+    run_script(&mut interpreter, "let a = 1\nlet b = 2\nlet c = 3", None).unwrap();
+    assert_eq!(*fired.borrow(), ["first", "second", "second"]);
+}
+#[test]
+pub fn interpreter_replay() {
+    let text = "return (math.random(), math.random_int(100), os.time())";
+    let run_with = |replay: Replay| {
+        let mut interpreter = Interpreter::default();
+        std_hydra::import(&mut interpreter);
+        interpreter.replay = replay;
+        (run_script(&mut interpreter, text, None), interpreter.replay)
+    };
+    let (recorded, log) = run_with(Replay::Record(vec![]));
+    let Replay::Record(entries) = log else {
+        panic!("expected a record")
+    };
+    dbg!(&entries);
+    assert_eq!(
+        entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["random", "random", "time"]
+    );
+    let (replayed, _) = run_with(Replay::Replay(entries.into()));
+    assert_eq!(recorded.unwrap(), replayed.unwrap());
+
+    let log = Replay::parse("time\tfloat 1.5\nrandom\tstr a\\tb").unwrap();
+    let (result, _) = run_with(log);
+    assert_eq!(
+        result.unwrap_err().err.to_string(),
+        "math.random: replay diverged: script called random where the log has time"
+    );
+    assert!(Replay::parse("random\tfloat x").is_err());
+}
+#[test]
+pub fn interpreter_typed_globals() {
+    let mut interpreter = Interpreter::default();
+    interpreter.set("app.config.width", 640).unwrap();
+    interpreter.set("app.config.title", "hydra").unwrap();
+    interpreter.set("scale", 1.5).unwrap();
+    assert_eq!(interpreter.get_as::<i64>("app.config.width"), Some(640));
+    assert_eq!(interpreter.get_as::<String>("app.config.width"), None);
+    assert_eq!(
+        interpreter.get_as::<String>("app.config.title"),
+        Some("hydra".into())
+    );
+    assert_eq!(interpreter.get_as::<f64>("scale"), Some(1.5));
+    assert_eq!(interpreter.get("app.missing"), None);
+    assert_eq!(
+        interpreter.set("scale.x", 1),
+        Err(RunTimeErrorKind::InvalidFieldHead("float"))
+    );
+    run_script(
+        &mut interpreter,
+        "app.config.width = app.config.width * 2",
+        None,
+    )
+    .unwrap();
+    assert_eq!(interpreter.get_as::<i32>("app.config.width"), Some(1280));
+    assert_eq!(
+        Vec::<i64>::from_value(Value::from(vec![1, 2, 3])),
+        Some(vec![1, 2, 3])
+    );
+    assert_eq!(Option::<i64>::from_value(Value::Null), Some(None));
+}
+#[test]
+pub fn interpreter_app_data() {
+    #[derive(Debug, PartialEq)]
+    struct Counter(i64);
+    let mut interpreter = Interpreter::default();
+    assert_eq!(interpreter.insert_app_data(Counter(0)), None);
+    interpreter
+        .set(
+            "count",
+            Value::Fn(FnKind::Native(Rc::new(|interpreter, _| {
+                let Some(counter) = interpreter.app_data_mut::<Counter>() else {
+                    return Err("no counter".into());
+                };
+                counter.0 += 1;
+                Ok(Some(Value::Int(counter.0)))
+            }))),
+        )
+        .unwrap();
+    assert_eq!(
+        run_script(&mut interpreter, "count()\ncount()\nreturn count()", None).unwrap(),
+        Some(Value::Int(3))
+    );
+    assert_eq!(interpreter.app_data::<Counter>(), Some(&Counter(3)));
+    assert_eq!(interpreter.app_data::<String>(), None);
+    assert_eq!(interpreter.insert_app_data(Counter(10)), Some(Counter(3)));
+    assert_eq!(interpreter.remove_app_data::<Counter>(), Some(Counter(10)));
+    assert_eq!(interpreter.app_data::<Counter>(), None);
+}
+#[test]
+pub fn native_object_set() {
+    struct Rect {
+        width: i64,
+        height: i64,
+    }
+    impl NativeObject for Rect {
+        fn typ(&self) -> &'static str {
+            "rect"
+        }
+        fn get(&self, key: &str) -> Option<Value> {
+            match key {
+                "width" => Some(self.width.into()),
+                "height" => Some(self.height.into()),
+                "area" => Some((self.width * self.height).into()),
+                _ => None,
+            }
+        }
+        fn set(&mut self, key: &str, value: Value) -> Result<(), Box<dyn Error>> {
+            let Value::Int(value) = value else {
+                return Err(format!("expected int for {key}, got {}", value.typ()).into());
+            };
+            match key {
+                "width" => self.width = value,
+                "height" => self.height = value,
+                _ => return Err(format!("can't set field {key:?} of rect").into()),
+            }
+            Ok(())
+        }
+    }
+    let run_with = |text: &str| {
+        let mut interpreter = Interpreter::default();
+        let rect: Arc<Mutex<dyn NativeObject>> = Arc::new(Mutex::new(Rect {
+            width: 2,
+            height: 3,
+        }));
+        interpreter.set("rect", Value::NativeObject(rect)).unwrap();
+        run_script(&mut interpreter, text, None)
+    };
+    assert_eq!(
+        run_with("rect.width = 5\nreturn rect.area").unwrap(),
+        Some(Value::Int(15))
+    );
+    assert_eq!(
+        run_with("rect.area = 1").unwrap_err().err,
+        RunTimeErrorKind::Custom("can't set field \"area\" of rect".into())
+    );
+    assert_eq!(
+        run_with("rect.width = \"wide\"").unwrap_err().err,
+        RunTimeErrorKind::Custom("expected int for width, got str".into())
+    );
+}
+#[test]
+pub fn native_object_protocols() {
+    struct Squares(i64);
+    impl NativeObject for Squares {
+        fn typ(&self) -> &'static str {
+            "squares"
+        }
+        fn __len(&self) -> Option<usize> {
+            Some(self.0 as usize)
+        }
+        fn __iter(&self) -> Option<Box<dyn Iterator<Item = Value>>> {
+            Some(Box::new((0..self.0).map(|i| Value::Int(i * i))))
+        }
+        fn __index_int(&self, index: i64) -> Result<Value, RunTimeErrorKind> {
+            if (0..self.0).contains(&index) {
+                Ok(Value::Int(index * index))
+            } else {
+                Err(RunTimeErrorKind::IndexOutOfRange {
+                    index,
+                    len: self.0 as usize,
+                })
+            }
+        }
+    }
+    let run_with = |text: &str| {
+        let mut interpreter = Interpreter::default();
+        std_hydra::import(&mut interpreter);
+        let squares: Arc<Mutex<dyn NativeObject>> = Arc::new(Mutex::new(Squares(4)));
+        interpreter
+            .set("squares", Value::NativeObject(squares))
+            .unwrap();
+        run_script(&mut interpreter, text, None)
+    };
+    assert_eq!(
+        run_with("let sum = 0\nfor x in squares\n    sum = sum + x\nreturn (squares:len(), sum, squares[3])")
+            .unwrap(),
+        Some(make_tuple!(Value::Int(4), Value::Int(14), Value::Int(9)))
+    );
+    assert_eq!(
+        run_with("return squares[4]").unwrap_err().err,
+        RunTimeErrorKind::IndexOutOfRange { index: 4, len: 4 }
+    );
+    assert_eq!(
+        run_with("return squares[\"x\"]").unwrap(),
+        Some(Value::Null)
+    );
+    assert_eq!(
+        run_with("return (len(squares), len(\"añb\"), len([1, 2]), len((1,)), len({ a = 1 }))")
+            .unwrap(),
+        Some(make_tuple!(
+            Value::Int(4),
+            Value::Int(3),
+            Value::Int(2),
+            Value::Int(1),
+            Value::Int(1)
+        ))
+    );
+    assert!(run_with("return len(1)").is_err());
+    assert!(run_with("return len(iter([]))").is_err());
+    // `len` counts the chars of a string, its method with and without the intrinsic
+    // counts the bytes its indices and slices go by
+    assert_eq!(
+        run_with("let s = \"héllo\"\nreturn (len(s), s:len(), s.len(s), s:sub(0, s:len()))")
+            .unwrap(),
+        Some(make_tuple!(
+            Value::Int(5),
+            Value::Int(6),
+            Value::Int(6),
+            Value::String("héllo".into())
+        ))
+    );
+}
+#[test]
+pub fn value_struct_conversions() {
+    #[derive(Debug, PartialEq, Default)]
+    struct Window {
+        width: i64,
+        height: i64,
+    }
+    #[derive(Debug, PartialEq)]
+    struct Config {
+        title: String,
+        window: Window,
+        scale: Option<f64>,
+        tags: Vec<String>,
+        cache: Vec<i64>,
+    }
+    crate::value_struct! {
+        Window { width, height }
+    }
+    crate::value_struct! {
+        Config {
+            title as "name",
+            window,
+            scale,
+            tags,
+        }
+        skip { cache }
+    }
+    let config = Config {
+        title: "hydra".into(),
+        window: Window {
+            width: 640,
+            height: 480,
+        },
+        scale: None,
+        tags: vec!["a".into()],
+        cache: vec![1, 2],
+    };
+    let mut interpreter = Interpreter::default();
+    interpreter.set("config", config).unwrap();
+    assert_eq!(
+        run_script(
+            &mut interpreter,
+            "config.window.width = config.window.width * 2\nconfig.scale = 1.5\nreturn config.name",
+            None
+        )
+        .unwrap(),
+        Some(Value::String("hydra".into()))
+    );
+    assert_eq!(
+        interpreter.get_as::<Config>("config"),
+        Some(Config {
+            title: "hydra".into(),
+            window: Window {
+                width: 1280,
+                height: 480,
+            },
+            scale: Some(1.5),
+            tags: vec!["a".into()],
+            cache: vec![],
+        })
+    );
+    interpreter.set("config.window.width", "wide").unwrap();
+    assert_eq!(interpreter.get_as::<Config>("config"), None);
+}
+#[test]
+pub fn compiler_unpack() {
+    let closure = compile::<Chunk>("let q, r = divmod(7, 2)\nreturn q * 10 + r", None).unwrap();
+    dbg!(&closure.code);
+    assert!(closure
+        .code
+        .iter()
+        .any(|bytecode| matches!(bytecode, ByteCode::Unpack { amount: 2, .. })));
+    let mut interpreter = Interpreter::default();
+    interpreter
+        .set(
+            "divmod",
+            Value::Fn(FnKind::Native(Rc::new(|_, args| {
+                let (Some(Value::Int(a)), Some(Value::Int(b))) = (args.first(), args.get(1)) else {
+                    return Err("expected ints".into());
+                };
+                Ok(Some(make_tuple!(a / b, a % b)))
+            }))),
+        )
+        .unwrap();
+    assert_eq!(
+        run_code(&mut interpreter, closure).unwrap(),
+        Some(Value::Int(31))
+    );
+    assert_eq!(
+        run("let a = 1\nlet a, b = [2, 3]\nreturn a + b", vec![], None).unwrap(),
+        Some(Value::Int(5))
+    );
+    assert_eq!(
+        run("let a, b, c = (1, 2)\nreturn c", vec![], None).unwrap(),
+        Some(Value::Null)
+    );
+}
+#[test]
+pub fn compound_type_strings() {
+    let text = r#"return (
+    1 is "int|float",
+    null is "str?",
+    "a" is "str?",
+    [1, 2] is "vec<int>",
+    [1, "a"] is "vec<int>",
+    [1, "a"] is "vec<int|str>",
+    (1, [2.5]) is "tuple<int|vec<float>>",
+    {a = 1} is "map<int>",
+    3 is ("vec<int>", "any"),
+)"#;
+    assert_eq!(
+        run(text, vec![], None).unwrap(),
+        Some(make_tuple!(
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(true),
+        ))
+    );
+    assert_eq!(
+        run_std(r#"return check(2.5, "int|float")"#).unwrap(),
+        Some(Value::Float(2.5))
+    );
+    assert_eq!(
+        run_std(r#"return expect([1, "a"], "vec<int>")"#)
+            .unwrap_err()
+            .err,
+        RunTimeErrorKind::Native {
+            name: "expect".into(),
+            msg: "expected vec<int>, got vec<int|str>".into()
+        }
+    );
+}
+#[test]
+pub fn type_tags() {
+    let values = [
+        Value::Null,
+        Value::Int(1),
+        Value::Float(1.5),
+        Value::Bool(true),
+        Value::Char('a'),
+        Value::String("a".into()),
+        make_tuple!(Value::Int(1), Value::Int(2)),
+    ];
+    for value in values {
+        assert_eq!(Tag::from_name(value.typ()), Some(value.tag()));
+        assert!(value.is_type(value.typ()));
+        assert!(value.is_type(" any "));
+    }
+    assert_eq!(Tag::from_name(types::ANY), None);
+    assert_eq!(Tag::from_name(types::FILE), None);
+    assert_eq!(Tag::from_name("vec<int>"), None);
+    assert!(types::same(types::STR, "str"));
+    assert!(!types::same(types::STR, types::STDIN));
+    let text =
+        r#"return (1 is "float|int", [1] is "map", null is "str?", (1, 'a') is "tuple<int>")"#;
+    assert_eq!(
+        run(text, vec![], None).unwrap(),
+        Some(make_tuple!(
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::Bool(false)
+        ))
+    );
+}
+#[test]
+pub fn type_names() {
+    assert_eq!(
+        run_std(r#"return (check(1, "vec<str>|" + types.int), types.meta_data)"#).unwrap(),
+        Some(make_tuple!(
+            Value::Int(1),
+            Value::String("meta_data".into())
+        ))
+    );
+    assert_eq!(
+        run_std(r#"return check(1, "int|strr")"#).unwrap_err().err,
+        RunTimeErrorKind::Native {
+            name: "check".into(),
+            msg: r#"unknown type "strr", did you mean "str"?"#.into()
+        }
+    );
+    assert_eq!(
+        run_std(r#"return expect(1, "widget")"#).unwrap_err().err,
+        RunTimeErrorKind::Native {
+            name: "expect".into(),
+            msg: r#"unknown type "widget""#.into()
+        }
+    );
+    assert_eq!(
+        run_std("types.widget = \"widget\"\nreturn check(1, \"widget?\")").unwrap(),
+        Some(Value::Null)
+    );
+    assert_eq!(
+        run_std(r#"return 1 as "flaot""#)
+            .unwrap_err()
+            .err
+            .to_string(),
+        r#"unknown type to cast to "flaot", did you mean "float"?"#
+    );
+}
+#[test]
+pub fn std_error_values() {
+    let run_with = |text: &str| {
+        let mut interpreter = Interpreter::default();
+        std_hydra::import(&mut interpreter);
+        run_script(&mut interpreter, text, Some("test.hy".into()))
+    };
+    let text = r#"let inner = error.new("file missing", "io")
+let outer = error.wrap(inner, "can't load config")
+return (error.kind(outer), outer.cause.msg, error.kind(1), outer.ln)"#;
+    assert_eq!(
+        run_with(text).unwrap(),
+        Some(make_tuple!(
+            Value::String("io".into()),
+            Value::String("file missing".into()),
+            Value::Null,
+            Value::Int(2)
+        ))
+    );
+    let text = r#"fn load()
+    error(error.wrap(error.new("file missing", "io"), "can't load config"))
+load()"#;
+    let RunTimeError { err, ln } = run_with(text).unwrap_err();
+    assert_eq!(ln, 1);
+    assert_eq!(err.to_string(), "can't load config: file missing");
+    let RunTimeErrorKind::Thrown { value, .. } = err else {
+        panic!("expected a thrown error value")
+    };
+    let traceback = value
+        .field(
+            &mut Interpreter::default(),
+            Value::String("traceback".into()),
+            0,
+        )
+        .unwrap();
+    assert_eq!(
+        format!("{traceback:?}"),
+        r#"[("load", "test.hy", 2), (null, "test.hy", 3)]"#
+    );
+    assert_eq!(
+        run_with(r#"error("bad input", "value")"#)
+            .unwrap_err()
+            .err
+            .to_string(),
+        "bad input"
+    );
+    let text = r#"let texts = []
+try
+    error("boom", "custom")
+catch e
+    texts:push(str(e))
+try
+    error(error.wrap(error.new("file missing", "io"), "can't load config"))
+catch e
+    texts:push(str(e))
+let ok, err = pcall(fn() => 1 + null)
+texts:push(str(err))
+return texts"#;
+    assert_eq!(
+        format!("{:?}", run_std(text).unwrap().unwrap()),
+        r#"["custom: boom", "io: can't load config: file missing", "IllegalBinaryOperation: illegal binary operation \"+\" on int 1 with null null"]"#
+    );
+}
+#[test]
+pub fn compiler_defer() {
+    let text = r#"record = fn(log, msg) => log:push(msg)
+fn f(log, early)
+    defer record(log, "first")
+    if early
+        return 1
+    defer log:push("second")
+    log:push("body")
+    return 2
+let a = []
+let b = []
+return (f(a, false), f(b, true), a, b)"#;
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        r#"(2, 1, ["body", "second", "first"], ["first"])"#
+    );
+    // errors unwinding out of a frame run its deferred calls after closing its `with`s
+    let text = r#"resource = fn(log) => { __exit = fn(self) => log:push("closed") }
+fn g(log)
+    defer log:push("g")
+    error("x")
+fn f(log)
+    defer log:push("f")
+    with resource(log)
+        g(log)
+let a = []
+let b = []
+try
+    f(a)
+catch e
+    a:push(e.msg)
+let ok, err = pcall(f, b)
+return (a, b, ok)"#;
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        r#"(["g", "closed", "f", "x"], ["g", "closed", "f"], false)"#
+    );
+    assert_eq!(
+        parse::<Chunk>("defer 1 + 2").unwrap_err().value.to_string(),
+        ParseError::ExpectedDeferCall.to_string()
+    );
+}
+#[test]
+pub fn compiler_with() {
+    let path = std::env::temp_dir().join("hydra_compiler_with.txt");
+    let text = format!(
+        r#"resource = fn(log, name) => {{ log = log, name = name, __exit = fn(self) => self.log:push(self.name) }}
+fn f(log, early)
+    with resource(log, "outer") as r
+        with resource(log, "inner")
+            if early
+                return r.name
+        log:push("body")
+    return "done"
+let a = []
+let b = []
+let c = []
+for i in [1, 2]
+    with resource(c, i)
+        if i == 1
+            continue
+        break
+let file = fs.open({path:?}, "w")
+with file as handle
+    handle:write("text")
+return (f(a, false), f(b, true), a, b, c, file.closed)"#
+    );
+    let value = run_std(&text).unwrap().unwrap();
+    let _ = std::fs::remove_file(path);
+    assert_eq!(
+        format!("{value:?}"),
+        r#"("done", "outer", ["inner", "body", "outer"], ["inner", "outer"], [1, 2], true)"#
+    );
+    // errors close the objects they unwind past, but not the ones around the `try`
+    let text = r#"resource = fn(log, name) => { log = log, name = name, __exit = fn(self) => self.log:push(self.name) }
+fn fail(log)
+    with resource(log, "call")
+        error("in call")
+let log = []
+with resource(log, "around")
+    try
+        with resource(log, "try")
+            with resource(log, "nested")
+                error("in try")
+    catch e
+        log:push(e.msg)
+    let ok, err = pcall(fail, log)
+    log:push(err.msg)
+return log"#;
+    assert_eq!(
+        format!("{:?}", run_std(text).unwrap().unwrap()),
+        r#"["nested", "try", "in try", "call", "in call", "around"]"#
+    );
+    // a close that fails takes the place of the error being handled
+    let text = r#"let broken = { close = fn(self) => error("in close") }
+try
+    with broken
+        error("in body")
+catch e
+    return e.msg"#;
+    assert_eq!(
+        run_std(text).unwrap(),
+        Some(Value::String("in close".into()))
+    );
+}
+#[test]
+pub fn interpreter_add_assign() {
+    let text = r#"let v = [1]
+let alias = v
+v += 2
+v += [3, 4]
+let m = { a = 1, b = 2 }
+m += { b = 3, c = 4 }
+let h = { items = [] }
+h.items += "x"
+let n = 1
+n += 2
+let s = "a"
+s += "b"
+return (alias, m.a, m.b, m.c, h.items, n, s)"#;
+    let value = run(text, vec![], None).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        r#"([1, 2, 3, 4], 1, 3, 4, ["x"], 3, "ab")"#
+    );
+    assert!(run("let m = {}\nm += 1", vec![], None).is_err());
+}
+#[test]
+pub fn std_map_hooks() {
+    let text = r#"point = fn(x, y) => {
+    x = x, y = y,
+    __str = fn(self) => "(" + str(self.x) + "|" + str(self.y) + ")",
+    __eq = fn(self, other) => self.x == other.x and self.y == other.y,
+}
+let a = point(1, 2)
+let m = {}
+return (str(a), str([a, "s"]), a == point(1, 2), a != point(1, 3), a == point(2, 2), m == m)"#;
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        r#"("(1|2)", "[(1|2), \"s\"]", true, true, false, true)"#
+    );
+}
+#[test]
+pub fn std_math_globals() {
+    let value = run_std(
+        "return (min(3, 1, 2), max([1, 2.5, 2]), sum([1, 2, 3]), sum(1, 0.5), sum([]), abs(-2), clamp(5, 0, 3), clamp(0.5, 1, 2))",
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(format!("{value:?}"), "(1, 2.5, 6, 1.5, 0, 2, 3, 1.0)");
+    assert!(run_std("return min([])").is_err());
+    assert!(run_std("return max(1, \"a\")").is_err());
+    assert!(run_std("return clamp(1, 3, 0)").is_err());
+}
+#[test]
+pub fn std_path_module() {
+    let sep = std::path::MAIN_SEPARATOR_STR;
+    let value = run_std(
+        "let p = path.join(\"dir\", \"sub/file.txt\")\nreturn (p, path.name(p), path.ext(p), path.parent(p), path.parent(\"file\"), path.normalize(\"a/b\"), path.sep)",
+    )
+    .unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            Value::String(format!("dir{sep}sub{sep}file.txt")),
+            Value::String("file.txt".into()),
+            Value::String("txt".into()),
+            Value::String(format!("dir{sep}sub")),
+            Value::Null,
+            Value::String(format!("a{sep}b")),
+            Value::String(sep.into())
+        ))
+    );
+    assert!(run_std("return path.join(\"a\", 1)").is_err());
+}
+#[test]
+pub fn std_range() {
+    let sum = |args: &str| {
+        run_std(&format!(
+            "let s = 0\nlet n = 0\nfor i in range({args})\n    s = s * 10 + i\n    n = n + 1\nreturn (s, n)"
+        ))
+        .unwrap()
+    };
+    assert_eq!(sum("4"), Some(make_tuple!(Value::Int(123), Value::Int(4))));
+    assert_eq!(
+        sum("2, 5"),
+        Some(make_tuple!(Value::Int(234), Value::Int(3)))
+    );
+    assert_eq!(
+        sum("1, 8, 3"),
+        Some(make_tuple!(Value::Int(147), Value::Int(3)))
+    );
+    assert_eq!(
+        sum("9, 0, -4"),
+        Some(make_tuple!(Value::Int(951), Value::Int(3)))
+    );
+    assert_eq!(sum("5, 2"), Some(make_tuple!(Value::Int(0), Value::Int(0))));
+    assert!(run_std("range(1, 2, 0)").is_err());
+}
+#[test]
+pub fn map_insertion_order() {
+    let text = "let m = { zeta = 1, alpha = 2, mid = 3 }\nm.beta = 4\nmap.set(m, \"zeta\", 5)\nlet s = \"\"\nfor k in m\n    s = s + k\nlet e = \"\"\nfor (i, k) in enumerate(m)\n    e = e + str(i) + k\nlet ks = \"\"\nfor k in map.keys(m)\n    ks = ks + k\nreturn (s, e, ks, m)";
+    let Some(Value::Tuple(values)) = run_std(text).unwrap() else {
+        panic!("expected a tuple");
+    };
+    let values = values.lock().unwrap();
+    assert_eq!(values[0], Value::String("zetaalphamidbeta".into()));
+    assert_eq!(values[1], Value::String("0zeta1alpha2mid3beta".into()));
+    assert_eq!(values[2], Value::String("zetaalphamidbeta".into()));
+    assert_eq!(
+        format!("{:?}", values[3]),
+        "{ \"zeta\" = 5, \"alpha\" = 2, \"mid\" = 3, \"beta\" = 4 }"
+    );
+}
+#[test]
+pub fn std_iterable_globals() {
+    let text = "let v = [3, 1, 2, 1]\nreturn (sorted(v), reversed(iter(v)), unique(v), sorted((2.5, 1)), v)";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        "([1, 1, 2, 3], [1, 2, 1, 3], [3, 1, 2], [1, 2.5], [3, 1, 2, 1])"
+    );
+}
+#[test]
+pub fn interpreter_strict_arithmetic() {
+    let run_strict = |text: &str, strict_arithmetic: bool| {
+        let mut interpreter = Interpreter {
+            strict_arithmetic,
+            ..Default::default()
+        };
+        run_script(&mut interpreter, text, None)
+    };
+    let text = "let a = 1\nlet b = 2.5\nreturn a + b";
+    assert_eq!(run_strict(text, false).unwrap(), Some(Value::Float(3.5)));
+    let err = run_strict(text, true).unwrap_err();
+    assert_eq!(
+        err.err,
+        RunTimeErrorKind::MixedArithmetic {
+            op: BinaryOperation::Add,
+            left: "int",
+            right: "float"
+        }
+    );
+    assert_eq!(err.ln, 2);
+    assert!(run_strict("let a = 1\nreturn a < 2.0", true).is_err());
+    assert_eq!(
+        run_strict("let a = 1\nreturn (a as \"float\") + 2.5", true).unwrap(),
+        Some(Value::Float(3.5))
+    );
+}
+#[test]
+pub fn std_number_parsing() {
+    let value = run_std(
+        r#"return (int("ff", 16), int(" 1_000 "), int("-101", 2), float(" 2.5\n"), float("1_0.5"))"#,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(format!("{value:?}"), "(255, 1000, -5, 2.5, 10.5)");
+    for text in [
+        r#"return int("12a")"#,
+        r#"return int("1__0")"#,
+        r#"return int("_1")"#,
+        r#"return int("z", 37)"#,
+        r#"return int(1.5, 2)"#,
+        r#"return float("")"#,
+        "return int([])",
+    ] {
+        assert!(run_std(text).is_err(), "{text}");
+    }
+}
+#[test]
+pub fn interpreter_undefined_global() {
+    let err = run("let a = 1\nreturn a + missing", vec![], None).unwrap_err();
+    assert_eq!(
+        err.value.to_string(),
+        RunTimeErrorKind::UndefinedGlobal("missing".into()).to_string()
+    );
+    assert_eq!(err.pos.ln.start, 1);
+    assert_eq!(
+        run("defined = 2\nreturn defined", vec![], None).unwrap(),
+        Some(Value::Int(2))
+    );
+    let mut interpreter = Interpreter {
+        warn_undefined_globals: true,
+        ..Default::default()
+    };
+    assert!(run_script(&mut interpreter, "return missing", None).is_ok());
+    assert!(!interpreter.globals.contains_key("missing"));
+}
+#[test]
+pub fn interpreter_error_snippets() {
+    let err = run("let a = 1\nreturn a + \"text\"", vec![], None).unwrap_err();
+    assert_eq!(
+        err.value.to_string(),
+        r#"illegal binary operation "+" on int 1 with str "text""#
+    );
+    let err = run("let v = [1]\nreturn v[1.5]", vec![], None).unwrap_err();
+    assert_eq!(
+        err.value.to_string(),
+        "can't index vec with float 1.5, it isn't a whole number"
+    );
+    let err = run(
+        "let a = \"a very long string that goes on\"\nreturn -a",
+        vec![],
+        None,
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.value.to_string(),
+        r#"illegal unary operation "-" on str "a very long string t..."#
+    );
+}
+#[test]
+pub fn pkg_manifest() {
+    let manifest = Manifest::parse(
+        r#"
+[package]
+name = "app"
+entry = "src/main.hydra"
+
+[dependencies]
+utils = { path = "../utils" }
+json = { git = "https://example.com/json.git", rev = "v1.0" }
+"#,
+    )
+    .unwrap();
+    assert_eq!(manifest.name, "app");
+    assert_eq!(manifest.entry.to_str(), Some("src/main.hydra"));
+    let sources = manifest
+        .dependencies
+        .iter()
+        .map(|dep| (dep.name.as_str(), &dep.source))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sources,
+        vec![
+            (
+                "json",
+                &DependencySource::Git {
+                    url: "https://example.com/json.git".into(),
+                    rev: Some("v1.0".into())
+                }
+            ),
+            ("utils", &DependencySource::Path("../utils".into())),
+        ]
+    );
+    let manifest = Manifest::parse("[package]\nname = \"app\"").unwrap();
+    assert_eq!(manifest.entry.to_str(), Some("main.hydra"));
+    assert!(manifest.dependencies.is_empty());
+    assert_eq!(
+        Manifest::parse("[dependencies]"),
+        Err(ManifestError::Missing("package"))
+    );
+    assert_eq!(
+        Manifest::parse("[package]\nname = \"app\"\n[dependencies]\nutils = { rev = \"1\" }"),
+        Err(ManifestError::InvalidDependency("utils".into()))
+    );
+    // git would read these as options
+    assert_eq!(
+        Manifest::parse(
+            "[package]\nname = \"app\"\n[dependencies]\nx = { git = \"--template=/x\" }"
+        ),
+        Err(ManifestError::InvalidField {
+            key: "dependencies.x.git".into(),
+            expected: "a git url"
+        })
+    );
+    assert_eq!(
+        Manifest::parse(
+            "[package]\nname = \"app\"\n[dependencies]\nx = { git = \"a.git\", rev = \"-p\" }"
+        ),
+        Err(ManifestError::InvalidField {
+            key: "dependencies.x.rev".into(),
+            expected: "a git revision"
+        })
+    );
+    assert_eq!(
+        Manifest::parse("[package]\nname = \"../app\""),
+        Err(ManifestError::InvalidName("../app".into()))
+    );
+}
+#[test]
+pub fn interpreter_output() {
+    let text = "print(1, \"a\")\nwrite([2])\ndebug(\"b\")";
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    let output = Rc::new(RefCell::new(String::new()));
+    let sink = Rc::clone(&output);
+    interpreter.set_output(move |text| sink.borrow_mut().push_str(text));
+    run_script(&mut interpreter, text, None).unwrap();
+    assert_eq!(output.borrow().as_str(), "1 a\n[2]\"b\"\n");
+}
+#[test]
+pub fn run_limited_report() {
+    let report = run_limited("print(\"héllo\")\nreturn 1 + 2", 100, 64);
+    assert_eq!(format!("{:?}", report.result.unwrap()), "Some(3)");
+    assert_eq!(report.output, "héllo\n");
+    assert!(!report.output_truncated && !report.out_of_fuel);
+    assert!(report.instructions > 0 && report.instructions < 100);
+    let report = run_limited("print(\"héllo\")", 100, 2);
+    assert_eq!(report.output, "h");
+    assert!(report.output_truncated);
+    let report = run_limited("let i = 0\nwhile true\n    i = i + 1", 1000, 64);
+    assert!(report.out_of_fuel);
+    assert_eq!(report.instructions, 1000);
+    assert_eq!(
+        report.result.unwrap_err().value.to_string(),
+        "ran out of fuel"
+    );
+    assert!(run_limited("return 1 +", 100, 64).result.is_err());
+}
+#[cfg(feature = "capi")]
+#[test]
+pub fn capi_eval() {
+    use crate::capi::*;
+    use std::ffi::{CStr, CString};
+    let state = hydra_new();
+    unsafe {
+        let json = CString::new(r#"{"n": 2, "xs": [1.5, "a"]}"#).unwrap();
+        let name = CString::new("cfg").unwrap();
+        assert_eq!(hydra_set_global(state, name.as_ptr(), json.as_ptr()), 0);
+        let source = CString::new("return [cfg.n * 2, cfg.xs[1]]").unwrap();
+        let result = hydra_eval(state, source.as_ptr());
+        assert_eq!(CStr::from_ptr(result).to_str(), Ok(r#"[4,"a"]"#));
+        hydra_string_free(result);
+        let source = CString::new("return cfg.n + \"a\"").unwrap();
+        assert!(hydra_eval(state, source.as_ptr()).is_null());
+        assert!(CStr::from_ptr(hydra_error(state))
+            .to_str()
+            .unwrap()
+            .contains("illegal binary operation"));
+        hydra_free(state);
+    }
+}
+#[cfg(all(feature = "ffi", target_os = "linux"))]
+#[test]
+pub fn std_ffi_call() {
+    use crate::std_hydra::std_ffi::{self, FfiPolicy};
+    let run_with = |text: &str, libraries: &[&str]| {
+        let mut interpreter = Interpreter::default();
+        std_hydra::import(&mut interpreter);
+        let policy = FfiPolicy {
+            libraries: libraries
+                .iter()
+                .map(|library| library.to_string())
+                .collect(),
+            allow_any: false,
+        };
+        std_ffi::import(&mut interpreter, policy);
+        run_script(&mut interpreter, text, None)
+    };
+    let value = run_with(
+        "let m = ffi.open(\"libm.so.6\")\nreturn (m:call(\"cos\", \"f64\", [0.0]), m:call(\"pow\", \"f64\", [2.0, 3.0]))",
+        &["libm.so.6"],
+    )
+    .unwrap();
+    assert_eq!(format!("{value:?}"), "Some((1.0, 8.0))");
+    let err = run_with("return ffi.open(\"libm.so.6\")", &[]).unwrap_err();
+    assert_eq!(
+        err.err.to_string(),
+        "ffi.open: ffi isn't allowed to open \"libm.so.6\""
+    );
+    let err = run_with(
+        "return ffi.open(\"libm.so.6\"):call(\"ldexp\", \"f64\", [1.0, 2])",
+        &["libm.so.6"],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.err.to_string(),
+        "can't mix float arguments with other arguments"
+    );
+}
+#[test]
+pub fn declare_module_macro() {
+    fn add(_: &mut Interpreter, args: &[Value]) -> Result<Option<Value>, Box<dyn Error>> {
+        let (Some(Value::Int(a)), Some(Value::Int(b))) = (args.first(), args.get(1)) else {
+            return Err("expected two ints".into());
+        };
+        Ok(Some(Value::Int(a + b)))
+    }
+    let mut interpreter = Interpreter::default();
+    crate::declare_module!(interpreter: "mymod" {
+        "add" => add,
+        const "version" => "1.0",
+        const "nested" => crate::declare_module!({ "add" => add }),
+    });
+    let value = run_script(
+        &mut interpreter,
+        "return (mymod.add(1, 2), mymod.version, mymod.nested.add(3, 4))",
+        None,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(format!("{value:?}"), r#"(3, "1.0", 7)"#);
+}
+#[test]
+pub fn interpreter_load_module() {
+    struct Greeting(&'static str);
+    impl HydraModule for Greeting {
+        fn name(&self) -> &str {
+            "greeting"
+        }
+        fn register(&self, interpreter: &mut Interpreter) {
+            crate::declare_module!(interpreter: "greeting" {
+                const "text" => self.0,
+            });
+        }
+    }
+    let mut interpreter = Interpreter::default();
+    assert!(interpreter.load_module(Box::new(Greeting("hello"))));
+    assert!(!interpreter.load_module(Box::new(Greeting("bye"))));
+    let value = run_script(&mut interpreter, "return greeting.text", None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(value, Value::String("hello".into()));
+}
+#[test]
+pub fn bytecode_header() {
+    let closure = compile::<Chunk>(
+        "let f = fn(v) => freeze(v)\ndefer f([1])\nreturn [1, 2]",
+        None,
+    )
+    .unwrap();
+    let header = Header::new(&closure);
+    assert_eq!(header.version, FORMAT_VERSION);
+    assert!(header.features.contains(Features::DEFER));
+    assert!(!header.features.contains(Features::CLOSE));
+    let mut bytes = vec![];
+    header.write(&mut bytes).unwrap();
+    assert_eq!(&bytes[..4], b"HYDC");
+    assert_eq!(Header::read(&mut bytes.as_slice()), Ok(header));
+    assert_eq!(header.check(), Ok(()));
+    assert_eq!(
+        Header::read(&mut &b"HYDX"[..]),
+        Err(FormatError::NotBytecode)
+    );
+    assert_eq!(Header::read(&mut &bytes[..7]), Err(FormatError::Truncated));
+    let newer = Header {
+        version: Version {
+            minor: FORMAT_VERSION.minor + 1,
+            ..FORMAT_VERSION
+        },
+        ..header
+    };
+    assert!(matches!(
+        newer.check(),
+        Err(FormatError::IncompatibleVersion { .. })
+    ));
+    let unknown = Header {
+        features: Features::from_bits(1 << 31),
+        ..header
+    };
+    assert_eq!(
+        unknown.check(),
+        Err(FormatError::UnsupportedFeatures(Features::from_bits(
+            1 << 31
+        )))
+    );
+}
+#[test]
+pub fn closure_disassemble() {
+    let closure = compile::<Chunk>("let x = \"hello\"\nprint(x)", None).unwrap();
+    let code = (0..closure.code.len())
+        .map(|addr| closure.disassemble(addr))
+        .collect::<Vec<String>>();
+    assert_eq!(code[0], "move       !0:x = const \"hello\"");
+    assert!(code
+        .iter()
+        .any(|s| s.starts_with("call       global \"print\"")));
+    assert_eq!(closure.local_name(0, 0), Some("x"));
+    assert_eq!(closure.local_name(1, 0), None);
+    // operands wider than the column stay apart from the line number
+    let closure = compile::<Chunk>("return math.pi", None).unwrap();
+    let field = closure.to_string();
+    let field = field.lines().find(|line| line.contains("field")).unwrap();
+    assert!(field.ends_with("const \"pi\" (1)"));
+}
+#[test]
+pub fn interpreter_dump_state() {
+    let text = "let greeting = \"hi\"\nfn fail(v)\n    return v + \"x\"\nfail(3)";
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    assert!(run_script(&mut interpreter, text, None).is_err());
+    let mut out = vec![];
+    interpreter.dump_state(&mut out).unwrap();
+    let dump = String::from_utf8(out).unwrap();
+    assert!(dump.contains("#1 fail at ?:3"));
+    assert!(dump.contains("@0 v = 3"));
+    assert!(dump.contains("@0 greeting = \"hi\""));
+    assert!(dump.contains("print"));
+}
+#[test]
+pub fn interpreter_float_indices() {
+    let run_indexed = |text: &str, float_indices: bool| {
+        let mut interpreter = Interpreter {
+            float_indices,
+            ..Default::default()
+        };
+        run_script(&mut interpreter, text, None)
+    };
+    let text = "let v = [1, 2, 3]\nv[2 * 1.0] = 4\nreturn v[-1.0]";
+    let err = run_indexed(text, false).unwrap_err();
+    assert_eq!(
+        err.err,
+        RunTimeErrorKind::FloatIndex {
+            head: "vec",
+            index: 2.0
+        }
+    );
+    assert_eq!(
+        err.err.to_string(),
+        "can't index vec with float 2.0, convert it with `as \"int\"`"
+    );
+    assert_eq!(run_indexed(text, true).unwrap(), Some(Value::Int(4)));
+    assert_eq!(
+        run_indexed("return \"abc\"[1.0]", true).unwrap(),
+        Some(Value::Char('b'))
+    );
+    assert!(run_indexed("return (1, 2)[0.5]", true).is_err());
+}
+#[test]
+pub fn negative_indices() {
+    for len in 0..5usize {
+        for index in -7i64..7 {
+            let from_end = len as i64 + index;
+            let (expected_index, expected_position) = if index < 0 {
+                let resolved = (from_end >= 0).then_some(from_end as usize);
+                (resolved, resolved)
+            } else {
+                (
+                    (index < len as i64).then_some(index as usize),
+                    (index <= len as i64).then_some(index as usize),
+                )
+            };
+            assert_eq!(resolve_index(index, len), expected_index);
+            assert_eq!(resolve_position(index, len), expected_position);
+        }
+    }
+    assert_eq!(resolve_index(i64::MIN, 3), None);
+    assert_eq!(resolve_index(i64::MAX, 3), None);
+    let text = "let v = [1, 2, 3]\nreturn (v[-1], v[-3], v[-4], v[3], v:get(-4, 0), v:get(-1))";
+    assert_eq!(
+        run_std(text).unwrap(),
+        Some(make_tuple!(
+            Value::Int(3),
+            Value::Int(1),
+            Value::Null,
+            Value::Null,
+            Value::Int(0),
+            Value::Int(3)
+        ))
+    );
+    let text = "let t = (1, 2)\nreturn (t[-2], t[-3], t:get(-3, 0), \"abc\"[-1], \"abc\"[-4])";
+    assert_eq!(
+        run_std(text).unwrap(),
+        Some(make_tuple!(
+            Value::Int(1),
+            Value::Null,
+            Value::Int(0),
+            Value::Char('c'),
+            Value::Null
+        ))
+    );
+    let text = "let s = \"hello\"\nreturn (s:sub(-3), s:sub(1, -1), s:sub(4, 2), s:split_at(-1))";
+    assert_eq!(
+        run_std(text).unwrap(),
+        Some(make_tuple!(
+            Value::String("llo".into()),
+            Value::String("ell".into()),
+            Value::Null,
+            make_tuple!(Value::String("hell".into()), Value::String("o".into()))
+        ))
+    );
+    let text = "let v = [1, 2, 3]\nv:push(9, -1)\nv:swap(0, -1)\nv[-2] = 8\nreturn v";
+    assert_eq!(
+        format!("{:?}", run_std(text).unwrap().unwrap()),
+        "[3, 2, 8, 1]"
+    );
+    for text in [
+        "let v = [1, 2, 3]\nv[-4] = 0",
+        "let v = [1, 2, 3]\nv:pop(3)",
+        "let v = [1, 2, 3]\nv:push(0, -4)",
+        "let v = [1, 2, 3]\nv:swap(0, 3)",
+        "\"abc\":remove(-4)",
+        "\"é\":remove(1)",
+    ] {
+        assert!(run_std(text).is_err(), "{text}");
+    }
+}
+#[test]
+pub fn std_vec_constructors() {
+    let text = "let v = vec.with_capacity(8)\nv:push(1)\nvec.extend(v, (2, 3))\nv:extend(iter([4]))\nreturn (v, vec.filled(2, \"a\"), vec.from_iter(\"ab\"), vec(1, 2))";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        r#"([1, 2, 3, 4], ["a", "a"], ['a', 'b'], [1, 2])"#
+    );
+}
+#[test]
+pub fn closure_upvalues() {
+    let text = "fn counter()\n    let n = 0\n    fn inc()\n        n = n + 1\n        return n\n    return inc\nlet a = counter()\nlet b = counter()\nlet counts = (a(), a(), b())\nfn fact(n)\n    if n <= 1\n        return 1\n    return n * fact(n - 1)\nlet fs = []\nfor i in [1, 2, 3]\n    fs:push(fn() => i)\nlet f = null\nif true\n    let x = 1\n    f = fn() => x\nlet y = 5\nreturn (counts, fact(5), fs[0](), fs[2](), f())";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "((1, 2, 1), 120, 1, 3, 1)");
+}
+#[test]
+pub fn std_tuple_module() {
+    let text = "let t = (1, 2, 3)\nlet doubled = tuple.map(t, fn(x) => x * 2)\nreturn (tuple.len(t), t:to_vec(), tuple.concat(t, (4,)), t:slice(-2), t:slice(2, 9), doubled, t)";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        "(3, [1, 2, 3], (1, 2, 3, 4), (2, 3), null, (2, 4, 6), (1, 2, 3))"
+    );
+    // natives called by `map` report their errors under their own name
+    for text in [
+        "return pcall(fn() => (\"a\",):map(math.sqrt))[1].msg",
+        "return pcall(fn() => [\"a\"]:map(math.sqrt))[1].msg",
+    ] {
+        let value = run_std(text).unwrap().unwrap();
+        assert!(value.to_string().contains("math.sqrt: expected int/float"));
+    }
+}
+#[test]
+pub fn bytecode_round_trip() {
+    let text = "fn counter()\n    let n = 0\n    fn inc()\n        n = n + 1\n        return n\n    return inc\nlet c = counter()\nc()\nlet t = ('a', 2.5, not true, -3)\nreturn (c(), t, \"text\", [1, 2]:len())";
+    let closure = compile::<Chunk>(text, Some("round_trip.hydra".into())).unwrap();
+    let mut bytes = vec![];
+    format::write_program(&closure, &mut bytes).unwrap();
+    let read = format::read_program(&mut bytes.as_slice()).unwrap();
+    assert_eq!(read.code, closure.code);
+    assert_eq!(read.lines, closure.lines);
+    assert_eq!(read.constants, closure.constants);
+    assert_eq!(read.locals.len(), closure.locals.len());
+    let inc = &read.closures[0].closures[0];
+    assert_eq!(inc.code, closure.closures[0].closures[0].code);
+    assert_eq!(inc.upvalues, closure.closures[0].closures[0].upvalues);
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    let value = run_code(&mut interpreter, read).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        r#"(2, ('a', 2.5, false, -3), "text", 2)"#
+    );
+    assert_eq!(
+        format::read_program(&mut &bytes[..bytes.len() - 1]).err(),
+        Some(FormatError::Truncated)
+    );
+    // header, path, name, parameters, registers, varargs and the code length come first
+    let first_opcode = 14 + 21 + 1 + 3 + 4;
+    bytes[first_opcode] = 0xff;
+    assert_eq!(
+        format::read_program(&mut bytes.as_slice()).err(),
+        Some(FormatError::Corrupt("unknown instruction 0xff".into()))
+    );
+}
+#[test]
+pub fn bytecode_verify() {
+    let closure = compile::<Chunk>("let a = 1\nif a\n    a = 2\nreturn a", None).unwrap();
+    let read = |closure: &Closure| {
+        let mut bytes = vec![];
+        format::write_program(closure, &mut bytes).unwrap();
+        format::read_program(&mut bytes.as_slice()).err()
+    };
+    assert_eq!(read(&closure), None);
+    // the jump past a `catch` that returns lands at the end of the chunk
+    let try_closure = compile::<Chunk>("try\n    let x = 1\ncatch e\n    return 2", None).unwrap();
+    assert_eq!(read(&try_closure), None);
+    assert!(run_std("try\n    let x = 1\ncatch e\n    return 2")
+        .unwrap()
+        .is_none());
+    let mut corrupted = closure.clone();
+    corrupted.code[0] = ByteCode::Move {
+        dst: Location::Register(closure.registers),
+        src: Source::Int(1),
+    };
+    assert_eq!(
+        read(&corrupted),
+        Some(FormatError::Corrupt(format!(
+            "register {} out of range at [0000] in <main>",
+            closure.registers
+        )))
+    );
+    let mut corrupted = closure.clone();
+    corrupted.code[1] = ByteCode::Jump { addr: 99 };
+    assert_eq!(
+        read(&corrupted),
+        Some(FormatError::Corrupt(
+            "jump to [0099] out of range at [0001] in <main>".into()
+        ))
+    );
+    let mut corrupted = closure.clone();
+    corrupted.code.pop();
+    corrupted.lines.pop();
+    assert_eq!(
+        read(&corrupted),
+        Some(FormatError::Corrupt(
+            "code running past its end in <main>".into()
+        ))
+    );
+    // operands of the wrong type pass the check and error out when they run
+    let spread = Closure {
+        code: vec![
+            ByteCode::CallSpread {
+                dst: None,
+                func: Source::Global(0),
+                args: Source::Int(1),
+            },
+            ByteCode::Return { src: None },
+        ],
+        lines: vec![0, 0],
+        constants: vec![Value::String("print".into())],
+        ..Default::default()
+    };
+    let mut bytes = vec![];
+    format::write_program(&spread, &mut bytes).unwrap();
+    let spread = format::read_program(&mut bytes.as_slice()).unwrap();
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    assert_eq!(
+        run_code(&mut interpreter, spread).unwrap_err().err,
+        RunTimeErrorKind::CannotSpread {
+            head: "fn",
+            src: "int"
+        }
+    );
+}
+#[test]
+pub fn tuple_immutable() {
+    let err = run_std("let t = (1, 2)\nt[0] = 3").unwrap_err();
+    assert_eq!(err.err, RunTimeErrorKind::Immutable("tuple"));
+    assert_eq!(err.ln, 1);
+    let value = run_std("let t = (1, 2)\nreturn (t:updated(0, 3), tuple.updated(t, -1, 4), t)")
+        .unwrap()
+        .unwrap();
+    assert_eq!(format!("{value:?}"), "((3, 2), (1, 4), (1, 2))");
+    assert!(run_std("return tuple.updated((1,), 1, 2)").is_err());
+}
+#[test]
+pub fn import_modules() {
+    let dir = std::env::temp_dir().join("hydra_import_modules");
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(
+        dir.join("lib").join("counter.hydra"),
+        "loads = 0\nloads += 1\nreturn { count = fn() => loads }",
+    )
+    .unwrap();
+    std::fs::write(dir.join("cycle.hydra"), "import \"cycle\"").unwrap();
+    let main = dir.join("main.hydra").display().to_string();
+    let text = "import \"lib/counter\"\nlet again = import \"lib/counter.hydra\"\nreturn (counter.count(), again == counter)";
+    let run = |text: &str| {
+        let mut interpreter = Interpreter::default();
+        run_script(&mut interpreter, text, Some(main.clone()))
+    };
+    let value = run(text).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "(1, true)");
+    let err = run("import \"cycle\"").unwrap_err();
+    assert_eq!(
+        err.err.to_string(),
+        "module \"cycle\" is part of an import cycle"
+    );
+    assert!(run("import \"missing\"").is_err());
+    assert!(compile::<Chunk>("import \"my-lib\"", None).is_err());
+}
+#[test]
+pub fn map_literal_keys() {
+    let text = "let name = \"ann\"\nlet k = \"a\"\nlet m = { name, [k + \"ge\"] = 3, ok = true }\nreturn (m.name, m.age, m.ok)";
+    let mut interpreter = Interpreter::default();
+    let value = run_script(&mut interpreter, text, None).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), r#"("ann", 3, true)"#);
+    assert!(compile::<Chunk>("return { [1] }", None).is_err());
+}
+#[test]
+pub fn literal_spreads() {
+    let text = "let a = [1, 2]\nlet b = (3,)\nlet defaults = { x = 1, y = 2 }\nlet v = [...a, ...b, 4, ...[]]\nlet m = { ...defaults, y = 3 }\nreturn (v, m.x, m.y, [0, ...a])";
+    let mut interpreter = Interpreter::default();
+    let value = run_script(&mut interpreter, text, None).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "([1, 2, 3, 4], 1, 3, [0, 1, 2])");
+    let err = run_script(&mut interpreter, "return [...1]", None).unwrap_err();
+    assert_eq!(
+        err.err,
+        RunTimeErrorKind::CannotSpread {
+            head: "vec",
+            src: "int"
+        }
+    );
+}
+#[test]
+pub fn if_chains_and_expressions() {
+    let text = "fn grade(n)\n    if n >= 90\n        return \"a\"\n    elif n >= 80\n        return \"b\"\n    else if n >= 70\n        return \"c\"\n    else\n        return \"f\"\nlet x = -2\nlet sign = if x > 0 then \"pos\" else if x < 0 then \"neg\" else \"zero\"\nlet double = if x < 0 then\n    x * -2\nelse\n    x\nreturn (grade(95), grade(85), grade(75), grade(10), sign, double)";
+    let mut interpreter = Interpreter::default();
+    let value = run_script(&mut interpreter, text, None).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), r#"("a", "b", "c", "f", "neg", 4)"#);
+    assert!(compile::<Chunk>("let a = if true then 1", None).is_err());
+}
+#[test]
+pub fn postfix_chains() {
+    let text = "let user = { name = fn(self) => \"ann\", tags = [\"x\"], n = 1 }\nlet data = { users = [user] }\nfn get()\n    return data\nget().users[0].tags[0] = \"y\"\nget()[\"users\"][0].n += 2\nreturn (data[\"users\"][0]:name(), get().users[0]:name():upper(), user.tags[0], user.n)";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), r#"("ann", "ANN", "y", 3)"#);
+    assert!(compile::<Chunk>("f() = 1", None).is_err());
+}
+#[test]
+pub fn invalid_assign_targets() {
+    for text in ["f() = 1", "(a) = 1", "a + 1 = 2", "a:b() += 1", "1 = 2"] {
+        let err = compile::<Chunk>(text, None).unwrap_err();
+        assert_eq!(
+            err.value.to_string(),
+            ParseError::InvalidAssignTarget.to_string()
+        );
+    }
+    for text in ["f(x).y = 1", "(a).b[0] = 1", "a[f()].b -= 1"] {
+        assert!(compile::<Chunk>(text, None).is_ok(), "{text}");
+    }
+}
+#[test]
+pub fn call_spreads() {
+    let text = "fn add(a, b, c)\n    return a + b + c\nfn forward(f, ...args)\n    return f(...args)\nlet v = [1, 2]\nlet m = { count = fn(self, ...xs) => xs:len() }\nreturn (add(...v, 3), forward(add, 4, 5, 6), add(0, ...(7, 8)), m:count(...v, 9))";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "(6, 15, 15, 3)");
+    let err = compile::<Chunk>("defer print(...[1])", None).unwrap_err();
+    assert_eq!(err.value.to_string(), ParseError::DeferSpread.to_string());
+}
+#[test]
+pub fn native_error_names() {
+    let run_with = |text: &str| run_std(text).unwrap_err().err;
+    assert_eq!(
+        run_with("fn f()\n    return math.log(\"a\")\nf()"),
+        RunTimeErrorKind::Native {
+            name: "math.log".into(),
+            msg: "expected int/float for argument #1, got str".into()
+        }
+    );
+    // the functions of native objects don't get names
+    assert_eq!(run_with("tuple.len(1)").name(), "Custom");
+}
+#[test]
+pub fn typed_script_results() {
+    assert_eq!(run_as::<i64>("return 2 + 3", vec![], None).unwrap(), 5);
+    assert_eq!(
+        run_as::<Vec<String>>("return [\"a\", \"b\"]", vec![], None).unwrap(),
+        vec!["a".to_string(), "b".to_string()]
+    );
+    assert_eq!(
+        run_as::<Option<i64>>("let a = 1", vec![], None).unwrap(),
+        None
+    );
+    let err = run_as::<i64>("return \"a\"", vec![], None).unwrap_err();
+    assert_eq!(
+        err.value.to_string(),
+        "can't convert the script's str result to i64"
+    );
+}
+#[test]
+pub fn try_catch() {
+    let text = "fn fail(n)\n    if n == 0\n        error(\"boom\", \"custom\")\n    return fail(n - 1)\nlet caught = []\ntry\n    fail(3)\n    caught:push(\"unreachable\")\ncatch e\n    caught:push(e.msg + \" \" + e.kind)\ntry\n    let a = 1 + null\ncatch e\n    caught:push(e.kind)\nlet i = 0\nwhile i < 3\n    try\n        i += 1\n        if i == 2\n            break\n    catch\n        pass\ntry\n    error(\"after break\")\ncatch e\n    caught:push(e.msg)\nfn safe(x)\n    try\n        return x + null\n    catch e\n        return 0\nreturn (caught, i, [1, 2]:map(safe))";
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    let value = run_script(&mut interpreter, text, None).unwrap().unwrap();
+    assert_eq!(
+        value.to_string(),
+        "([\"boom custom\", \"IllegalBinaryOperation\", \"after break\"], 2, [0, 0])"
+    );
+    assert!(interpreter.call_stack.is_empty());
+
+    let err = compile::<Chunk>("try\n    pass\nlet a = 1", None).unwrap_err();
+    assert_eq!(err.value.to_string(), ParseError::ExpectedCatch.to_string());
+    // running out of fuel can't be caught
+    let report = run_limited(
+        "while true\n    try\n        pass\n    catch\n        pass",
+        100,
+        64,
+    );
+    assert!(report.out_of_fuel);
+}
+#[test]
+pub fn null_and_no_result() {
+    assert_eq!(run("let a = 1", vec![], None).unwrap(), None);
+    assert_eq!(run("return", vec![], None).unwrap(), None);
+    assert_eq!(run("return null", vec![], None).unwrap(), Some(Value::Null));
+    assert_eq!(
+        run("fn f()\n    pass\nreturn f()", vec![], None).unwrap(),
+        Some(Value::Null)
+    );
+    // functions returning nothing and returning null look the same to scripts
+    let text = "fn nothing()\n    pass\nfn nul()\n    return null\nlet hits = []\nif let a = nothing()\n    hits:push(1)\nif let a = nul()\n    hits:push(2)\nif let a = none()\n    hits:push(3)\nif let a = null_value()\n    hits:push(4)\nif let a = zero()\n    hits:push(a)\nwhile let a = nothing()\n    hits:push(5)\nreturn (hits, nothing() == nul(), none() == null_value())";
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    for (name, value) in [
+        ("none", None),
+        ("null_value", Some(Value::Null)),
+        ("zero", Some(Value::Int(0))),
+    ] {
+        interpreter
+            .set(
+                name,
+                Value::Fn(FnKind::Native(Rc::new(move |_, _| Ok(value.clone())))),
+            )
+            .unwrap();
+    }
+    let value = run_script(&mut interpreter, text, None).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "([0], true, true)");
+}
+#[test]
+pub fn protected_calls() {
+    let text = "fn div(a, b)\n    if b == 0\n        error(\"division by zero\", \"math\")\n    return a / b\nlet ok, err = pcall(div, 1, 0)\nreturn (pcall(div, 6, 3), ok, err.msg, err.kind, pcall(math.log, \"a\")[1].kind, pcall(1)[1].kind)";
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    let value = run_script(&mut interpreter, text, None).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        "((true, 2), false, \"division by zero\", \"math\", \"Native\", \"CannotCall\")"
+    );
+    assert!(interpreter.call_stack.is_empty());
+    let report = run_limited(
+        "fn spin()\n    while true\n        pass\npcall(spin)",
+        100,
+        64,
+    );
+    assert!(report.out_of_fuel);
+}
+#[test]
+pub fn display_columns() {
+    let line = "\tlet s = \"日本🎉\" + x";
+    // the tab takes up a whole tab stop and the wide characters two columns each
+    assert_eq!(display_col(line, 1), 8);
+    assert_eq!(display_col(line, 14), 24);
+    assert_eq!(byte_col(line, 14), 21);
+    assert_eq!(byte_col(line, 19), line.len() + 1);
+    let err = compile::<Chunk>("x = \"日本🎉\" + let", None).unwrap_err();
+    assert_eq!(
+        err.pos.underline("x = \"日本🎉\" + let").unwrap(),
+        "x = \"日本🎉\" + let\n               ^^^"
+    );
+    assert_eq!(
+        Position::new(0..0, 2..2).underline("a\tb").unwrap(),
+        "a       b\n        ^"
+    );
+}
+#[test]
+pub fn execution_limits() {
+    let run_in = |mut interpreter: Interpreter, text: &str| {
+        std_hydra::import(&mut interpreter);
+        run_script(&mut interpreter, text, None).map_err(|err| err.err)
+    };
+    let run_with = |text: &str| run_in(Interpreter::with_limits(10_000, 16), text);
+    // runaway recursion stops even without limits set
+    assert_eq!(
+        run_in(
+            Interpreter::new(InterpreterOptions::default()),
+            "fn f(n)\n    return f(n + 1)\nf(0)"
+        ),
+        Err(RunTimeErrorKind::StackOverflow {
+            depth: DEFAULT_MAX_CALL_DEPTH
+        })
+    );
+    assert_eq!(
+        run_with("fn f(n)\n    return f(n + 1)\nf(0)"),
+        Err(RunTimeErrorKind::StackOverflow { depth: 16 })
+    );
+    assert_eq!(
+        run_with("while true\n    pass"),
+        Err(RunTimeErrorKind::BudgetExceeded)
+    );
+    // running out of fuel can't be caught, not even through a native
+    assert_eq!(
+        run_with("fn f()\n    while true\n        pass\ntry\n    pcall(f)\ncatch e\n    pass"),
+        Err(RunTimeErrorKind::BudgetExceeded)
+    );
+    // a stack overflow unwinds like any other error
+    assert_eq!(
+        run_with("fn f(n)\n    return f(n + 1)\nlet ok, err = pcall(f, 0)\nreturn err.kind"),
+        Ok(Some(Value::String("StackOverflow".into())))
+    );
+    assert_eq!(
+        run_with("fn f(n)\n    if n == 0\n        return 0\n    return 1 + f(n - 1)\nreturn f(10)"),
+        Ok(Some(Value::Int(10)))
+    );
+}
+#[test]
+pub fn parallel_vectors() {
+    let text = "let v = []\nlet i = 0\nwhile i < 100\n    v:push(i)\n    i += 1\nlet offset = 1\nfn square(x)\n    print(x)\n    return x * x + offset\nlet calls = 0\nfn even(x)\n    calls += 1\n    return x % 2 == 0\nlet squares = vec.par_map(v, square)\nlet evens = vec.par_filter(v, even)\nreturn (len(squares), squares[99], len(evens), evens[49], calls, pcall(vec.par_map, v, fn(x) => x + null)[0])";
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    let output = Rc::new(RefCell::new(String::new()));
+    let sink = Rc::clone(&output);
+    interpreter.set_output(move |text| sink.borrow_mut().push_str(text));
+    let value = run_script(&mut interpreter, text, None).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "(100, 9802, 50, 98, 100, false)");
+    // the workers' output keeps the order of the vector
+    let expected = (0..100).map(|x| format!("{x}\n")).collect::<String>();
+    assert_eq!(output.borrow().as_str(), expected);
+    // elements holding functions can't be shared with the workers, so they run serially
+    let text = "let v = []\nwhile len(v) < 100\n    let n = len(v)\n    v:push({ f = fn() => n })\nlet got = vec.par_map(v, fn(m) => m.f())\nreturn (len(got), got[99])";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "(100, 99)");
+}
+#[test]
+pub fn hook_lines() {
+    let text = "fn f(x)\n    return x + 1\nlet a = 0\nwhile a < 2\n    a = f(a)\nreturn a";
+    let closure = compile::<Chunk>(text, None).unwrap();
+    let mut interpreter = Interpreter::default();
+    let events = Rc::new(RefCell::new(vec![]));
+    {
+        let events = Rc::clone(&events);
+        interpreter.set_hook(
+            HookMask::LINE | HookMask::CALL,
+            move |interpreter, event, ln| {
+                let call_frame = interpreter.call_frame().unwrap();
+                let name = call_frame.closure.name.as_deref().unwrap_or("main");
+                events
+                    .borrow_mut()
+                    .push(format!("{event:?} {name} {}", ln + 1));
+                Ok(())
+            },
+        );
+    }
+    assert_eq!(
+        run_code(&mut interpreter, closure).unwrap(),
+        Some(Value::Int(2))
+    );
+    // statements report their own line, not the one after them
+    assert_eq!(
+        events.borrow().join(", "),
+        "Call main 1, Line main 1, Line main 3, Line main 4, Line main 5, Call f 2, Line f 2, \
+         Line main 5, Line main 4, Line main 5, Call f 2, Line f 2, Line main 5, Line main 4, \
+         Line main 6"
+    );
+}
+#[test]
+pub fn load_strings() {
+    let text = "base = 10\nlet add = loadstring(\"return base + 1\", \"add.hy\")\nlet ok, err = pcall(loadstring, \"let = 1\", \"bad.hy\")\nreturn (add(), eval(\"return base * 2\"), eval(\"base = 3\"), base, err.msg)";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        "(11, 20, null, 3, \"loadstring: bad.hy:1:5: expected <ident>, got =\")"
+    );
+}
+#[test]
+pub fn trust_levels() {
+    let run_at = |level: TrustLevel, text: &str| {
+        let mut interpreter = Interpreter::default();
+        std_hydra::import(&mut interpreter);
+        interpreter.set_trust_level(level);
+        run_script(&mut interpreter, text, None).map_err(|err| err.err.to_string())
+    };
+    let text = "fn message(result)\n    if result[0]\n        return null\n    return result[1].msg\nlet a = pcall(eval, \"return 1\")\nlet b = pcall(fs.list, \".\")\nlet c = pcall(env.set_var, \"HYDRA_TRUST_TEST\", \"1\")\nreturn (a[0], b[0], c[0], message(a))";
+    assert_eq!(
+        format!("{:?}", run_at(TrustLevel::Trusted, text).unwrap().unwrap()),
+        "(true, true, true, null)"
+    );
+    assert_eq!(
+        format!("{:?}", run_at(TrustLevel::Restricted, text).unwrap().unwrap()),
+        "(false, true, false, \"eval: loading code from strings isn't allowed at the restricted trust level\")"
+    );
+    assert_eq!(
+        format!("{:?}", run_at(TrustLevel::Sandbox, text).unwrap().unwrap()),
+        "(false, false, false, \"eval: loading code from strings isn't allowed at the sandbox trust level\")"
+    );
+    assert_eq!(
+        run_at(TrustLevel::Sandbox, "import \"missing\""),
+        Err("importing modules isn't allowed at the sandbox trust level".into())
+    );
+    assert!(TrustLevel::Restricted.allows(Capability::FileSystem));
+    assert!(!TrustLevel::Restricted.allows(Capability::Ffi));
+}
+#[test]
+pub fn file_and_line_globals() {
+    let text = "fn f()\n    return (__file, __line)\nreturn (f(), __line)";
+    let value = run(text, vec![], Some("main.hy".into())).unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            make_tuple!(Value::String("main.hy".into()), Value::Int(2)),
+            Value::Int(3)
+        ))
+    );
+    assert_eq!(
+        run("return __file", vec![], None).unwrap(),
+        Some(Value::Null)
+    );
+    assert_eq!(
+        run("let __line = 0\nreturn __line", vec![], None).unwrap(),
+        Some(Value::Int(0))
+    );
+    // after an error the interpreter is still in the file the error happened in
+    let module = compile::<Chunk>("return 1 + \"a\"", Some("module.hy".into())).unwrap();
+    let mut interpreter = Interpreter::default();
+    assert!(run_code(&mut interpreter, module).is_err());
+    assert_eq!(interpreter.path().map(String::as_str), Some("module.hy"));
+}
+#[test]
+pub fn iterator_combinators() {
+    let value = run_std(
+        "let v = range(10):filter(fn(x) => x % 2 == 1):map(fn(x) => x * x):skip(1):take(2):collect()\nreturn (v:len(), v[0], v[1])",
+    )
+    .unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(Value::Int(2), Value::Int(9), Value::Int(25)))
+    );
+    // the stages only run as far as the values are asked for
+    let value = run_std(
+        "let seen = []\nlet it = range(1000000):map(fn(x) => seen:push(x) or x)\nlet v = it:take(3):collect()\nreturn (v:len(), seen:len(), type(it))",
+    )
+    .unwrap();
+    assert_eq!(
+        value,
+        Some(make_tuple!(
+            Value::Int(3),
+            Value::Int(3),
+            Value::String("iterator".into())
+        ))
+    );
+    let value = run_std(
+        "let s = \"\"\nfor (i, c) in iter([1, 2, 3]):zip(\"ab\")\n    s = s + str(i) + str(c)\nreturn s",
+    )
+    .unwrap();
+    assert_eq!(value, Some(Value::String("1a2b".into())));
+    assert!(run_std("range(3):take(-1)").is_err());
+    // a stage asked for its next value from inside its own callback errors out
+    let value = run_std(
+        "let mapped = null\nmapped = range(3):map(fn(x) => mapped:next())\nlet ok, err = pcall(fn() => mapped:next())\nlet doubled = range(3):map(fn(x) => x * 2)\nlet firsts = range(3):map(fn(x) => next(doubled))\nreturn (ok, err.msg, firsts:collect())",
+    )
+    .unwrap();
+    let value = format!("{value:?}");
+    assert!(value.starts_with("Some((false, "));
+    assert!(value.contains("can't get the next value of an iterator while it's getting one"));
+    assert!(value.ends_with(", [0, 2, 4]))"));
+}
+#[test]
+pub fn lazy_std_modules() {
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    assert!(!interpreter.globals.contains_key("math"));
+    assert!(interpreter.lazy_globals.contains_key("math"));
+    assert_eq!(
+        interpreter.get_as::<f64>("math.pi"),
+        Some(std::f64::consts::PI)
+    );
+    assert!(interpreter.globals.contains_key("math"));
+    assert_eq!(
+        run_std("return (math.floor(2.5), \"ab\":len(), path.ext(\"a.b\"))").unwrap(),
+        Some(make_tuple!(
+            Value::Int(2),
+            Value::Int(2),
+            Value::String("b".into())
+        ))
+    );
+    // a global the script sets first is never replaced by the module
+    assert_eq!(
+        run_std("global math = 1\nreturn math").unwrap(),
+        Some(Value::Int(1))
+    );
+    let err = run_std("return math.sqrt(\"a\")").unwrap_err();
+    assert!(matches!(err.err, RunTimeErrorKind::Native { name, .. } if name == "math.sqrt"));
+}
+#[test]
+pub fn bytecode_control_flow() {
+    let exec = |code: Vec<ByteCode>| {
+        let closure = Closure {
+            lines: (0..code.len()).collect(),
+            code,
+            registers: 4,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::default();
+        run_code(&mut interpreter, closure)
+    };
+    // counts r0 up to 10 with a jump back to the addition
+    let counter = vec![
+        ByteCode::Move {
+            dst: Location::Register(0),
+            src: Source::Int(0),
+        },
+        ByteCode::Binary {
+            op: BinaryOperation::Add,
+            dst: Location::Register(0),
+            left: Source::Register(0),
+            right: Source::Int(1),
+        },
+        ByteCode::Binary {
+            op: BinaryOperation::LT,
+            dst: Location::Register(1),
+            left: Source::Register(0),
+            right: Source::Int(10),
+        },
+        ByteCode::JumpIf {
+            negative: false,
+            cond: Source::Register(1),
+            addr: 1,
+        },
+        ByteCode::Return {
+            src: Some(Source::Register(0)),
+        },
+    ];
+    assert_eq!(exec(counter).unwrap(), Some(Value::Int(10)));
+    let branch = |jump: ByteCode| {
+        exec(vec![
+            ByteCode::None,
+            jump,
+            ByteCode::Return {
+                src: Some(Source::Char('n')),
+            },
+            ByteCode::Return {
+                src: Some(Source::Char('y')),
+            },
+        ])
+        .unwrap()
+    };
+    assert_eq!(branch(ByteCode::Jump { addr: 3 }), Some(Value::Char('y')));
+    for (negative, cond, jumps) in [
+        (false, Source::Bool(true), true),
+        (false, Source::Bool(false), false),
+        (true, Source::Bool(true), false),
+        (true, Source::Bool(false), true),
+    ] {
+        let jump = ByteCode::JumpIf {
+            negative,
+            cond,
+            addr: 3,
+        };
+        let expected = Value::Char(if jumps { 'y' } else { 'n' });
+        assert_eq!(branch(jump), Some(expected));
+    }
+    for (negative, src, jumps) in [
+        (false, Source::Bool(false), true),
+        (false, Source::Null, false),
+        (true, Source::Null, true),
+        (true, Source::Int(0), false),
+    ] {
+        let jump = ByteCode::JumpIfSome {
+            negative,
+            src,
+            addr: 3,
+        };
+        let expected = Value::Char(if jumps { 'y' } else { 'n' });
+        assert_eq!(branch(jump), Some(expected));
+    }
+    assert_eq!(exec(vec![ByteCode::Return { src: None }]).unwrap(), None);
+    assert_eq!(
+        exec(vec![ByteCode::Return {
+            src: Some(Source::Null)
+        }])
+        .unwrap(),
+        Some(Value::Null)
+    );
+    let err = exec(vec![
+        ByteCode::None,
+        ByteCode::Unary {
+            op: UnaryOperation::Neg,
+            dst: Location::Register(0),
+            right: Source::Bool(true),
+        },
+    ])
+    .unwrap_err();
+    assert_eq!(err.ln, 1);
+}
+#[test]
+pub fn bytecode_operands() {
+    let exec = |interpreter: &mut Interpreter, closure: Closure| {
+        let closure = Closure {
+            lines: vec![0; closure.code.len()],
+            ..closure
+        };
+        run_code(interpreter, closure)
+    };
+    let mut interpreter = Interpreter::default();
+    let closure = Closure {
+        code: vec![
+            ByteCode::Move {
+                dst: Location::Register(255),
+                src: Source::Float(1.5),
+            },
+            ByteCode::Binary {
+                op: BinaryOperation::Mul,
+                dst: Location::Register(255),
+                left: Source::Register(255),
+                right: Source::Float(2.0),
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(255)),
+            },
+        ],
+        registers: 255,
+        ..Default::default()
+    };
+    assert_eq!(
+        exec(&mut interpreter, closure).unwrap(),
+        Some(Value::Float(3.0))
+    );
+    let constants = (0..=u16::MAX as i64).map(Value::Int).collect::<Vec<_>>();
+    let closure = Closure {
+        code: vec![
+            ByteCode::Binary {
+                op: BinaryOperation::Sub,
+                dst: Location::Register(0),
+                left: Source::Constant(u16::MAX),
+                right: Source::Constant(300),
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(0)),
+            },
+        ],
+        registers: 1,
+        constants,
+        ..Default::default()
+    };
+    assert_eq!(
+        exec(&mut interpreter, closure).unwrap(),
+        Some(Value::Int(u16::MAX as i64 - 300))
+    );
+    // a constant past the end reads as null
+    let closure = Closure {
+        code: vec![ByteCode::Return {
+            src: Some(Source::Constant(u16::MAX)),
+        }],
+        ..Default::default()
+    };
+    assert_eq!(exec(&mut interpreter, closure).unwrap(), Some(Value::Null));
+    // globals are named by a constant, writing one creates it
+    let closure = Closure {
+        code: vec![
+            ByteCode::Move {
+                dst: Location::Global(1),
+                src: Source::Char('g'),
+            },
+            ByteCode::Return {
+                src: Some(Source::Global(1)),
+            },
+        ],
+        constants: vec![Value::Null, Value::String("answer".into())],
+        ..Default::default()
+    };
+    assert_eq!(
+        exec(&mut interpreter, closure).unwrap(),
+        Some(Value::Char('g'))
+    );
+    assert_eq!(interpreter.get("answer").unwrap(), Value::Char('g'));
+    let closure = Closure {
+        code: vec![ByteCode::Return {
+            src: Some(Source::Global(0)),
+        }],
+        constants: vec![Value::String("missing".into())],
+        ..Default::default()
+    };
+    let err = exec(&mut interpreter, closure).unwrap_err();
+    assert_eq!(err.err, RunTimeErrorKind::UndefinedGlobal("missing".into()));
+}
+#[test]
+pub fn bytecode_functions() {
+    let exec = |interpreter: &mut Interpreter, closure: Closure| {
+        let closure = Closure {
+            lines: vec![0; closure.code.len()],
+            ..closure
+        };
+        run_code(interpreter, closure)
+    };
+    let log = Rc::new(RefCell::new(vec![]));
+    let mut interpreter = Interpreter::default();
+    let calls = Rc::clone(&log);
+    interpreter
+        .set(
+            "record",
+            Value::Fn(FnKind::Native(Rc::new(move |_, args| {
+                calls.borrow_mut().push(args.to_vec());
+                Ok(Some(Value::Int(args.len() as i64)))
+            }))),
+        )
+        .unwrap();
+    // adds its two parameters and the register it captured from the enclosing frame
+    let add = Closure {
+        code: vec![
+            ByteCode::Binary {
+                op: BinaryOperation::Add,
+                dst: Location::Register(0),
+                left: Source::Register(0),
+                right: Source::Register(1),
+            },
+            ByteCode::Binary {
+                op: BinaryOperation::Add,
+                dst: Location::Register(0),
+                left: Source::Register(0),
+                right: Source::Upvalue(0),
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(0)),
+            },
+        ],
+        lines: vec![0; 3],
+        parameters: 2,
+        registers: 2,
+        upvalues: vec![Upvalue {
+            name: "base".into(),
+            capture: Capture::Register(0),
+        }],
+        ..Default::default()
+    };
+    let closure = Closure {
+        code: vec![
+            ByteCode::Move {
+                dst: Location::Register(0),
+                src: Source::Int(100),
+            },
+            ByteCode::Fn {
+                dst: Location::Register(1),
+                addr: 0,
+            },
+            ByteCode::Move {
+                dst: Location::Register(2),
+                src: Source::Int(1),
+            },
+            ByteCode::Move {
+                dst: Location::Register(3),
+                src: Source::Int(2),
+            },
+            ByteCode::Call {
+                dst: Some(Location::Register(4)),
+                func: Source::Register(1),
+                start: 2,
+                amount: 2,
+            },
+            // the closure sees writes to the register it captured until the register is
+            // detached from it
+            ByteCode::Move {
+                dst: Location::Register(0),
+                src: Source::Int(200),
+            },
+            ByteCode::Call {
+                dst: Some(Location::Register(5)),
+                func: Source::Register(1),
+                start: 2,
+                amount: 2,
+            },
+            ByteCode::Detach {
+                start: 0,
+                amount: 1,
+            },
+            ByteCode::Move {
+                dst: Location::Register(0),
+                src: Source::Int(300),
+            },
+            ByteCode::Call {
+                dst: Some(Location::Register(6)),
+                func: Source::Register(1),
+                start: 2,
+                amount: 2,
+            },
+            ByteCode::Vector {
+                dst: Location::Register(7),
+                start: 2,
+                amount: 2,
+            },
+            ByteCode::CallSpread {
+                dst: Some(Location::Register(7)),
+                func: Source::Register(1),
+                args: Source::Register(7),
+            },
+            ByteCode::Tuple {
+                dst: Location::Register(0),
+                start: 4,
+                amount: 4,
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(0)),
+            },
+        ],
+        registers: 8,
+        closures: vec![Rc::new(add)],
+        ..Default::default()
+    };
+    let value = exec(&mut interpreter, closure).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "(103, 203, 203, 203)");
+    // a call without a destination drops the result, deferred calls run at the return
+    let closure = Closure {
+        code: vec![
+            ByteCode::Move {
+                dst: Location::Register(0),
+                src: Source::Int(1),
+            },
+            ByteCode::Defer {
+                func: Source::Global(0),
+                start: 0,
+                amount: 1,
+            },
+            ByteCode::Move {
+                dst: Location::Register(0),
+                src: Source::Int(2),
+            },
+            ByteCode::Defer {
+                func: Source::Global(0),
+                start: 0,
+                amount: 1,
+            },
+            ByteCode::Call {
+                dst: None,
+                func: Source::Global(0),
+                start: 0,
+                amount: 0,
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(0)),
+            },
+        ],
+        registers: 1,
+        constants: vec![Value::String("record".into())],
+        ..Default::default()
+    };
+    assert_eq!(
+        exec(&mut interpreter, closure).unwrap(),
+        Some(Value::Int(2))
+    );
+    assert_eq!(
+        log.borrow().clone(),
+        vec![vec![], vec![Value::Int(2)], vec![Value::Int(1)]]
+    );
+    let closure = Closure {
+        code: vec![ByteCode::Call {
+            dst: None,
+            func: Source::Int(1),
+            start: 0,
+            amount: 0,
+        }],
+        ..Default::default()
+    };
+    assert!(exec(&mut interpreter, closure).is_err());
+}
+#[test]
+pub fn bytecode_intrinsics() {
+    let exec = |interpreter: &mut Interpreter, code: Vec<ByteCode>, constants: Vec<Value>| {
+        let closure = Closure {
+            lines: vec![0; code.len()],
+            code,
+            registers: 5,
+            constants,
+            ..Default::default()
+        };
+        run_code(interpreter, closure)
+    };
+    let intrinsic = |intrinsic: Intrinsic, dst: u8, amount: u8| ByteCode::Intrinsic {
+        dst: Some(Location::Register(dst)),
+        intrinsic,
+        start: 0,
+        amount,
+    };
+    let code = vec![
+        ByteCode::Move {
+            dst: Location::Register(1),
+            src: Source::Char('a'),
+        },
+        ByteCode::Vector {
+            dst: Location::Register(0),
+            start: 1,
+            amount: 1,
+        },
+        ByteCode::Move {
+            dst: Location::Register(1),
+            src: Source::Char('b'),
+        },
+        intrinsic(Intrinsic::Push, 4, 2),
+        ByteCode::Move {
+            dst: Location::Register(1),
+            src: Source::Int(5),
+        },
+        ByteCode::Move {
+            dst: Location::Register(2),
+            src: Source::Char('d'),
+        },
+        // out of range with and without a default
+        intrinsic(Intrinsic::Get, 3, 3),
+        intrinsic(Intrinsic::Get, 2, 2),
+        ByteCode::Move {
+            dst: Location::Register(1),
+            src: Source::Int(-1),
+        },
+        intrinsic(Intrinsic::Get, 1, 2),
+        intrinsic(Intrinsic::Len, 4, 1),
+        ByteCode::Tuple {
+            dst: Location::Register(0),
+            start: 0,
+            amount: 5,
+        },
+        ByteCode::Return {
+            src: Some(Source::Register(0)),
+        },
+    ];
+    let mut interpreter = Interpreter::default();
+    let value = exec(&mut interpreter, code, vec![]).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "(['a', 'b'], 'b', null, 'd', 2)");
+    // anything the fast path doesn't take is a call of the method of the same name
+    interpreter
+        .set(
+            "size",
+            Value::Fn(FnKind::Native(Rc::new(|_, args| {
+                Ok(Some(Value::Int(args.len() as i64 * 10)))
+            }))),
+        )
+        .unwrap();
+    let code = vec![
+        ByteCode::Map {
+            dst: Location::Register(0),
+        },
+        ByteCode::SetField {
+            head: Source::Register(0),
+            field: Source::Constant(0),
+            src: Source::Global(1),
+        },
+        intrinsic(Intrinsic::Len, 1, 1),
+        ByteCode::Return {
+            src: Some(Source::Register(1)),
+        },
+    ];
+    let constants = vec![Value::String("len".into()), Value::String("size".into())];
+    assert_eq!(
+        exec(&mut interpreter, code, constants).unwrap(),
+        Some(Value::Int(10))
+    );
+}
+#[test]
+pub fn bytecode_values() {
+    let exec = |interpreter: &mut Interpreter, code: Vec<ByteCode>, constants: Vec<Value>| {
+        let closure = Closure {
+            lines: vec![0; code.len()],
+            code,
+            registers: 6,
+            constants,
+            ..Default::default()
+        };
+        run_code(interpreter, closure)
+    };
+    let code = vec![
+        ByteCode::Move {
+            dst: Location::Register(1),
+            src: Source::Int(1),
+        },
+        ByteCode::Vector {
+            dst: Location::Register(0),
+            start: 1,
+            amount: 1,
+        },
+        ByteCode::Copy {
+            dst: Location::Register(1),
+            src: Source::Register(0),
+        },
+        ByteCode::SetField {
+            head: Source::Register(1),
+            field: Source::Int(0),
+            src: Source::Int(2),
+        },
+        ByteCode::Tuple {
+            dst: Location::Register(0),
+            start: 0,
+            amount: 2,
+        },
+        ByteCode::Return {
+            src: Some(Source::Register(0)),
+        },
+    ];
+    let mut interpreter = Interpreter::default();
+    let value = exec(&mut interpreter, code.clone(), vec![])
+        .unwrap()
+        .unwrap();
+    assert_eq!(format!("{value:?}"), "([2], [2])");
+    let mut interpreter = Interpreter {
+        copy_containers: true,
+        ..Default::default()
+    };
+    let value = exec(&mut interpreter, code, vec![]).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "([1], [2])");
+    let code = vec![
+        ByteCode::Map {
+            dst: Location::Register(0),
+        },
+        ByteCode::SetField {
+            head: Source::Register(0),
+            field: Source::Constant(0),
+            src: Source::Int(1),
+        },
+        ByteCode::Map {
+            dst: Location::Register(1),
+        },
+        ByteCode::SetField {
+            head: Source::Register(1),
+            field: Source::Constant(1),
+            src: Source::Int(2),
+        },
+        ByteCode::Extend {
+            head: Source::Register(0),
+            src: Source::Register(1),
+        },
+        ByteCode::Field {
+            dst: Location::Register(2),
+            head: Source::Register(0),
+            field: Source::Constant(1),
+        },
+        ByteCode::Unary {
+            op: UnaryOperation::Neg,
+            dst: Location::Register(2),
+            right: Source::Register(2),
+        },
+        ByteCode::Move {
+            dst: Location::Register(4),
+            src: Source::Bool(false),
+        },
+        ByteCode::Vector {
+            dst: Location::Register(3),
+            start: 4,
+            amount: 1,
+        },
+        ByteCode::Tuple {
+            dst: Location::Register(4),
+            start: 0,
+            amount: 3,
+        },
+        ByteCode::Extend {
+            head: Source::Register(3),
+            src: Source::Register(4),
+        },
+        ByteCode::Unary {
+            op: UnaryOperation::Not,
+            dst: Location::Register(5),
+            right: Source::Bool(false),
+        },
+        ByteCode::Extend {
+            head: Source::Register(3),
+            src: Source::Register(3),
+        },
+        ByteCode::Return {
+            src: Some(Source::Register(3)),
+        },
+    ];
+    let constants = vec![Value::String("a".into()), Value::String("b".into())];
+    let value = exec(&mut interpreter, code, constants.clone())
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        format!("{value:?}"),
+        "[false, { \"a\" = 1, \"b\" = 2 }, { \"b\" = 2 }, -2, false, { \"a\" = 1, \"b\" = 2 }, { \"b\" = 2 }, -2]"
+    );
+    let err = exec(
+        &mut interpreter,
+        vec![
+            ByteCode::Map {
+                dst: Location::Register(0),
+            },
+            ByteCode::Extend {
+                head: Source::Register(0),
+                src: Source::Int(1),
+            },
+        ],
+        vec![],
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.err,
+        RunTimeErrorKind::CannotSpread {
+            head: "map",
+            src: "int"
+        }
+    );
+    let err = exec(
+        &mut interpreter,
+        vec![
+            ByteCode::Map {
+                dst: Location::Register(0),
+            },
+            ByteCode::Freeze {
+                src: Source::Register(0),
+            },
+            ByteCode::SetField {
+                head: Source::Register(0),
+                field: Source::Constant(0),
+                src: Source::Int(1),
+            },
+        ],
+        constants,
+    )
+    .unwrap_err();
+    assert_eq!(err.err, RunTimeErrorKind::Frozen("map"));
+    // unpacking takes the first values of tuples and vectors and indexes anything else
+    let code = vec![
+        ByteCode::Move {
+            dst: Location::Register(0),
+            src: Source::Int(1),
+        },
+        ByteCode::Move {
+            dst: Location::Register(1),
+            src: Source::Int(2),
+        },
+        ByteCode::Move {
+            dst: Location::Register(2),
+            src: Source::Int(3),
+        },
+        ByteCode::Tuple {
+            dst: Location::Register(3),
+            start: 0,
+            amount: 3,
+        },
+        ByteCode::Unpack {
+            start: 4,
+            amount: 2,
+            src: Source::Register(3),
+        },
+        ByteCode::Binary {
+            op: BinaryOperation::Sub,
+            dst: Location::Register(0),
+            left: Source::Register(4),
+            right: Source::Register(5),
+        },
+        ByteCode::Unpack {
+            start: 4,
+            amount: 2,
+            src: Source::Constant(0),
+        },
+        ByteCode::ScratchTuple {
+            dst: Location::Register(3),
+            start: 0,
+            amount: 1,
+        },
+        ByteCode::Tuple {
+            dst: Location::Register(0),
+            start: 3,
+            amount: 3,
+        },
+        ByteCode::Return {
+            src: Some(Source::Register(0)),
+        },
+    ];
+    let constants = vec![Value::String("xyz".into())];
+    let value = exec(&mut interpreter, code, constants).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "((-1), 'x', 'y')");
+}
+#[test]
+pub fn bytecode_scratch_containers() {
+    // builds a container from the loop counter three times, keeping the last one
+    let exec = |scratch: ByteCode| {
+        let code = vec![
+            ByteCode::Move {
+                dst: Location::Register(0),
+                src: Source::Int(0),
+            },
+            scratch,
+            ByteCode::Binary {
+                op: BinaryOperation::Add,
+                dst: Location::Register(0),
+                left: Source::Register(0),
+                right: Source::Int(1),
+            },
+            ByteCode::Binary {
+                op: BinaryOperation::LT,
+                dst: Location::Register(1),
+                left: Source::Register(0),
+                right: Source::Int(3),
+            },
+            ByteCode::JumpIf {
+                negative: false,
+                cond: Source::Register(1),
+                addr: 1,
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(2)),
+            },
+        ];
+        let closure = Closure {
+            lines: vec![0; code.len()],
+            code,
+            registers: 3,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::default();
+        run_code(&mut interpreter, closure).unwrap().unwrap()
+    };
+    let value = exec(ByteCode::ScratchVector {
+        dst: Location::Register(2),
+        start: 0,
+        amount: 2,
+    });
+    assert_eq!(format!("{value:?}"), "[2, true]");
+    let value = exec(ByteCode::ScratchTuple {
+        dst: Location::Register(2),
+        start: 0,
+        amount: 2,
+    });
+    assert_eq!(format!("{value:?}"), "(2, true)");
+}
+#[test]
+pub fn bytecode_try_close_import() {
+    let dir = std::env::temp_dir().join("hydra_bytecode_import");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("answer.hydra"), "return 42").unwrap();
+    let main = dir.join("main.hydra").display().to_string();
+    let exec = |interpreter: &mut Interpreter, code: Vec<ByteCode>, constants: Vec<Value>| {
+        let closure = Closure {
+            path: Some(main.clone()),
+            lines: vec![0; code.len()],
+            code,
+            registers: 3,
+            constants,
+            ..Default::default()
+        };
+        run_code(interpreter, closure)
+    };
+    let mut interpreter = Interpreter::default();
+    // the handler jumps to its address with the error, `EndTry` stops handling errors
+    let guarded = |end: bool| {
+        vec![
+            ByteCode::Try {
+                addr: 4,
+                dst: Location::Register(0),
+            },
+            if end {
+                ByteCode::EndTry
+            } else {
+                ByteCode::None
+            },
+            ByteCode::Unary {
+                op: UnaryOperation::Neg,
+                dst: Location::Register(0),
+                right: Source::Bool(true),
+            },
+            ByteCode::Return { src: None },
+            ByteCode::Field {
+                dst: Location::Register(0),
+                head: Source::Register(0),
+                field: Source::Constant(0),
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(0)),
+            },
+        ]
+    };
+    let constants = vec![Value::String("kind".into())];
+    assert_eq!(
+        exec(&mut interpreter, guarded(false), constants.clone()).unwrap(),
+        Some(Value::String("IllegalUnaryOperation".into()))
+    );
+    assert!(exec(&mut interpreter, guarded(true), constants).is_err());
+    let closed = Rc::new(RefCell::new(0));
+    let count = Rc::clone(&closed);
+    interpreter
+        .set(
+            "closer",
+            Value::Fn(FnKind::Native(Rc::new(move |_, args| {
+                assert!(matches!(args, [Value::Map(_)]));
+                *count.borrow_mut() += 1;
+                Ok(None)
+            }))),
+        )
+        .unwrap();
+    let close = |method: usize| {
+        vec![
+            ByteCode::Close { src: Source::Null },
+            ByteCode::Map {
+                dst: Location::Register(0),
+            },
+            ByteCode::SetField {
+                head: Source::Register(0),
+                field: Source::Constant(method as u16),
+                src: Source::Global(2),
+            },
+            ByteCode::Close {
+                src: Source::Register(0),
+            },
+            ByteCode::Import {
+                dst: Location::Register(1),
+                path: 3,
+            },
+            ByteCode::Return {
+                src: Some(Source::Register(1)),
+            },
+        ]
+    };
+    let constants = vec![
+        Value::String("__exit".into()),
+        Value::String("close".into()),
+        Value::String("closer".into()),
+        Value::String("answer".into()),
+    ];
+    for method in [0, 1] {
+        assert_eq!(
+            exec(&mut interpreter, close(method), constants.clone()).unwrap(),
+            Some(Value::Int(42))
+        );
+    }
+    assert_eq!(*closed.borrow(), 2);
+    let err = exec(&mut interpreter, close(3), constants).unwrap_err();
+    assert_eq!(err.err.to_string(), "map has no __exit or close method");
+    let err = exec(
+        &mut interpreter,
+        vec![ByteCode::Import {
+            dst: Location::Register(0),
+            path: 0,
+        }],
+        vec![Value::Int(1)],
+    )
+    .unwrap_err();
+    assert_eq!(err.err.to_string(), "can't import int");
+}
+#[test]
+pub fn primitive_methods() {
+    let text = "let n = -3\nreturn (\"abc\":upper(), n:abs(), 255:to_hex(), 2.5:floor(), 'a':upper(), [1, 2]:len(), (1, 2, 3):len())";
+    let value = run_std(text).unwrap().unwrap();
+    assert_eq!(format!("{value:?}"), "(\"ABC\", 3, \"ff\", 2.0, 'A', 2, 3)");
+    // a method the type's module doesn't have is null like any missing field
+    assert_eq!(
+        run_std("let n = 1\nreturn n.missing").unwrap(),
+        Some(Value::Null)
+    );
+    assert!(run_std("true:missing()").is_err());
+    assert_eq!(
+        run_std("return 1[0]").unwrap_err().err,
+        RunTimeErrorKind::InvalidFieldHead("int")
+    );
+}
+#[test]
+pub fn std_os_quoting() {
+    assert_eq!(std_os::quote_posix("plain-name_1.txt"), "plain-name_1.txt");
+    assert_eq!(std_os::quote_posix(""), "''");
+    assert_eq!(std_os::quote_posix("it's $HOME"), "'it'\\''s $HOME'");
+    assert_eq!(
+        std_os::split_args_posix("cp 'a b' \"c \\\"d\\\"\" e\\ f g''h").unwrap(),
+        vec!["cp", "a b", "c \"d\"", "e f", "gh"]
+    );
+    assert!(std_os::split_args_posix("echo 'open").is_err());
+    assert_eq!(std_os::quote_windows("C:\\dir\\file"), "C:\\dir\\file");
+    assert_eq!(std_os::quote_windows("C:\\my dir\\"), "\"C:\\my dir\\\\\"");
+    assert_eq!(std_os::quote_windows("say \"hi\""), "\"say \\\"hi\\\"\"");
+    assert_eq!(
+        std_os::split_args_windows("copy \"a b\" c\\\\\\\"d \"e\"\"f\" \"\"").unwrap(),
+        vec!["copy", "a b", "c\\\"d", "e\"f", ""]
+    );
+    assert!(std_os::split_args_windows("dir \"open").is_err());
+    let value = run_std("let arg = os.quote(\"a b\")\nreturn os.split_args(arg + \" c\")")
+        .unwrap()
+        .unwrap();
+    assert_eq!(format!("{value:?}"), "[\"a b\", \"c\"]");
+}
+#[test]
+pub fn interpreter_run_for() {
+    let start = |text: &str| {
+        let closure = compile::<Chunk>(text, None).unwrap();
+        let mut interpreter = Interpreter::default();
+        interpreter
+            .call(
+                &Function {
+                    closure: Rc::new(closure),
+                    upvalues: vec![],
+                },
+                &[],
+                None,
+            )
+            .unwrap();
+        interpreter
+    };
+    let text = "fn step(n)\n    return n + 1\nlet n = 0\nwhile n < 100\n    n = step(n)\nreturn n";
+    let mut interpreter = start(text);
+    let mut slices = 1;
+    let status = loop {
+        match interpreter.run_for(50) {
+            RunStatus::Paused => slices += 1,
+            status => break status,
+        }
+    };
+    assert_eq!(status, RunStatus::Completed(Some(Value::Int(100))));
+    assert!(slices > 10);
+    assert_eq!(interpreter.paused_run, None);
+    assert!(interpreter.call_stack.is_empty());
+    // errors caught in a later slice than the `try` started in still reach it
+    let text = "let i = 0\ntry\n    while true\n        i = i + 1\n        if i == 20\n            error(\"stop\")\ncatch err\n    return (i, err)\nreturn null";
+    let mut interpreter = start(text);
+    interpreter
+        .set(
+            "error",
+            Value::Fn(FnKind::Native(Rc::new(|_, _| Err("stopped".into())))),
+        )
+        .unwrap();
+    assert_eq!(interpreter.run_for(10), RunStatus::Paused);
+    let status = loop {
+        match interpreter.run_for(10) {
+            RunStatus::Paused => {}
+            status => break status,
+        }
+    };
+    let RunStatus::Completed(Some(Value::Tuple(values))) = status else {
+        panic!("expected a tuple, got {status:?}");
+    };
+    assert_eq!(values.lock().unwrap()[0], Value::Int(20));
+    let mut interpreter = start("let a = 1\nreturn a + true");
+    assert_eq!(interpreter.run_for(0), RunStatus::Paused);
+    assert!(matches!(interpreter.run_for(10), RunStatus::Error(_)));
+    assert_eq!(interpreter.paused_run, None);
+}
+/// small expressions over a few operands, with every binary operator that takes any
+/// expression on its right and both unary ones
+fn expression_strategy() -> impl Strategy<Value = Located<Expression>> {
+    let located = |expr| Located::new(expr, Position::default());
+    let atom = prop_oneof![
+        Just(Atom::Null),
+        any::<bool>().prop_map(Atom::Bool),
+        (0..1000i64).prop_map(Atom::Int),
+        (0..40i64).prop_map(|n| Atom::Float(n as f64 / 4.0)),
+        prop::sample::select(vec!['a', 'Z', '0', ' ']).prop_map(Atom::Char),
+        "[a-z ]{0,6}".prop_map(Atom::String),
+        prop::sample::select(vec!["a", "b", "count", "x1"])
+            .prop_map(|name| Atom::Path(Path::Ident(name.into()))),
+    ];
+    let ops = (0..)
+        .map_while(BinaryOperator::layer)
+        .flatten()
+        .copied()
+        .filter(|op| !matches!(op, BinaryOperator::Is | BinaryOperator::As))
+        .collect::<Vec<_>>();
+    atom.prop_map(move |atom| located(Expression::Atom(atom)))
+        .prop_recursive(4, 24, 2, move |inner| {
+            prop_oneof![
+                (
+                    prop::sample::select(ops.clone()),
+                    inner.clone(),
+                    inner.clone()
+                )
+                    .prop_map(move |(op, left, right)| located(
+                        Expression::Binary {
+                            op,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        }
+                    )),
+                (
+                    prop::sample::select(vec![UnaryOperator::Minus, UnaryOperator::Not]),
+                    inner
+                )
+                    .prop_map(move |(op, right)| located(Expression::Unary {
+                        op,
+                        right: Box::new(right),
+                    })),
+            ]
+        })
+}
+/// how tightly `expr` binds: the layer of binary operators, then unary operators, then
+/// atoms
+fn expression_level(expr: &Expression) -> usize {
+    let layers = (0..).map_while(BinaryOperator::layer);
+    match expr {
+        Expression::Binary { op, .. } => layers
+            .enumerate()
+            .find(|(_, ops)| ops.contains(op))
+            .map(|(layer, _)| layer)
+            .unwrap(),
+        Expression::Unary { .. } => layers.count(),
+        _ => layers.count() + 1,
+    }
+}
+/// `expr` as source text, with parentheses only where the parser needs them or, if
+/// `full` is set, around every operation
+fn print_expression(expr: &Expression, full: bool) -> String {
+    let operand = |operand: &Located<Expression>, parens: bool| {
+        let text = print_expression(&operand.value, full);
+        if parens && !full {
+            format!("({text})")
+        } else {
+            text
+        }
+    };
+    let text = match expr {
+        Expression::Binary { op, left, right } => {
+            let level = expression_level(expr);
+            format!(
+                "{} {} {}",
+                operand(left, expression_level(&left.value) < level),
+                BinaryOperation::from(*op),
+                operand(right, expression_level(&right.value) <= level),
+            )
+        }
+        Expression::Unary {
+            op: UnaryOperator::Not,
+            right,
+        } => format!(
+            "not {}",
+            operand(
+                right,
+                expression_level(&right.value) < expression_level(expr)
+            )
+        ),
+        // `not` binds looser than `-`, so it can't follow it without parentheses
+        Expression::Unary {
+            op: UnaryOperator::Minus,
+            right,
+        } => {
+            let parens = matches!(
+                right.value,
+                Expression::Unary {
+                    op: UnaryOperator::Not,
+                    ..
+                }
+            );
+            format!(
+                "-{}",
+                operand(
+                    right,
+                    parens || expression_level(&right.value) < expression_level(expr)
+                )
+            )
+        }
+        Expression::Atom(Atom::Expression(inner)) if full => {
+            return print_expression(&inner.value, full)
+        }
+        Expression::Atom(Atom::Expression(inner)) => {
+            format!("({})", print_expression(&inner.value, full))
+        }
+        Expression::Atom(Atom::Path(Path::Ident(name))) => name.clone(),
+        Expression::Atom(Atom::Null) => "null".into(),
+        Expression::Atom(Atom::Bool(value)) => value.to_string(),
+        Expression::Atom(Atom::Int(value)) => value.to_string(),
+        Expression::Atom(Atom::Float(value)) => format!("{value:?}"),
+        Expression::Atom(Atom::Char(value)) => format!("{value:?}"),
+        Expression::Atom(Atom::String(value)) => format!("{value:?}"),
+        expr => unreachable!("not generated: {expr:?}"),
+    };
+    match expr {
+        Expression::Binary { .. } | Expression::Unary { .. } if full => format!("({text})"),
+        _ => text,
+    }
+}
+proptest! {
+    #[test]
+    fn expression_print_round_trip(expr in expression_strategy()) {
+        let text = print_expression(&expr.value, false);
+        let parsed = parse::<Expression>(&text)
+            .map_err(|err| TestCaseError::fail(format!("{text}: {}", err.value)))?;
+        prop_assert_eq!(
+            print_expression(&parsed.value, true),
+            print_expression(&expr.value, true),
+            "{}",
+            text
+        );
+    }
+    #[test]
+    fn bytecode_format_round_trip(expr in expression_strategy()) {
+        let text = format!(
+            "let a = 1\nlet b = 2.5\nlet count = [a, b]\nfn f(x1)\n    return {}\nreturn f(a) or {}",
+            print_expression(&expr.value, false),
+            print_expression(&expr.value, true),
+        );
+        let closure = compile::<Chunk>(&text, Some("round_trip.hydra".into()))
+            .map_err(|err| TestCaseError::fail(format!("{text}: {}", err.value)))?;
+        let mut bytes = vec![];
+        format::write_program(&closure, &mut bytes).unwrap();
+        let read = format::read_program(&mut bytes.as_slice()).unwrap();
+        prop_assert_eq!(&read.code, &closure.code);
+        prop_assert_eq!(&read.lines, &closure.lines);
+        prop_assert_eq!(&read.constants, &closure.constants);
+        prop_assert_eq!(&read.closures[0].code, &closure.closures[0].code);
+        let disassemble =
+            |closure: &Closure| (0..closure.code.len()).map(|addr| closure.disassemble(addr)).collect::<Vec<_>>();
+        prop_assert_eq!(disassemble(&read), disassemble(&closure));
+    }
+    #[test]
+    fn shell_quote_round_trip(args in prop::collection::vec(".{0,8}", 0..4)) {
+        for (quote, split) in [
+            (std_os::quote_posix as fn(&str) -> String, std_os::split_args_posix as fn(&str) -> _),
+            (std_os::quote_windows, std_os::split_args_windows),
+        ] {
+            let line = args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ");
+            prop_assert_eq!(split(&line), Ok(args.clone()), "{}", line);
+        }
+    }
+}
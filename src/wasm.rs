@@ -0,0 +1,96 @@
+use crate::{
+    compile,
+    run::{
+        interpreter::Interpreter,
+        value::{Function, Value},
+    },
+    scan::ast::Chunk,
+    std_hydra,
+};
+use js_sys::{Array, Error as JsError, Function as JsFunction, Object, Reflect};
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    static OUTPUT: RefCell<Option<JsFunction>> = const { RefCell::new(None) };
+}
+
+/// calls `callback` with the text of every `print`, `write` and `debug` of the scripts run
+/// afterwards, or drops their output if it's null
+#[wasm_bindgen]
+pub fn set_output(callback: Option<JsFunction>) {
+    OUTPUT.with(|output| *output.borrow_mut() = callback);
+}
+
+/// compiles and runs `text` with the standard library and returns the script's value as
+/// a JS value, throwing an `Error` with the line of the failure otherwise
+#[wasm_bindgen]
+pub fn run_source(text: &str) -> Result<JsValue, JsValue> {
+    let closure = compile::<Chunk>(text, None).map_err(|err| {
+        js_error(&format!(
+            "{}:{}: {}",
+            err.pos.ln.start + 1,
+            err.pos.col.start + 1,
+            err.value
+        ))
+    })?;
+    let mut interpreter = Interpreter::default();
+    std_hydra::import(&mut interpreter);
+    interpreter.set_output(|text| {
+        OUTPUT.with(|output| {
+            if let Some(callback) = &*output.borrow() {
+                let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(text));
+            }
+        })
+    });
+    interpreter
+        .call(
+            &Function {
+                closure: Rc::new(closure),
+                upvalues: vec![],
+            },
+            &[],
+            None,
+        )
+        .and_then(|_| interpreter.run())
+        .map(|value| value.as_ref().map(to_js).unwrap_or(JsValue::UNDEFINED))
+        .map_err(|err| js_error(&format!("{}: {}", err.ln + 1, err.err)))
+}
+
+/// converts `value` to the closest JS value. ints become numbers, so ones outside of
+/// 2^53 lose precision, and functions and native objects become their debug strings
+pub fn to_js(value: &Value) -> JsValue {
+    match value {
+        Value::Null => JsValue::NULL,
+        Value::Int(v) => JsValue::from_f64(*v as f64),
+        Value::Float(v) => JsValue::from_f64(*v),
+        Value::Bool(v) => JsValue::from_bool(*v),
+        Value::Char(v) => JsValue::from_str(&v.to_string()),
+        Value::String(v) => JsValue::from_str(v),
+        Value::Vector(values) => values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(to_js)
+            .collect::<Array>()
+            .into(),
+        Value::Tuple(values) => values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(to_js)
+            .collect::<Array>()
+            .into(),
+        Value::Map(entries) => {
+            let object = Object::new();
+            for (key, value) in entries.lock().unwrap().iter() {
+                let _ = Reflect::set(&object, &JsValue::from_str(key), &to_js(value));
+            }
+            object.into()
+        }
+        Value::Fn(_) | Value::NativeObject(_) => JsValue::from_str(&format!("{value:?}")),
+    }
+}
+fn js_error(message: &str) -> JsValue {
+    JsError::new(message).into()
+}